@@ -6,11 +6,11 @@ set DEST_CS=..\critical-point-u3d\Assets\Scripts
 if "%1" == "--release" (
     cargo build --release
     copy .\target\release\critical_point_u3d.dll %DEST_CP%
-    .\target\release\compiler.exe %DEST_CP% resource.yml id.yml
+    .\target\release\compiler.exe build %DEST_CP% resource.yml id.yml
 ) else (
     cargo build
     copy .\target\debug\critical_point_u3d.dll %DEST_CP%
-    .\target\debug\compiler.exe %DEST_CP% resource.yml id.yml
+    .\target\debug\compiler.exe build %DEST_CP% resource.yml id.yml
 )
 
 copy .\target\FFIData.cs %DEST_CS%
@@ -10,3 +10,4 @@ pub mod shape;
 
 pub use ncollide3d::bounding_volume;
 pub use ncollide3d::query;
+pub use ncollide3d::transformation;
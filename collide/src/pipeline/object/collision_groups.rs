@@ -1,3 +1,4 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CollisionGroups {
     pub team_membership: u16,
     pub team_whitelist: u16,
@@ -1,11 +1,17 @@
 use super::collision_object::CollisionObject;
 use math::Fx;
 use ncollide3d::pipeline::{CollisionObjectSet, CollisionObjectSlabHandle};
-use slab::{Iter, IterMut, Slab};
+use std::collections::BTreeSet;
+use std::iter::Enumerate;
 use std::ops::{Index, IndexMut};
+use std::slice::{Iter, IterMut};
 
+// Inserts always take the lowest vacant handle, so the handles only depend on the objects alive.
+// A world rebuilt from a snapshot hands out the same handles as the world it was saved from.
 pub struct CollisionObjectSlab<T> {
-    pub(crate) objects: Slab<CollisionObject<T>>,
+    entries: Vec<Option<CollisionObject<T>>>,
+    vacant: BTreeSet<usize>,
+    len: usize,
 }
 
 impl<T> CollisionObjectSet<Fx> for CollisionObjectSlab<T> {
@@ -20,8 +26,8 @@ impl<T> CollisionObjectSet<Fx> for CollisionObjectSlab<T> {
     }
 
     fn foreach(&self, mut f: impl FnMut(Self::CollisionObjectHandle, &Self::CollisionObject)) {
-        for co in self.objects.iter() {
-            f(CollisionObjectSlabHandle(co.0), co.1)
+        for (handle, co) in self.iter() {
+            f(handle, co)
         }
     }
 }
@@ -29,29 +35,76 @@ impl<T> CollisionObjectSet<Fx> for CollisionObjectSlab<T> {
 impl<T> CollisionObjectSlab<T> {
     pub fn new() -> CollisionObjectSlab<T> {
         return CollisionObjectSlab {
-            objects: Slab::new(),
+            entries: Vec::new(),
+            vacant: BTreeSet::new(),
+            len: 0,
         };
     }
 
     pub fn with_capacity(capacity: usize) -> CollisionObjectSlab<T> {
         return CollisionObjectSlab {
-            objects: Slab::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+            vacant: BTreeSet::new(),
+            len: 0,
         };
     }
 
+    // Handle of the next insert.
     #[inline]
-    pub fn insert(&mut self, co: CollisionObject<T>) -> CollisionObjectSlabHandle {
-        return CollisionObjectSlabHandle(self.objects.insert(co));
+    pub fn next_handle(&self) -> CollisionObjectSlabHandle {
+        return match self.vacant.iter().next() {
+            Some(key) => CollisionObjectSlabHandle(*key),
+            None => CollisionObjectSlabHandle(self.entries.len()),
+        };
     }
 
     #[inline]
+    pub fn insert(&mut self, co: CollisionObject<T>) -> CollisionObjectSlabHandle {
+        let handle = self.next_handle();
+        self.insert_at(handle, co);
+        return handle;
+    }
+
+    // Panics if the handle is in use.
+    pub fn insert_at(
+        &mut self,
+        handle: CollisionObjectSlabHandle,
+        co: CollisionObject<T>,
+    ) -> &mut CollisionObject<T> {
+        let key = handle.0;
+        while self.entries.len() <= key {
+            self.vacant.insert(self.entries.len());
+            self.entries.push(None);
+        }
+        assert!(self.entries[key].is_none(), "Handle {} in use", key);
+        self.vacant.remove(&key);
+        self.len += 1;
+        return self.entries[key].get_or_insert(co);
+    }
+
+    // Panics if the handle is vacant.
     pub fn remove(&mut self, handle: CollisionObjectSlabHandle) -> CollisionObject<T> {
-        return self.objects.remove(handle.0);
+        let co = match self
+            .entries
+            .get_mut(handle.0)
+            .and_then(|entry| entry.take())
+        {
+            Some(co) => co,
+            None => panic!("Invalid handle {}", handle.0),
+        };
+        self.vacant.insert(handle.0);
+        self.len -= 1;
+        // Trailing vacant entries are dropped, the next handle stays the lowest free one.
+        while let Some(None) = self.entries.last() {
+            self.vacant.remove(&(self.entries.len() - 1));
+            self.entries.pop();
+        }
+        return co;
     }
 
     #[inline]
     pub fn get(&self, handle: CollisionObjectSlabHandle) -> Option<&CollisionObject<T>> {
-        return self.objects.get(handle.0);
+        return self.entries.get(handle.0).and_then(|entry| entry.as_ref());
     }
 
     #[inline]
@@ -59,7 +112,10 @@ impl<T> CollisionObjectSlab<T> {
         &mut self,
         handle: CollisionObjectSlabHandle,
     ) -> Option<&mut CollisionObject<T>> {
-        return self.objects.get_mut(handle.0);
+        return self
+            .entries
+            .get_mut(handle.0)
+            .and_then(|entry| entry.as_mut());
     }
 
     #[inline]
@@ -72,50 +128,50 @@ impl<T> CollisionObjectSlab<T> {
         Option<&mut CollisionObject<T>>,
     ) {
         assert_ne!(handle1, handle2, "The two handles must not be the same.");
-        let a = self.objects.get_mut(handle1.0).map(|o| o as *mut _);
+        let a = self.get_mut(handle1).map(|o| o as *mut _);
         return (
             a.map(|a| unsafe { std::mem::transmute(a) }),
-            self.objects.get_mut(handle2.0),
+            self.get_mut(handle2),
         );
     }
 
     #[inline]
     pub fn contains(&self, handle: CollisionObjectSlabHandle) -> bool {
-        return self.objects.contains(handle.0);
+        return self.get(handle).is_some();
     }
 
     #[inline]
     pub fn iter(&self) -> CollisionObjects<T> {
         return CollisionObjects {
-            iter: self.objects.iter(),
+            iter: self.entries.iter().enumerate(),
         };
     }
 
     #[inline]
     pub fn iter_mut(&mut self) -> CollisionObjectsMut<T> {
         return CollisionObjectsMut {
-            iter_mut: self.objects.iter_mut(),
+            iter_mut: self.entries.iter_mut().enumerate(),
         };
     }
 
     #[inline]
     pub fn len(&self) -> usize {
-        return self.objects.len();
+        return self.len;
     }
 
     #[inline]
     pub fn capacity(&self) -> usize {
-        return self.objects.capacity();
+        return self.entries.capacity();
     }
 
     #[inline]
     pub fn reserve(&mut self, additional: usize) {
-        self.objects.reserve(additional);
+        self.entries.reserve(additional);
     }
 
     #[inline]
     pub fn reserve_exact(&mut self, additional: usize) {
-        self.objects.reserve_exact(additional);
+        self.entries.reserve_exact(additional);
     }
 }
 
@@ -124,19 +180,19 @@ impl<T> Index<CollisionObjectSlabHandle> for CollisionObjectSlab<T> {
 
     #[inline]
     fn index(&self, handle: CollisionObjectSlabHandle) -> &Self::Output {
-        return &self.objects[handle.0];
+        return self.get(handle).expect("Invalid handle");
     }
 }
 
 impl<T> IndexMut<CollisionObjectSlabHandle> for CollisionObjectSlab<T> {
     #[inline]
     fn index_mut(&mut self, handle: CollisionObjectSlabHandle) -> &mut Self::Output {
-        return &mut self.objects[handle.0];
+        return self.get_mut(handle).expect("Invalid handle");
     }
 }
 
 pub struct CollisionObjects<'a, T: 'a> {
-    iter: Iter<'a, CollisionObject<T>>,
+    iter: Enumerate<Iter<'a, Option<CollisionObject<T>>>>,
 }
 
 impl<'a, T: 'a> Iterator for CollisionObjects<'a, T> {
@@ -144,15 +200,17 @@ impl<'a, T: 'a> Iterator for CollisionObjects<'a, T> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        return self
-            .iter
-            .next()
-            .map(|obj| ((CollisionObjectSlabHandle(obj.0), obj.1)));
+        while let Some((key, entry)) = self.iter.next() {
+            if let Some(co) = entry {
+                return Some((CollisionObjectSlabHandle(key), co));
+            }
+        }
+        return None;
     }
 }
 
 pub struct CollisionObjectsMut<'a, T: 'a> {
-    iter_mut: IterMut<'a, CollisionObject<T>>,
+    iter_mut: Enumerate<IterMut<'a, Option<CollisionObject<T>>>>,
 }
 
 impl<'a, T: 'a> Iterator for CollisionObjectsMut<'a, T> {
@@ -160,9 +218,75 @@ impl<'a, T: 'a> Iterator for CollisionObjectsMut<'a, T> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        return self
-            .iter_mut
-            .next()
-            .map(|obj| ((CollisionObjectSlabHandle(obj.0), obj.1)));
+        while let Some((key, entry)) = self.iter_mut.next() {
+            if let Some(co) = entry {
+                return Some((CollisionObjectSlabHandle(key), co));
+            }
+        }
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::object::{CollisionGroups, CollisionObjectType};
+    use crate::shape::{Ball, ShapeHandle};
+    use math::fi;
+    use na::Isometry3;
+    use ncollide3d::pipeline::GeometricQueryType;
+
+    fn new_co(data: u64) -> CollisionObject<u64> {
+        return CollisionObject::new(
+            CollisionObjectType::Move,
+            None,
+            None,
+            Isometry3::identity(),
+            ShapeHandle::new(Ball::new(fi(1))),
+            CollisionGroups {
+                team_membership: 1,
+                team_whitelist: 0xFFFF,
+                role_membership: 1,
+                role_whitelist: 0xFFFF,
+            },
+            GeometricQueryType::Proximity(fi(0)),
+            data,
+        );
+    }
+
+    #[test]
+    fn test_collision_object_slab() {
+        let mut slab = CollisionObjectSlab::new();
+        for data in 0..4 {
+            assert_eq!(slab.insert(new_co(data)).0, data as usize);
+        }
+        slab.remove(CollisionObjectSlabHandle(2));
+        slab.remove(CollisionObjectSlabHandle(0));
+        assert_eq!(slab.len(), 2);
+        assert_eq!(slab.next_handle().0, 0);
+        assert_eq!(slab.insert(new_co(10)).0, 0);
+        assert_eq!(slab.insert(new_co(11)).0, 2);
+        assert_eq!(slab.insert(new_co(12)).0, 4);
+
+        // Rebuilt with the same live handles, the next inserts match.
+        let mut other = CollisionObjectSlab::new();
+        for (handle, co) in slab.iter() {
+            if handle.0 != 1 {
+                other.insert_at(handle, new_co(*co.data()));
+            }
+        }
+        slab.remove(CollisionObjectSlabHandle(1));
+        assert_eq!(other.len(), slab.len());
+        assert_eq!(other.next_handle(), slab.next_handle());
+        assert_eq!(
+            other.iter().map(|(handle, _)| handle.0).collect::<Vec<_>>(),
+            vec![0, 2, 3, 4]
+        );
+        assert!(!other.contains(CollisionObjectSlabHandle(1)));
+        assert_eq!(*other[CollisionObjectSlabHandle(4)].data(), 12);
+
+        slab.remove(CollisionObjectSlabHandle(4));
+        assert_eq!(slab.next_handle().0, 1);
+        assert_eq!(slab.capacity() >= 4, true);
     }
 }
@@ -62,8 +62,32 @@ impl<T: 'static> CollisionWorld<T> {
         query_type: GeometricQueryType<Fx>,
         data: T,
     ) -> (CollisionObjectSlabHandle, &mut CollisionObject<T>) {
-        let entry = self.objects.objects.vacant_entry();
-        let handle = CollisionObjectSlabHandle(entry.key());
+        let handle = self.objects.next_handle();
+        let co = self.add_at(
+            handle,
+            obj_type,
+            position,
+            shape,
+            collision_groups,
+            query_type,
+            data,
+        );
+        return (handle, co);
+    }
+
+    // Adds an object under a given handle, used to rebuild a world from a snapshot.
+    // Panics if the handle is in use.
+    pub fn add_at(
+        &mut self,
+        handle: CollisionObjectSlabHandle,
+        obj_type: CollisionObjectType,
+        position: Isometry<Fx>,
+        shape: ShapeHandle<Fx>,
+        collision_groups: CollisionGroups,
+        query_type: GeometricQueryType<Fx>,
+        data: T,
+    ) -> &mut CollisionObject<T> {
+        assert!(!self.objects.contains(handle), "Handle {} in use", handle.0);
 
         let mut aabb = shape.aabb(&position);
         aabb.loosen(query_type.query_limit());
@@ -82,7 +106,7 @@ impl<T: 'static> CollisionWorld<T> {
             data,
         );
 
-        return (handle, entry.insert(co));
+        return self.objects.insert_at(handle, co);
     }
 
     pub fn update(&mut self, types: &[CollisionObjectType]) {
@@ -335,7 +359,7 @@ impl<T: 'static> CollisionWorld<T> {
                             co.position(),
                             &Vector::zeros(),
                             co.shape().as_ref(),
-                            Fx::max_value(),
+                            max_distance,
                             Fx::c0(),
                         )
                         .unwrap_or(None);
@@ -370,7 +394,7 @@ impl<T: 'static> CollisionWorld<T> {
         let aabb = a.merged(&b);
 
         let mut ret = None;
-        let min_toi = Fx::max_value();
+        let mut min_toi = Fx::max_value();
 
         assert!(self.handles.is_empty());
         self.broad_phase
@@ -394,6 +418,7 @@ impl<T: 'static> CollisionWorld<T> {
                         .unwrap_or(None);
                     if let Some(toi) = res {
                         if toi.toi < min_toi {
+                            min_toi = toi.toi;
                             ret = Some((*handle, toi));
                         }
                     }
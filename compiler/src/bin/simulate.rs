@@ -0,0 +1,482 @@
+extern crate anyhow;
+extern crate core;
+extern crate math;
+extern crate nalgebra as na;
+extern crate serde;
+extern crate serde_json;
+
+use anyhow::{anyhow, Result};
+use core::agent::{InputRecorder, ReplayAgent, ReplayFile, SyncLogicAgent};
+use core::engine::{
+    CmdNewPrefab, Command, DataPool, EventDead, LogicEngine, LogicObjSuper, OpAction, OpCommand,
+    Operation, StateCharaHuman, StateDumper, StateProjectile,
+};
+use core::id::{ObjID, ResID};
+use core::resource::{ResActionAny, ResCache};
+use core::utils::{deserialize, Prng};
+use math::{ff, fi, Fx};
+use na::Vector2;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::process;
+use std::sync::Arc;
+
+const USAGE: &'static str = "\
+Usage: simulate <./root/path> <resource.yml> <id.yml> <PrefabResID> [options]
+Options: --ticks <n>            max ticks of a match (default 3600)
+         --fps <n>              logic fps (default 20)
+         --script <input.yml>   scripted inputs [{ tick, obj_id, command }]
+         --replay <replay.json> replays a recorded match and checks its checksums
+         --record <replay.json> records the inputs of the match
+         --ai                   AI-vs-AI, bots are seeded by --seed
+         --seed <n>             seed of the first match (default 0)
+         --matches <n>          matches to run with --ai, one seed each (default 1)
+         --states               dumps the logic states every tick
+         --out <out.jsonl>      writes the JSON lines to a file instead of stdout";
+
+const BOT_REACH: Fx = ff(1.5);
+
+struct Args {
+    positions: Vec<String>,
+    ticks: u32,
+    fps: u32,
+    script: Option<String>,
+    replay: Option<String>,
+    record: Option<String>,
+    ai: bool,
+    seed: u64,
+    matches: u32,
+    states: bool,
+    out: Option<String>,
+}
+
+impl Args {
+    fn parse(mut iter: impl Iterator<Item = String>) -> Result<Args> {
+        let mut args = Args {
+            positions: Vec::new(),
+            ticks: 3600,
+            fps: 20,
+            script: None,
+            replay: None,
+            record: None,
+            ai: false,
+            seed: 0,
+            matches: 1,
+            states: false,
+            out: None,
+        };
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or(anyhow!("Missing value of {:?}", arg));
+            match arg.as_str() {
+                "--ticks" => args.ticks = value()?.parse()?,
+                "--fps" => args.fps = value()?.parse()?,
+                "--script" => args.script = Some(value()?),
+                "--replay" => args.replay = Some(value()?),
+                "--record" => args.record = Some(value()?),
+                "--ai" => args.ai = true,
+                "--seed" => args.seed = value()?.parse()?,
+                "--matches" => args.matches = value()?.parse()?,
+                "--states" => args.states = true,
+                "--out" => args.out = Some(value()?),
+                _ if arg.starts_with("--") => return Err(anyhow!("Unknown option {:?}", arg)),
+                _ => args.positions.push(arg),
+            }
+        }
+
+        if args.positions.len() != 4 {
+            return Err(anyhow!(
+                "Expects 4 argument(s), got {}",
+                args.positions.len()
+            ));
+        }
+        let inputs = [args.script.is_some(), args.replay.is_some(), args.ai];
+        if inputs.iter().filter(|input| **input).count() > 1 {
+            return Err(anyhow!("--script, --replay and --ai are exclusive"));
+        }
+        if args.matches == 0 || (args.matches > 1 && !args.ai) {
+            return Err(anyhow!("--matches needs --ai and must be positive"));
+        }
+        if args.record.is_some() && args.matches > 1 {
+            return Err(anyhow!("--record only supports a single match"));
+        }
+        if args.record.is_some() && args.replay.is_some() {
+            return Err(anyhow!("--record and --replay are exclusive"));
+        }
+        return Ok(args);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ScriptInput {
+    tick: u32,
+    #[serde(flatten)]
+    operation: Operation,
+}
+
+// Where the operations of every tick come from.
+enum Inputs {
+    None,
+    Script(BTreeMap<u32, Vec<Operation>>),
+    Replay(ReplayAgent<Command>),
+    Bot(Prng),
+}
+
+// What a match keeps from a tick, read from the engine before the DataPool is dispatched.
+struct TickOutput {
+    checksum: u64,
+    events: Value,
+    states: Option<Value>,
+    deaths: Vec<(ObjID, Option<ResID>)>,
+    charas: usize,
+    alive: usize,
+    bot_operations: Vec<Operation>,
+}
+
+fn main() {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = run(&args) {
+        eprintln!("Error: {:#}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<()> {
+    let cache = ResCache::restore(&args.positions[0], &args.positions[1], &args.positions[2])?;
+    let mut writer: Box<dyn Write> = match &args.out {
+        Some(out) => Box::new(BufWriter::new(File::create(out)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    run_matches(args, &cache, &mut writer)?;
+    writer.flush()?;
+    return Ok(());
+}
+
+fn run_matches(args: &Args, cache: &Arc<ResCache>, writer: &mut dyn Write) -> Result<()> {
+    let prefab = ResID::from(args.positions[3].as_str());
+    for idx in 0..args.matches {
+        let seed = args.seed + idx as u64;
+        let inputs = if let Some(script) = &args.script {
+            let script: Vec<ScriptInput> = deserialize(script)?;
+            let mut ticks = BTreeMap::new();
+            for input in script {
+                ticks
+                    .entry(input.tick)
+                    .or_insert_with(Vec::new)
+                    .push(input.operation);
+            }
+            Inputs::Script(ticks)
+        } else if let Some(replay) = &args.replay {
+            let replay = ReplayFile::load(replay)?;
+            Inputs::Replay(ReplayAgent::new(
+                replay,
+                cache.id_table(),
+                fi(args.fps as i64),
+            )?)
+        } else if args.ai {
+            Inputs::Bot(Prng::new(seed))
+        } else {
+            Inputs::None
+        };
+        run_match(args, cache, &prefab, idx, seed, inputs, writer)?;
+    }
+    return Ok(());
+}
+
+// Runs until --ticks, the end of the replay, or a single character is left alive.
+// The prefab is spawned by the engine on the first tick, so tick 0 carries the spawn events.
+fn run_match(
+    args: &Args,
+    cache: &Arc<ResCache>,
+    prefab: &ResID,
+    idx: u32,
+    seed: u64,
+    mut inputs: Inputs,
+    writer: &mut dyn Write,
+) -> Result<()> {
+    let fps = fi(args.fps as i64);
+    let mut agent = SyncLogicAgent::new(cache.clone(), fps)?;
+    let initial_commands = match &inputs {
+        Inputs::Replay(replay) => replay.initial_commands().to_vec(),
+        _ => vec![Command::NewPrefab(CmdNewPrefab {
+            res_id: prefab.clone(),
+        })],
+    };
+    for cmd in &initial_commands {
+        agent.run_command(cmd.clone());
+    }
+    let mut recorder = match &args.record {
+        Some(_) => Some(InputRecorder::new(
+            cache.id_table(),
+            fps,
+            initial_commands.clone(),
+        )),
+        None => None,
+    };
+
+    let mut dumper = StateDumper::new();
+    dumper.register::<StateCharaHuman>();
+    dumper.register::<StateProjectile>();
+    writeln!(
+        writer,
+        "{}",
+        json!({ "match": idx, "seed": seed, "prefab": prefab })
+    )?;
+
+    let mut tick = 0;
+    let mut bot_operations = Vec::new();
+    let mut deaths: Vec<(ObjID, Option<ResID>, u32)> = Vec::new();
+    let mut checksum = 0;
+    let mut last_states = Value::Null;
+    while tick < args.ticks {
+        let output = match &mut inputs {
+            Inputs::Replay(replay) => {
+                let mut output = None;
+                let running = replay.run_tick(|commands| {
+                    for cmd in commands {
+                        agent.run_command(cmd.clone());
+                    }
+                    let tick_output = agent.run_tick_with(|engine, pool| {
+                        return inspect_tick(engine, pool, &dumper, args.states, None);
+                    })?;
+                    let checksum = tick_output.checksum;
+                    output = Some(tick_output);
+                    return Ok(checksum);
+                })?;
+                match (running, output) {
+                    (true, Some(output)) => output,
+                    _ => break,
+                }
+            }
+            inputs => {
+                let (operations, prng) = match inputs {
+                    Inputs::Script(script) => {
+                        (script.get(&tick).cloned().unwrap_or_default(), None)
+                    }
+                    Inputs::Bot(prng) => (mem::take(&mut bot_operations), Some(prng)),
+                    _ => (Vec::new(), None),
+                };
+                let commands: Vec<Command> =
+                    operations.into_iter().map(Command::Operation).collect();
+                for cmd in &commands {
+                    agent.run_command(cmd.clone());
+                }
+                let output = agent.run_tick_with(|engine, pool| {
+                    return inspect_tick(engine, pool, &dumper, args.states, prng);
+                })?;
+                if let Some(recorder) = &mut recorder {
+                    recorder.record_tick(&commands, Some(output.checksum));
+                }
+                output
+            }
+        };
+
+        checksum = output.checksum;
+        bot_operations = output.bot_operations;
+        deaths.extend(
+            output
+                .deaths
+                .into_iter()
+                .map(|(obj_id, res_id)| (obj_id, res_id, tick)),
+        );
+        let has_events = output
+            .events
+            .as_array()
+            .map_or(false, |events| !events.is_empty());
+        if let Some(states) = &output.states {
+            last_states = states.clone();
+        }
+        if args.states || has_events {
+            let mut line = json!({ "match": idx, "tick": tick, "events": output.events });
+            if let Some(states) = output.states {
+                line["states"] = states;
+            }
+            writeln!(writer, "{}", line)?;
+        }
+        tick += 1;
+        if output.charas > 1 && output.alive <= 1 {
+            break;
+        }
+    }
+
+    // Time to kill counts the tick of the killing blow.
+    let deaths: Vec<Value> = deaths
+        .iter()
+        .map(|(obj_id, res_id, tick)| {
+            let seconds = (*tick + 1) as f64 / args.fps as f64;
+            return json!({ "obj_id": obj_id, "res_id": res_id, "tick": tick, "seconds": seconds });
+        })
+        .collect();
+    let mut summary = json!({ "ticks": tick, "checksum": checksum, "deaths": deaths });
+    if args.states {
+        summary["states"] = last_states;
+    }
+    writeln!(writer, "{}", json!({ "match": idx, "summary": summary }))?;
+
+    if let (Some(path), Some(recorder)) = (&args.record, recorder) {
+        recorder.finish().save(path)?;
+    }
+    return Ok(());
+}
+
+fn inspect_tick(
+    engine: &LogicEngine,
+    pool: &DataPool,
+    dumper: &StateDumper,
+    states: bool,
+    prng: Option<&mut Prng>,
+) -> Result<TickOutput> {
+    let mut deaths = Vec::new();
+    for event in pool.events().iter() {
+        if event.cast::<EventDead>().is_some() {
+            let res_id = engine
+                .find_chara(event.obj_id())
+                .map(|chara| chara.res().res_id.clone());
+            deaths.push((event.obj_id(), res_id));
+        }
+    }
+
+    let mut bot_operations = Vec::new();
+    if let Some(prng) = prng {
+        bot_operations_of(engine, prng, &mut bot_operations);
+    }
+    return Ok(TickOutput {
+        checksum: engine.checksum(pool),
+        events: serde_json::to_value(pool.events().dump_events()?)?,
+        states: match states {
+            true => Some(serde_json::to_value(dumper.dump(pool)?)?),
+            false => None,
+        },
+        deaths,
+        charas: engine.charas().len(),
+        alive: engine
+            .charas()
+            .iter()
+            .filter(|chara| !chara.is_dead())
+            .count(),
+        bot_operations,
+    });
+}
+
+// A naive brawler for AI-vs-AI balance runs: walks to the nearest living enemy, then randomly attacks or waits.
+// Only reads the logic state, so it stays deterministic for a given seed.
+fn bot_operations_of(engine: &LogicEngine, prng: &mut Prng, out: &mut Vec<Operation>) {
+    for chara in engine.charas() {
+        if chara.is_dead() {
+            continue;
+        }
+        match chara.action() {
+            Some(ResActionAny::Idle(_)) | Some(ResActionAny::Run(_)) => {}
+            _ => continue,
+        };
+        let position = chara.position().translation.vector;
+        let target = engine
+            .charas()
+            .iter()
+            .filter(|other| other.obj_id() != chara.obj_id() && !other.is_dead())
+            .map(|other| other.position().translation.vector - position)
+            .min_by_key(|offset| offset.norm());
+        let offset = match target {
+            Some(offset) => offset,
+            None => continue,
+        };
+        let command = if offset.norm() > BOT_REACH {
+            let dir = Vector2::new(offset.x, offset.z);
+            // Straight above or below, there is no direction to walk.
+            if dir.norm() == fi(0) {
+                continue;
+            }
+            OpCommand::Move(dir / dir.norm())
+        } else if prng.next_range(3) == 0 {
+            continue;
+        } else {
+            OpCommand::Attack1(OpAction::Press, Vector2::zeros())
+        };
+        out.push(Operation {
+            obj_id: chara.obj_id(),
+            command,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &'static str = "../test_files/simulate";
+
+    fn parse(args: &[&str]) -> Args {
+        let mut all = vec![ROOT, "resource.yml", "id.yml", "Prefab.Duel"];
+        all.extend_from_slice(args);
+        return Args::parse(all.iter().map(|arg| arg.to_string())).unwrap();
+    }
+
+    // Compiles the checked-in fixture in memory, nothing is written to disk.
+    fn simulate(args: &Args) -> Vec<Value> {
+        let cache = ResCache::compile(ROOT, "resource.yml").unwrap();
+        let mut out = Vec::new();
+        run_matches(args, &cache, &mut out).unwrap();
+        return String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+    }
+
+    #[test]
+    fn test_simulate_args() {
+        let args = parse(&["--ai", "--seed", "3", "--matches", "2", "--states"]);
+        assert!(args.ai && args.states);
+        assert_eq!((args.seed, args.matches), (3, 2));
+        let bad = |extra: &[&str]| {
+            let mut all = vec![ROOT, "resource.yml", "id.yml", "Prefab.Duel"];
+            all.extend_from_slice(extra);
+            return Args::parse(all.iter().map(|arg| arg.to_string())).is_err();
+        };
+        assert!(bad(&["--ai", "--replay", "replay.json"]));
+        assert!(bad(&["--replay", "replay.json", "--record", "record.json"]));
+        assert!(bad(&["--matches", "2"]));
+        assert!(bad(&["--ticks"]));
+        assert!(Args::parse(vec![ROOT.to_string()].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_simulate_idle() {
+        let lines = simulate(&parse(&["--ticks", "10", "--states"]));
+        assert_eq!(lines[0]["prefab"], "Prefab.Duel");
+        let summary = &lines.last().unwrap()["summary"];
+        assert_eq!(summary["ticks"], 10);
+        assert_eq!(summary["deaths"].as_array().unwrap().len(), 0);
+        let states = summary["states"].as_array().unwrap();
+        assert_eq!(states.len(), 2);
+    }
+
+    #[test]
+    fn test_simulate_bot() {
+        let args = parse(&["--ai", "--seed", "7", "--ticks", "2000"]);
+        let lines = simulate(&args);
+        let summary = &lines.last().unwrap()["summary"];
+        let deaths = summary["deaths"].as_array().unwrap();
+        assert_eq!(deaths.len(), 1);
+        assert_eq!(deaths[0]["res_id"], "Chara.Test");
+        let tick = deaths[0]["tick"].as_u64().unwrap();
+        assert_eq!(summary["ticks"].as_u64().unwrap(), tick + 1);
+        assert_eq!(
+            deaths[0]["seconds"].as_f64().unwrap(),
+            (tick + 1) as f64 / 20.0
+        );
+
+        // Same seed, same match.
+        assert_eq!(simulate(&args), lines);
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::Result;
+use core::id::ResID;
+use core::resource::ResCache;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind")]
+pub enum ResDiff {
+    Added {
+        res_id: ResID,
+    },
+    Removed {
+        res_id: ResID,
+    },
+    Changed {
+        res_id: ResID,
+        fields: Vec<FieldDiff>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldDiff {
+    pub field: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+pub fn diff_res_cache(old: &ResCache, new: &ResCache) -> Result<Vec<ResDiff>> {
+    let old_values = collect_values(old)?;
+    let new_values = collect_values(new)?;
+
+    let mut diffs = Vec::new();
+    for (res_id, old_value) in &old_values {
+        match new_values.get(res_id) {
+            None => diffs.push(ResDiff::Removed {
+                res_id: res_id.clone(),
+            }),
+            Some(new_value) => {
+                let fields = diff_value(old_value, new_value);
+                if !fields.is_empty() {
+                    diffs.push(ResDiff::Changed {
+                        res_id: res_id.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    for res_id in new_values.keys() {
+        if !old_values.contains_key(res_id) {
+            diffs.push(ResDiff::Added {
+                res_id: res_id.clone(),
+            });
+        }
+    }
+    diffs.sort_by(|a, b| a.res_id().cmp(b.res_id()));
+    return Ok(diffs);
+}
+
+fn collect_values(cache: &ResCache) -> Result<BTreeMap<ResID, Value>> {
+    let mut values = BTreeMap::new();
+    for (res_id, res) in cache.res_objs() {
+        values.insert(res_id.clone(), serde_json::to_value(res)?);
+    }
+    return Ok(values);
+}
+
+pub fn diff_value(old: &Value, new: &Value) -> Vec<FieldDiff> {
+    let mut old_fields = BTreeMap::new();
+    flatten_value(old, "", &mut old_fields);
+    let mut new_fields = BTreeMap::new();
+    flatten_value(new, "", &mut new_fields);
+
+    let mut diffs = Vec::new();
+    for (field, old_value) in &old_fields {
+        match new_fields.get(field) {
+            Some(new_value) if new_value == old_value => {}
+            new_value => diffs.push(FieldDiff {
+                field: field.clone(),
+                old: Some(old_value.clone()),
+                new: new_value.cloned(),
+            }),
+        }
+    }
+    for (field, new_value) in &new_fields {
+        if !old_fields.contains_key(field) {
+            diffs.push(FieldDiff {
+                field: field.clone(),
+                old: None,
+                new: Some(new_value.clone()),
+            });
+        }
+    }
+    diffs.sort_by(|a, b| a.field.cmp(&b.field));
+    return diffs;
+}
+
+fn flatten_value(value: &Value, path: &str, out: &mut BTreeMap<String, Value>) {
+    let join = |key: &str| match path {
+        "" => key.to_string(),
+        _ => format!("{}.{}", path, key),
+    };
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                flatten_value(value, &join(key), out);
+            }
+        }
+        Value::Array(list) if !list.is_empty() => {
+            for (idx, value) in list.iter().enumerate() {
+                flatten_value(value, &format!("{}[{}]", path, idx), out);
+            }
+        }
+        _ => {
+            out.insert(path.to_string(), value.clone());
+        }
+    }
+}
+
+impl ResDiff {
+    pub fn res_id(&self) -> &ResID {
+        return match self {
+            ResDiff::Added { res_id } => res_id,
+            ResDiff::Removed { res_id } => res_id,
+            ResDiff::Changed { res_id, .. } => res_id,
+        };
+    }
+}
+
+impl Display for ResDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        return match self {
+            ResDiff::Added { res_id } => write!(f, "+ {}", String::from(res_id.clone())),
+            ResDiff::Removed { res_id } => write!(f, "- {}", String::from(res_id.clone())),
+            ResDiff::Changed { res_id, fields } => {
+                write!(f, "~ {}", String::from(res_id.clone()))?;
+                for field in fields {
+                    write!(f, "\n    {}", field)?;
+                }
+                Ok(())
+            }
+        };
+    }
+}
+
+impl Display for FieldDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let show = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "(none)".to_string(),
+        };
+        return write!(
+            f,
+            "{}: {} -> {}",
+            self.field,
+            show(&self.old),
+            show(&self.new)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_value() {
+        let old = json!({"type": "CharaHuman", "max_health": 100, "collision": {"radius": 1}, "skills": ["A"]});
+        let new = json!({"type": "CharaHuman", "max_health": 120, "collision": {"radius": 1}, "skills": ["A", "B"]});
+        let diffs = diff_value(&old, &new);
+        assert_eq!(
+            diffs,
+            vec![
+                FieldDiff {
+                    field: "max_health".to_string(),
+                    old: Some(json!(100)),
+                    new: Some(json!(120)),
+                },
+                FieldDiff {
+                    field: "skills[1]".to_string(),
+                    old: None,
+                    new: Some(json!("B")),
+                },
+            ]
+        );
+        assert_eq!(diffs[0].to_string(), "max_health: 100 -> 120");
+        assert!(diff_value(&old, &old).is_empty());
+
+        let diff = ResDiff::Changed {
+            res_id: ResID::from("Chara.Test"),
+            fields: diffs,
+        };
+        assert_eq!(
+            serde_json::to_value(&diff).unwrap(),
+            json!({
+                "kind": "Changed",
+                "res_id": "Chara.Test",
+                "fields": [
+                    { "field": "max_health", "old": 100, "new": 120 },
+                    { "field": "skills[1]", "old": null, "new": "B" },
+                ],
+            })
+        );
+        let diff = ResDiff::Added {
+            res_id: ResID::from("Chara.New"),
+        };
+        assert_eq!(
+            serde_json::to_value(&diff).unwrap(),
+            json!({ "kind": "Added", "res_id": "Chara.New" })
+        );
+    }
+}
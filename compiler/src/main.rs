@@ -1,25 +1,411 @@
+extern crate anyhow;
 extern crate core;
+extern crate serde;
+extern crate serde_json;
+extern crate serde_yaml;
 
+mod diff;
+mod stats;
+
+use anyhow::{anyhow, Result};
+use core::id::ResID;
 use core::resource::ResCache;
 use core::utils::serialize;
+use diff::diff_res_cache;
+use serde_json::{json, Value};
+use stats::collect_stats;
 use std::env;
+use std::fs::File;
 use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+
+const USAGE: &'static str = "\
+Usage: compiler check <./root/path> <resource.yml> [--profile <name>] [--json]
+       compiler build <./root/path> <resource.yml> <id.yml> [--profile <name>] [--bundle <bundle.json>] [--json]
+       compiler dump <./root/path> <resource.yml> <ResID> [--profile <name>] [--json]
+       compiler stats <./root/path> <resource.yml> [--profile <name>] [--json]
+       compiler diff <./old/root/path> <./new/root/path> <resource.yml> [--json]";
+
+struct Args {
+    command: String,
+    positions: Vec<String>,
+    profile: Option<String>,
+    bundle: Option<String>,
+    json: bool,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut iter: I) -> Result<Args> {
+        let command = iter.next().ok_or(anyhow!("Missing command"))?;
+        let mut args = Args {
+            command,
+            positions: Vec::new(),
+            profile: None,
+            bundle: None,
+            json: false,
+        };
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--json" => args.json = true,
+                "--profile" => {
+                    args.profile = Some(iter.next().ok_or(anyhow!("Missing profile name"))?)
+                }
+                "--bundle" => {
+                    args.bundle = Some(iter.next().ok_or(anyhow!("Missing bundle file"))?)
+                }
+                _ if arg.starts_with("--") => return Err(anyhow!("Unknown option {:?}", arg)),
+                _ => args.positions.push(arg),
+            }
+        }
+        return Ok(args);
+    }
+
+    fn expect(&self, count: usize) -> Result<()> {
+        if self.positions.len() != count {
+            return Err(anyhow!(
+                "Command {:?} expects {} argument(s), got {}",
+                self.command,
+                count,
+                self.positions.len()
+            ));
+        }
+        return Ok(());
+    }
+
+    fn compile(&self) -> Result<Arc<ResCache>> {
+        return self.compile_root(&self.positions[0], &self.positions[1]);
+    }
+
+    fn compile_root(&self, root_path: &str, res_file: &str) -> Result<Arc<ResCache>> {
+        return match &self.profile {
+            Some(profile) => ResCache::compile_with_profile(root_path, res_file, profile),
+            None => ResCache::compile(root_path, res_file),
+        };
+    }
+}
+
+// Result of a command, printed as text or as one JSON object with --json.
+struct Report {
+    ok: bool,
+    json: Value,
+    text: String,
+}
+
+impl Report {
+    fn new(ok: bool, json: Value, text: String) -> Report {
+        return Report { ok, json, text };
+    }
+}
 
 fn main() {
-    if env::args().len() != 4 {
-        println!("Usage: compiler <./root/path> <resource.yml> <id.yml>");
-        return;
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    let res = match args.command.as_str() {
+        "check" => check(&args),
+        "build" => build(&args),
+        "dump" => dump(&args),
+        "stats" => stats(&args),
+        "diff" => diff(&args),
+        _ => {
+            eprintln!("Unknown command {:?}\n{}", args.command, USAGE);
+            process::exit(2);
+        }
+    };
+    match res {
+        Ok(report) => {
+            if args.json {
+                println!("{}", report.json);
+            } else {
+                println!("{}", report.text);
+            }
+            if !report.ok {
+                process::exit(1);
+            }
+        }
+        Err(err) => {
+            if args.json {
+                println!("{}", error_json(&err));
+            } else {
+                eprintln!("Error: {:#}", err);
+            }
+            process::exit(1);
+        }
+    }
+}
+
+// Same layout as the check errors, tools parse one format for all failures.
+fn error_json(err: &anyhow::Error) -> Value {
+    return json!({ "ok": false, "errors": [{ "res_id": null, "message": format!("{:#}", err) }] });
+}
+
+fn check(args: &Args) -> Result<Report> {
+    args.expect(2)?;
+    let errors = ResCache::check(
+        &args.positions[0],
+        &args.positions[1],
+        args.profile.as_deref(),
+    );
+    let mut text = String::new();
+    for err in &errors {
+        match &err.res_id {
+            Some(res_id) => text += &format!("{}: {}\n", String::from(res_id.clone()), err.message),
+            None => text += &format!("{}\n", err.message),
+        }
     }
-    let root_path = env::args().nth(1).unwrap();
-    let res_file = env::args().nth(2).unwrap();
-    let id_file = env::args().nth(3).unwrap();
+    text += &format!("{} error(s) found", errors.len());
+    let json = json!({ "ok": errors.is_empty(), "errors": errors });
+    return Ok(Report::new(errors.is_empty(), json, text));
+}
 
-    let cache = ResCache::compile(&root_path, &res_file).unwrap();
-    let table = cache.id_table();
+fn build(args: &Args) -> Result<Report> {
+    args.expect(3)?;
+    let cache = args.compile()?;
 
-    let mut id_path = PathBuf::from(root_path);
-    id_path.push(id_file);
-    serialize(id_path, table).unwrap();
+    let mut id_path = PathBuf::from(&args.positions[0]);
+    id_path.push(&args.positions[2]);
+    serialize(&id_path, cache.id_table())?;
 
-    println!("Compile resource success");
+    let mut bundle_path = None;
+    if let Some(bundle) = &args.bundle {
+        let mut res_objs: Vec<_> = cache.res_objs().collect();
+        res_objs.sort_by(|a, b| a.0.cmp(b.0));
+        let resource: Vec<_> = res_objs.into_iter().map(|(_, res)| res).collect();
+
+        let mut path = PathBuf::from(&args.positions[0]);
+        path.push(bundle);
+        serde_json::to_writer(File::create(&path)?, &json!({ "resource": resource }))?;
+        bundle_path = Some(path);
+    }
+
+    let json = json!({ "ok": true, "id_file": id_path, "bundle_file": bundle_path, "count": cache.res_objs().count() });
+    return Ok(Report::new(
+        true,
+        json,
+        "Compile resource success".to_string(),
+    ));
+}
+
+fn dump(args: &Args) -> Result<Report> {
+    args.expect(3)?;
+    let cache = args.compile()?;
+    let res_id = ResID::from(args.positions[2].as_str());
+    let res = cache.find_res_by_id(&res_id)?;
+    let patches: Vec<Value> = cache
+        .find_patches(&res_id)
+        .iter()
+        .map(
+            |patch| json!({ "profile": patch.profile, "file": patch.file, "fields": patch.fields }),
+        )
+        .collect();
+    let json = json!({ "ok": true, "resource": res, "patches": patches });
+
+    let mut text = serde_yaml::to_string(&res)?;
+    for patch in cache.find_patches(&res_id) {
+        text += &format!(
+            "# patched by {:?} in {:?}: {}\n",
+            patch.profile,
+            patch.file,
+            patch.fields.join(", ")
+        );
+    }
+    return Ok(Report::new(true, json, text.trim_end().to_string()));
+}
+
+fn stats(args: &Args) -> Result<Report> {
+    args.expect(2)?;
+    let cache = args.compile()?;
+    let stats = collect_stats(&cache, &args.positions[0])?;
+    let json = json!({ "ok": true, "stats": stats });
+    return Ok(Report::new(true, json, stats.to_string()));
+}
+
+fn diff(args: &Args) -> Result<Report> {
+    args.expect(3)?;
+    let old_cache = args.compile_root(&args.positions[0], &args.positions[2])?;
+    let new_cache = args.compile_root(&args.positions[1], &args.positions[2])?;
+    let diffs = diff_res_cache(&old_cache, &new_cache)?;
+    let mut text = String::new();
+    for diff in &diffs {
+        text += &format!("{}\n", diff);
+    }
+    text += &format!("{} resource(s) differ", diffs.len());
+    let json = json!({ "ok": true, "diffs": diffs });
+    return Ok(Report::new(true, json, text));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const ROOT: &'static str = "../test_files/resource";
+
+    fn args(line: &str) -> Args {
+        return Args::parse(line.split_whitespace().map(|arg| arg.to_string())).unwrap();
+    }
+
+    // A copy of the fixture resources, commands writing files never touch ../test_files.
+    fn copy_root(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!(
+            "critical_point_compiler_{}_{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for entry in fs::read_dir(ROOT).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_file() {
+                fs::copy(&path, root.join(path.file_name().unwrap())).unwrap();
+            }
+        }
+        return root;
+    }
+
+    #[test]
+    fn test_args() {
+        let args = args("build ./res resource.yml id.yml --profile hard --bundle out.json --json");
+        assert_eq!(args.command, "build");
+        assert_eq!(args.positions, vec!["./res", "resource.yml", "id.yml"]);
+        assert_eq!(args.profile.as_deref(), Some("hard"));
+        assert_eq!(args.bundle.as_deref(), Some("out.json"));
+        assert!(args.json);
+        assert!(args.expect(2).is_err());
+
+        let parse = |line: &str| Args::parse(line.split_whitespace().map(|arg| arg.to_string()));
+        assert!(parse("check ./res --unknown").is_err());
+        assert!(parse("check ./res --profile").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let report = check(&args(&format!("check {} resource.yaml", ROOT))).unwrap();
+        assert!(report.ok);
+        assert_eq!(report.json, json!({ "ok": true, "errors": [] }));
+        assert_eq!(report.text, "0 error(s) found");
+
+        let root = copy_root("check");
+        fs::write(
+            root.join("character.yml"),
+            fs::read_to_string(root.join("character.yml"))
+                .unwrap()
+                .replace("max_health: 10000", "max_health: abc"),
+        )
+        .unwrap();
+        let report = check(&args(&format!("check {} resource.yaml", root.display()))).unwrap();
+        assert!(!report.ok);
+        assert_eq!(report.json["ok"], json!(false));
+        let errors = report.json["errors"].as_array().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]["message"].is_string());
+        assert!(errors[0].get("res_id").is_some());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_build() {
+        let root = copy_root("build");
+        let report = build(&args(&format!(
+            "build {} resource.yaml out_id.yml --bundle bundle.json",
+            root.display()
+        )))
+        .unwrap();
+        assert!(report.ok);
+        assert_eq!(report.json["ok"], json!(true));
+        assert_eq!(report.json["count"], json!(3));
+        assert!(root.join("out_id.yml").exists());
+
+        let bundle: Value =
+            serde_json::from_str(&fs::read_to_string(root.join("bundle.json")).unwrap()).unwrap();
+        let res_ids: Vec<_> = bundle["resource"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|res| res["res_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(res_ids, vec!["Chara.Test", "Command.Test", "Stage.Test"]);
+        assert!(ResCache::restore(root.to_str().unwrap(), "resource.yaml", "out_id.yml").is_ok());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_dump() {
+        let report = dump(&args(&format!("dump {} resource.yaml Chara.Test", ROOT))).unwrap();
+        assert_eq!(report.json["ok"], json!(true));
+        assert_eq!(report.json["resource"]["res_id"], json!("Chara.Test"));
+        assert_eq!(report.json["resource"]["max_health"], json!(10000));
+        assert_eq!(report.json["patches"], json!([]));
+        assert!(report.text.contains("res_id: Chara.Test"));
+
+        let err = dump(&args(&format!("dump {} resource.yaml Chara.None", ROOT)))
+            .err()
+            .unwrap();
+        let json = error_json(&err);
+        assert_eq!(json["ok"], json!(false));
+        assert_eq!(json["errors"][0]["res_id"], Value::Null);
+        assert!(json["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Chara.None"));
+
+        let err = dump(&args(&format!("dump {} resource.yaml", ROOT)))
+            .err()
+            .unwrap();
+        assert!(error_json(&err)["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("expects 3 argument(s)"));
+    }
+
+    #[test]
+    fn test_stats() {
+        let report = stats(&args(&format!("stats {} resource.yaml", ROOT))).unwrap();
+        assert_eq!(report.json["ok"], json!(true));
+        assert_eq!(report.json["stats"]["total"], json!(3));
+        assert_eq!(report.json["stats"]["classes"]["CharaHuman"], json!(1));
+        assert_eq!(report.json["stats"]["meshes"], json!([]));
+    }
+
+    #[test]
+    fn test_diff() {
+        let root = copy_root("diff");
+        fs::write(
+            root.join("character.yml"),
+            fs::read_to_string(root.join("character.yml"))
+                .unwrap()
+                .replace("max_health: 10000", "max_health: 12000"),
+        )
+        .unwrap();
+        let report = diff(&args(&format!(
+            "diff {} {} resource.yaml",
+            ROOT,
+            root.display()
+        )))
+        .unwrap();
+        assert_eq!(
+            report.json["diffs"],
+            json!([{
+                "kind": "Changed",
+                "res_id": "Chara.Test",
+                "fields": [{ "field": "max_health", "old": 10000, "new": 12000 }],
+            }])
+        );
+
+        // The profile applies to both sides.
+        let err = diff(&args(&format!(
+            "diff {} {} resource.yaml --profile hard",
+            ROOT,
+            root.display()
+        )))
+        .err()
+        .unwrap();
+        assert!(format!("{:#}", err).contains("hard"));
+        let _ = fs::remove_dir_all(&root);
+    }
 }
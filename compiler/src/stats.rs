@@ -0,0 +1,141 @@
+use anyhow::Result;
+use core::id::ResID;
+use core::resource::{ResCache, ResShapeAny};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResStats {
+    pub total: usize,
+    pub classes: BTreeMap<String, usize>,
+    pub meshes: Vec<MeshStats>,
+    pub scripts: Vec<ScriptStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MeshStats {
+    pub res_id: ResID,
+    pub file: String,
+    pub name: String,
+    pub triangles: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptStats {
+    pub res_id: ResID,
+    pub field: String,
+    pub bytes: usize,
+}
+
+pub fn collect_stats(cache: &ResCache, root_path: &str) -> Result<ResStats> {
+    let mut res_objs: Vec<_> = cache.res_objs().collect();
+    res_objs.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut stats = ResStats::default();
+    for (res_id, res) in res_objs {
+        stats.total += 1;
+        let class_id: &'static str = res.class_id().into();
+        *stats.classes.entry(class_id.to_string()).or_insert(0) += 1;
+
+        let value = serde_json::to_value(res)?;
+        collect_value(&mut stats, res_id, root_path, &value, "")?;
+    }
+    return Ok(stats);
+}
+
+fn collect_value(
+    stats: &mut ResStats,
+    res_id: &ResID,
+    root_path: &str,
+    value: &Value,
+    path: &str,
+) -> Result<()> {
+    match value {
+        Value::Object(map) => {
+            let kind = map.get("type").and_then(|kind| kind.as_str());
+            if kind == Some("TriMesh") || kind == Some("ConvexHull") {
+                let (file, name, triangles) = match serde_json::from_value(value.clone())? {
+                    ResShapeAny::TriMesh(mesh) => {
+                        let triangles = mesh.triangle_count(root_path)?;
+                        (mesh.file, mesh.name, triangles)
+                    }
+                    ResShapeAny::ConvexHull(hull) => {
+                        let triangles = hull.triangle_count(root_path)?;
+                        (hull.file, hull.name, triangles)
+                    }
+                    _ => unreachable!(),
+                };
+                stats.meshes.push(MeshStats {
+                    res_id: res_id.clone(),
+                    file,
+                    name,
+                    triangles,
+                });
+                return Ok(());
+            }
+            for (key, value) in map {
+                let field = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                if let (true, Some(script)) = (key.ends_with("script"), value.as_str()) {
+                    stats.scripts.push(ScriptStats {
+                        res_id: res_id.clone(),
+                        field,
+                        bytes: script.len(),
+                    });
+                } else {
+                    collect_value(stats, res_id, root_path, value, &field)?;
+                }
+            }
+        }
+        Value::Array(list) => {
+            for (idx, value) in list.iter().enumerate() {
+                collect_value(
+                    stats,
+                    res_id,
+                    root_path,
+                    value,
+                    &format!("{}[{}]", path, idx),
+                )?;
+            }
+        }
+        _ => {}
+    }
+    return Ok(());
+}
+
+impl Display for ResStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Resources: {}", self.total)?;
+        for (class_id, count) in &self.classes {
+            writeln!(f, "    {:<16} {}", class_id, count)?;
+        }
+        let triangles: usize = self.meshes.iter().map(|mesh| mesh.triangles).sum();
+        writeln!(f, "Meshes: {} ({} triangles)", self.meshes.len(), triangles)?;
+        for mesh in &self.meshes {
+            writeln!(
+                f,
+                "    {} {}#{} {}",
+                String::from(mesh.res_id.clone()),
+                mesh.file,
+                mesh.name,
+                mesh.triangles
+            )?;
+        }
+        let bytes: usize = self.scripts.iter().map(|script| script.bytes).sum();
+        write!(f, "Scripts: {} ({} bytes)", self.scripts.len(), bytes)?;
+        for script in &self.scripts {
+            write!(
+                f,
+                "\n    {} {} {}",
+                String::from(script.res_id.clone()),
+                script.field,
+                script.bytes
+            )?;
+        }
+        return Ok(());
+    }
+}
@@ -1,25 +1,40 @@
-use crate::engine::Command;
 use crate::engine::LogicEngine;
-use crate::resource::ResCache;
-use crate::state::{StateBinder, StateBus, StatePool};
-use anyhow::Result;
+use crate::engine::{Command, DataPool};
+use crate::resource::{ResCache, ResCacheUpdate};
+use crate::state::{StateBinder, StateBus};
+use anyhow::{anyhow, Result};
 use m::Fx;
 use std::mem;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError, TrySendError};
 use std::sync::Arc;
-use std::thread;
+use std::thread::{self, JoinHandle};
 
 const DEFAULT_VEC_CAPACITY: usize = 128;
+const DEFAULT_PIPELINE_DEPTH: usize = 2;
 
 struct AsyncInput {
+    res_update: Option<ResCacheUpdate>,
     commands: Vec<Command>,
+    pools: Vec<Box<DataPool>>,
 }
 
+type AsyncOutput = Result<Box<DataPool>>;
+
+// Ticks are pipelined, the caller submits tick N+1 while the engine thread runs tick N.
+// At most depth ticks are in flight, both queues hold depth ticks so neither side ever blocks on a
+// full queue. The caller must receive a tick before submitting more once the pipeline is full.
 pub struct AsyncLogicAgent {
     state_bus: StateBus,
     commands: Vec<Command>,
-    input_sx: Sender<Option<AsyncInput>>,
-    output_rx: Receiver<Box<StatePool>>,
+    res_update: Option<ResCacheUpdate>,
+    // Pools handed back, sent to the engine thread with the next tick.
+    pools: Vec<Box<DataPool>>,
+    input_sx: SyncSender<Option<AsyncInput>>,
+    output_rx: Option<Receiver<AsyncOutput>>,
+    thread: Option<JoinHandle<()>>,
+    depth: usize,
+    in_flight: usize,
+    stopped: bool,
 }
 
 impl !Send for AsyncLogicAgent {}
@@ -27,33 +42,64 @@ impl !Sync for AsyncLogicAgent {}
 
 impl AsyncLogicAgent {
     pub fn new(res_cache: Arc<ResCache>, fps: Fx) -> AsyncLogicAgent {
-        let (input_sx, input_rx) = channel::<Option<AsyncInput>>();
-        let (output_sx, output_rx) = channel::<Box<StatePool>>();
-        let _ = thread::spawn(move || Self::engine_thread(res_cache, fps, input_rx, output_sx));
+        return Self::with_depth(res_cache, fps, DEFAULT_PIPELINE_DEPTH);
+    }
+
+    pub fn with_depth(res_cache: Arc<ResCache>, fps: Fx, depth: usize) -> AsyncLogicAgent {
+        let depth = depth.max(1);
+        let (input_sx, input_rx) = sync_channel::<Option<AsyncInput>>(depth);
+        let (output_sx, output_rx) = sync_channel::<AsyncOutput>(depth);
+        let thread =
+            thread::spawn(move || Self::engine_thread(res_cache, fps, input_rx, output_sx));
 
         return AsyncLogicAgent {
             state_bus: StateBus::new(),
             commands: Vec::with_capacity(DEFAULT_VEC_CAPACITY),
+            res_update: None,
+            pools: Vec::new(),
             input_sx,
-            output_rx,
+            output_rx: Some(output_rx),
+            thread: Some(thread),
+            depth,
+            in_flight: 0,
+            stopped: false,
         };
     }
 
+    // An engine error is sent back as the output of its tick, then the thread exits.
     fn engine_thread(
         res_cache: Arc<ResCache>,
         fps: Fx,
         input_rx: Receiver<Option<AsyncInput>>,
-        output_sx: Sender<Box<StatePool>>,
-    ) -> Result<()> {
-        let mut engine = LogicEngine::new(res_cache, fps)?;
-        while let Some(input) = input_rx.recv()? {
-            for cmd in &input.commands {
-                engine.run_command(cmd)?;
+        output_sx: SyncSender<AsyncOutput>,
+    ) {
+        let mut engine = match LogicEngine::new(res_cache, fps) {
+            Ok(engine) => engine,
+            Err(err) => {
+                let _ = output_sx.send(Err(err));
+                return;
+            }
+        };
+        while let Ok(Some(input)) = input_rx.recv() {
+            let output = Self::run_input(&mut engine, input);
+            let failed = output.is_err();
+            if output_sx.send(output).is_err() || failed {
+                return;
             }
-            let pool = engine.run_tick()?;
-            output_sx.send(pool)?;
         }
-        return Ok(());
+    }
+
+    fn run_input(engine: &mut LogicEngine, input: AsyncInput) -> AsyncOutput {
+        for pool in input.pools {
+            engine.recycle_pool(pool);
+        }
+        if let Some(update) = input.res_update {
+            engine.update_res_cache(update.cache, &update.changed)?;
+        }
+        for cmd in &input.commands {
+            engine.run_command(cmd)?;
+        }
+        return engine.run_tick();
     }
 
     pub fn new_binder(&self) -> StateBinder {
@@ -64,43 +110,287 @@ impl AsyncLogicAgent {
         self.commands.push(cmd);
     }
 
+    pub fn update_res_cache(&mut self, update: ResCacheUpdate) {
+        self.res_update = Some(update);
+    }
+
+    // Hands a dispatched pool back to the engine once its states are consumed.
+    pub fn recycle_pool(&mut self, pool: Box<DataPool>) {
+        self.pools.push(pool);
+    }
+
+    #[inline]
+    pub fn depth(&self) -> usize {
+        return self.depth;
+    }
+
+    // Ticks submitted but not received yet.
+    #[inline]
+    pub fn in_flight(&self) -> usize {
+        return self.in_flight;
+    }
+
+    #[inline]
+    pub fn is_stopped(&self) -> bool {
+        return self.stopped;
+    }
+
+    // Submits a tick and waits for all ticks in flight, states are dispatched in order.
     pub fn run_tick(&mut self) -> Result<()> {
-        let input = AsyncInput {
-            commands: mem::replace(&mut self.commands, Vec::with_capacity(DEFAULT_VEC_CAPACITY)),
+        self.submit_tick()?;
+        while self.in_flight > 0 {
+            self.receive_tick()?;
+        }
+        return Ok(());
+    }
+
+    // Sends the commands since the last submit as a new tick.
+    // Fails and keeps the commands if depth ticks are in flight, receive_tick() first.
+    pub fn submit_tick(&mut self) -> Result<()> {
+        self.check_stopped()?;
+        if self.in_flight >= self.depth {
+            return Err(anyhow!(
+                "AsyncLogicAgent pipeline full ({} ticks in flight)",
+                self.in_flight
+            ));
+        }
+        let input = self.take_input();
+        if self.input_sx.send(Some(input)).is_err() {
+            return Err(self.stop());
+        }
+        self.in_flight += 1;
+        return Ok(());
+    }
+
+    // Like submit_tick() but returns false and keeps the commands if the pipeline is full.
+    pub fn try_submit_tick(&mut self) -> Result<bool> {
+        self.check_stopped()?;
+        if self.in_flight >= self.depth {
+            return Ok(false);
+        }
+        let input = self.take_input();
+        match self.input_sx.try_send(Some(input)) {
+            Ok(_) => {
+                self.in_flight += 1;
+                return Ok(true);
+            }
+            Err(TrySendError::Full(input)) => {
+                if let Some(input) = input {
+                    self.res_update = input.res_update;
+                    self.commands = input.commands;
+                    self.pools = input.pools;
+                }
+                return Ok(false);
+            }
+            Err(TrySendError::Disconnected(_)) => return Err(self.stop()),
+        };
+    }
+
+    // Waits for the oldest tick in flight and dispatches its states, returns false if none.
+    pub fn receive_tick(&mut self) -> Result<bool> {
+        return match self.receive_tick_pool()? {
+            Some(pool) => {
+                self.state_bus.dispatch_states(pool);
+                Ok(true)
+            }
+            None => Ok(false),
         };
-        self.input_sx.send(Some(input))?;
-        let pool = self.output_rx.recv()?;
+    }
+
+    // Like receive_tick() but hands the pool to the caller instead of the state bus.
+    pub fn receive_tick_pool(&mut self) -> Result<Option<Box<DataPool>>> {
+        self.check_stopped()?;
+        if self.in_flight == 0 {
+            return Ok(None);
+        }
+        let output = match self.output_rx.as_ref().unwrap().recv() {
+            Ok(output) => output,
+            Err(_) => return Err(self.stop()),
+        };
+        return Ok(Some(self.take_output(output)?));
+    }
+
+    // Like receive_tick() but returns false if the oldest tick is not finished yet.
+    pub fn try_receive_tick(&mut self) -> Result<bool> {
+        self.check_stopped()?;
+        if self.in_flight == 0 {
+            return Ok(false);
+        }
+        let output = match self.output_rx.as_ref().unwrap().try_recv() {
+            Ok(output) => output,
+            Err(TryRecvError::Empty) => return Ok(false),
+            Err(TryRecvError::Disconnected) => return Err(self.stop()),
+        };
+        let pool = self.take_output(output)?;
         self.state_bus.dispatch_states(pool);
+        return Ok(true);
+    }
+
+    fn take_input(&mut self) -> AsyncInput {
+        return AsyncInput {
+            res_update: self.res_update.take(),
+            commands: mem::replace(&mut self.commands, Vec::with_capacity(DEFAULT_VEC_CAPACITY)),
+            pools: mem::take(&mut self.pools),
+        };
+    }
+
+    fn take_output(&mut self, output: AsyncOutput) -> Result<Box<DataPool>> {
+        self.in_flight -= 1;
+        match output {
+            Ok(pool) => return Ok(pool),
+            Err(err) => {
+                self.stopped = true;
+                self.in_flight = 0;
+                return Err(err);
+            }
+        };
+    }
+
+    fn check_stopped(&self) -> Result<()> {
+        if self.stopped {
+            return Err(anyhow!("AsyncLogicAgent stopped"));
+        }
         return Ok(());
     }
+
+    // The engine thread exited, returns the error it sent back if any.
+    fn stop(&mut self) -> anyhow::Error {
+        self.stopped = true;
+        self.in_flight = 0;
+        let rx = self.output_rx.as_ref().unwrap();
+        while let Ok(output) = rx.try_recv() {
+            if let Err(err) = output {
+                return err;
+            }
+        }
+        return anyhow!("AsyncLogicAgent engine thread exited");
+    }
+}
+
+impl Drop for AsyncLogicAgent {
+    fn drop(&mut self) {
+        // Closes the output first, so the engine thread can't block on a full queue.
+        self.output_rx = None;
+        let _ = self.input_sx.send(None);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::engine::CmdNewStageGeneral;
-    use crate::id::{FastObjID, ResID};
-    use crate::stage::StateStageGeneral;
-    use crate::state::{StateLifecycle, StateRef};
-    use m::ff;
+    use crate::engine::{CmdNewCharaHuman, LogicLifecycle, StateCharaHuman};
+    use crate::id::{ObjID, ResID};
+    use crate::resource::test_res::{restore_res, write_res, CHARA_TEST};
+    use m::fi;
+
+    fn new_chara(res_id: &str) -> Command {
+        return Command::NewCharaHuman(CmdNewCharaHuman {
+            res_id: ResID::from(res_id),
+        });
+    }
 
     #[test]
     fn test_async_logic_agent() {
-        let mut agent = AsyncLogicAgent::new(Arc::new(ResCache::new()), ff(1.0 / 20.0));
+        let dir = write_res("async_agent", CHARA_TEST);
+        let mut agent = AsyncLogicAgent::new(restore_res(&dir), fi(20));
+        agent.run_command(new_chara("Chara.Test"));
+        agent.submit_tick().unwrap();
+        let pool = agent.receive_tick_pool().unwrap().unwrap();
+        let state = pool.find_state::<StateCharaHuman>(ObjID::from(100000));
+        assert_eq!(state.unwrap().lifecycle(), LogicLifecycle::Created);
+        assert!(agent.receive_tick_pool().unwrap().is_none());
 
-        let state_ref: StateRef<StateStageGeneral> =
-            StateRef::new_and_start(FastObjID::from(123), agent.new_binder()).unwrap();
+        // The returned pool goes back to the engine thread with the next tick.
+        let ptr = &*pool as *const DataPool;
+        agent.recycle_pool(pool);
+        agent.submit_tick().unwrap();
+        let pool = agent.receive_tick_pool().unwrap().unwrap();
+        assert_eq!(&*pool as *const DataPool, ptr);
+        let state = pool.find_state::<StateCharaHuman>(ObjID::from(100000));
+        assert_eq!(state.unwrap().lifecycle(), LogicLifecycle::Running);
 
-        agent.run_command(Command::NewStageGeneral(CmdNewStageGeneral {
-            res_id: ResID::from("res-id"),
-            fobj_id: FastObjID::from(123),
-        }));
         agent.run_tick().unwrap();
+        assert_eq!(agent.in_flight(), 0);
+        assert!(!agent.is_stopped());
+    }
+
+    #[test]
+    fn test_async_logic_agent_pipeline() {
+        let dir = write_res("async_agent_pipeline", CHARA_TEST);
+        let mut agent = AsyncLogicAgent::with_depth(restore_res(&dir), fi(20), 2);
+
+        agent.run_command(new_chara("Chara.Test"));
+        agent.submit_tick().unwrap();
+        agent.submit_tick().unwrap();
+        assert_eq!(agent.in_flight(), 2);
+
+        assert!(agent.receive_tick().unwrap());
+        agent.submit_tick().unwrap();
+        assert_eq!(agent.in_flight(), 2);
+
+        assert!(agent.receive_tick().unwrap());
+        assert!(agent.receive_tick().unwrap());
+        assert!(!agent.receive_tick().unwrap());
+        assert!(!agent.try_receive_tick().unwrap());
+        assert_eq!(agent.in_flight(), 0);
+        assert!(!agent.is_stopped());
+    }
+
+    #[test]
+    fn test_async_logic_agent_depth() {
+        let dir = write_res("async_agent_depth", CHARA_TEST);
+        let mut agent = AsyncLogicAgent::with_depth(restore_res(&dir), fi(20), 2);
+        assert_eq!(agent.depth(), 2);
+        agent.submit_tick().unwrap();
+        agent.submit_tick().unwrap();
+
+        // Full pipeline, the commands stay for the next submit.
+        agent.run_command(new_chara("Chara.Test"));
+        assert!(agent.submit_tick().is_err());
+        assert!(!agent.try_submit_tick().unwrap());
+        assert_eq!(agent.in_flight(), 2);
+        assert!(!agent.is_stopped());
+
+        assert!(agent.receive_tick().unwrap());
+        assert!(agent.try_submit_tick().unwrap());
+        assert!(!agent.try_submit_tick().unwrap());
+        agent.run_tick().unwrap_err();
+        assert!(agent.receive_tick().unwrap());
+        assert!(agent.receive_tick().unwrap());
+        assert_eq!(agent.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_async_logic_agent_error() {
+        let dir = write_res("async_agent_error", CHARA_TEST);
+        let mut agent = AsyncLogicAgent::with_depth(restore_res(&dir), fi(20), 2);
+        agent.submit_tick().unwrap();
+        agent.run_command(new_chara("Chara.NotFound"));
+        agent.submit_tick().unwrap();
+
+        assert!(agent.receive_tick().unwrap());
+        let err = agent.receive_tick().unwrap_err();
+        assert!(format!("{:#}", err).contains("Chara.NotFound"));
+        assert!(agent.is_stopped());
+        assert_eq!(agent.in_flight(), 0);
+        assert!(agent.submit_tick().is_err());
+        assert!(agent.receive_tick().is_err());
+    }
+
+    #[test]
+    fn test_async_logic_agent_drop() {
+        let dir = write_res("async_agent_drop", CHARA_TEST);
+        let res_cache = restore_res(&dir);
+        let mut agent = AsyncLogicAgent::with_depth(res_cache.clone(), fi(20), 2);
+        agent.submit_tick().unwrap();
+        agent.submit_tick().unwrap();
+        assert!(Arc::strong_count(&res_cache) > 1);
 
-        assert_eq!(state_ref.state().unwrap().fobj_id, FastObjID::from(123));
-        assert_eq!(
-            state_ref.state().unwrap().lifecycle,
-            StateLifecycle::Created
-        );
+        // Ticks still in flight, the engine thread must exit and drop its ResCache before drop returns.
+        mem::drop(agent);
+        assert_eq!(Arc::strong_count(&res_cache), 1);
     }
 }
@@ -0,0 +1,493 @@
+use super::sync_agent::SyncLogicAgent;
+use crate::engine::{Command, Operation};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LockstepMessage {
+    Input {
+        player: u32,
+        tick: u32,
+        operations: Vec<Operation>,
+    },
+    Checksum {
+        player: u32,
+        tick: u32,
+        checksum: u64,
+    },
+}
+
+// Broadcasts messages to all the other peers, any socket layer can implement it.
+// Messages of one peer must arrive in order and without losses.
+pub trait LockstepTransport {
+    fn send(&mut self, message: &LockstepMessage) -> Result<()>;
+    // Returns None when nothing is pending, never blocks.
+    fn recv(&mut self) -> Result<Option<LockstepMessage>>;
+}
+
+// In process transport, mainly for tests.
+pub struct LoopbackTransport {
+    peers: Vec<Sender<LockstepMessage>>,
+    receiver: Receiver<LockstepMessage>,
+}
+
+impl LoopbackTransport {
+    pub fn group(count: usize) -> Vec<LoopbackTransport> {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..count).map(|_| channel()).unzip();
+        return receivers
+            .into_iter()
+            .enumerate()
+            .map(|(idx, receiver)| {
+                let peers = senders
+                    .iter()
+                    .enumerate()
+                    .filter(|(peer, _)| *peer != idx)
+                    .map(|(_, sender)| sender.clone())
+                    .collect();
+                return LoopbackTransport { peers, receiver };
+            })
+            .collect();
+    }
+}
+
+impl LockstepTransport for LoopbackTransport {
+    fn send(&mut self, message: &LockstepMessage) -> Result<()> {
+        for peer in &self.peers {
+            peer.send(message.clone())?;
+        }
+        return Ok(());
+    }
+
+    fn recv(&mut self) -> Result<Option<LockstepMessage>> {
+        return match self.receiver.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(anyhow!("Loopback transport disconnected")),
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockstepDesync {
+    pub tick: u32,
+    // Checksums of all players in canonical order.
+    pub checksums: Vec<(u32, u64)>,
+}
+
+// Collects the operations of every player for each tick, and only runs a tick once all of them arrived.
+// Operations of a tick are applied in canonical (ascending) player order on every peer.
+pub struct LockstepSession<T: LockstepTransport> {
+    transport: T,
+    local_player: u32,
+    players: Vec<u32>,
+    input_delay: u32,
+    tick: u32,
+    local_tick: u32,
+    inputs: BTreeMap<u32, BTreeMap<u32, Vec<Operation>>>,
+    checksums: BTreeMap<u32, BTreeMap<u32, u64>>,
+    // Last tick each player reported a checksum for.
+    reported: BTreeMap<u32, u32>,
+    desync: Option<LockstepDesync>,
+}
+
+impl<T: LockstepTransport> LockstepSession<T> {
+    // Local inputs are scheduled input_delay ticks ahead, hiding the network latency.
+    pub fn new(
+        transport: T,
+        local_player: u32,
+        players: &[u32],
+        input_delay: u32,
+    ) -> Result<LockstepSession<T>> {
+        let mut players = players.to_vec();
+        players.sort();
+        players.dedup();
+        if !players.contains(&local_player) {
+            return Err(anyhow!("Local player {} not in session", local_player));
+        }
+
+        let mut inputs = BTreeMap::new();
+        for tick in 0..input_delay {
+            let empty: BTreeMap<u32, Vec<Operation>> =
+                players.iter().map(|player| (*player, Vec::new())).collect();
+            inputs.insert(tick, empty);
+        }
+        return Ok(LockstepSession {
+            transport,
+            local_player,
+            players,
+            input_delay,
+            tick: 0,
+            local_tick: input_delay,
+            inputs,
+            checksums: BTreeMap::new(),
+            reported: BTreeMap::new(),
+            desync: None,
+        });
+    }
+
+    #[inline]
+    pub fn tick(&self) -> u32 {
+        return self.tick;
+    }
+
+    #[inline]
+    pub fn players(&self) -> &[u32] {
+        return &self.players;
+    }
+
+    // Ticks with checksums still waiting for some players.
+    #[inline]
+    pub fn pending_checksums(&self) -> usize {
+        return self.checksums.len();
+    }
+
+    #[inline]
+    pub fn desync(&self) -> Option<&LockstepDesync> {
+        return self.desync.as_ref();
+    }
+
+    // Local inputs may run ahead of the simulation by input_delay ticks at most.
+    #[inline]
+    pub fn can_add_input(&self) -> bool {
+        return self.local_tick <= self.tick + self.input_delay;
+    }
+
+    pub fn add_local_input(&mut self, operations: Vec<Operation>) -> Result<()> {
+        if !self.can_add_input() {
+            return Err(anyhow!("Local input too far ahead {}", self.local_tick));
+        }
+        let tick = self.local_tick;
+        self.local_tick += 1;
+        self.transport.send(&LockstepMessage::Input {
+            player: self.local_player,
+            tick,
+            operations: operations.clone(),
+        })?;
+        self.insert_input(self.local_player, tick, operations)?;
+        return Ok(());
+    }
+
+    // Receives pending messages, call it every frame even while stalled.
+    pub fn poll(&mut self) -> Result<()> {
+        while let Some(message) = self.transport.recv()? {
+            match message {
+                LockstepMessage::Input {
+                    player,
+                    tick,
+                    operations,
+                } => self.insert_input(player, tick, operations)?,
+                LockstepMessage::Checksum {
+                    player,
+                    tick,
+                    checksum,
+                } => self.insert_checksum(player, tick, checksum)?,
+            };
+        }
+        return Ok(());
+    }
+
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        return match self.inputs.get(&self.tick) {
+            Some(inputs) => inputs.len() == self.players.len(),
+            None => false,
+        };
+    }
+
+    // Runs the next tick if all inputs arrived, returns false when stalled.
+    // run gets the tick and its operations in canonical order, and returns the checksum of the state after it.
+    pub fn advance<F>(&mut self, run: F) -> Result<bool>
+    where
+        F: FnOnce(u32, &[Operation]) -> Result<u64>,
+    {
+        if let Some(desync) = &self.desync {
+            return Err(anyhow!("Lockstep desync at tick {}", desync.tick));
+        }
+        self.poll()?;
+        if !self.is_ready() {
+            return Ok(false);
+        }
+
+        let tick = self.tick;
+        let inputs = self.inputs.remove(&tick).unwrap();
+        let operations: Vec<Operation> = inputs.into_iter().flat_map(|(_, ops)| ops).collect();
+        let checksum = run(tick, &operations)?;
+        self.tick += 1;
+
+        self.transport.send(&LockstepMessage::Checksum {
+            player: self.local_player,
+            tick,
+            checksum,
+        })?;
+        self.insert_checksum(self.local_player, tick, checksum)?;
+        return match &self.desync {
+            Some(desync) => Err(anyhow!("Lockstep desync at tick {}", desync.tick)),
+            None => Ok(true),
+        };
+    }
+
+    // Runs the next tick on agent, its checksum covers the states of the tick and the collision world.
+    pub fn advance_agent(&mut self, agent: &mut SyncLogicAgent) -> Result<bool> {
+        return self.advance(|_, operations| {
+            for op in operations {
+                agent.run_command(Command::Operation(op.clone()));
+            }
+            return agent.run_tick_with(|engine, pool| Ok(engine.checksum(pool)));
+        });
+    }
+
+    fn check_player(&self, player: u32) -> Result<()> {
+        if self.players.binary_search(&player).is_err() {
+            return Err(anyhow!("Unknown lockstep player {}", player));
+        }
+        return Ok(());
+    }
+
+    fn insert_input(&mut self, player: u32, tick: u32, operations: Vec<Operation>) -> Result<()> {
+        self.check_player(player)?;
+        if tick < self.tick {
+            return Err(anyhow!("Input of player {} for past tick {}", player, tick));
+        }
+        let inputs = self.inputs.entry(tick).or_insert_with(BTreeMap::new);
+        if inputs.contains_key(&player) {
+            return Err(anyhow!(
+                "Duplicate input of player {} tick {}",
+                player,
+                tick
+            ));
+        }
+        inputs.insert(player, operations);
+        return Ok(());
+    }
+
+    fn insert_checksum(&mut self, player: u32, tick: u32, checksum: u64) -> Result<()> {
+        self.check_player(player)?;
+        if let Some(last) = self.reported.get(&player) {
+            if tick <= *last {
+                return Err(anyhow!(
+                    "Checksum of player {} tick {} after tick {}",
+                    player,
+                    tick,
+                    last
+                ));
+            }
+        }
+        self.reported.insert(player, tick);
+
+        let checksums = self.checksums.entry(tick).or_insert_with(BTreeMap::new);
+        checksums.insert(player, checksum);
+        if checksums.len() == self.players.len() {
+            let checksums = self.checksums.remove(&tick).unwrap();
+            let first = *checksums.values().next().unwrap();
+            if self.desync.is_none() && checksums.values().any(|checksum| *checksum != first) {
+                self.desync = Some(LockstepDesync {
+                    tick,
+                    checksums: checksums.into_iter().collect(),
+                });
+            }
+        }
+        self.prune_checksums();
+        return Ok(());
+    }
+
+    // Ticks up to the last one every player reported can't complete anymore, a player skipping
+    // a checksum must not keep them forever.
+    fn prune_checksums(&mut self) {
+        if self.reported.len() < self.players.len() {
+            return;
+        }
+        let floor = *self.reported.values().min().unwrap();
+        self.checksums = self.checksums.split_off(&(floor + 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{CmdNewCharaHuman, OpCommand};
+    use crate::id::{ObjID, ResID};
+    use crate::resource::test_res::{restore_res, write_res, CHARA_TEST};
+    use crate::utils::FnvHasher;
+    use m::fi;
+    use na::Vector2;
+    use std::hash::Hasher;
+
+    fn new_sessions(delay: u32) -> Vec<LockstepSession<LoopbackTransport>> {
+        return LoopbackTransport::group(2)
+            .into_iter()
+            .zip([20, 10].iter())
+            .map(|(transport, player)| {
+                return LockstepSession::new(transport, *player, &[20, 10], delay).unwrap();
+            })
+            .collect();
+    }
+
+    fn op(obj_id: u64) -> Operation {
+        return Operation {
+            obj_id: ObjID::from(obj_id),
+            command: OpCommand::Jump,
+        };
+    }
+
+    // A fake engine hashing the applied operations.
+    fn run(state: &mut FnvHasher, tick: u32, ops: &[Operation]) -> Result<u64> {
+        state.write_u32(tick);
+        for op in ops {
+            state.write_u64(u64::from(op.obj_id));
+        }
+        return Ok(state.finish());
+    }
+
+    #[test]
+    fn test_lockstep_session() {
+        let mut sessions = new_sessions(1);
+        let mut states = vec![FnvHasher::new(), FnvHasher::new()];
+        let mut applied = Vec::new();
+
+        sessions[0].add_local_input(vec![op(2)]).unwrap();
+        assert!(sessions[0].add_local_input(vec![]).is_err());
+
+        // Tick 0 is covered by the input delay.
+        for idx in 0..2 {
+            assert!(sessions[idx]
+                .advance(|tick, ops| run(&mut states[idx], tick, ops))
+                .unwrap());
+        }
+        assert!(!sessions[0]
+            .advance(|tick, ops| run(&mut states[0], tick, ops))
+            .unwrap());
+
+        sessions[1].add_local_input(vec![op(1)]).unwrap();
+        for idx in 0..2 {
+            assert!(sessions[idx]
+                .advance(|tick, ops| {
+                    applied.push(ops.to_vec());
+                    return run(&mut states[idx], tick, ops);
+                })
+                .unwrap());
+        }
+        // Player 10 goes first on both peers.
+        assert_eq!(applied[0], vec![op(1), op(2)]);
+        assert_eq!(applied[0], applied[1]);
+
+        for idx in 0..2 {
+            sessions[idx].poll().unwrap();
+            assert!(sessions[idx].desync().is_none());
+            assert_eq!(sessions[idx].tick(), 2);
+        }
+    }
+
+    #[test]
+    fn test_lockstep_desync() {
+        let mut sessions = new_sessions(0);
+        sessions[0].add_local_input(vec![op(1)]).unwrap();
+        sessions[1].add_local_input(vec![op(1)]).unwrap();
+        assert!(sessions[0].advance(|_, _| Ok(111)).unwrap());
+        assert!(sessions[1].advance(|_, _| Ok(222)).is_err());
+
+        let desync = sessions[1].desync().unwrap();
+        assert_eq!(desync.tick, 0);
+        assert_eq!(desync.checksums, vec![(10, 222), (20, 111)]);
+        sessions[0].poll().unwrap();
+        assert!(sessions[0].desync().is_some());
+    }
+
+    #[test]
+    fn test_lockstep_invalid() {
+        let mut transports = LoopbackTransport::group(2);
+        let mut session = LockstepSession::new(transports.remove(0), 1, &[1, 2], 0).unwrap();
+        transports[0]
+            .send(&LockstepMessage::Input {
+                player: 3,
+                tick: 0,
+                operations: Vec::new(),
+            })
+            .unwrap();
+        assert!(session.poll().is_err());
+        assert!(LockstepSession::new(transports.remove(0), 3, &[1, 2], 0).is_err());
+    }
+
+    #[test]
+    fn test_lockstep_prune_checksums() {
+        let mut transports = LoopbackTransport::group(3);
+        let mut session = LockstepSession::new(transports.remove(0), 1, &[1, 2, 3], 0).unwrap();
+        let checksum = |transport: &mut LoopbackTransport, player: u32, tick: u32| {
+            transport
+                .send(&LockstepMessage::Checksum {
+                    player,
+                    tick,
+                    checksum: 7,
+                })
+                .unwrap();
+        };
+
+        // Player 3 lags behind, its ticks stay pending.
+        for tick in 0..10 {
+            checksum(&mut transports[0], 2, tick);
+            session.insert_checksum(1, tick, 7).unwrap();
+        }
+        session.poll().unwrap();
+        assert_eq!(session.pending_checksums(), 10);
+
+        // Player 3 skips ticks 0 to 5, they can't complete anymore.
+        checksum(&mut transports[1], 3, 6);
+        session.poll().unwrap();
+        assert_eq!(session.pending_checksums(), 3);
+        for tick in 7..10 {
+            checksum(&mut transports[1], 3, tick);
+        }
+        session.poll().unwrap();
+        assert_eq!(session.pending_checksums(), 0);
+        assert!(session.desync().is_none());
+
+        checksum(&mut transports[1], 3, 9);
+        assert!(session.poll().is_err());
+    }
+
+    #[test]
+    fn test_lockstep_agent() {
+        let dir = write_res("lockstep_agent", CHARA_TEST);
+        let mut sessions = new_sessions(0);
+        let mut agents: Vec<_> = (0..2)
+            .map(|_| {
+                let mut agent = SyncLogicAgent::new(restore_res(&dir), fi(20)).unwrap();
+                agent.run_command(Command::NewCharaHuman(CmdNewCharaHuman {
+                    res_id: ResID::from("Chara.Test"),
+                }));
+                return agent;
+            })
+            .collect();
+
+        let obj_id = ObjID::from(100000);
+        for _ in 0..5 {
+            sessions[0]
+                .add_local_input(vec![Operation {
+                    obj_id,
+                    command: OpCommand::Move(Vector2::new(fi(1), fi(0))),
+                }])
+                .unwrap();
+            sessions[1].add_local_input(vec![]).unwrap();
+            for idx in 0..2 {
+                assert!(sessions[idx].advance_agent(&mut agents[idx]).unwrap());
+            }
+        }
+        for idx in 0..2 {
+            sessions[idx].poll().unwrap();
+            assert!(sessions[idx].desync().is_none());
+            assert_eq!(sessions[idx].tick(), 5);
+            assert_eq!(sessions[idx].pending_checksums(), 0);
+        }
+
+        // Operations on an unknown object fail the tick on the agent.
+        sessions[0]
+            .add_local_input(vec![Operation {
+                obj_id: ObjID::from(1),
+                command: OpCommand::Jump,
+            }])
+            .unwrap();
+        sessions[1].add_local_input(vec![]).unwrap();
+        assert!(sessions[0].advance_agent(&mut agents[0]).is_err());
+    }
+}
@@ -1,5 +1,11 @@
 mod async_agent;
+mod lockstep;
+mod replay;
 mod sync_agent;
 
 pub use async_agent::AsyncLogicAgent;
+pub use lockstep::{
+    LockstepDesync, LockstepMessage, LockstepSession, LockstepTransport, LoopbackTransport,
+};
+pub use replay::{InputRecorder, ReplayAgent, ReplayFile, ReplayTick, REPLAY_VERSION};
 pub use sync_agent::SyncLogicAgent;
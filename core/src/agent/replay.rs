@@ -0,0 +1,306 @@
+use crate::resource::IDTable;
+use crate::utils::{deserialize, serialize};
+use anyhow::{anyhow, Result};
+use math::Fx;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const REPLAY_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayTick<C> {
+    pub commands: Vec<C>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u64>,
+}
+
+// Everything needed to rerun a match headlessly, C is the engine command type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayFile<C> {
+    pub version: u32,
+    pub id_table: u64,
+    pub fps: Fx,
+    pub initial_commands: Vec<C>,
+    pub ticks: Vec<ReplayTick<C>>,
+}
+
+impl<C: DeserializeOwned> ReplayFile<C> {
+    // Json or yaml by extension, as utils::deserialize.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ReplayFile<C>> {
+        let replay: ReplayFile<C> = deserialize(path)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(anyhow!(
+                "Replay version {} not supported, expect {}",
+                replay.version,
+                REPLAY_VERSION
+            ));
+        }
+        return Ok(replay);
+    }
+}
+
+impl<C: Serialize> ReplayFile<C> {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        return serialize(path, self);
+    }
+}
+
+#[derive(Debug)]
+pub struct InputRecorder<C> {
+    replay: ReplayFile<C>,
+}
+
+impl<C: Clone> InputRecorder<C> {
+    pub fn new(id_table: &IDTable, fps: Fx, initial_commands: Vec<C>) -> InputRecorder<C> {
+        return InputRecorder {
+            replay: ReplayFile {
+                version: REPLAY_VERSION,
+                id_table: id_table.checksum(),
+                fps,
+                initial_commands,
+                ticks: Vec::with_capacity(1024),
+            },
+        };
+    }
+
+    #[inline]
+    pub fn tick_count(&self) -> usize {
+        return self.replay.ticks.len();
+    }
+
+    // Records the commands applied in a tick, with the checksum of the state after it if known.
+    pub fn record_tick(&mut self, commands: &[C], checksum: Option<u64>) {
+        self.replay.ticks.push(ReplayTick {
+            commands: commands.to_vec(),
+            checksum,
+        });
+    }
+
+    #[inline]
+    pub fn replay(&self) -> &ReplayFile<C> {
+        return &self.replay;
+    }
+
+    pub fn finish(self) -> ReplayFile<C> {
+        return self.replay;
+    }
+}
+
+// Feeds a replay back tick by tick, optionally checking the recorded checksums.
+#[derive(Debug)]
+pub struct ReplayAgent<C> {
+    replay: ReplayFile<C>,
+    tick: usize,
+    verify: bool,
+}
+
+impl<C> ReplayAgent<C> {
+    // Fails if the replay was recorded with other resources or another fps.
+    pub fn new(replay: ReplayFile<C>, id_table: &IDTable, fps: Fx) -> Result<ReplayAgent<C>> {
+        if replay.id_table != id_table.checksum() {
+            return Err(anyhow!("Replay recorded with another IDTable"));
+        }
+        if replay.fps != fps {
+            return Err(anyhow!("Replay fps {} != {}", replay.fps, fps));
+        }
+        return Ok(ReplayAgent {
+            replay,
+            tick: 0,
+            verify: true,
+        });
+    }
+
+    #[inline]
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    #[inline]
+    pub fn initial_commands(&self) -> &[C] {
+        return &self.replay.initial_commands;
+    }
+
+    #[inline]
+    pub fn tick(&self) -> usize {
+        return self.tick;
+    }
+
+    #[inline]
+    pub fn tick_count(&self) -> usize {
+        return self.replay.ticks.len();
+    }
+
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        return self.tick >= self.replay.ticks.len();
+    }
+
+    // Runs the next recorded tick, returns false once the replay is finished.
+    // run applies the commands and returns the checksum of the state after the tick.
+    pub fn run_tick<F>(&mut self, run: F) -> Result<bool>
+    where
+        F: FnOnce(&[C]) -> Result<u64>,
+    {
+        let tick = match self.replay.ticks.get(self.tick) {
+            Some(tick) => tick,
+            None => return Ok(false),
+        };
+        let checksum = run(&tick.commands)?;
+        if let (true, Some(expected)) = (self.verify, tick.checksum) {
+            if checksum != expected {
+                return Err(anyhow!(
+                    "Replay desync at tick {} ({:x} != {:x})",
+                    self.tick,
+                    checksum,
+                    expected
+                ));
+            }
+        }
+        self.tick += 1;
+        return Ok(true);
+    }
+
+    pub fn run_all<F>(&mut self, mut run: F) -> Result<()>
+    where
+        F: FnMut(&[C]) -> Result<u64>,
+    {
+        while self.run_tick(&mut run)? {}
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::SyncLogicAgent;
+    use crate::engine::{CmdNewCharaHuman, Command, OpCommand, Operation};
+    use crate::id::{ObjID, ResID};
+    use crate::resource::test_res::{restore_res, write_res, CHARA_TEST};
+    use crate::utils::{FnvHasher, TestDir};
+    use math::fi;
+    use na::Vector2;
+    use std::hash::Hasher;
+
+    fn op(obj_id: u64) -> Operation {
+        return Operation {
+            obj_id: ObjID::from(obj_id),
+            command: OpCommand::Jump,
+        };
+    }
+
+    // A fake engine hashing the applied operations.
+    fn run(state: &mut FnvHasher, ops: &[Operation]) -> Result<u64> {
+        for op in ops {
+            state.write_u64(u64::from(op.obj_id));
+        }
+        return Ok(state.finish());
+    }
+
+    fn record(id_table: &IDTable) -> ReplayFile<Operation> {
+        let mut recorder = InputRecorder::new(id_table, fi(20), vec![op(1)]);
+        let mut state = FnvHasher::new();
+        for tick in 0..5 {
+            let ops = vec![op(tick), op(tick * 2)];
+            let checksum = run(&mut state, &ops).unwrap();
+            recorder.record_tick(&ops, Some(checksum));
+        }
+        assert_eq!(recorder.tick_count(), 5);
+        return recorder.finish();
+    }
+
+    #[test]
+    fn test_replay_roundtrip() {
+        let id_table = IDTable::new();
+        let dir = TestDir::new("replay");
+        let path = dir.path("replay.json");
+        record(&id_table).save(&path).unwrap();
+
+        let replay: ReplayFile<Operation> = ReplayFile::load(&path).unwrap();
+        let mut agent = ReplayAgent::new(replay, &id_table, fi(20)).unwrap();
+        assert_eq!(agent.initial_commands(), &[op(1)]);
+        let mut state = FnvHasher::new();
+        agent.run_all(|ops| run(&mut state, ops)).unwrap();
+        assert!(agent.is_finished());
+        assert_eq!(agent.tick(), 5);
+        assert!(!agent.run_tick(|_| Ok(0)).unwrap());
+    }
+
+    #[test]
+    fn test_replay_invalid() {
+        let id_table = IDTable::new();
+        assert!(ReplayAgent::new(record(&id_table), &id_table, fi(30)).is_err());
+
+        let mut agent = ReplayAgent::new(record(&id_table), &id_table, fi(20)).unwrap();
+        assert!(agent.run_tick(|_| Ok(1234)).is_err());
+        assert_eq!(agent.tick(), 0);
+        agent.set_verify(false);
+        agent.run_all(|_| Ok(1234)).unwrap();
+
+        let mut replay = record(&id_table);
+        replay.version = REPLAY_VERSION + 1;
+        let dir = TestDir::new("replay_version");
+        let path = dir.path("replay.json");
+        replay.save(&path).unwrap();
+        assert!(ReplayFile::<Operation>::load(&path).is_err());
+    }
+
+    const ACTION: &'static str = "  action: Action.Test\n- type: Action\n  res_id: Action.Test\n  actions:\n  - type: Idle\n    name: idle\n    transitions:\n    - { to: run, trigger: Move }\n  - type: Run\n    name: run\n    move_speed: 4\n    transitions:\n    - { to: idle, trigger: Idle }\n";
+
+    fn move_to(x: i64, z: i64) -> Command {
+        return Command::Operation(Operation {
+            obj_id: ObjID::from(100000),
+            command: OpCommand::Move(Vector2::new(fi(x), fi(z))),
+        });
+    }
+
+    fn run_agent(agent: &mut SyncLogicAgent, commands: &[Command]) -> Result<u64> {
+        for cmd in commands {
+            agent.run_command(cmd.clone());
+        }
+        return agent.run_tick_with(|engine, pool| Ok(engine.checksum(pool)));
+    }
+
+    #[test]
+    fn test_replay_logic_agent() {
+        let dir = write_res("replay_logic_agent", &format!("{}{}", CHARA_TEST, ACTION));
+        let cache = restore_res(&dir);
+        let spawn = Command::NewCharaHuman(CmdNewCharaHuman {
+            res_id: ResID::from("Chara.Test"),
+        });
+        let initial_commands = vec![spawn.clone(), spawn];
+
+        let mut agent = SyncLogicAgent::new(cache.clone(), fi(20)).unwrap();
+        let mut recorder = InputRecorder::new(cache.id_table(), fi(20), initial_commands.clone());
+        for cmd in &initial_commands {
+            agent.run_command(cmd.clone());
+        }
+        for tick in 0..10 {
+            let commands = match tick {
+                2..=5 => vec![move_to(1, 0)],
+                _ => Vec::new(),
+            };
+            let checksum = run_agent(&mut agent, &commands).unwrap();
+            recorder.record_tick(&commands, Some(checksum));
+        }
+        let path = dir.path("replay.json");
+        recorder.finish().save(&path).unwrap();
+
+        let run_replay = |replay: ReplayFile<Command>| -> Result<usize> {
+            let mut agent = SyncLogicAgent::new(cache.clone(), fi(20))?;
+            let mut replay = ReplayAgent::new(replay, cache.id_table(), fi(20))?;
+            for cmd in replay.initial_commands() {
+                agent.run_command(cmd.clone());
+            }
+            replay.run_all(|commands| run_agent(&mut agent, commands))?;
+            return Ok(replay.tick());
+        };
+        let replay: ReplayFile<Command> = ReplayFile::load(&path).unwrap();
+        assert_eq!(run_replay(replay.clone()).unwrap(), 10);
+
+        let mut changed = replay;
+        changed.ticks[3].commands[0] = move_to(0, 1);
+        let err = run_replay(changed).unwrap_err();
+        assert!(err.to_string().contains("desync at tick 3"));
+    }
+}
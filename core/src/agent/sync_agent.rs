@@ -1,6 +1,6 @@
-use crate::engine::Command;
 use crate::engine::LogicEngine;
-use crate::resource::ResCache;
+use crate::engine::{Command, DataPool};
+use crate::resource::{ResCache, ResCacheUpdate};
 use crate::state::{StateBinder, StateBus};
 use anyhow::Result;
 use m::Fx;
@@ -13,6 +13,7 @@ pub struct SyncLogicAgent {
     state_bus: StateBus,
     engine: LogicEngine,
     commands: Vec<Command>,
+    res_update: Option<ResCacheUpdate>,
 }
 
 impl !Send for SyncLogicAgent {}
@@ -24,6 +25,7 @@ impl SyncLogicAgent {
             state_bus: StateBus::new(),
             engine: LogicEngine::new(res_cache, fps)?,
             commands: Vec::with_capacity(DEFAULT_VEC_CAPACITY),
+            res_update: None,
         });
     }
 
@@ -35,43 +37,80 @@ impl SyncLogicAgent {
         self.commands.push(cmd);
     }
 
+    pub fn update_res_cache(&mut self, update: ResCacheUpdate) {
+        self.res_update = Some(update);
+    }
+
+    // Hands a dispatched pool back to the engine once its states are consumed.
+    pub fn recycle_pool(&mut self, pool: Box<DataPool>) {
+        self.engine.recycle_pool(pool);
+    }
+
     pub fn run_tick(&mut self) -> Result<()> {
+        return self.run_tick_with(|_, _| Ok(()));
+    }
+
+    // Runs a tick, inspect sees the engine and the states of the tick before they are dispatched.
+    pub fn run_tick_with<R, F>(&mut self, inspect: F) -> Result<R>
+    where
+        F: FnOnce(&LogicEngine, &DataPool) -> Result<R>,
+    {
+        let pool = self.run_tick_pool()?;
+        let ret = inspect(&self.engine, &pool)?;
+        self.state_bus.dispatch_states(pool);
+        return Ok(ret);
+    }
+
+    // Runs a tick and hands its pool to the caller instead of the state bus.
+    pub fn run_tick_pool(&mut self) -> Result<Box<DataPool>> {
+        if let Some(update) = self.res_update.take() {
+            self.engine
+                .update_res_cache(update.cache, &update.changed)?;
+        }
         let commands = mem::replace(&mut self.commands, Vec::with_capacity(DEFAULT_VEC_CAPACITY));
         for cmd in &commands {
             self.engine.run_command(cmd)?;
         }
-        let pool = self.engine.run_tick()?;
-        self.state_bus.dispatch_states(pool);
-        return Ok(());
+        return self.engine.run_tick();
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::engine::CmdNewStageGeneral;
-    use crate::id::{FastObjID, ResID};
-    use crate::stage::StateStageGeneral;
-    use crate::state::{StateLifecycle, StateRef};
-    use m::ff;
+    use crate::engine::{CmdNewCharaHuman, LogicLifecycle, StateCharaHuman};
+    use crate::id::{ObjID, ResID};
+    use crate::resource::test_res::{restore_res, write_res, CHARA_TEST};
+    use m::fi;
 
     #[test]
     fn test_sync_logic_agent() {
-        let mut agent = SyncLogicAgent::new(Arc::new(ResCache::new()), ff(1.0 / 20.0)).unwrap();
+        let dir = write_res("sync_agent", CHARA_TEST);
+        let mut agent = SyncLogicAgent::new(restore_res(&dir), fi(20)).unwrap();
 
-        let state_ref: StateRef<StateStageGeneral> =
-            StateRef::new_and_start(FastObjID::from(123), agent.new_binder()).unwrap();
-
-        agent.run_command(Command::NewStageGeneral(CmdNewStageGeneral {
-            res_id: ResID::from("res-id"),
-            fobj_id: FastObjID::from(123),
+        agent.run_command(Command::NewCharaHuman(CmdNewCharaHuman {
+            res_id: ResID::from("Chara.Test"),
         }));
-        agent.run_tick().unwrap();
+        let lifecycle = agent
+            .run_tick_with(|engine, pool| {
+                assert_eq!(engine.frame(), 1);
+                let state = pool.find_state::<StateCharaHuman>(ObjID::from(100000));
+                return Ok(state.unwrap().lifecycle());
+            })
+            .unwrap();
+        assert_eq!(lifecycle, LogicLifecycle::Created);
+
+        let pool = agent.run_tick_pool().unwrap();
+        let ptr = &*pool as *const DataPool;
+        agent.recycle_pool(pool);
+        let pool = agent.run_tick_pool().unwrap();
+        assert_eq!(&*pool as *const DataPool, ptr);
+        let state = pool.find_state::<StateCharaHuman>(ObjID::from(100000));
+        assert_eq!(state.unwrap().lifecycle(), LogicLifecycle::Running);
 
-        assert_eq!(state_ref.state().unwrap().fobj_id, FastObjID::from(100000));
-        assert_eq!(
-            state_ref.state().unwrap().lifecycle,
-            StateLifecycle::Created
-        );
+        agent.run_command(Command::NewCharaHuman(CmdNewCharaHuman {
+            res_id: ResID::from("Chara.NotFound"),
+        }));
+        assert!(agent.run_tick().is_err());
     }
 }
@@ -0,0 +1,312 @@
+use super::operation::{OpAction, OpCommand};
+use super::snapshot::{LogicSnapshot, SnapshotReader, SnapshotWriter};
+use crate::resource::{ResAction, ResActionAny, ResActionTrigger};
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+
+impl ResActionTrigger {
+    pub fn from_command(cmd: &OpCommand) -> Option<ResActionTrigger> {
+        return match cmd {
+            OpCommand::Move(_) => Some(ResActionTrigger::Move),
+            OpCommand::Dash(_) => Some(ResActionTrigger::Dash),
+            OpCommand::Jump => Some(ResActionTrigger::Jump),
+            OpCommand::Attack1(OpAction::HoldEnd, _) => None,
+            OpCommand::Attack1(_, _) => Some(ResActionTrigger::Attack1),
+            OpCommand::Attack2(OpAction::HoldEnd, _) => None,
+            OpCommand::Attack2(_, _) => Some(ResActionTrigger::Attack2),
+            OpCommand::Defend(OpAction::HoldEnd, _) => Some(ResActionTrigger::DefendEnd),
+            OpCommand::Defend(_, _) => Some(ResActionTrigger::Defend),
+            OpCommand::Skill1(OpAction::HoldEnd) => None,
+            OpCommand::Skill1(_) => Some(ResActionTrigger::Skill1),
+            OpCommand::Skill2(OpAction::HoldEnd) => None,
+            OpCommand::Skill2(_) => Some(ResActionTrigger::Skill2),
+            OpCommand::SkillEx(OpAction::HoldEnd) => None,
+            OpCommand::SkillEx(_) => Some(ResActionTrigger::SkillEx),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActionStep {
+    pub action_idx: usize,
+    pub frame: u32,
+    pub changed: bool,
+}
+
+// Steps a character through its ResAction graph, one call per tick.
+#[derive(Debug, Clone)]
+pub struct ActionMachine {
+    res: Arc<ResAction>,
+    action_idx: usize,
+    frame: u32,
+    max_buffer: u32,
+    buffer: Vec<(ResActionTrigger, u32)>,
+}
+
+impl ActionMachine {
+    pub fn new(res: Arc<ResAction>) -> Result<ActionMachine> {
+        if res.actions.is_empty() {
+            return Err(anyhow!("Empty action list {:?}", res.res_id));
+        }
+        return Ok(ActionMachine {
+            max_buffer: max_buffer(&res),
+            res,
+            action_idx: 0,
+            frame: 0,
+            buffer: Vec::with_capacity(8),
+        });
+    }
+
+    // Swaps a reloaded graph, the current action keeps running unless its index is gone.
+    pub fn refresh(&mut self, res: Arc<ResAction>) -> Result<()> {
+        if res.actions.is_empty() {
+            return Err(anyhow!("Empty action list {:?}", res.res_id));
+        }
+        if self.action_idx >= res.actions.len() {
+            self.action_idx = 0;
+            self.frame = 0;
+        }
+        self.max_buffer = max_buffer(&res);
+        self.res = res;
+        return Ok(());
+    }
+
+    #[inline]
+    pub fn res(&self) -> &Arc<ResAction> {
+        return &self.res;
+    }
+
+    #[inline]
+    pub fn action_idx(&self) -> usize {
+        return self.action_idx;
+    }
+
+    #[inline]
+    pub fn action(&self) -> &ResActionAny {
+        return &self.res.actions[self.action_idx];
+    }
+
+    #[inline]
+    pub fn frame(&self) -> u32 {
+        return self.frame;
+    }
+
+    // Triggers must be given in a deterministic order, the first matching transition wins.
+    pub fn step(&mut self, triggers: &[ResActionTrigger]) -> ActionStep {
+        let max_buffer = self.max_buffer;
+        self.buffer.retain(|(_, age)| *age < max_buffer);
+        for (_, age) in &mut self.buffer {
+            *age += 1;
+        }
+
+        self.frame += 1;
+        let frames = self.action().frames();
+        let finished = frames != 0 && self.frame >= frames;
+        if triggers
+            .iter()
+            .all(|trigger| *trigger != ResActionTrigger::Move)
+        {
+            self.buffer.push((ResActionTrigger::Idle, 0));
+        }
+        self.buffer
+            .extend(triggers.iter().map(|trigger| (*trigger, 0)));
+        if finished {
+            self.buffer.push((ResActionTrigger::Finish, 0));
+        }
+
+        let res = self.res.clone();
+        for trans in res.actions[self.action_idx].transitions() {
+            if let Some((start_frame, finish_frame)) = trans.window {
+                if trans.trigger != ResActionTrigger::Finish
+                    && (self.frame < start_frame || self.frame > finish_frame)
+                {
+                    continue;
+                }
+            }
+            let matched = self
+                .buffer
+                .iter()
+                .any(|(trigger, age)| *trigger == trans.trigger && *age <= trans.buffer);
+            if matched {
+                return self.switch(trans.to_index);
+            }
+        }
+
+        if finished {
+            return self.switch(0);
+        }
+        return ActionStep {
+            action_idx: self.action_idx,
+            frame: self.frame,
+            changed: false,
+        };
+    }
+
+    fn switch(&mut self, action_idx: usize) -> ActionStep {
+        self.action_idx = action_idx;
+        self.frame = 0;
+        self.buffer.clear();
+        return ActionStep {
+            action_idx,
+            frame: 0,
+            changed: true,
+        };
+    }
+}
+
+// The graph itself is saved by the owner, which creates the machine from it before loading.
+impl LogicSnapshot for ActionMachine {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_u32(self.action_idx as u32);
+        writer.write_u32(self.frame);
+        writer.write_u32(self.buffer.len() as u32);
+        for (trigger, age) in &self.buffer {
+            writer.write_trigger(*trigger);
+            writer.write_u32(*age);
+        }
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        let action_idx = reader.read_u32()? as usize;
+        if action_idx >= self.res.actions.len() {
+            return Err(anyhow!(
+                "Action index {} out of {:?}",
+                action_idx,
+                self.res.res_id
+            ));
+        }
+        self.action_idx = action_idx;
+        self.frame = reader.read_u32()?;
+        self.buffer.clear();
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            self.buffer
+                .push((reader.read_trigger()?, reader.read_u32()?));
+        }
+        return Ok(());
+    }
+}
+
+fn max_buffer(res: &ResAction) -> u32 {
+    return res
+        .actions
+        .iter()
+        .flat_map(|action| action.transitions())
+        .map(|trans| trans.buffer)
+        .max()
+        .unwrap_or(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ResActionTrigger::*;
+
+    fn new_machine() -> ActionMachine {
+        let mut res: ResAction = serde_yaml::from_str(
+            r#"
+res_id: Action.Test
+actions:
+- type: Idle
+  name: idle
+  transitions:
+  - { to: run, trigger: Move }
+  - { to: attack1, trigger: Attack1 }
+- type: Run
+  name: run
+  move_speed: 5
+  transitions:
+  - { to: idle, trigger: Idle }
+  - { to: attack1, trigger: Attack1 }
+- type: Attack
+  name: attack1
+  frames: 10
+  transitions:
+  - { to: attack2, trigger: Attack1, window: [6, 10], buffer: 3 }
+- type: Attack
+  name: attack2
+  frames: 12
+- type: HitStun
+  name: stun
+  frames: 5
+"#,
+        )
+        .unwrap();
+        res.resolve().unwrap();
+        return ActionMachine::new(Arc::new(res)).unwrap();
+    }
+
+    #[test]
+    fn test_action_machine_move() {
+        let mut machine = new_machine();
+        assert_eq!(machine.action().name(), "idle");
+        assert!(machine.step(&[Move]).changed);
+        assert_eq!(machine.action().name(), "run");
+        assert!(!machine.step(&[Move]).changed);
+        assert!(machine.step(&[]).changed);
+        assert_eq!(machine.action().name(), "idle");
+    }
+
+    #[test]
+    fn test_action_machine_chain() {
+        let mut machine = new_machine();
+        machine.step(&[Attack1]);
+        assert_eq!(machine.action().name(), "attack1");
+
+        // Pressed at frame 4, buffered until the cancel window opens at frame 6.
+        for _ in 0..3 {
+            machine.step(&[]);
+        }
+        machine.step(&[Attack1]);
+        assert_eq!(machine.frame(), 4);
+        machine.step(&[]);
+        let step = machine.step(&[]);
+        assert!(step.changed);
+        assert_eq!(machine.action().name(), "attack2");
+
+        // No transition, falls back to the entry action once finished.
+        for _ in 0..11 {
+            assert!(!machine.step(&[]).changed);
+        }
+        assert!(machine.step(&[]).changed);
+        assert_eq!(machine.action_idx(), 0);
+    }
+
+    #[test]
+    fn test_action_machine_buffer_expired() {
+        let mut machine = new_machine();
+        machine.step(&[Attack1]);
+        machine.step(&[Attack1]);
+        for _ in 0..9 {
+            machine.step(&[]);
+        }
+        assert_eq!(machine.action().name(), "idle");
+    }
+
+    #[test]
+    fn test_action_machine_snapshot() {
+        let mut machine = new_machine();
+        machine.step(&[Attack1]);
+        for _ in 0..3 {
+            machine.step(&[]);
+        }
+        machine.step(&[Attack1]);
+        let mut writer = SnapshotWriter::new();
+        machine.save_snapshot(&mut writer);
+
+        // The buffered Attack1 survives the rollback and still chains at frame 6.
+        let mut other = ActionMachine::new(machine.res().clone()).unwrap();
+        other.load_snapshot(&mut writer.reader()).unwrap();
+        assert_eq!(other.action().name(), "attack1");
+        assert_eq!(other.frame(), 4);
+        other.step(&[]);
+        assert!(other.step(&[]).changed);
+        assert_eq!(other.action().name(), "attack2");
+
+        let res = new_machine().res().clone();
+        let mut truncated = ActionMachine::new(res).unwrap();
+        let mut reader = SnapshotReader::new(&writer.as_bytes()[..6]);
+        assert!(truncated.load_snapshot(&mut reader).is_err());
+    }
+}
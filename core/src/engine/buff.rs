@@ -0,0 +1,439 @@
+use super::damage::CharaStats;
+use super::logic_data::StateHash;
+use super::snapshot::{LogicSnapshot, SnapshotReader, SnapshotWriter};
+use crate::derive::{script_ctx, script_var};
+use crate::id::{FastResID, ObjID, ResID};
+use crate::resource::{ResBuff, ResBuffOperator, ResBuffStacking, ResBuffStat, ResCache};
+use crate::script::ScriptExecutor;
+use anyhow::Result;
+use math::{fi, Fx};
+use na::ComplexField;
+use serde::{Deserialize, Serialize};
+use std::hash::Hasher;
+use std::sync::Arc;
+
+#[script_var(prefix = "buff")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VarBuffIn {
+    pub stacks: Fx,
+    pub frame: Fx,
+    pub remain: Fx,
+}
+
+#[script_var(prefix = "chara")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VarBuffChara {
+    pub health: Fx,
+    pub energy: Fx,
+    pub posture: Fx,
+    pub max_health: Fx,
+    pub max_energy: Fx,
+    pub max_posture: Fx,
+}
+
+#[script_var(prefix = "delta")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VarBuffDelta {
+    pub health: Fx,
+    pub energy: Fx,
+    pub posture: Fx,
+}
+
+#[script_ctx]
+#[derive(Debug)]
+pub struct CtxBuffTick<'t> {
+    pub buff: &'t VarBuffIn,
+    pub chara: &'t VarBuffChara,
+    pub delta: &'t mut VarBuffDelta,
+}
+
+// Active buff exposed in the character state.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuffState {
+    pub fres_id: FastResID,
+    pub source: ObjID,
+    pub stacks: u32,
+    pub frame: u32,
+    // Frames before the buff expires, 0 for a permanent buff.
+    pub remain: u32,
+}
+
+impl StateHash for BuffState {
+    fn state_hash(&self, hasher: &mut dyn Hasher) {
+        self.fres_id.state_hash(hasher);
+        self.source.state_hash(hasher);
+        self.stacks.state_hash(hasher);
+        self.frame.state_hash(hasher);
+        self.remain.state_hash(hasher);
+    }
+}
+
+// Result of a tick script, already applied on the character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuffTick {
+    pub fres_id: FastResID,
+    pub source: ObjID,
+    pub health: i32,
+    pub energy: i32,
+    pub posture: i32,
+}
+
+#[derive(Debug)]
+struct ActiveBuff {
+    res: Arc<ResBuff>,
+    state: BuffState,
+}
+
+// Buffs of a character, kept in the order they were first applied.
+pub struct BuffContainer {
+    buffs: Vec<ActiveBuff>,
+    executor: ScriptExecutor,
+}
+
+impl BuffContainer {
+    pub fn new() -> BuffContainer {
+        return BuffContainer {
+            buffs: Vec::new(),
+            executor: ScriptExecutor::new(),
+        };
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        return self.buffs.len();
+    }
+
+    pub fn states(&self) -> impl Iterator<Item = &BuffState> {
+        return self.buffs.iter().map(|buff| &buff.state);
+    }
+
+    pub fn find(&self, fres_id: FastResID) -> Option<&BuffState> {
+        return self
+            .buffs
+            .iter()
+            .find(|buff| buff.state.fres_id == fres_id)
+            .map(|buff| &buff.state);
+    }
+
+    // Returns the buff state after the add, None if an Ignore buff was already active.
+    pub fn add(&mut self, res: Arc<ResBuff>, source: ObjID) -> Option<BuffState> {
+        let duration = res.duration;
+        if let Some(buff) = self
+            .buffs
+            .iter_mut()
+            .find(|buff| buff.state.fres_id == res.fres_id)
+        {
+            let state = &mut buff.state;
+            match res.stacking {
+                ResBuffStacking::Refresh => {
+                    state.source = source;
+                    state.frame = 0;
+                    state.remain = duration;
+                }
+                ResBuffStacking::Stack => {
+                    state.source = source;
+                    state.stacks = (state.stacks + 1).min(res.max_stacks);
+                    state.remain = duration;
+                }
+                ResBuffStacking::Extend => {
+                    if duration != 0 {
+                        state.remain += duration;
+                    }
+                }
+                ResBuffStacking::Ignore => return None,
+            }
+            return Some(*state);
+        }
+
+        let state = BuffState {
+            fres_id: res.fres_id,
+            source,
+            stacks: 1,
+            frame: 0,
+            remain: duration,
+        };
+        self.buffs.push(ActiveBuff { state, res });
+        return Some(state);
+    }
+
+    pub fn remove(&mut self, fres_id: FastResID) -> bool {
+        let len = self.buffs.len();
+        self.buffs.retain(|buff| buff.state.fres_id != fres_id);
+        return self.buffs.len() != len;
+    }
+
+    pub fn clear(&mut self) {
+        self.buffs.clear();
+    }
+
+    // Re-fetches the changed buffs after a hot reload, stacks and frames are kept.
+    pub fn refresh_res(&mut self, cache: &ResCache, changed: &[ResID]) -> Result<()> {
+        for buff in &mut self.buffs {
+            if changed.contains(&buff.res.res_id) {
+                buff.res = cache
+                    .find_res_by_id(&buff.res.res_id)?
+                    .cast_as::<ResBuff>()?;
+            }
+        }
+        return Ok(());
+    }
+
+    // Advances every buff by one frame, runs the due tick scripts and drops the expired buffs.
+    pub fn update(&mut self, stats: &mut CharaStats, ticks: &mut Vec<BuffTick>) -> Result<()> {
+        for buff in &mut self.buffs {
+            let state = &mut buff.state;
+            state.frame += 1;
+
+            let interval = buff.res.tick_interval;
+            if let Some(byte_code) = &buff.res.tick_byte_code {
+                if interval != 0 && state.frame % interval == 0 {
+                    let var_buff = VarBuffIn {
+                        stacks: fi(state.stacks as i64),
+                        frame: fi(state.frame as i64),
+                        remain: fi(state.remain as i64),
+                    };
+                    let var_chara = VarBuffChara {
+                        health: fi(stats.health as i64),
+                        energy: fi(stats.energy as i64),
+                        posture: fi(stats.posture as i64),
+                        max_health: fi(stats.max_health as i64),
+                        max_energy: fi(stats.max_energy as i64),
+                        max_posture: fi(stats.max_posture as i64),
+                    };
+                    let mut delta = VarBuffDelta::default();
+                    self.executor.run(
+                        byte_code,
+                        CtxBuffTick {
+                            buff: &var_buff,
+                            chara: &var_chara,
+                            delta: &mut delta,
+                        },
+                    )?;
+                    ticks.push(BuffTick {
+                        fres_id: state.fres_id,
+                        source: state.source,
+                        health: change(&mut stats.health, delta.health, stats.max_health),
+                        energy: change(&mut stats.energy, delta.energy, stats.max_energy),
+                        posture: change(&mut stats.posture, delta.posture, stats.max_posture),
+                    });
+                }
+            }
+
+            if state.remain != 0 {
+                state.remain -= 1;
+            }
+        }
+        self.buffs
+            .retain(|buff| buff.res.duration == 0 || buff.state.remain != 0);
+        return Ok(());
+    }
+
+    // Recomputes the modified stats from base, in operator order then in buff order.
+    pub fn modify(&self, base: &CharaStats, stats: &mut CharaStats) {
+        let (health, energy, posture) = (stats.health, stats.energy, stats.posture);
+        *stats = *base;
+
+        let mut modifiers: Vec<_> = self
+            .buffs
+            .iter()
+            .flat_map(|buff| {
+                let stacks = fi(buff.state.stacks as i64);
+                return buff
+                    .res
+                    .modifiers
+                    .iter()
+                    .map(move |modifier| (modifier, stacks));
+            })
+            .collect();
+        modifiers.sort_by_key(|(modifier, _)| modifier.operator);
+
+        for (modifier, stacks) in modifiers {
+            let stat = stat_mut(stats, modifier.stat);
+            let value = fi(*stat as i64);
+            let value = match modifier.operator {
+                ResBuffOperator::Add => value + modifier.value * stacks,
+                ResBuffOperator::Multiply => value * (fi(1) + modifier.value * stacks),
+                ResBuffOperator::Override => modifier.value,
+            };
+            *stat = value.round().to_i32();
+        }
+
+        stats.health = health.min(stats.max_health);
+        stats.energy = energy.min(stats.max_energy);
+        stats.posture = posture.min(stats.max_posture);
+    }
+}
+
+// Buffs keep their resource as a snapshot reference, the script executor is scratch space.
+impl LogicSnapshot for BuffContainer {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_u32(self.buffs.len() as u32);
+        for buff in &self.buffs {
+            writer.write_ref(&buff.res);
+            writer.write_u64(u64::from(buff.state.fres_id));
+            writer.write_obj_id(buff.state.source);
+            writer.write_u32(buff.state.stacks);
+            writer.write_u32(buff.state.frame);
+            writer.write_u32(buff.state.remain);
+        }
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        self.buffs.clear();
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            let res = reader.read_ref::<ResBuff>()?;
+            let state = BuffState {
+                fres_id: FastResID::from(reader.read_u64()?),
+                source: reader.read_obj_id()?,
+                stacks: reader.read_u32()?,
+                frame: reader.read_u32()?,
+                remain: reader.read_u32()?,
+            };
+            self.buffs.push(ActiveBuff { res, state });
+        }
+        return Ok(());
+    }
+}
+
+fn stat_mut(stats: &mut CharaStats, stat: ResBuffStat) -> &mut i32 {
+    return match stat {
+        ResBuffStat::MaxHealth => &mut stats.max_health,
+        ResBuffStat::MaxEnergy => &mut stats.max_energy,
+        ResBuffStat::MaxPosture => &mut stats.max_posture,
+        ResBuffStat::StaggerPosture => &mut stats.stagger_posture,
+        ResBuffStat::PhysicalAttack => &mut stats.physical_attack,
+        ResBuffStat::PhysicalDefense => &mut stats.physical_defense,
+        ResBuffStat::ElementalAttack => &mut stats.elemental_attack,
+        ResBuffStat::ElementalDefense => &mut stats.elemental_defense,
+        ResBuffStat::ArcaneAttack => &mut stats.arcane_attack,
+        ResBuffStat::ArcaneDefense => &mut stats.arcane_defense,
+    };
+}
+
+// Applies a scripted delta clamped to 0..=max, returns the applied amount.
+fn change(value: &mut i32, delta: Fx, max: i32) -> i32 {
+    let old = *value;
+    *value = (old + delta.round().to_i32()).max(0).min(max);
+    return *value - old;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::ScriptCompiler;
+
+    fn new_buff(fres_id: u64, text: &str) -> Arc<ResBuff> {
+        let mut res: ResBuff = serde_yaml::from_str(text).unwrap();
+        res.fres_id = FastResID::from(fres_id);
+        if let Some(script) = &res.tick_script {
+            let byte_code = ScriptCompiler::new().run::<CtxBuffTick>(script).unwrap();
+            res.tick_byte_code = Some(byte_code);
+        }
+        return Arc::new(res);
+    }
+
+    fn new_stats() -> CharaStats {
+        return CharaStats {
+            max_health: 1000,
+            max_energy: 100,
+            max_posture: 100,
+            health: 1000,
+            energy: 100,
+            posture: 100,
+            physical_attack: 100,
+            physical_defense: 100,
+            ..CharaStats::default()
+        };
+    }
+
+    #[test]
+    fn test_buff_container_stacking() {
+        let burn = new_buff(
+            1,
+            "res_id: Buff.Burn\nduration: 10\nstacking: Stack\nmax_stacks: 2\n",
+        );
+        let mut buffs = BuffContainer::new();
+        let mut stats = new_stats();
+        let mut ticks = Vec::new();
+        buffs.add(burn.clone(), ObjID::from(1));
+        for _ in 0..5 {
+            buffs.update(&mut stats, &mut ticks).unwrap();
+        }
+        buffs.add(burn.clone(), ObjID::from(1));
+        buffs.add(burn.clone(), ObjID::from(1));
+        let state = buffs.find(burn.fres_id).unwrap();
+        assert_eq!(state.stacks, 2);
+        assert_eq!(state.remain, 10);
+
+        for _ in 0..9 {
+            buffs.update(&mut stats, &mut ticks).unwrap();
+        }
+        assert_eq!(buffs.len(), 1);
+        buffs.update(&mut stats, &mut ticks).unwrap();
+        assert_eq!(buffs.len(), 0);
+
+        let extend = new_buff(2, "res_id: Buff.Extend\nduration: 10\nstacking: Extend\n");
+        buffs.add(extend.clone(), ObjID::from(1));
+        let state = buffs.add(extend.clone(), ObjID::from(1)).unwrap();
+        assert_eq!(state.remain, 20);
+        assert_eq!(buffs.find(extend.fres_id).unwrap().remain, 20);
+
+        let ignore = new_buff(3, "res_id: Buff.Ignore\nduration: 10\nstacking: Ignore\n");
+        assert_eq!(buffs.add(ignore.clone(), ObjID::from(1)).unwrap().stacks, 1);
+        assert_eq!(buffs.add(ignore.clone(), ObjID::from(2)), None);
+        assert_eq!(buffs.find(ignore.fres_id).unwrap().source, ObjID::from(1));
+        assert!(buffs.remove(extend.fres_id));
+        assert!(!buffs.remove(extend.fres_id));
+    }
+
+    #[test]
+    fn test_buff_container_tick() {
+        let burn = new_buff(
+            1,
+            "res_id: Buff.Burn\nduration: 0\nstacking: Stack\nmax_stacks: 3\ntick_interval: 2\ntick_script: delta.health = -10 * buff.stacks\n",
+        );
+        let mut buffs = BuffContainer::new();
+        let mut stats = new_stats();
+        let mut ticks = Vec::new();
+        buffs.add(burn.clone(), ObjID::from(7));
+        buffs.add(burn.clone(), ObjID::from(7));
+        for _ in 0..4 {
+            buffs.update(&mut stats, &mut ticks).unwrap();
+        }
+        assert_eq!(ticks.len(), 2);
+        assert_eq!(ticks[0].health, -20);
+        assert_eq!(ticks[0].source, ObjID::from(7));
+        assert_eq!(stats.health, 960);
+        assert_eq!(buffs.len(), 1);
+    }
+
+    #[test]
+    fn test_buff_container_modify() {
+        let weak = new_buff(
+            1,
+            "res_id: Buff.Weak\nduration: 0\nmodifiers:\n- { stat: PhysicalAttack, operator: Multiply, value: 0.5 }\n- { stat: MaxHealth, operator: Add, value: -600 }\n",
+        );
+        let rage = new_buff(
+            2,
+            "res_id: Buff.Rage\nduration: 0\nmodifiers:\n- { stat: PhysicalAttack, operator: Add, value: 20 }\n- { stat: PhysicalDefense, operator: Override, value: 0 }\n",
+        );
+        let mut buffs = BuffContainer::new();
+        buffs.add(weak, ObjID::from(1));
+        buffs.add(rage.clone(), ObjID::from(1));
+
+        let base = new_stats();
+        let mut stats = base;
+        buffs.modify(&base, &mut stats);
+        assert_eq!(stats.physical_attack, 180);
+        assert_eq!(stats.physical_defense, 0);
+        assert_eq!(stats.max_health, 400);
+        assert_eq!(stats.health, 400);
+
+        buffs.remove(rage.fres_id);
+        buffs.modify(&base, &mut stats);
+        assert_eq!(stats.physical_attack, 150);
+        assert_eq!(stats.physical_defense, 100);
+    }
+}
@@ -0,0 +1,963 @@
+use super::action::ActionMachine;
+use super::buff::{BuffContainer, BuffState, BuffTick};
+use super::damage::{CharaStats, DamageEvent, DamagePipeline, DamageReaction, HitEvent};
+use super::event::{EventActionStarted, EventBuffApplied, EventDamage, EventDead};
+use super::hit_box::{HitBoxes, HitCoords};
+use super::hit_path::{HitImpact, HitPaths};
+use super::interpolator::StateTransform;
+use super::logic_data::{DataPool, LogicLifecycle};
+use super::logic_obj::LogicObj;
+use super::motion::MotionCurves;
+use super::operation::OpCommand;
+use super::snapshot::{LogicSnapshot, SnapshotReader, SnapshotWriter};
+use super::target::{TargetLock, TargetSearch};
+use crate::derive::{def_obj, def_state};
+use crate::ffi::{FFIQuaternion, FFITransform, FFIVec3f};
+use crate::id::{ClassID, FastResID, ObjID, ResID};
+use crate::resource::{
+    ResAction, ResActionAny, ResActionTrigger, ResBuff, ResCache, ResCharaHuman, ResHitArea,
+    ResHitAttachment, ResHitPathRay, ResMotion, ResObjSuper, ResProjectile, ResSkill,
+};
+use crate::utils::Prng;
+use anyhow::{anyhow, Result};
+use collide::pipeline::{
+    CollisionGroups, CollisionObjectSlabHandle, CollisionObjectType, CollisionWorld,
+    GeometricQueryType,
+};
+use collide::query::{self, Proximity};
+use collide::shape::Shape;
+use math::{fi, Fx};
+use na::{Isometry3, UnitQuaternion, Vector2, Vector3};
+use serde::Serialize;
+use std::mem;
+use std::sync::Arc;
+
+#[def_state(ClassID::CharaHuman)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct StateCharaHuman {
+    pub fres_id: FastResID,
+    pub action_idx: u32,
+    pub action_frame: u32,
+    pub position: FFIVec3f,
+    pub rotation: FFIQuaternion,
+    pub health: i32,
+    pub energy: i32,
+    pub posture: i32,
+    // Buffs beyond the array are active but not exported.
+    pub buff_count: u32,
+    pub buffs: [BuffState; 8],
+}
+
+impl StateTransform for StateCharaHuman {
+    fn transform(&self) -> FFITransform {
+        return FFITransform::new(self.position, self.rotation);
+    }
+}
+
+// A hit area of the current skill touching a character, in (hit, area, handle) order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HitTarget {
+    hit_idx: usize,
+    area_idx: usize,
+    pub(crate) target: ObjID,
+}
+
+#[derive(Debug)]
+enum CharaHit {
+    Boxes(HitBoxes),
+    Paths(HitPaths),
+}
+
+impl CharaHit {
+    fn area(&self, area_idx: usize) -> &ResHitArea {
+        return match self {
+            CharaHit::Boxes(boxes) => &boxes.res().areas[area_idx],
+            CharaHit::Paths(paths) => &paths.res().areas[area_idx],
+        };
+    }
+
+    fn accept_hit(&mut self, area_idx: usize, target: ObjID, frame: u32) -> bool {
+        return match self {
+            CharaHit::Boxes(boxes) => boxes.accept_hit(area_idx, target, frame),
+            CharaHit::Paths(paths) => paths.accept_hit(area_idx, target, frame),
+        };
+    }
+
+    fn is_finished(&self, frame: u32) -> bool {
+        return match self {
+            CharaHit::Boxes(boxes) => boxes.is_finished(frame),
+            CharaHit::Paths(paths) => paths.is_finished(frame),
+        };
+    }
+
+    fn clear(&mut self, world: &mut CollisionWorld<u64>) {
+        match self {
+            CharaHit::Boxes(boxes) => boxes.clear(world),
+            CharaHit::Paths(paths) => paths.clear(),
+        };
+    }
+
+    fn refresh(
+        &mut self,
+        cache: &ResCache,
+        changed: &[ResID],
+        world: &mut CollisionWorld<u64>,
+    ) -> Result<()> {
+        match self {
+            CharaHit::Boxes(boxes) if changed.contains(&boxes.res().res_id) => {
+                let res = cache.find_res_by_id(&boxes.res().res_id)?;
+                boxes.refresh(world, res.cast_as::<ResHitAttachment>()?);
+            }
+            CharaHit::Paths(paths) if changed.contains(&paths.res().res_id) => {
+                let res = cache.find_res_by_id(&paths.res().res_id)?;
+                paths.refresh(res.cast_as::<ResHitPathRay>()?);
+            }
+            _ => {}
+        };
+        return Ok(());
+    }
+}
+
+// A human character: operations feed its action graph once per tick, attack actions run their skill's
+// hit areas. Collision data of the world is the ObjID of the owner.
+#[def_obj(ClassID::CharaHuman)]
+pub struct LogicCharaHuman {
+    obj_id: ObjID,
+    res: Arc<ResCharaHuman>,
+    lifecycle: LogicLifecycle,
+    machine: Option<ActionMachine>,
+    base_stats: CharaStats,
+    stats: CharaStats,
+    buffs: BuffContainer,
+    buff_ticks: Vec<BuffTick>,
+    position: Isometry3<Fx>,
+    handle: CollisionObjectSlabHandle,
+    groups: CollisionGroups,
+    move_dir: Vector2<Fx>,
+    triggers: Vec<ResActionTrigger>,
+    skill: Option<Arc<ResSkill>>,
+    skill_origin: Isometry3<Fx>,
+    skill_frame: u32,
+    // Curves of the skill motions, rebuilt with the skill.
+    motions: Option<MotionCurves>,
+    search: TargetSearch,
+    lock: TargetLock,
+    hits: Vec<CharaHit>,
+    impacts: Vec<HitImpact>,
+    candidates: Vec<CollisionObjectSlabHandle>,
+    // Events of the tick, emitted with the state.
+    action_started: bool,
+    damages: Vec<DamageEvent>,
+    applied_buffs: Vec<BuffState>,
+}
+
+impl LogicCharaHuman {
+    pub fn new(
+        obj_id: ObjID,
+        res: Arc<ResCharaHuman>,
+        cache: &ResCache,
+        position: Isometry3<Fx>,
+        groups: CollisionGroups,
+        world: &mut CollisionWorld<u64>,
+    ) -> Result<LogicCharaHuman> {
+        let machine = match &res.action {
+            Some(action) => Some(ActionMachine::new(
+                cache.find_res_by_id(action)?.cast_as::<ResAction>()?,
+            )?),
+            None => None,
+        };
+        let (handle, _) = world.add(
+            CollisionObjectType::Move,
+            position * res.collision.transform,
+            res.collision.handle.clone(),
+            groups,
+            GeometricQueryType::Contacts(fi(0), fi(0)),
+            u64::from(obj_id),
+        );
+        return Ok(LogicCharaHuman {
+            obj_id,
+            lifecycle: LogicLifecycle::Created,
+            machine,
+            base_stats: CharaStats::new(&res),
+            stats: CharaStats::new(&res),
+            buffs: BuffContainer::new(),
+            buff_ticks: Vec::new(),
+            position,
+            handle,
+            groups,
+            move_dir: Vector2::zeros(),
+            triggers: Vec::new(),
+            skill: None,
+            skill_origin: Isometry3::identity(),
+            skill_frame: 0,
+            motions: None,
+            search: TargetSearch::new(),
+            lock: TargetLock::new(),
+            hits: Vec::new(),
+            impacts: Vec::new(),
+            candidates: Vec::new(),
+            action_started: false,
+            damages: Vec::new(),
+            applied_buffs: Vec::new(),
+            res,
+        });
+    }
+
+    // A character of res outside the world, its snapshot is loaded into it on rollback.
+    pub(crate) fn from_res(res: Arc<ResCharaHuman>) -> LogicCharaHuman {
+        return LogicCharaHuman {
+            obj_id: ObjID::invalid(),
+            lifecycle: LogicLifecycle::Created,
+            machine: None,
+            base_stats: CharaStats::new(&res),
+            stats: CharaStats::new(&res),
+            buffs: BuffContainer::new(),
+            buff_ticks: Vec::new(),
+            position: Isometry3::identity(),
+            handle: CollisionObjectSlabHandle(0),
+            groups: CollisionGroups {
+                team_membership: 0,
+                team_whitelist: 0,
+                role_membership: 0,
+                role_whitelist: 0,
+            },
+            move_dir: Vector2::zeros(),
+            triggers: Vec::new(),
+            skill: None,
+            skill_origin: Isometry3::identity(),
+            skill_frame: 0,
+            motions: None,
+            search: TargetSearch::new(),
+            lock: TargetLock::new(),
+            hits: Vec::new(),
+            impacts: Vec::new(),
+            candidates: Vec::new(),
+            action_started: false,
+            damages: Vec::new(),
+            applied_buffs: Vec::new(),
+            res,
+        };
+    }
+
+    #[inline]
+    pub fn res(&self) -> &Arc<ResCharaHuman> {
+        return &self.res;
+    }
+
+    #[inline]
+    pub fn action(&self) -> Option<&ResActionAny> {
+        return self.machine.as_ref().map(|machine| machine.action());
+    }
+
+    #[inline]
+    pub fn action_frame(&self) -> u32 {
+        return self
+            .machine
+            .as_ref()
+            .map(|machine| machine.frame())
+            .unwrap_or(0);
+    }
+
+    #[inline]
+    pub fn stats(&self) -> &CharaStats {
+        return &self.stats;
+    }
+
+    #[inline]
+    pub fn buffs(&self) -> &BuffContainer {
+        return &self.buffs;
+    }
+
+    #[inline]
+    pub fn skill(&self) -> Option<&Arc<ResSkill>> {
+        return self.skill.as_ref();
+    }
+
+    #[inline]
+    pub fn position(&self) -> &Isometry3<Fx> {
+        return &self.position;
+    }
+
+    #[inline]
+    pub fn is_dead(&self) -> bool {
+        return self.stats.is_dead();
+    }
+
+    #[inline]
+    pub fn is_guarding(&self) -> bool {
+        return match self.action() {
+            Some(ResActionAny::Guard(_)) => true,
+            _ => false,
+        };
+    }
+
+    pub(crate) fn set_rotation(&mut self, rotation: UnitQuaternion<Fx>) {
+        self.position.rotation = rotation;
+    }
+
+    #[inline]
+    pub fn target(&self) -> Option<ObjID> {
+        return self.lock.target();
+    }
+
+    pub(crate) fn hit_coords(&self) -> HitCoords<'static> {
+        return HitCoords {
+            source: self.position,
+            target: self.lock.position(),
+            skill: self.skill_origin,
+            bones: &[],
+        };
+    }
+
+    // Collects an operation of the tick, triggers are consumed in arrival order by the next update.
+    pub fn push_operation(&mut self, command: &OpCommand) {
+        if let OpCommand::Move(dir) = command {
+            self.move_dir = *dir;
+        }
+        if let Some(trigger) = ResActionTrigger::from_command(command) {
+            self.triggers.push(trigger);
+        }
+    }
+
+    // Steps the action graph, a new attack action starts its skill.
+    // Projectiles of the skill are appended to launches, the engine owns them.
+    pub fn update_action(
+        &mut self,
+        cache: &ResCache,
+        world: &mut CollisionWorld<u64>,
+        launches: &mut Vec<Arc<ResProjectile>>,
+    ) -> Result<()> {
+        let triggers = mem::take(&mut self.triggers);
+        if self.is_dead() {
+            return Ok(());
+        }
+        let machine = match &mut self.machine {
+            Some(machine) => machine,
+            None => return Ok(()),
+        };
+        let step = machine.step(&triggers);
+        if !step.changed {
+            return Ok(());
+        }
+        self.action_started = true;
+
+        for hit in &mut self.hits {
+            hit.clear(world);
+        }
+        self.hits.clear();
+        self.skill = None;
+        self.motions = None;
+        self.lock.release();
+
+        let skill_id = match machine.action() {
+            ResActionAny::Attack(attack) => attack.skill.clone(),
+            _ => None,
+        };
+        if let Some(skill_id) = skill_id {
+            let skill = cache.find_res_by_id(&skill_id)?.cast_as::<ResSkill>()?;
+            for hit_id in &skill.hits {
+                let res = cache.find_res_by_id(hit_id)?;
+                match res.class_id() {
+                    ClassID::HitAttachment => self.hits.push(CharaHit::Boxes(HitBoxes::new(
+                        res.cast_as::<ResHitAttachment>()?,
+                    ))),
+                    ClassID::HitPathRay => self.hits.push(CharaHit::Paths(HitPaths::new(
+                        res.cast_as::<ResHitPathRay>()?,
+                    ))),
+                    ClassID::Projectile => launches.push(res.cast_as::<ResProjectile>()?),
+                    _ => return Err(anyhow!("Unsupported hit {:?}", hit_id)),
+                };
+            }
+            self.motions = Some(MotionCurves::new(&skill.motions)?);
+            self.skill = Some(skill);
+            self.skill_origin = self.position;
+            self.skill_frame = 0;
+        }
+        return Ok(());
+    }
+
+    // Advances the skill, its motions move the character in the motion coordinate.
+    // Run follows the move direction, dash moves forward over its frames.
+    pub fn update_move(&mut self, frame_time: Fx) {
+        if self.is_dead() {
+            return;
+        }
+        if let (Some(skill), Some(motions)) = (&self.skill, &self.motions) {
+            self.skill_frame += 1;
+            let delta = motions.delta(self.skill_frame, frame_time);
+            let coord = self.hit_coords().coordinate(skill.motion_coord).rotation;
+            self.position.translation.vector += coord * delta.translation.vector;
+            self.position.rotation =
+                coord * delta.rotation * coord.inverse() * self.position.rotation;
+        }
+
+        let dir = Vector3::new(self.move_dir.x, fi(0), self.move_dir.y);
+        let offset = match self.machine.as_ref().map(|machine| machine.action()) {
+            Some(ResActionAny::Run(run)) if dir.norm() > fi(0) => {
+                if let Some(rotation) = UnitQuaternion::rotation_between(&Vector3::z(), &dir) {
+                    self.position.rotation = rotation;
+                }
+                dir.normalize() * run.move_speed * frame_time
+            }
+            Some(ResActionAny::Dash(dash)) if dash.frames > 0 => {
+                self.position.rotation * Vector3::z() * dash.distance / fi(dash.frames as i64)
+            }
+            _ => return,
+        };
+        self.position.translation.vector += offset;
+    }
+
+    // Moves the collision object to the character, the world still needs an update.
+    pub fn sync_world(&self, world: &mut CollisionWorld<u64>) {
+        if let Some(co) = world.get_mut(self.handle) {
+            co.set_position(self.position * self.res.collision.transform);
+        }
+    }
+
+    // Runs the SearchTarget motions of the skill within their frames while nothing is locked,
+    // then follows the locked character. The world must be updated since the characters moved.
+    pub(crate) fn update_target(&mut self, world: &mut CollisionWorld<u64>, prng: &mut Prng) {
+        let skill = match &self.skill {
+            Some(skill) if !self.is_dead() => skill,
+            _ => return,
+        };
+        let source = self.obj_id;
+        let target_id = |data: &u64| Some(ObjID::from(*data)).filter(|obj_id| *obj_id != source);
+        for motion in &skill.motions {
+            let motion = match motion {
+                ResMotion::SearchTarget(motion) => motion,
+                _ => continue,
+            };
+            if self.lock.target().is_none()
+                && motion.start_frame <= self.skill_frame
+                && self.skill_frame <= motion.finish_frame
+            {
+                let found = self.search.search(
+                    world,
+                    motion,
+                    &self.position,
+                    &self.groups,
+                    prng,
+                    &target_id,
+                );
+                if let Some(found) = found {
+                    self.lock.lock(&found);
+                }
+            }
+        }
+        self.lock.update(world, &target_id);
+    }
+
+    // Moves the hit areas of the current skill to its frame and appends the characters they touch.
+    // The world must be updated since the characters moved.
+    pub(crate) fn sweep_hits(&mut self, world: &mut CollisionWorld<u64>, out: &mut Vec<HitTarget>) {
+        if self.hits.is_empty() || self.is_dead() {
+            return;
+        }
+        let frame = self.skill_frame;
+        let source = u64::from(self.obj_id);
+        let coords = self.hit_coords();
+
+        for (hit_idx, hit) in self.hits.iter_mut().enumerate() {
+            match hit {
+                CharaHit::Boxes(boxes) => {
+                    boxes.update(world, frame, &coords, self.groups, source);
+                    for (area_idx, area) in boxes.res().areas.iter().enumerate() {
+                        let position = match boxes.handle(area_idx) {
+                            Some(handle) => *world.collision_object(handle).unwrap().position(),
+                            None => continue,
+                        };
+                        let shape = area.shape.handle.as_ref();
+                        self.candidates.clear();
+                        world.interferences_with_aabb(
+                            CollisionObjectType::Move,
+                            &shape.aabb(&position),
+                            &self.groups,
+                            &mut self.candidates,
+                        );
+                        self.candidates.sort_by_key(|handle| handle.0);
+                        for handle in &self.candidates {
+                            let co = match world.collision_object(*handle) {
+                                Some(co) if *co.data() != source => co,
+                                _ => continue,
+                            };
+                            let proximity = query::proximity(
+                                &position,
+                                shape,
+                                co.position(),
+                                co.shape().as_ref(),
+                                fi(0),
+                            );
+                            if proximity == Proximity::Intersecting {
+                                out.push(HitTarget {
+                                    hit_idx,
+                                    area_idx,
+                                    target: ObjID::from(*co.data()),
+                                });
+                            }
+                        }
+                    }
+                }
+                CharaHit::Paths(paths) => {
+                    self.impacts.clear();
+                    paths.sweep(world, frame, &coords, &self.groups, &mut self.impacts);
+                    for impact in &self.impacts {
+                        let target = match world.collision_object(impact.handle) {
+                            Some(co) if *co.data() != source => ObjID::from(*co.data()),
+                            _ => continue,
+                        };
+                        out.push(HitTarget {
+                            hit_idx,
+                            area_idx: impact.area_idx,
+                            target,
+                        });
+                    }
+                }
+            };
+        }
+    }
+
+    // Counts the hit on its area, None once max_hits or hit_interval rejects it.
+    pub(crate) fn accept_hit(&mut self, target: &HitTarget) -> Option<HitEvent> {
+        let frame = self.skill_frame;
+        let hit = &mut self.hits[target.hit_idx];
+        if !hit.accept_hit(target.area_idx, target.target, frame) {
+            return None;
+        }
+        let skill = self.skill.as_ref()?;
+        return Some(HitEvent::new(
+            self.obj_id,
+            target.target,
+            hit.area(target.area_idx),
+            &skill.effect.target_damage,
+        ));
+    }
+
+    // Removes the hit areas once all of them are over.
+    pub(crate) fn finish_hits(&mut self, world: &mut CollisionWorld<u64>) {
+        let frame = self.skill_frame;
+        if !self.hits.is_empty() && self.hits.iter().all(|hit| hit.is_finished(frame)) {
+            for hit in &mut self.hits {
+                hit.clear(world);
+            }
+            self.hits.clear();
+        }
+    }
+
+    // Rebinds the hit areas of the current skill to their reloaded resources, hit counts are kept.
+    pub(crate) fn refresh_hits(
+        &mut self,
+        cache: &ResCache,
+        changed: &[ResID],
+        world: &mut CollisionWorld<u64>,
+    ) -> Result<()> {
+        for hit in &mut self.hits {
+            hit.refresh(cache, changed, world)?;
+        }
+        return Ok(());
+    }
+
+    // Adds a buff and recomputes the stats it modifies.
+    pub fn add_buff(&mut self, res: Arc<ResBuff>, source: ObjID) {
+        if let Some(state) = self.buffs.add(res, source) {
+            self.applied_buffs.push(state);
+        }
+        self.buffs.modify(&self.base_stats, &mut self.stats);
+    }
+
+    // Runs the buff tick scripts and expires the buffs at the start of the tick.
+    pub fn update_buffs(&mut self) -> Result<()> {
+        self.buff_ticks.clear();
+        if self.is_dead() || self.buffs.len() == 0 {
+            return Ok(());
+        }
+        self.buffs.update(&mut self.stats, &mut self.buff_ticks)?;
+        self.buffs.modify(&self.base_stats, &mut self.stats);
+        return Ok(());
+    }
+
+    // Applies a hit on this character, its reaction feeds the action graph on the next tick.
+    pub fn take_hit(
+        &mut self,
+        damage: &mut DamagePipeline,
+        hit: &HitEvent,
+        attacker: &CharaStats,
+    ) -> Result<DamageEvent> {
+        let mut hit = hit.clone();
+        hit.guarding = self.is_guarding();
+        let event = damage.apply(&hit, attacker, &mut self.stats)?;
+        match event.reaction {
+            DamageReaction::Stagger | DamageReaction::PostureBreak => {
+                self.triggers.push(ResActionTrigger::Hit)
+            }
+            DamageReaction::KnockDown => self.triggers.push(ResActionTrigger::KnockDown),
+            _ => {}
+        };
+        self.damages.push(event.clone());
+        return Ok(event);
+    }
+
+    // Damages are emitted by the damaged character, EventDead follows the killing blow.
+    fn emit_events(&mut self, pool: &mut DataPool) {
+        let class_id = ClassID::CharaHuman;
+        if mem::take(&mut self.action_started) {
+            if let Some(machine) = &self.machine {
+                pool.event(
+                    self.obj_id,
+                    class_id,
+                    EventActionStarted {
+                        fres_id: machine.res().fres_id,
+                        action_idx: machine.action_idx() as u32,
+                    },
+                );
+            }
+        }
+        for damage in self.damages.drain(..) {
+            pool.event(self.obj_id, class_id, EventDamage::from(&damage));
+            if damage.reaction == DamageReaction::Dead {
+                pool.event(
+                    self.obj_id,
+                    class_id,
+                    EventDead {
+                        killer: damage.source,
+                    },
+                );
+            }
+        }
+        for buff in self.applied_buffs.drain(..) {
+            pool.event(self.obj_id, class_id, EventBuffApplied::from(&buff));
+        }
+    }
+}
+
+// The character resource is saved by the owner. Events of a tick are emitted within the tick,
+// so the snapshot taken between ticks has none.
+impl LogicSnapshot for LogicCharaHuman {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_obj_id(self.obj_id);
+        writer.write_lifecycle(self.lifecycle);
+        writer.write_bool(self.machine.is_some());
+        if let Some(machine) = &self.machine {
+            writer.write_ref(machine.res());
+            machine.save_snapshot(writer);
+        }
+        self.base_stats.save_snapshot(writer);
+        self.stats.save_snapshot(writer);
+        self.buffs.save_snapshot(writer);
+        writer.write_isometry(&self.position);
+        writer.write_handle(self.handle);
+        writer.write_groups(&self.groups);
+        writer.write_vec2(&self.move_dir);
+        writer.write_u32(self.triggers.len() as u32);
+        for trigger in &self.triggers {
+            writer.write_trigger(*trigger);
+        }
+        writer.write_bool(self.skill.is_some());
+        if let Some(skill) = &self.skill {
+            writer.write_ref(skill);
+        }
+        writer.write_isometry(&self.skill_origin);
+        writer.write_u32(self.skill_frame);
+        self.lock.save_snapshot(writer);
+        writer.write_u32(self.hits.len() as u32);
+        for hit in &self.hits {
+            match hit {
+                CharaHit::Boxes(boxes) => {
+                    writer.write_u8(0);
+                    writer.write_ref(boxes.res());
+                    boxes.save_snapshot(writer);
+                }
+                CharaHit::Paths(paths) => {
+                    writer.write_u8(1);
+                    writer.write_ref(paths.res());
+                    paths.save_snapshot(writer);
+                }
+            };
+        }
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        self.obj_id = reader.read_obj_id()?;
+        self.lifecycle = reader.read_lifecycle()?;
+        self.machine = match reader.read_bool()? {
+            true => {
+                let mut machine = ActionMachine::new(reader.read_ref::<ResAction>()?)?;
+                machine.load_snapshot(reader)?;
+                Some(machine)
+            }
+            false => None,
+        };
+        self.base_stats.load_snapshot(reader)?;
+        self.stats.load_snapshot(reader)?;
+        self.buffs.load_snapshot(reader)?;
+        self.position = reader.read_isometry()?;
+        self.handle = reader.read_handle()?;
+        self.groups = reader.read_groups()?;
+        self.move_dir = reader.read_vec2()?;
+        self.triggers.clear();
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            self.triggers.push(reader.read_trigger()?);
+        }
+        self.skill = match reader.read_bool()? {
+            true => Some(reader.read_ref::<ResSkill>()?),
+            false => None,
+        };
+        self.motions = match &self.skill {
+            Some(skill) => Some(MotionCurves::new(&skill.motions)?),
+            None => None,
+        };
+        self.skill_origin = reader.read_isometry()?;
+        self.skill_frame = reader.read_u32()?;
+        self.lock.load_snapshot(reader)?;
+        self.hits.clear();
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            let hit = match reader.read_u8()? {
+                0 => {
+                    let mut boxes = HitBoxes::new(reader.read_ref::<ResHitAttachment>()?);
+                    boxes.load_snapshot(reader)?;
+                    CharaHit::Boxes(boxes)
+                }
+                1 => {
+                    let mut paths = HitPaths::new(reader.read_ref::<ResHitPathRay>()?);
+                    paths.load_snapshot(reader)?;
+                    CharaHit::Paths(paths)
+                }
+                tag => return Err(anyhow!("Unknown hit tag {}", tag)),
+            };
+            self.hits.push(hit);
+        }
+
+        self.buff_ticks.clear();
+        self.impacts.clear();
+        self.candidates.clear();
+        self.action_started = false;
+        self.damages.clear();
+        self.applied_buffs.clear();
+        return Ok(());
+    }
+}
+
+impl LogicObj for LogicCharaHuman {
+    fn update_prop(&mut self, _pool: &mut DataPool) -> Result<()> {
+        return Ok(());
+    }
+
+    fn update_state(&mut self, pool: &mut DataPool) -> Result<()> {
+        let mut state = StateCharaHuman {
+            fres_id: self.res.fres_id,
+            action_idx: self
+                .machine
+                .as_ref()
+                .map(|machine| machine.action_idx() as u32)
+                .unwrap_or(0),
+            action_frame: self.action_frame(),
+            position: FFIVec3f::from(self.position.translation.vector),
+            rotation: FFIQuaternion::from(self.position.rotation),
+            health: self.stats.health,
+            energy: self.stats.energy,
+            posture: self.stats.posture,
+            buff_count: 0,
+            buffs: Default::default(),
+        };
+        for (slot, buff) in state.buffs.iter_mut().zip(self.buffs.states()) {
+            *slot = *buff;
+            state.buff_count += 1;
+        }
+        pool.state(self.obj_id, self.lifecycle, state)?;
+        self.emit_events(pool);
+        if self.lifecycle == LogicLifecycle::Created {
+            self.lifecycle = LogicLifecycle::Running;
+        }
+        return Ok(());
+    }
+
+    // The current action, skill and buffs keep running on the reloaded resources.
+    // Health, energy and posture are kept within the reloaded maximums.
+    fn refresh_res(&mut self, cache: &ResCache, changed: &[ResID]) -> Result<()> {
+        let chara_changed = changed.contains(&self.res.res_id);
+        if chara_changed {
+            self.res = cache
+                .find_res_by_id(&self.res.res_id)?
+                .cast_as::<ResCharaHuman>()?;
+            self.base_stats = CharaStats::new(&self.res);
+        }
+
+        let action_changed = match (&self.machine, &self.res.action) {
+            (Some(machine), Some(action_id)) => {
+                machine.res().res_id != *action_id || changed.contains(action_id)
+            }
+            (None, None) => false,
+            _ => true,
+        };
+        if action_changed {
+            let action = match &self.res.action {
+                Some(action_id) => Some(cache.find_res_by_id(action_id)?.cast_as::<ResAction>()?),
+                None => None,
+            };
+            self.machine = match (self.machine.take(), action) {
+                (Some(mut machine), Some(action)) => {
+                    machine.refresh(action)?;
+                    Some(machine)
+                }
+                (None, Some(action)) => Some(ActionMachine::new(action)?),
+                (_, None) => None,
+            };
+        }
+
+        if let Some(skill) = &self.skill {
+            if changed.contains(&skill.res_id) {
+                let skill = cache.find_res_by_id(&skill.res_id)?.cast_as::<ResSkill>()?;
+                self.motions = Some(MotionCurves::new(&skill.motions)?);
+                self.skill = Some(skill);
+            }
+        }
+        self.buffs.refresh_res(cache, changed)?;
+        self.buffs.modify(&self.base_stats, &mut self.stats);
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::OpAction;
+    use crate::resource::test_res::{restore_res, write_res, CHARA_TEST};
+    use math::ff;
+    use na::{RealField, Translation3};
+
+    const RESOURCE: &'static str = r#"- type: Action
+  res_id: Action.Test
+  actions:
+  - type: Idle
+    name: idle
+    transitions:
+    - { to: attack, trigger: Attack1 }
+  - type: Attack
+    name: attack
+    frames: 8
+    skill: Skill.Dash
+- type: Skill
+  res_id: Skill.Dash
+  frames: 8
+  shape: { type: Ball, radius: 1 }
+  center: [0, 0, 0]
+  origin:
+    rotation: [0, 0, 0]
+    translation: [0, 0, 0]
+  origin_coord: Source
+  motion_coord: Source
+  motions:
+  - { type: Move, start_frame: 0, finish_frame: 4, start_value: [0, 0, 0], finish_value: [0, 0, 2] }
+  - type: SearchTarget
+    start_frame: 0
+    finish_frame: 8
+    shape: { type: Ball, radius: 5 }
+    transform:
+      rotation: [0, 0, 0]
+      translation: [0, 0, 0]
+    searcher: Nearest
+  effect:
+    target_damage: { health: 0, energy: 0, posture: 0, physical: 0, elemental: 0, arcane: 0 }
+"#;
+
+    fn new_chara(
+        cache: &ResCache,
+        world: &mut CollisionWorld<u64>,
+        obj_id: ObjID,
+        position: Isometry3<Fx>,
+    ) -> LogicCharaHuman {
+        let res = cache
+            .find_res_by_id(&ResID::from("Chara.Test"))
+            .unwrap()
+            .cast_as::<ResCharaHuman>()
+            .unwrap();
+        let groups = CollisionGroups {
+            team_membership: 1,
+            team_whitelist: 0xFFFF,
+            role_membership: 1,
+            role_whitelist: 0xFFFF,
+        };
+        let chara = LogicCharaHuman::new(obj_id, res, cache, position, groups, world).unwrap();
+        world.update(&[CollisionObjectType::Move]);
+        return chara;
+    }
+
+    fn new_cache(name: &str) -> Arc<ResCache> {
+        let dir = write_res(
+            name,
+            &format!("{}  action: Action.Test\n{}", CHARA_TEST, RESOURCE),
+        );
+        return restore_res(&dir);
+    }
+
+    #[test]
+    fn test_chara_skill_motion() {
+        let cache = new_cache("chara_skill_motion");
+        let mut world = CollisionWorld::new(fi(0));
+        // Facing +x, the skill moves forward in the source coordinate.
+        let start = Isometry3::from_parts(
+            Translation3::new(fi(1), fi(0), fi(0)),
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), Fx::frac_pi_2()),
+        );
+        let mut chara = new_chara(&cache, &mut world, ObjID::from(1), start);
+
+        let mut launches = Vec::new();
+        chara.push_operation(&OpCommand::Attack1(OpAction::Press, Vector2::zeros()));
+        let mut positions = Vec::new();
+        for _ in 0..6 {
+            chara
+                .update_action(&cache, &mut world, &mut launches)
+                .unwrap();
+            chara.update_move(fi(1) / fi(20));
+            positions.push(chara.position().translation.vector);
+        }
+        assert!(chara.skill().is_some());
+        assert!(fi(1) < positions[0].x && positions[0].x < positions[1].x);
+        assert!(positions[1].x < positions[2].x && positions[2].x < positions[3].x);
+        assert_eq!(positions[3], positions[4]);
+        assert_eq!(positions[4], positions[5]);
+        assert!((positions[5] - Vector3::new(fi(3), fi(0), fi(0))).norm() < ff(0.01));
+    }
+
+    #[test]
+    fn test_chara_target_lock() {
+        let cache = new_cache("chara_target_lock");
+        let mut world = CollisionWorld::new(fi(0));
+        let mut prng = Prng::new(1);
+        let mut chara = new_chara(&cache, &mut world, ObjID::from(1), Isometry3::identity());
+        let other_position = Isometry3::translation(fi(3), fi(0), fi(0));
+        let other = new_chara(&cache, &mut world, ObjID::from(2), other_position);
+
+        // Nothing is searched before the skill starts.
+        chara.update_target(&mut world, &mut prng);
+        assert_eq!(chara.target(), None);
+        assert_eq!(chara.hit_coords().target, None);
+
+        let mut launches = Vec::new();
+        chara.push_operation(&OpCommand::Attack1(OpAction::Press, Vector2::zeros()));
+        chara
+            .update_action(&cache, &mut world, &mut launches)
+            .unwrap();
+        chara.update_move(fi(1) / fi(20));
+        chara.sync_world(&mut world);
+        world.update(&[CollisionObjectType::Move]);
+        chara.update_target(&mut world, &mut prng);
+        assert_eq!(chara.target(), Some(ObjID::from(2)));
+        assert_eq!(chara.hit_coords().target, Some(other_position));
+
+        // The lock survives a snapshot round trip.
+        let mut writer = SnapshotWriter::new();
+        chara.save_snapshot(&mut writer);
+        let mut restored = LogicCharaHuman::from_res(chara.res().clone());
+        restored.load_snapshot(&mut writer.reader()).unwrap();
+        assert_eq!(restored.target(), Some(ObjID::from(2)));
+
+        world.remove(&[other.handle]);
+        world.update(&[CollisionObjectType::Move]);
+        chara.update_target(&mut world, &mut prng);
+        assert_eq!(chara.target(), None);
+        assert_eq!(chara.hit_coords().target, None);
+    }
+}
@@ -0,0 +1,305 @@
+use super::logic_data::{DataPool, LogicLifecycle, LogicState, LogicStateStatic};
+use crate::id::{ClassID, ObjID};
+use crate::utils::FnvHasher;
+use anyhow::Result;
+use collide::pipeline::CollisionWorld;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hasher;
+
+// Checksum of a tick, over all states of the pool and the collision object positions.
+// Identical on every platform as long as the simulation is identical.
+pub fn logic_checksum<T: 'static>(pool: &DataPool, world: &CollisionWorld<T>) -> u64 {
+    let mut hasher = FnvHasher::new();
+    pool.hash_states(&mut hasher);
+    // Slab iteration follows the handles, not the insertion order.
+    for (handle, co) in world.collision_objects() {
+        let position = co.position();
+        hasher.write_u64(handle.0 as u64);
+        for value in position.translation.vector.iter() {
+            hasher.write_i64(value.to_bits());
+        }
+        for value in position.rotation.coords.iter() {
+            hasher.write_i64(value.to_bits());
+        }
+    }
+    return hasher.finish();
+}
+
+// A LogicState decoded by its concrete type, for desync reports.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateDump {
+    pub obj_id: ObjID,
+    pub class_id: ClassID,
+    pub lifecycle: LogicLifecycle,
+    pub value: Value,
+}
+
+type StateValueFn = fn(*const LogicState<()>) -> serde_json::Result<Value>;
+
+fn state_value<S: Serialize>(state: *const LogicState<()>) -> serde_json::Result<Value> {
+    let state = unsafe { &*(state as *const LogicState<S>) };
+    return serde_json::to_value(state.state());
+}
+
+// Dumps the states of a pool as JSON, only once the checksums of the peers differ.
+// States of unregistered classes are dumped as null.
+#[derive(Debug, Default)]
+pub struct StateDumper {
+    value_fns: HashMap<ClassID, StateValueFn>,
+}
+
+impl StateDumper {
+    pub fn new() -> StateDumper {
+        return StateDumper::default();
+    }
+
+    pub fn register<S>(&mut self)
+    where
+        S: LogicStateStatic + Serialize + 'static,
+    {
+        self.value_fns.insert(S::id(), state_value::<S>);
+    }
+
+    // Dumps all states ordered by ObjID then ClassID, independent of the order objects were updated in.
+    pub fn dump(&self, pool: &DataPool) -> Result<Vec<StateDump>> {
+        let mut dumps = Vec::with_capacity(pool.state_count());
+        for state in pool.state_ptrs() {
+            let header = unsafe { &**state };
+            let value = match self.value_fns.get(&header.class_id()) {
+                Some(value_fn) => value_fn(*state)?,
+                None => Value::Null,
+            };
+            dumps.push(StateDump {
+                obj_id: header.obj_id(),
+                class_id: header.class_id(),
+                lifecycle: header.lifecycle(),
+                value,
+            });
+        }
+        dumps.sort_by_key(dump_key);
+        return Ok(dumps);
+    }
+}
+
+// First divergent value between the state dumps of two peers at the same tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesyncReport {
+    pub obj_id: ObjID,
+    pub class_id: ClassID,
+    // Dotted path of the field, empty when a whole state is missing on one side.
+    pub path: String,
+    pub local: Option<Value>,
+    pub remote: Option<Value>,
+}
+
+impl fmt::Display for DesyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "<none>".to_string(),
+        };
+        return write!(
+            f,
+            "Desync {:?} {:?} '{}': local {} remote {}",
+            self.obj_id,
+            self.class_id,
+            self.path,
+            show(&self.local),
+            show(&self.remote)
+        );
+    }
+}
+
+impl DesyncReport {
+    // Both dumps must be sorted as StateDumper::dump() does.
+    pub fn diff(local: &[StateDump], remote: &[StateDump]) -> Option<DesyncReport> {
+        let (mut li, mut ri) = (0, 0);
+        while li < local.len() || ri < remote.len() {
+            let order = match (local.get(li), remote.get(ri)) {
+                (Some(l), Some(r)) => dump_key(l).cmp(&dump_key(r)),
+                (Some(_), None) => Ordering::Less,
+                _ => Ordering::Greater,
+            };
+            match order {
+                Ordering::Less => {
+                    return Some(DesyncReport::missing(&local[li], true));
+                }
+                Ordering::Greater => {
+                    return Some(DesyncReport::missing(&remote[ri], false));
+                }
+                Ordering::Equal => {
+                    if let Some(report) = DesyncReport::diff_dump(&local[li], &remote[ri]) {
+                        return Some(report);
+                    }
+                    li += 1;
+                    ri += 1;
+                }
+            };
+        }
+        return None;
+    }
+
+    fn missing(dump: &StateDump, is_local: bool) -> DesyncReport {
+        let value = Some(dump.value.clone());
+        return DesyncReport {
+            obj_id: dump.obj_id,
+            class_id: dump.class_id,
+            path: String::new(),
+            local: if is_local { value.clone() } else { None },
+            remote: if is_local { None } else { value },
+        };
+    }
+
+    fn diff_dump(local: &StateDump, remote: &StateDump) -> Option<DesyncReport> {
+        let report = |path: String, l: &Value, r: &Value| DesyncReport {
+            obj_id: local.obj_id,
+            class_id: local.class_id,
+            path,
+            local: Some(l.clone()),
+            remote: Some(r.clone()),
+        };
+        if local.lifecycle != remote.lifecycle {
+            let l = serde_json::to_value(local.lifecycle).unwrap_or(Value::Null);
+            let r = serde_json::to_value(remote.lifecycle).unwrap_or(Value::Null);
+            return Some(report("lifecycle".to_string(), &l, &r));
+        }
+        let (path, l, r) = diff_value(String::new(), &local.value, &remote.value)?;
+        return Some(report(path, l, r));
+    }
+}
+
+static NULL: Value = Value::Null;
+
+fn dump_key(dump: &StateDump) -> (u64, u16) {
+    return (u64::from(dump.obj_id), dump.class_id as u16);
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    return if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    };
+}
+
+fn diff_value<'t>(
+    path: String,
+    local: &'t Value,
+    remote: &'t Value,
+) -> Option<(String, &'t Value, &'t Value)> {
+    match (local, remote) {
+        (Value::Object(l), Value::Object(r)) => {
+            for (key, lv) in l.iter() {
+                match r.get(key) {
+                    Some(rv) => {
+                        if let Some(diff) = diff_value(join_path(&path, key), lv, rv) {
+                            return Some(diff);
+                        }
+                    }
+                    None => return Some((join_path(&path, key), lv, &NULL)),
+                }
+            }
+            for (key, rv) in r.iter() {
+                if !l.contains_key(key) {
+                    return Some((join_path(&path, key), &NULL, rv));
+                }
+            }
+            return None;
+        }
+        (Value::Array(l), Value::Array(r)) if l.len() == r.len() => {
+            for (idx, (lv, rv)) in l.iter().zip(r.iter()).enumerate() {
+                if let Some(diff) = diff_value(join_path(&path, &idx.to_string()), lv, rv) {
+                    return Some(diff);
+                }
+            }
+            return None;
+        }
+        _ if local != remote => return Some((path, local, remote)),
+        _ => return None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{LogicLifecycle, StateProjectile};
+    use crate::ffi::FFIVec3f;
+    use crate::id::FastResID;
+
+    fn new_pool(ids: &[u64], y: f32) -> DataPool {
+        let mut pool = DataPool::new(1024 * 4);
+        for id in ids {
+            pool.state(
+                ObjID::from(*id),
+                LogicLifecycle::Running,
+                StateProjectile {
+                    source: ObjID::from(1),
+                    fres_id: FastResID::from(7),
+                    frame: 3,
+                    target: ObjID::invalid(),
+                    position: FFIVec3f::new(1.0, y, 2.0),
+                    rotation: Default::default(),
+                },
+            )
+            .unwrap();
+        }
+        return pool;
+    }
+
+    fn dumper() -> StateDumper {
+        let mut dumper = StateDumper::new();
+        dumper.register::<StateProjectile>();
+        return dumper;
+    }
+
+    #[test]
+    fn test_logic_checksum() {
+        let world: CollisionWorld<u64> = CollisionWorld::new(math::fi(0));
+        let pool1 = new_pool(&[10, 11, 12], 0.5);
+        let pool2 = new_pool(&[12, 10, 11], 0.5);
+        let pool3 = new_pool(&[10, 11, 12], 0.25);
+        let pool4 = new_pool(&[10, 11, 12], -0.5);
+        let checksum = logic_checksum(&pool1, &world);
+        assert_eq!(logic_checksum(&pool2, &world), checksum);
+        assert_ne!(logic_checksum(&pool3, &world), checksum);
+        assert_ne!(logic_checksum(&pool4, &world), checksum);
+    }
+
+    #[test]
+    fn test_state_dumper() {
+        let dumps = dumper().dump(&new_pool(&[12, 10, 11], 0.5)).unwrap();
+        let ids: Vec<_> = dumps.iter().map(|dump| dump.obj_id).collect();
+        assert_eq!(ids, vec![ObjID::from(10), ObjID::from(11), ObjID::from(12)]);
+        assert_eq!(dumps[0].value["frame"], Value::from(3));
+        assert_eq!(dumps[0].value["position"]["y"], Value::from(0.5));
+
+        let dumps = StateDumper::new().dump(&new_pool(&[10], 0.5)).unwrap();
+        assert_eq!(dumps[0].value, Value::Null);
+    }
+
+    #[test]
+    fn test_desync_report() {
+        let dumper = dumper();
+        let dumps1 = dumper.dump(&new_pool(&[10, 11, 12], 0.5)).unwrap();
+        let dumps2 = dumper.dump(&new_pool(&[12, 10, 11], 0.5)).unwrap();
+        assert_eq!(DesyncReport::diff(&dumps1, &dumps2), None);
+
+        let mut dumps3 = dumper.dump(&new_pool(&[10, 11, 12], 0.5)).unwrap();
+        dumps3[1].value["position"]["y"] = Value::from(0.75);
+        let report = DesyncReport::diff(&dumps1, &dumps3).unwrap();
+        assert_eq!(report.obj_id, ObjID::from(11));
+        assert_eq!(report.class_id, ClassID::Projectile);
+        assert_eq!(report.path, "position.y");
+        assert_eq!(report.remote, Some(Value::from(0.75)));
+
+        let dumps4 = dumper.dump(&new_pool(&[10, 12], 0.5)).unwrap();
+        let report = DesyncReport::diff(&dumps1, &dumps4).unwrap();
+        assert_eq!(report.obj_id, ObjID::from(11));
+        assert_eq!(report.path, "");
+        assert!(report.local.is_some() && report.remote.is_none());
+    }
+}
@@ -0,0 +1,428 @@
+use super::snapshot::{LogicSnapshot, SnapshotReader, SnapshotWriter};
+use crate::derive::{def_enum, script_ctx, script_var};
+use crate::id::ObjID;
+use crate::resource::{ResCharaHuman, ResDamage, ResHitArea};
+use crate::script::{ScriptByteCode, ScriptCompiler, ScriptExecutor};
+use anyhow::Result;
+use math::{fi, fx_bool, Fx};
+use na::ComplexField;
+use serde::{Deserialize, Serialize};
+
+// Default formula, each damage type is scaled by attack / defense of the matching element.
+pub const DEFAULT_DAMAGE_SCRIPT: &str = "
+    out.health = dmg.health
+    out.health += dmg.physical * atk.physical / max(def.physical, 1)
+    out.health += dmg.elemental * atk.elemental / max(def.elemental, 1)
+    out.health += dmg.arcane * atk.arcane / max(def.arcane, 1)
+    out.health = max(out.health, 0) * dmg.scale
+    out.energy = dmg.energy * dmg.scale
+    out.posture = dmg.posture * dmg.scale
+    if def.guarding {
+        out.health = out.health * 0.2
+        out.posture = out.posture * 1.5
+    }
+";
+
+#[script_var(prefix = "atk")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VarAttacker {
+    pub physical: Fx,
+    pub elemental: Fx,
+    pub arcane: Fx,
+}
+
+#[script_var(prefix = "def")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VarDefender {
+    pub physical: Fx,
+    pub elemental: Fx,
+    pub arcane: Fx,
+    pub health: Fx,
+    pub posture: Fx,
+    pub max_health: Fx,
+    pub max_posture: Fx,
+    pub guarding: Fx,
+}
+
+#[script_var(prefix = "dmg")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VarDamageIn {
+    pub health: Fx,
+    pub energy: Fx,
+    pub posture: Fx,
+    pub physical: Fx,
+    pub elemental: Fx,
+    pub arcane: Fx,
+    pub scale: Fx,
+}
+
+#[script_var(prefix = "out")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VarDamageOut {
+    pub health: Fx,
+    pub energy: Fx,
+    pub posture: Fx,
+}
+
+#[script_ctx]
+#[derive(Debug)]
+pub struct CtxDamage<'t> {
+    pub atk: &'t VarAttacker,
+    pub def: &'t VarDefender,
+    pub dmg: &'t VarDamageIn,
+    pub out: &'t mut VarDamageOut,
+}
+
+// Stat snapshot of a character, taken when a hit is resolved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CharaStats {
+    pub max_health: i32,
+    pub max_energy: i32,
+    pub max_posture: i32,
+    pub health: i32,
+    pub energy: i32,
+    pub posture: i32,
+    pub stagger_posture: i32,
+    pub physical_attack: i32,
+    pub physical_defense: i32,
+    pub elemental_attack: i32,
+    pub elemental_defense: i32,
+    pub arcane_attack: i32,
+    pub arcane_defense: i32,
+}
+
+impl CharaStats {
+    pub fn new(res: &ResCharaHuman) -> CharaStats {
+        return CharaStats {
+            max_health: res.max_health,
+            max_energy: res.max_energy,
+            max_posture: res.max_posture,
+            health: res.max_health,
+            energy: res.max_energy,
+            posture: res.max_posture,
+            stagger_posture: res.stagger_posture,
+            physical_attack: res.physical_attack,
+            physical_defense: res.physical_defense,
+            elemental_attack: res.elemental_attack,
+            elemental_defense: res.elemental_defense,
+            arcane_attack: res.arcane_attack,
+            arcane_defense: res.arcane_defense,
+        };
+    }
+
+    #[inline]
+    pub fn is_dead(&self) -> bool {
+        return self.health <= 0;
+    }
+
+    fn attacker_var(&self) -> VarAttacker {
+        return VarAttacker {
+            physical: fi(self.physical_attack as i64),
+            elemental: fi(self.elemental_attack as i64),
+            arcane: fi(self.arcane_attack as i64),
+        };
+    }
+
+    fn defender_var(&self, guarding: bool) -> VarDefender {
+        return VarDefender {
+            physical: fi(self.physical_defense as i64),
+            elemental: fi(self.elemental_defense as i64),
+            arcane: fi(self.arcane_defense as i64),
+            health: fi(self.health as i64),
+            posture: fi(self.posture as i64),
+            max_health: fi(self.max_health as i64),
+            max_posture: fi(self.max_posture as i64),
+            guarding: fx_bool(guarding),
+        };
+    }
+}
+
+impl LogicSnapshot for CharaStats {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        for value in &[
+            self.max_health,
+            self.max_energy,
+            self.max_posture,
+            self.health,
+            self.energy,
+            self.posture,
+            self.stagger_posture,
+            self.physical_attack,
+            self.physical_defense,
+            self.elemental_attack,
+            self.elemental_defense,
+            self.arcane_attack,
+            self.arcane_defense,
+        ] {
+            writer.write_i32(*value);
+        }
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        for value in &mut [
+            &mut self.max_health,
+            &mut self.max_energy,
+            &mut self.max_posture,
+            &mut self.health,
+            &mut self.energy,
+            &mut self.posture,
+            &mut self.stagger_posture,
+            &mut self.physical_attack,
+            &mut self.physical_defense,
+            &mut self.elemental_attack,
+            &mut self.elemental_defense,
+            &mut self.arcane_attack,
+            &mut self.arcane_defense,
+        ] {
+            **value = reader.read_i32()?;
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HitEvent {
+    pub source: ObjID,
+    pub target: ObjID,
+    pub damage: ResDamage,
+    pub scale: Fx,
+    pub hit_stun: u32,
+    pub knock_down: bool,
+    pub guarding: bool,
+}
+
+impl HitEvent {
+    pub fn new(source: ObjID, target: ObjID, area: &ResHitArea, damage: &ResDamage) -> HitEvent {
+        return HitEvent {
+            source,
+            target,
+            damage: damage.clone(),
+            scale: area.damage_scale,
+            hit_stun: area.hit_stun,
+            knock_down: area.knock_down,
+            guarding: false,
+        };
+    }
+}
+
+// Ordered by severity, a hit reports the strongest reaction it caused.
+#[def_enum]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum DamageReaction {
+    None,
+    Guard,
+    Stagger,
+    PostureBreak,
+    KnockDown,
+    Dead,
+}
+
+// Resolved damage of a hit, sent to the front end.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DamageEvent {
+    pub source: ObjID,
+    pub target: ObjID,
+    pub health: i32,
+    pub energy: i32,
+    pub posture: i32,
+    pub reaction: DamageReaction,
+    pub hit_stun: u32,
+}
+
+pub struct DamagePipeline {
+    formula: ScriptByteCode,
+    executor: ScriptExecutor,
+}
+
+impl DamagePipeline {
+    pub fn new(script: Option<&str>) -> Result<DamagePipeline> {
+        let mut compiler = ScriptCompiler::new();
+        let formula = compiler.run::<CtxDamage>(script.unwrap_or(DEFAULT_DAMAGE_SCRIPT))?;
+        return Ok(DamagePipeline {
+            formula,
+            executor: ScriptExecutor::new(),
+        });
+    }
+
+    // Runs the formula and applies its result on the defender, hits must be given in a deterministic order.
+    pub fn apply(
+        &mut self,
+        hit: &HitEvent,
+        attacker: &CharaStats,
+        defender: &mut CharaStats,
+    ) -> Result<DamageEvent> {
+        let atk = attacker.attacker_var();
+        let def = defender.defender_var(hit.guarding);
+        let dmg = VarDamageIn {
+            health: fi(hit.damage.health as i64),
+            energy: fi(hit.damage.energy as i64),
+            posture: fi(hit.damage.posture as i64),
+            physical: fi(hit.damage.physical as i64),
+            elemental: fi(hit.damage.elemental as i64),
+            arcane: fi(hit.damage.arcane as i64),
+            scale: hit.scale,
+        };
+        let mut out = VarDamageOut::default();
+        self.executor.run(
+            &self.formula,
+            CtxDamage {
+                atk: &atk,
+                def: &def,
+                dmg: &dmg,
+                out: &mut out,
+            },
+        )?;
+
+        let health = consume(out.health, defender.health);
+        let energy = consume(out.energy, defender.energy);
+        let posture = consume(out.posture, defender.posture);
+        defender.health -= health;
+        defender.energy -= energy;
+        defender.posture -= posture;
+
+        let reaction = if defender.is_dead() {
+            DamageReaction::Dead
+        } else if hit.knock_down {
+            DamageReaction::KnockDown
+        } else if posture > 0 && defender.posture <= 0 {
+            DamageReaction::PostureBreak
+        } else if hit.guarding {
+            DamageReaction::Guard
+        } else if defender.stagger_posture > 0 && posture >= defender.stagger_posture {
+            DamageReaction::Stagger
+        } else {
+            DamageReaction::None
+        };
+
+        // A broken posture is restored once the break is reported.
+        if reaction == DamageReaction::PostureBreak {
+            defender.posture = defender.max_posture;
+        }
+        let hit_stun = match reaction {
+            DamageReaction::None | DamageReaction::Guard | DamageReaction::Dead => 0,
+            _ => hit.hit_stun,
+        };
+
+        return Ok(DamageEvent {
+            source: hit.source,
+            target: hit.target,
+            health,
+            energy,
+            posture,
+            reaction,
+            hit_stun,
+        });
+    }
+}
+
+// Rounds a formula output to an amount that does not exceed the remaining value.
+fn consume(value: Fx, remain: i32) -> i32 {
+    return value.round().to_i32().max(0).min(remain.max(0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_stats() -> CharaStats {
+        return CharaStats {
+            max_health: 1000,
+            max_energy: 100,
+            max_posture: 100,
+            health: 1000,
+            energy: 100,
+            posture: 100,
+            stagger_posture: 30,
+            physical_attack: 200,
+            physical_defense: 100,
+            elemental_attack: 100,
+            elemental_defense: 200,
+            arcane_attack: 100,
+            arcane_defense: 100,
+        };
+    }
+
+    fn new_hit(posture: i32) -> HitEvent {
+        return HitEvent {
+            source: ObjID::from(1),
+            target: ObjID::from(2),
+            damage: ResDamage {
+                health: 10,
+                energy: 5,
+                posture,
+                physical: 50,
+                elemental: 40,
+                arcane: 0,
+            },
+            scale: fi(1),
+            hit_stun: 12,
+            knock_down: false,
+            guarding: false,
+        };
+    }
+
+    #[test]
+    fn test_damage_pipeline_formula() {
+        let mut pipeline = DamagePipeline::new(None).unwrap();
+        let attacker = new_stats();
+        let mut defender = new_stats();
+
+        let event = pipeline
+            .apply(&new_hit(10), &attacker, &mut defender)
+            .unwrap();
+        assert_eq!(event.health, 10 + 100 + 20);
+        assert_eq!(event.energy, 5);
+        assert_eq!(event.posture, 10);
+        assert_eq!(event.reaction, DamageReaction::None);
+        assert_eq!(event.hit_stun, 0);
+        assert_eq!(defender.health, 1000 - 130);
+        assert_eq!(defender.posture, 90);
+
+        let mut hit = new_hit(10);
+        hit.guarding = true;
+        let event = pipeline.apply(&hit, &attacker, &mut defender).unwrap();
+        assert_eq!(event.health, 26);
+        assert_eq!(event.posture, 15);
+        assert_eq!(event.reaction, DamageReaction::Guard);
+    }
+
+    #[test]
+    fn test_damage_pipeline_reaction() {
+        let mut pipeline = DamagePipeline::new(None).unwrap();
+        let attacker = new_stats();
+        let mut defender = new_stats();
+
+        let event = pipeline
+            .apply(&new_hit(40), &attacker, &mut defender)
+            .unwrap();
+        assert_eq!(event.reaction, DamageReaction::Stagger);
+        assert_eq!(event.hit_stun, 12);
+
+        let event = pipeline
+            .apply(&new_hit(80), &attacker, &mut defender)
+            .unwrap();
+        assert_eq!(event.posture, 60);
+        assert_eq!(event.reaction, DamageReaction::PostureBreak);
+        assert_eq!(defender.posture, 100);
+
+        defender.health = 50;
+        let event = pipeline
+            .apply(&new_hit(0), &attacker, &mut defender)
+            .unwrap();
+        assert_eq!(event.health, 50);
+        assert_eq!(event.reaction, DamageReaction::Dead);
+    }
+
+    #[test]
+    fn test_damage_pipeline_custom_script() {
+        let mut pipeline = DamagePipeline::new(Some("out.health = def.max_health / 4")).unwrap();
+        let mut defender = new_stats();
+        let event = pipeline
+            .apply(&new_hit(0), &new_stats(), &mut defender)
+            .unwrap();
+        assert_eq!(event.health, 250);
+        assert!(DamagePipeline::new(Some("out.health = unknown.value")).is_err());
+        assert!(DamagePipeline::new(Some("atk.physical = 1")).is_err());
+    }
+}
@@ -0,0 +1,781 @@
+use super::chara::{HitTarget, LogicCharaHuman};
+use super::checksum::logic_checksum;
+use super::damage::{CharaStats, DamagePipeline, HitEvent};
+use super::logic_data::DataPool;
+use super::logic_obj::{LogicObj, LogicObjSuper};
+use super::operation::Operation;
+use super::projectile::LogicProjectile;
+use super::snapshot::{LogicSnapshot, SnapshotReader, SnapshotWriter};
+use crate::id::{ObjID, ObjIDGener, ResID};
+use crate::resource::{ResBuff, ResCache, ResCharaHuman, ResPrefab, ResPrefabArgs, ResProjectile};
+use crate::utils::Prng;
+use anyhow::{anyhow, Result};
+use collide::pipeline::{CollisionGroups, CollisionObjectType, CollisionWorld};
+use math::{fi, Fx};
+use na::{Isometry3, RealField, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use std::mem;
+use std::sync::Arc;
+
+const START_OBJ_ID: u64 = 100000;
+const POOL_CHUNK_SIZE: usize = 64 * 1024;
+// Pools of the interpolator and the pipeline in flight, more returned pools are freed.
+const MAX_FREE_POOLS: usize = 4;
+const SPAWN_SPACING: i64 = 2;
+
+const GROUPS: CollisionGroups = CollisionGroups {
+    team_membership: 1,
+    team_whitelist: 0xFFFF,
+    role_membership: 1,
+    role_whitelist: 0xFFFF,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CmdNewPrefab {
+    pub res_id: ResID,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CmdNewCharaHuman {
+    pub res_id: ResID,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Command {
+    NewPrefab(CmdNewPrefab),
+    NewCharaHuman(CmdNewCharaHuman),
+    Operation(Operation),
+}
+
+// Runs the logic objects tick by tick: operations, action graphs, movement, hits and damage.
+// Collision data of the world is the ObjID of the owner.
+pub struct LogicEngine {
+    res_cache: Arc<ResCache>,
+    frame_time: Fx,
+    frame: u32,
+    id_gener: ObjIDGener,
+    prng: Prng,
+    world: CollisionWorld<u64>,
+    damage: DamagePipeline,
+    charas: Vec<LogicCharaHuman>,
+    projectiles: Vec<LogicProjectile>,
+    operations: Vec<Operation>,
+    targets: Vec<HitTarget>,
+    hits: Vec<HitEvent>,
+    launches: Vec<Arc<ResProjectile>>,
+    free_pools: Vec<Box<DataPool>>,
+}
+
+impl LogicEngine {
+    pub fn new(res_cache: Arc<ResCache>, fps: Fx) -> Result<LogicEngine> {
+        if fps <= fi(0) {
+            return Err(anyhow!("Invalid fps {}", fps));
+        }
+        return Ok(LogicEngine {
+            res_cache,
+            frame_time: fi(1) / fps,
+            frame: 0,
+            id_gener: ObjIDGener::new(START_OBJ_ID),
+            prng: Prng::new(0),
+            world: CollisionWorld::new(fi(0)),
+            damage: DamagePipeline::new(None)?,
+            charas: Vec::new(),
+            projectiles: Vec::new(),
+            operations: Vec::new(),
+            targets: Vec::new(),
+            hits: Vec::new(),
+            launches: Vec::new(),
+            free_pools: Vec::new(),
+        });
+    }
+
+    #[inline]
+    pub fn res_cache(&self) -> &Arc<ResCache> {
+        return &self.res_cache;
+    }
+
+    // Ticks run so far.
+    #[inline]
+    pub fn frame(&self) -> u32 {
+        return self.frame;
+    }
+
+    #[inline]
+    pub fn charas(&self) -> &[LogicCharaHuman] {
+        return &self.charas;
+    }
+
+    #[inline]
+    pub fn projectiles(&self) -> &[LogicProjectile] {
+        return &self.projectiles;
+    }
+
+    #[inline]
+    pub fn world(&self) -> &CollisionWorld<u64> {
+        return &self.world;
+    }
+
+    pub fn find_chara(&self, obj_id: ObjID) -> Option<&LogicCharaHuman> {
+        return self.charas.iter().find(|chara| chara.obj_id() == obj_id);
+    }
+
+    // Checksum of the tick that produced pool, run before the next tick moves the world.
+    pub fn checksum(&self, pool: &DataPool) -> u64 {
+        return logic_checksum(pool, &self.world);
+    }
+
+    // Takes back a pool returned by run_tick(), the next ticks fill it again instead of allocating.
+    pub fn recycle_pool(&mut self, mut pool: Box<DataPool>) {
+        if self.free_pools.len() < MAX_FREE_POOLS {
+            pool.reset();
+            self.free_pools.push(pool);
+        }
+    }
+
+    // Swaps the cache after a hot reload, logic objects re-fetch the changed resources.
+    pub fn update_res_cache(&mut self, res_cache: Arc<ResCache>, changed: &[ResID]) -> Result<()> {
+        for chara in &mut self.charas {
+            chara.refresh_res(&res_cache, changed)?;
+            chara.refresh_hits(&res_cache, changed, &mut self.world)?;
+        }
+        for projectile in &mut self.projectiles {
+            projectile.refresh_res(&res_cache, changed)?;
+        }
+        self.res_cache = res_cache;
+        return Ok(());
+    }
+
+    // Spawning commands run at once, operations are queued for the next tick.
+    pub fn run_command(&mut self, cmd: &Command) -> Result<()> {
+        match cmd {
+            Command::NewPrefab(cmd) => self.new_prefab(&cmd.res_id)?,
+            Command::NewCharaHuman(cmd) => {
+                self.new_chara_human(&cmd.res_id)?;
+            }
+            Command::Operation(op) => self.operations.push(op.clone()),
+        };
+        return Ok(());
+    }
+
+    // Characters of a prefab face the middle of their line. Stages are not simulated.
+    fn new_prefab(&mut self, res_id: &ResID) -> Result<()> {
+        let prefab = self
+            .res_cache
+            .find_res_by_id(res_id)?
+            .cast_as::<ResPrefab>()?;
+        for item in &prefab.items {
+            if let ResPrefabArgs::CharaHuman(_) = item.args {
+                self.new_chara_human(&item.res_id)?;
+            }
+        }
+        self.face_center();
+        return Ok(());
+    }
+
+    // Characters are lined up along +x in spawn order.
+    fn new_chara_human(&mut self, res_id: &ResID) -> Result<ObjID> {
+        let res = self
+            .res_cache
+            .find_res_by_id(res_id)?
+            .cast_as::<ResCharaHuman>()?;
+        let obj_id = self.id_gener.gen();
+        let x = fi(self.charas.len() as i64 * SPAWN_SPACING);
+        let chara = LogicCharaHuman::new(
+            obj_id,
+            res,
+            &self.res_cache,
+            Isometry3::translation(x, fi(0), fi(0)),
+            GROUPS,
+            &mut self.world,
+        )?;
+        self.charas.push(chara);
+        self.world.update(&[CollisionObjectType::Move]);
+        return Ok(obj_id);
+    }
+
+    fn face_center(&mut self) {
+        let count = self.charas.len() as i64;
+        let center = fi((count - 1) * SPAWN_SPACING) / fi(2);
+        for chara in &mut self.charas {
+            let angle = if chara.position().translation.x <= center {
+                Fx::frac_pi_2()
+            } else {
+                -Fx::frac_pi_2()
+            };
+            chara.set_rotation(UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle));
+            chara.sync_world(&mut self.world);
+        }
+        self.world.update(&[CollisionObjectType::Move]);
+    }
+
+    // Operations are validated before any is applied. An unknown object drops the operations
+    // of the tick and leaves the objects untouched.
+    pub fn run_tick(&mut self) -> Result<Box<DataPool>> {
+        let operations = mem::take(&mut self.operations);
+        if let Some(op) = operations
+            .iter()
+            .find(|op| self.find_chara(op.obj_id).is_none())
+        {
+            return Err(anyhow!("Operation on unknown object {:?}", op.obj_id));
+        }
+        for op in operations {
+            if let Some(chara) = self
+                .charas
+                .iter_mut()
+                .find(|chara| chara.obj_id() == op.obj_id)
+            {
+                chara.push_operation(&op.command);
+            }
+        }
+
+        for idx in 0..self.charas.len() {
+            self.charas[idx].update_buffs()?;
+            self.charas[idx].update_action(&self.res_cache, &mut self.world, &mut self.launches)?;
+            for res in mem::take(&mut self.launches) {
+                let chara = &self.charas[idx];
+                let obj_id = self.id_gener.gen();
+                let projectile = LogicProjectile::new(
+                    obj_id,
+                    chara.obj_id(),
+                    res,
+                    &chara.hit_coords(),
+                    GROUPS,
+                    &mut self.world,
+                    u64::from(obj_id),
+                )?;
+                self.projectiles.push(projectile);
+            }
+            self.charas[idx].update_move(self.frame_time);
+            self.charas[idx].sync_world(&mut self.world);
+        }
+        self.world.update(&[CollisionObjectType::Move]);
+
+        // Characters lock their targets and hit in spawn order, then projectiles in launch order.
+        for idx in 0..self.charas.len() {
+            self.targets.clear();
+            self.charas[idx].update_target(&mut self.world, &mut self.prng);
+            self.charas[idx].sweep_hits(&mut self.world, &mut self.targets);
+            for pos in 0..self.targets.len() {
+                let target = self.targets[pos];
+                if self
+                    .find_chara(target.target)
+                    .map_or(true, |chara| chara.is_dead())
+                {
+                    continue;
+                }
+                if let Some(hit) = self.charas[idx].accept_hit(&target) {
+                    let skill = self.charas[idx].skill().cloned();
+                    let buffs = skill.as_ref().map(|skill| &skill.effect.target_buffs[..]);
+                    self.apply_hit(&hit, buffs.unwrap_or(&[]))?;
+                }
+            }
+            self.charas[idx].finish_hits(&mut self.world);
+        }
+        // Collision data that is not a character is an obstacle.
+        let charas = &self.charas;
+        for projectile in &mut self.projectiles {
+            projectile.update(
+                &mut self.world,
+                self.frame_time,
+                &mut self.prng,
+                |data| {
+                    return charas
+                        .iter()
+                        .find(|chara| u64::from(chara.obj_id()) == *data)
+                        .map(|chara| (chara.obj_id(), chara.is_guarding()));
+                },
+                &mut self.hits,
+            );
+        }
+        for hit in mem::take(&mut self.hits) {
+            self.apply_hit(&hit, &[])?;
+        }
+
+        let mut pool = match self.free_pools.pop() {
+            Some(pool) => pool,
+            None => Box::new(DataPool::new(POOL_CHUNK_SIZE)),
+        };
+        for chara in &mut self.charas {
+            chara.update_state(&mut pool)?;
+        }
+        for projectile in &mut self.projectiles {
+            projectile.update_state(&mut pool)?;
+        }
+        // Destroyed projectiles wrote their last state.
+        self.projectiles
+            .retain(|projectile| !projectile.is_destroyed());
+        self.frame += 1;
+        return Ok(pool);
+    }
+
+    // Hits on dead or despawned characters are dropped, buffs are added unless the hit kills.
+    fn apply_hit(&mut self, hit: &HitEvent, buffs: &[ResID]) -> Result<()> {
+        let attacker = match self.find_chara(hit.source) {
+            Some(chara) => *chara.stats(),
+            None => CharaStats::default(),
+        };
+        let defender = match self
+            .charas
+            .iter_mut()
+            .find(|chara| chara.obj_id() == hit.target)
+        {
+            Some(chara) if !chara.is_dead() => chara,
+            _ => return Ok(()),
+        };
+        defender.take_hit(&mut self.damage, hit, &attacker)?;
+        if defender.is_dead() {
+            return Ok(());
+        }
+        for buff in buffs {
+            let res = self.res_cache.find_res_by_id(buff)?.cast_as::<ResBuff>()?;
+            defender.add_buff(res, hit.source);
+        }
+        return Ok(());
+    }
+}
+
+// Everything a tick depends on, saved between ticks. Resources are kept as references,
+// so a rollback after a hot reload resumes on the resources the snapshot was taken with.
+impl LogicSnapshot for LogicEngine {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_u32(self.frame);
+        self.id_gener.save_snapshot(writer);
+        self.prng.save_snapshot(writer);
+        self.operations.save_snapshot(writer);
+        writer.write_u32(self.charas.len() as u32);
+        for chara in &self.charas {
+            writer.write_ref(chara.res());
+            chara.save_snapshot(writer);
+        }
+        writer.write_u32(self.projectiles.len() as u32);
+        for projectile in &self.projectiles {
+            writer.write_ref(projectile.res());
+            projectile.save_snapshot(writer);
+        }
+        self.world.save_snapshot(writer);
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        self.frame = reader.read_u32()?;
+        self.id_gener.load_snapshot(reader)?;
+        self.prng.load_snapshot(reader)?;
+        self.operations.load_snapshot(reader)?;
+        self.charas.clear();
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            let mut chara = LogicCharaHuman::from_res(reader.read_ref::<ResCharaHuman>()?);
+            chara.load_snapshot(reader)?;
+            self.charas.push(chara);
+        }
+        self.projectiles.clear();
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            let mut projectile = LogicProjectile::from_res(reader.read_ref::<ResProjectile>()?)?;
+            projectile.load_snapshot(reader)?;
+            self.projectiles.push(projectile);
+        }
+        self.world.load_snapshot(reader)?;
+        self.targets.clear();
+        self.hits.clear();
+        self.launches.clear();
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{
+        EventActionStarted, EventBuffApplied, EventDamage, EventDead, LogicLifecycle, OpAction,
+        OpCommand, StateCharaHuman,
+    };
+    use crate::resource::test_res::{restore_res, write_res};
+    use crate::resource::ResActionAny;
+    use collide::shape::Shape;
+    use na::Vector2;
+    use std::ops::Range;
+
+    const RESOURCE: &'static str = r#"- type: CharaHuman
+  res_id: Chara.Test
+  collision: { type: Capsule, half_height: 0.5, radius: 0.5 }
+  max_health: 100
+  max_energy: 100
+  max_posture: 100
+  move_speed: 4
+  physical_attack: 10
+  physical_defense: 10
+  elemental_attack: 10
+  elemental_defense: 10
+  arcane_attack: 10
+  arcane_defense: 10
+  action: Action.Test
+- type: Action
+  res_id: Action.Test
+  actions:
+  - type: Idle
+    name: idle
+    transitions:
+    - { to: run, trigger: Move }
+    - { to: attack, trigger: Attack1 }
+  - type: Run
+    name: run
+    move_speed: 4
+    transitions:
+    - { to: idle, trigger: Idle }
+  - type: Attack
+    name: attack
+    frames: 5
+    skill: Skill.Punch
+- type: Skill
+  res_id: Skill.Punch
+  frames: 5
+  shape: { type: Ball, radius: 1 }
+  center: [0, 0, 0]
+  origin:
+    rotation: [0, 0, 0]
+    translation: [0, 0, 0]
+  origin_coord: Source
+  motion_coord: Source
+  hits: [Hit.Punch]
+  effect:
+    target_damage: { health: 60, energy: 0, posture: 0, physical: 0, elemental: 0, arcane: 0 }
+    target_buffs: [Buff.Weak]
+- type: Buff
+  res_id: Buff.Weak
+  duration: 3
+  modifiers:
+  - { stat: MaxEnergy, operator: Add, value: -50 }
+- type: HitAttachment
+  res_id: Hit.Punch
+  areas:
+  - shape:
+      type: Ball
+      radius: 1
+      transform: { rotation: [0, 0, 0], translation: [0, 0, 1] }
+    coordinate: Source
+    start_frame: 1
+    finish_frame: 3
+"#;
+
+    fn new_engine(name: &str) -> (LogicEngine, Vec<ObjID>) {
+        let dir = write_res(name, RESOURCE);
+        let mut engine = LogicEngine::new(restore_res(&dir), fi(20)).unwrap();
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            ids.push(engine.new_chara_human(&ResID::from("Chara.Test")).unwrap());
+        }
+        engine.face_center();
+        return (engine, ids);
+    }
+
+    fn operation(obj_id: ObjID, command: OpCommand) -> Command {
+        return Command::Operation(Operation { obj_id, command });
+    }
+
+    fn attack(obj_id: ObjID) -> Command {
+        return operation(
+            obj_id,
+            OpCommand::Attack1(OpAction::Press, Vector2::zeros()),
+        );
+    }
+
+    #[test]
+    fn test_engine_action() {
+        let (mut engine, ids) = new_engine("engine_action");
+        engine
+            .run_command(&operation(
+                ids[1],
+                OpCommand::Move(Vector2::new(fi(1), fi(0))),
+            ))
+            .unwrap();
+        engine.run_command(&attack(ids[0])).unwrap();
+        let pool = engine.run_tick().unwrap();
+
+        let state = pool.find_state::<StateCharaHuman>(ids[0]).unwrap();
+        assert_eq!(state.state().action_idx, 2);
+        match engine.charas()[0].action() {
+            Some(ResActionAny::Attack(_)) => {}
+            action => panic!("unexpected action {:?}", action),
+        };
+        match engine.charas()[1].action() {
+            Some(ResActionAny::Run(_)) => {}
+            action => panic!("unexpected action {:?}", action),
+        };
+        assert!(engine.charas()[1].position().translation.x > fi(2));
+    }
+
+    #[test]
+    fn test_engine_recycle_pool() {
+        let (mut engine, ids) = new_engine("engine_recycle_pool");
+        let pool = engine.run_tick().unwrap();
+        let ptr = &*pool as *const DataPool;
+        engine.recycle_pool(pool);
+
+        let pool = engine.run_tick().unwrap();
+        assert_eq!(&*pool as *const DataPool, ptr);
+        assert_eq!(pool.stats().resets, 1);
+        assert_eq!(pool.state_count(), 2);
+        let state = pool.find_state::<StateCharaHuman>(ids[0]).unwrap();
+        assert_eq!(state.lifecycle(), LogicLifecycle::Running);
+
+        // A pool still held elsewhere, the engine allocates a new one.
+        let other = engine.run_tick().unwrap();
+        assert_ne!(&*other as *const DataPool, ptr);
+    }
+
+    #[test]
+    fn test_engine_unknown_operation() {
+        let (mut engine, ids) = new_engine("engine_unknown_operation");
+        engine.run_command(&attack(ids[0])).unwrap();
+        engine.run_command(&attack(ObjID::from(1))).unwrap();
+        assert!(engine.run_tick().is_err());
+        assert_eq!(engine.frame(), 0);
+        assert!(engine.charas()[0].skill().is_none());
+
+        engine.run_tick().unwrap();
+        assert_eq!(engine.frame(), 1);
+        match engine.charas()[0].action() {
+            Some(ResActionAny::Idle(_)) => {}
+            action => panic!("unexpected action {:?}", action),
+        };
+    }
+
+    #[test]
+    fn test_engine_damage() {
+        let (mut engine, ids) = new_engine("engine_damage");
+        let mut dead_tick = None;
+        for tick in 0..30 {
+            if tick % 10 == 0 {
+                engine.run_command(&attack(ids[0])).unwrap();
+            }
+            let pool = engine.run_tick().unwrap();
+            let state = pool.find_state::<StateCharaHuman>(ids[1]).unwrap();
+            if tick == 5 {
+                assert_eq!(state.state().health, 40);
+            }
+            if state.state().health == 0 && dead_tick.is_none() {
+                dead_tick = Some(tick);
+            }
+        }
+        assert_eq!(dead_tick, Some(10));
+        assert!(engine.charas()[1].is_dead());
+        assert!(!engine.charas()[0].is_dead());
+    }
+
+    #[test]
+    fn test_engine_buff() {
+        let (mut engine, ids) = new_engine("engine_buff");
+        engine.run_command(&attack(ids[0])).unwrap();
+        let weak = engine
+            .res_cache()
+            .find_res_by_id(&ResID::from("Buff.Weak"))
+            .unwrap()
+            .fres_id();
+
+        let mut buff_counts = Vec::new();
+        for _ in 0..4 {
+            let pool = engine.run_tick().unwrap();
+            let state = pool.find_state::<StateCharaHuman>(ids[1]).unwrap().state();
+            buff_counts.push(state.buff_count);
+            assert_eq!(state.energy, 50);
+            if state.buff_count == 1 {
+                assert_eq!(state.buffs[0].fres_id, weak);
+                assert_eq!(state.buffs[0].source, ids[0]);
+            }
+        }
+        assert_eq!(buff_counts, vec![1, 1, 1, 0]);
+        assert_eq!(engine.charas()[1].stats().max_energy, 100);
+        assert_eq!(engine.charas()[0].buffs().len(), 0);
+    }
+
+    #[test]
+    fn test_engine_hit_boxes() {
+        let (mut engine, ids) = new_engine("engine_hit_boxes");
+        engine.run_command(&attack(ids[0])).unwrap();
+        let mut counts = Vec::new();
+        for _ in 0..5 {
+            engine.run_tick().unwrap();
+            counts.push(engine.world().objects.len());
+        }
+        assert_eq!(counts, vec![3, 3, 2, 2, 2]);
+        assert!(engine.charas()[0].skill().is_some());
+    }
+
+    #[test]
+    fn test_engine_events() {
+        let (mut engine, ids) = new_engine("engine_events");
+        engine.run_command(&attack(ids[0])).unwrap();
+        let pool = engine.run_tick().unwrap();
+        let events: Vec<_> = pool.events().iter().collect();
+        assert_eq!(events.len(), 3);
+
+        assert_eq!(events[0].obj_id(), ids[0]);
+        let started = events[0].cast::<EventActionStarted>().unwrap();
+        assert_eq!(started.action_idx, 2);
+        let action = engine
+            .res_cache()
+            .find_res_by_id(&ResID::from("Action.Test"))
+            .unwrap();
+        assert_eq!(started.fres_id, action.fres_id());
+
+        assert_eq!(events[1].obj_id(), ids[1]);
+        let damage = events[1].cast::<EventDamage>().unwrap();
+        assert_eq!((damage.source, damage.target), (ids[0], ids[1]));
+        assert_eq!(damage.health, 60);
+
+        assert_eq!(events[2].obj_id(), ids[1]);
+        let buff = events[2].cast::<EventBuffApplied>().unwrap();
+        assert_eq!((buff.source, buff.stacks), (ids[0], 1));
+
+        let mut dead = None;
+        for tick in 1..20 {
+            if tick == 10 {
+                engine.run_command(&attack(ids[0])).unwrap();
+            }
+            let pool = engine.run_tick().unwrap();
+            for event in pool.events().iter() {
+                if let Some(event) = event.cast::<EventDead>() {
+                    assert!(dead.is_none());
+                    dead = Some((tick, event.killer));
+                }
+            }
+        }
+        assert_eq!(dead, Some((10, ids[0])));
+    }
+
+    #[test]
+    fn test_engine_update_res_cache() {
+        let dir = write_res("engine_res_cache", RESOURCE);
+        let mut engine = LogicEngine::new(restore_res(&dir), fi(20)).unwrap();
+        let obj_id = engine.new_chara_human(&ResID::from("Chara.Test")).unwrap();
+        engine.run_command(&attack(obj_id)).unwrap();
+        engine.run_tick().unwrap();
+        engine.run_tick().unwrap();
+        let frame = engine.charas()[0].action_frame();
+
+        let resource = RESOURCE
+            .replace("max_health: 100", "max_health: 200")
+            .replace("name: attack\n    frames: 5", "name: attack\n    frames: 8");
+        dir.write("resource.yml", &format!("resource:\n{}", resource));
+        let (cache, changed) = ResCache::reload(engine.res_cache()).unwrap();
+        assert!(changed.contains(&ResID::from("Chara.Test")));
+        assert!(changed.contains(&ResID::from("Action.Test")));
+        engine.update_res_cache(cache.clone(), &changed).unwrap();
+        assert!(Arc::ptr_eq(engine.res_cache(), &cache));
+
+        let chara = &engine.charas()[0];
+        assert_eq!(chara.res().max_health, 200);
+        assert_eq!(chara.stats().max_health, 200);
+        assert_eq!(chara.stats().health, 100);
+        assert_eq!(chara.action_frame(), frame);
+        match chara.action() {
+            Some(ResActionAny::Attack(attack)) => assert_eq!(attack.frames, 8),
+            action => panic!("unexpected action {:?}", action),
+        };
+    }
+
+    #[test]
+    fn test_engine_refresh_hits() {
+        let dir = write_res("engine_refresh_hits", RESOURCE);
+        let mut engine = LogicEngine::new(restore_res(&dir), fi(20)).unwrap();
+        let obj_id = engine.new_chara_human(&ResID::from("Chara.Test")).unwrap();
+        engine.run_command(&attack(obj_id)).unwrap();
+        engine.run_tick().unwrap();
+        assert_eq!(engine.world().objects.len(), 2);
+
+        let resource = RESOURCE
+            .replace("radius: 1\n      transform", "radius: 2\n      transform")
+            .replace("finish_frame: 3", "finish_frame: 5");
+        dir.write("resource.yml", &format!("resource:\n{}", resource));
+        let (cache, changed) = ResCache::reload(engine.res_cache()).unwrap();
+        assert!(changed.contains(&ResID::from("Hit.Punch")));
+        engine.update_res_cache(cache, &changed).unwrap();
+        assert_eq!(engine.world().objects.len(), 1);
+
+        // The old area would be over on the third frame.
+        engine.run_tick().unwrap();
+        engine.run_tick().unwrap();
+        let (_, hit) = engine
+            .world()
+            .collision_objects()
+            .find(|(_, co)| co.obj_type() == CollisionObjectType::Hit)
+            .unwrap();
+        let aabb = hit.shape().aabb(hit.position());
+        assert_eq!(aabb.maxs().x - aabb.mins().x, fi(4));
+    }
+
+    #[test]
+    fn test_engine_determinism() {
+        let (mut engine1, ids) = new_engine("engine_determinism_1");
+        let (mut engine2, _) = new_engine("engine_determinism_2");
+        for tick in 0..20 {
+            let mut cmds = vec![operation(
+                ids[1],
+                OpCommand::Move(Vector2::new(fi(-1), fi(0))),
+            )];
+            if tick == 5 {
+                cmds.push(attack(ids[0]));
+            }
+            for cmd in &cmds {
+                engine1.run_command(cmd).unwrap();
+                engine2.run_command(cmd).unwrap();
+            }
+            let pool1 = engine1.run_tick().unwrap();
+            let pool2 = engine2.run_tick().unwrap();
+            assert_eq!(engine1.checksum(&pool1), engine2.checksum(&pool2));
+        }
+        assert!(engine1.charas()[1].position().translation.x < fi(2));
+    }
+
+    fn run_ticks(engine: &mut LogicEngine, ids: &[ObjID], ticks: Range<u32>) -> Vec<u64> {
+        let mut checksums = Vec::new();
+        for tick in ticks {
+            engine
+                .run_command(&operation(
+                    ids[1],
+                    OpCommand::Move(Vector2::new(fi(-1), fi(0))),
+                ))
+                .unwrap();
+            if tick == 2 || tick == 8 {
+                engine.run_command(&attack(ids[0])).unwrap();
+            }
+            let pool = engine.run_tick().unwrap();
+            checksums.push(engine.checksum(&pool));
+        }
+        return checksums;
+    }
+
+    #[test]
+    fn test_engine_snapshot_rollback() {
+        let (mut engine, ids) = new_engine("engine_snapshot_rollback");
+        run_ticks(&mut engine, &ids, 0..2);
+        let mut before_spawn = SnapshotWriter::new();
+        engine.save_snapshot(&mut before_spawn);
+
+        // The attack spawns a hit box, which despawns before the second attack spawns another.
+        let mut checksums = run_ticks(&mut engine, &ids, 2..3);
+        let mut spawned = SnapshotWriter::new();
+        engine.save_snapshot(&mut spawned);
+        assert_eq!(engine.world().objects.len(), 3);
+        checksums.extend(run_ticks(&mut engine, &ids, 3..14));
+        assert!(engine.charas()[1].is_dead());
+
+        engine.load_snapshot(&mut spawned.reader()).unwrap();
+        assert_eq!(engine.frame(), 3);
+        assert_eq!(engine.world().objects.len(), 3);
+        assert_eq!(engine.charas()[1].stats().health, 40);
+        assert_eq!(engine.charas()[1].buffs().len(), 1);
+        assert_eq!(run_ticks(&mut engine, &ids, 3..14), &checksums[1..]);
+
+        engine.load_snapshot(&mut before_spawn.reader()).unwrap();
+        assert_eq!(engine.frame(), 2);
+        assert_eq!(engine.world().objects.len(), 2);
+        assert_eq!(engine.charas()[1].stats().health, 100);
+        assert_eq!(engine.charas()[1].buffs().len(), 0);
+        assert_eq!(run_ticks(&mut engine, &ids, 2..14), checksums);
+        assert!(engine.charas()[1].is_dead());
+
+        let mut reader = SnapshotReader::new(spawned.as_bytes());
+        assert!(engine.load_snapshot(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use super::buff::BuffState;
+use super::damage::{DamageEvent, DamageReaction};
+use crate::derive::{def_enum, def_event};
+use crate::ffi::FFIVec3f;
+use crate::id::{ClassID, FastResID, ObjID};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::mem;
+use std::ptr;
+
+pub const EVENT_POOL_VERSION: u32 = 1;
+
+#[def_enum]
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EventID {
+    Invalid = 0,
+    HitLanded = 1,
+    ActionStarted = 2,
+    Damage = 3,
+    Dead = 4,
+    BuffApplied = 5,
+}
+
+impl Default for EventID {
+    fn default() -> EventID {
+        return EventID::Invalid;
+    }
+}
+
+pub trait LogicEventStatic {
+    fn id() -> EventID;
+}
+
+//
+// Events
+//
+
+#[def_event(EventID::HitLanded)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EventHitLanded {
+    pub source: ObjID,
+    pub target: ObjID,
+    pub position: FFIVec3f,
+}
+
+#[def_event(EventID::ActionStarted)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EventActionStarted {
+    // The ResAction graph, action_idx indexes its actions.
+    pub fres_id: FastResID,
+    pub action_idx: u32,
+}
+
+#[def_event(EventID::Damage)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EventDamage {
+    pub source: ObjID,
+    pub target: ObjID,
+    pub health: i32,
+    pub energy: i32,
+    pub posture: i32,
+    pub reaction: DamageReaction,
+    pub hit_stun: u32,
+}
+
+impl From<&DamageEvent> for EventDamage {
+    fn from(event: &DamageEvent) -> EventDamage {
+        return EventDamage {
+            source: event.source,
+            target: event.target,
+            health: event.health,
+            energy: event.energy,
+            posture: event.posture,
+            reaction: event.reaction,
+            hit_stun: event.hit_stun,
+        };
+    }
+}
+
+#[def_event(EventID::Dead)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EventDead {
+    pub killer: ObjID,
+}
+
+#[def_event(EventID::BuffApplied)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EventBuffApplied {
+    pub source: ObjID,
+    pub fres_id: FastResID,
+    pub stacks: u32,
+}
+
+impl From<&BuffState> for EventBuffApplied {
+    fn from(state: &BuffState) -> EventBuffApplied {
+        return EventBuffApplied {
+            source: state.source,
+            fres_id: state.fres_id,
+            stacks: state.stacks,
+        };
+    }
+}
+
+//
+// Event Pool
+//
+
+// Layout of an event pool, records are 16 aligned and follow each other:
+// | EventPoolHeader | LogicEvent<E> (size bytes) | LogicEvent<E> | ...
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventPoolHeader {
+    pub version: u32,
+    pub header_size: u32,
+    pub count: u32,
+    // Bytes of the header and all records.
+    pub total_size: u32,
+}
+
+// obj_id and class_id are the logic object which produced the event.
+#[repr(C)]
+#[derive(Debug)]
+pub struct LogicEvent<E> {
+    obj_id: ObjID,
+    class_id: ClassID,
+    event_id: EventID,
+    // Size of the record, header included.
+    size: u32,
+    event: E,
+}
+
+impl<E> LogicEvent<E> {
+    #[inline]
+    pub fn obj_id(&self) -> ObjID {
+        return self.obj_id;
+    }
+
+    #[inline]
+    pub fn class_id(&self) -> ClassID {
+        return self.class_id;
+    }
+
+    #[inline]
+    pub fn event_id(&self) -> EventID {
+        return self.event_id;
+    }
+}
+
+impl LogicEvent<()> {
+    pub fn cast<E: LogicEventStatic>(&self) -> Option<&E> {
+        if self.event_id != E::id() {
+            return None;
+        }
+        let event = unsafe { &*(self as *const LogicEvent<()> as *const LogicEvent<E>) };
+        return Some(&event.event);
+    }
+}
+
+// A LogicEvent decoded by its concrete type, for logs and JSON dumps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventDump {
+    pub obj_id: ObjID,
+    pub class_id: ClassID,
+    pub event_id: EventID,
+    pub value: Value,
+}
+
+type EventValueFn = fn(*const LogicEvent<()>) -> serde_json::Result<Value>;
+
+fn event_value<E: Serialize>(event: *const LogicEvent<()>) -> serde_json::Result<Value> {
+    let event = unsafe { &*(event as *const LogicEvent<E>) };
+    return serde_json::to_value(&event.event);
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+struct Block([u8; 16]);
+
+const BLOCK_SIZE: usize = mem::size_of::<Block>();
+const HEADER_SIZE: usize = mem::size_of::<EventPoolHeader>();
+
+// Events of a single tick in emission order, in one C ABI buffer the front ends read as is.
+// Events are plain data (Copy), clearing the pool never runs destructors.
+#[derive(Debug)]
+pub struct EventPool {
+    buffer: Vec<Block>,
+    count: usize,
+    size: usize,
+    event_values: Vec<EventValueFn>,
+}
+
+impl EventPool {
+    pub fn new() -> EventPool {
+        let mut pool = EventPool {
+            buffer: Vec::new(),
+            count: 0,
+            size: HEADER_SIZE,
+            event_values: Vec::new(),
+        };
+        pool.resize(HEADER_SIZE);
+        pool.write_header();
+        return pool;
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        return self.count;
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        return self.count == 0;
+    }
+
+    pub fn emit<E>(&mut self, obj_id: ObjID, class_id: ClassID, event: E)
+    where
+        E: LogicEventStatic + Copy + Serialize + 'static,
+    {
+        let size = (mem::size_of::<LogicEvent<E>>() + 15) & !15;
+        let offset = self.size;
+        self.resize(offset + size);
+        unsafe {
+            let ptr = (self.buffer.as_mut_ptr() as *mut u8).add(offset);
+            ptr::write(
+                ptr as *mut LogicEvent<E>,
+                LogicEvent {
+                    obj_id,
+                    class_id,
+                    event_id: E::id(),
+                    size: size as u32,
+                    event,
+                },
+            );
+        };
+        self.count += 1;
+        self.size += size;
+        self.event_values.push(event_value::<E>);
+        self.write_header();
+    }
+
+    pub fn clear(&mut self) {
+        self.count = 0;
+        self.size = HEADER_SIZE;
+        self.event_values.clear();
+        self.write_header();
+    }
+
+    pub fn iter(&self) -> EventIter<'_> {
+        return EventIter {
+            pool: self,
+            offset: HEADER_SIZE,
+        };
+    }
+
+    pub fn dump_events(&self) -> serde_json::Result<Vec<EventDump>> {
+        let mut dumps = Vec::with_capacity(self.count);
+        for (event, value) in self.iter().zip(self.event_values.iter()) {
+            dumps.push(EventDump {
+                obj_id: event.obj_id,
+                class_id: event.class_id,
+                event_id: event.event_id,
+                value: value(event)?,
+            });
+        }
+        return Ok(dumps);
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const EventPoolHeader {
+        return self.buffer.as_ptr() as *const EventPoolHeader;
+    }
+
+    #[inline]
+    pub fn header(&self) -> &EventPoolHeader {
+        return unsafe { &*self.as_ptr() };
+    }
+
+    fn resize(&mut self, size: usize) {
+        let blocks = (size + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        if blocks > self.buffer.len() {
+            self.buffer.resize(blocks, Block([0; 16]));
+        }
+    }
+
+    fn write_header(&mut self) {
+        let header = EventPoolHeader {
+            version: EVENT_POOL_VERSION,
+            header_size: HEADER_SIZE as u32,
+            count: self.count as u32,
+            total_size: self.size as u32,
+        };
+        unsafe { ptr::write(self.buffer.as_mut_ptr() as *mut EventPoolHeader, header) };
+    }
+}
+
+pub struct EventIter<'t> {
+    pool: &'t EventPool,
+    offset: usize,
+}
+
+impl<'t> Iterator for EventIter<'t> {
+    type Item = &'t LogicEvent<()>;
+
+    fn next(&mut self) -> Option<&'t LogicEvent<()>> {
+        if self.offset >= self.pool.size {
+            return None;
+        }
+        let event = unsafe {
+            let ptr = (self.pool.buffer.as_ptr() as *const u8).add(self.offset);
+            &*(ptr as *const LogicEvent<()>)
+        };
+        self.offset += event.size as usize;
+        return Some(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_pool() {
+        let mut pool = EventPool::new();
+        assert!(pool.is_empty());
+        assert_eq!(pool.header().count, 0);
+        assert_eq!(pool.header().total_size, 16);
+
+        for id in 0..20 {
+            pool.emit(
+                ObjID::from(100 + id),
+                ClassID::Projectile,
+                EventHitLanded {
+                    source: ObjID::from(1),
+                    target: ObjID::from(id),
+                    position: FFIVec3f::new(id as f32, 0.0, 0.0),
+                },
+            );
+        }
+        pool.emit(
+            ObjID::from(1),
+            ClassID::CharaHuman,
+            EventDead {
+                killer: ObjID::from(9),
+            },
+        );
+        assert_eq!(pool.len(), 21);
+        assert_eq!(pool.header().count, 21);
+        assert_eq!(pool.header().total_size % 16, 0);
+
+        let events: Vec<_> = pool.iter().collect();
+        assert_eq!(events.len(), 21);
+        for (id, event) in events[..20].iter().enumerate() {
+            assert_eq!(event.obj_id(), ObjID::from(100 + id as u64));
+            assert_eq!(event.class_id(), ClassID::Projectile);
+            assert_eq!(event.event_id(), EventID::HitLanded);
+            let hit = event.cast::<EventHitLanded>().unwrap();
+            assert_eq!(hit.target, ObjID::from(id as u64));
+            assert_eq!(hit.position, FFIVec3f::new(id as f32, 0.0, 0.0));
+            assert!(event.cast::<EventDead>().is_none());
+        }
+        let dead = events[20].cast::<EventDead>().unwrap();
+        assert_eq!(dead.killer, ObjID::from(9));
+
+        let dumps = pool.dump_events().unwrap();
+        assert_eq!(dumps[20].event_id, EventID::Dead);
+        assert_eq!(dumps[20].value["killer"], serde_json::json!(ObjID::from(9)));
+
+        pool.clear();
+        assert!(pool.is_empty());
+        assert_eq!(pool.iter().count(), 0);
+        assert_eq!(pool.header().total_size, 16);
+    }
+}
@@ -0,0 +1,365 @@
+use super::snapshot::{LogicSnapshot, SnapshotReader, SnapshotWriter};
+use crate::id::ObjID;
+use crate::resource::{ResCoordinate, ResHitArea, ResHitAttachment};
+use anyhow::Result;
+use collide::pipeline::{
+    CollisionGroups, CollisionObjectSlabHandle, CollisionObjectType, CollisionWorld,
+    GeometricQueryType,
+};
+use math::{fi, Fx};
+use na::Isometry3;
+use std::sync::Arc;
+
+// Transforms the ResCoordinate of a hit area resolves against, for the current tick.
+#[derive(Debug, Clone)]
+pub struct HitCoords<'t> {
+    pub source: Isometry3<Fx>,
+    pub target: Option<Isometry3<Fx>>,
+    pub skill: Isometry3<Fx>,
+    pub bones: &'t [(&'t str, Isometry3<Fx>)],
+}
+
+impl<'t> HitCoords<'t> {
+    // Target falls back to the source when nothing is locked.
+    pub fn coordinate(&self, coordinate: ResCoordinate) -> Isometry3<Fx> {
+        return match coordinate {
+            ResCoordinate::World => Isometry3::identity(),
+            ResCoordinate::Source => self.source,
+            ResCoordinate::Target => self.target.unwrap_or(self.source),
+            ResCoordinate::Skill => self.skill,
+        };
+    }
+
+    pub fn area_position(&self, area: &ResHitArea) -> Isometry3<Fx> {
+        let base = self.coordinate(area.coordinate);
+        let bone = area.bone.as_ref().and_then(|bone| {
+            self.bones
+                .iter()
+                .find(|(name, _)| *name == bone.as_str())
+                .map(|(_, bone)| *bone)
+        });
+        return match bone {
+            Some(bone) => base * bone * area.shape.transform,
+            None => base * area.shape.transform,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct HitRecord {
+    area_idx: usize,
+    target: ObjID,
+    hits: u32,
+    last_frame: u32,
+}
+
+// Per area and target hit counts, shared by hit boxes and hit paths.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HitRecords(Vec<HitRecord>);
+
+impl HitRecords {
+    pub(crate) fn accept(
+        &mut self,
+        area: &ResHitArea,
+        area_idx: usize,
+        target: ObjID,
+        frame: u32,
+    ) -> bool {
+        let record = self
+            .0
+            .iter_mut()
+            .find(|record| record.area_idx == area_idx && record.target == target);
+        return match record {
+            Some(record) => {
+                if record.hits >= area.max_hits || frame < record.last_frame + area.hit_interval {
+                    return false;
+                }
+                record.hits += 1;
+                record.last_frame = frame;
+                true
+            }
+            None => {
+                self.0.push(HitRecord {
+                    area_idx,
+                    target,
+                    hits: 1,
+                    last_frame: frame,
+                });
+                true
+            }
+        };
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    // Drops the records of areas a reloaded resource no longer has.
+    pub(crate) fn retain_areas(&mut self, len: usize) {
+        self.0.retain(|record| record.area_idx < len);
+    }
+}
+
+impl LogicSnapshot for HitRecords {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_u32(self.0.len() as u32);
+        for record in &self.0 {
+            writer.write_u32(record.area_idx as u32);
+            writer.write_obj_id(record.target);
+            writer.write_u32(record.hits);
+            writer.write_u32(record.last_frame);
+        }
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        self.0.clear();
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            self.0.push(HitRecord {
+                area_idx: reader.read_u32()? as usize,
+                target: reader.read_obj_id()?,
+                hits: reader.read_u32()?,
+                last_frame: reader.read_u32()?,
+            });
+        }
+        return Ok(());
+    }
+}
+
+// Keeps the areas of a ResHitAttachment in a CollisionWorld while their frames are active.
+#[derive(Debug)]
+pub struct HitBoxes {
+    res: Arc<ResHitAttachment>,
+    handles: Vec<Option<CollisionObjectSlabHandle>>,
+    records: HitRecords,
+}
+
+impl HitBoxes {
+    pub fn new(res: Arc<ResHitAttachment>) -> HitBoxes {
+        let handles = vec![None; res.areas.len()];
+        return HitBoxes {
+            res,
+            handles,
+            records: HitRecords::default(),
+        };
+    }
+
+    #[inline]
+    pub fn res(&self) -> &Arc<ResHitAttachment> {
+        return &self.res;
+    }
+
+    #[inline]
+    pub fn is_finished(&self, frame: u32) -> bool {
+        return frame >= self.res.frames();
+    }
+
+    pub fn handle(&self, area_idx: usize) -> Option<CollisionObjectSlabHandle> {
+        return self.handles.get(area_idx).cloned().flatten();
+    }
+
+    // Adds the areas entering their frame range, moves the active ones and removes the expired ones.
+    pub fn update<T: Clone + 'static>(
+        &mut self,
+        world: &mut CollisionWorld<T>,
+        frame: u32,
+        coords: &HitCoords,
+        groups: CollisionGroups,
+        data: T,
+    ) {
+        for (idx, area) in self.res.areas.iter().enumerate() {
+            let position = coords.area_position(area);
+            match (area.is_active(frame), self.handles[idx]) {
+                (true, None) => {
+                    let (handle, _) = world.add(
+                        CollisionObjectType::Hit,
+                        position,
+                        area.shape.handle.clone(),
+                        groups,
+                        GeometricQueryType::Proximity(fi(0)),
+                        data.clone(),
+                    );
+                    self.handles[idx] = Some(handle);
+                }
+                (true, Some(handle)) => {
+                    if let Some(obj) = world.get_mut(handle) {
+                        obj.set_position(position);
+                    }
+                }
+                (false, Some(handle)) => {
+                    world.remove(&[handle]);
+                    self.handles[idx] = None;
+                }
+                (false, None) => {}
+            }
+        }
+    }
+
+    pub fn clear<T: 'static>(&mut self, world: &mut CollisionWorld<T>) {
+        for handle in &mut self.handles {
+            if let Some(handle) = handle.take() {
+                world.remove(&[handle]);
+            }
+        }
+        self.records.clear();
+    }
+
+    // Rebinds a reloaded attachment, the next update adds the active areas back with their new shapes.
+    pub fn refresh<T: 'static>(
+        &mut self,
+        world: &mut CollisionWorld<T>,
+        res: Arc<ResHitAttachment>,
+    ) {
+        for handle in self.handles.drain(..) {
+            if let Some(handle) = handle {
+                world.remove(&[handle]);
+            }
+        }
+        self.handles.resize(res.areas.len(), None);
+        self.records.retain_areas(res.areas.len());
+        self.res = res;
+    }
+
+    // Counts a hit of an area on a target, false if max_hits or hit_interval rejects it.
+    pub fn accept_hit(&mut self, area_idx: usize, target: ObjID, frame: u32) -> bool {
+        return match self.res.areas.get(area_idx) {
+            Some(area) => self.records.accept(area, area_idx, target, frame),
+            None => false,
+        };
+    }
+}
+
+// The attachment is saved by the owner. Handles point into the collision world of the same snapshot.
+impl LogicSnapshot for HitBoxes {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        for handle in &self.handles {
+            writer.write_bool(handle.is_some());
+            if let Some(handle) = handle {
+                writer.write_handle(*handle);
+            }
+        }
+        self.records.save_snapshot(writer);
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        for handle in &mut self.handles {
+            *handle = match reader.read_bool()? {
+                true => Some(reader.read_handle()?),
+                false => None,
+            };
+        }
+        return self.records.load_snapshot(reader);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collide::shape::{Ball, Shape, ShapeHandle};
+    use math::ff;
+    use na::Vector3;
+
+    fn new_hit_boxes() -> HitBoxes {
+        let mut res: ResHitAttachment = serde_yaml::from_str(
+            r#"
+res_id: Hit.Test
+areas:
+- shape: { type: Ball, radius: 0.5 }
+  coordinate: Source
+  start_frame: 2
+  finish_frame: 5
+  max_hits: 2
+  hit_interval: 2
+- shape: { type: Ball, radius: 0.5 }
+  coordinate: World
+  start_frame: 4
+  finish_frame: 6
+"#,
+        )
+        .unwrap();
+        for area in &mut res.areas {
+            area.shape.handle = ShapeHandle::new(Ball::new(ff(0.5)));
+        }
+        return HitBoxes::new(Arc::new(res));
+    }
+
+    fn groups() -> CollisionGroups {
+        return CollisionGroups {
+            team_membership: 1,
+            team_whitelist: 0xFFFF,
+            role_membership: 1,
+            role_whitelist: 0xFFFF,
+        };
+    }
+
+    #[test]
+    fn test_hit_boxes_update() {
+        let mut world: CollisionWorld<u32> = CollisionWorld::new(fi(0));
+        let mut hit_boxes = new_hit_boxes();
+        let coords = HitCoords {
+            source: Isometry3::new(Vector3::new(fi(1), fi(0), fi(0)), na::zero()),
+            target: None,
+            skill: Isometry3::identity(),
+            bones: &[],
+        };
+        let mut counts = Vec::new();
+        for frame in 0..7 {
+            hit_boxes.update(&mut world, frame, &coords, groups(), 7);
+            counts.push(world.objects.len());
+        }
+        assert_eq!(counts, vec![0, 0, 1, 1, 2, 1, 0]);
+        assert!(hit_boxes.is_finished(6));
+
+        hit_boxes.update(&mut world, 3, &coords, groups(), 7);
+        let handle = hit_boxes.handle(0).unwrap();
+        let obj = world.collision_object(handle).unwrap();
+        assert_eq!(obj.position().translation.vector.x, fi(1));
+        hit_boxes.clear(&mut world);
+        assert_eq!(world.objects.len(), 0);
+    }
+
+    #[test]
+    fn test_hit_boxes_refresh() {
+        let mut world: CollisionWorld<u32> = CollisionWorld::new(fi(0));
+        let mut hit_boxes = new_hit_boxes();
+        let coords = HitCoords {
+            source: Isometry3::identity(),
+            target: None,
+            skill: Isometry3::identity(),
+            bones: &[],
+        };
+        hit_boxes.update(&mut world, 4, &coords, groups(), 7);
+        assert_eq!(world.objects.len(), 2);
+        assert!(hit_boxes.accept_hit(0, ObjID::from(1), 4));
+        assert!(hit_boxes.accept_hit(1, ObjID::from(1), 4));
+
+        let mut res = (**new_hit_boxes().res()).clone();
+        res.areas.truncate(1);
+        res.areas[0].shape.handle = ShapeHandle::new(Ball::new(fi(2)));
+        hit_boxes.refresh(&mut world, Arc::new(res));
+        assert_eq!(world.objects.len(), 0);
+        assert!(hit_boxes.handle(1).is_none());
+        assert!(!hit_boxes.accept_hit(0, ObjID::from(1), 5));
+
+        hit_boxes.update(&mut world, 4, &coords, groups(), 7);
+        assert_eq!(world.objects.len(), 1);
+        let obj = world
+            .collision_object(hit_boxes.handle(0).unwrap())
+            .unwrap();
+        let aabb = obj.shape().aabb(obj.position());
+        assert_eq!(aabb.maxs().x, fi(2));
+    }
+
+    #[test]
+    fn test_hit_boxes_accept_hit() {
+        let mut hit_boxes = new_hit_boxes();
+        let target = ObjID::from(1);
+        assert!(hit_boxes.accept_hit(0, target, 2));
+        assert!(!hit_boxes.accept_hit(0, target, 3));
+        assert!(hit_boxes.accept_hit(0, target, 4));
+        assert!(!hit_boxes.accept_hit(0, target, 6));
+        assert!(hit_boxes.accept_hit(1, target, 4));
+        assert!(!hit_boxes.accept_hit(1, target, 5));
+        assert!(hit_boxes.accept_hit(0, ObjID::from(2), 4));
+    }
+}
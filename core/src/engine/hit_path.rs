@@ -0,0 +1,296 @@
+use super::hit_box::{HitCoords, HitRecords};
+use super::snapshot::{LogicSnapshot, SnapshotReader, SnapshotWriter};
+use crate::id::ObjID;
+use crate::resource::{ResHitArea, ResHitPathRay};
+use anyhow::Result;
+use collide::pipeline::{
+    CollisionGroups, CollisionObjectSlabHandle, CollisionObjectType, CollisionWorld,
+};
+use collide::query::{self, Proximity, Ray};
+use collide::shape::Shape;
+use math::{fi, Fx};
+use na::{Isometry3, Point3, Unit};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HitImpact {
+    pub area_idx: usize,
+    pub handle: CollisionObjectSlabHandle,
+    // Fraction of the tick in [0, 1] when the area reaches the object.
+    pub toi: Fx,
+}
+
+// Sweeps the areas of a ResHitPathRay from their previous tick position to the current one,
+// so fast attacks cannot tunnel through thin objects at low fps.
+#[derive(Debug)]
+pub struct HitPaths {
+    res: Arc<ResHitPathRay>,
+    prev_positions: Vec<Option<Isometry3<Fx>>>,
+    records: HitRecords,
+    impacts: Vec<(CollisionObjectSlabHandle, Fx)>,
+}
+
+impl HitPaths {
+    pub fn new(res: Arc<ResHitPathRay>) -> HitPaths {
+        let prev_positions = vec![None; res.areas.len()];
+        return HitPaths {
+            res,
+            prev_positions,
+            records: HitRecords::default(),
+            impacts: Vec::new(),
+        };
+    }
+
+    #[inline]
+    pub fn res(&self) -> &Arc<ResHitPathRay> {
+        return &self.res;
+    }
+
+    #[inline]
+    pub fn is_finished(&self, frame: u32) -> bool {
+        return frame >= self.res.frames();
+    }
+
+    // Appends the impacts of this tick to out, ordered by time of impact then by handle.
+    pub fn sweep<T: 'static>(
+        &mut self,
+        world: &mut CollisionWorld<T>,
+        frame: u32,
+        coords: &HitCoords,
+        groups: &CollisionGroups,
+        out: &mut Vec<HitImpact>,
+    ) {
+        let start = out.len();
+        for (idx, area) in self.res.areas.iter().enumerate() {
+            if !area.is_active(frame) {
+                self.prev_positions[idx] = None;
+                continue;
+            }
+            let current = coords.area_position(area);
+            let prev = self.prev_positions[idx].unwrap_or(current);
+            self.prev_positions[idx] = Some(current);
+
+            Self::sweep_area(world, area, &prev, &current, groups, &mut self.impacts);
+            out.extend(self.impacts.drain(..).map(|(handle, toi)| HitImpact {
+                area_idx: idx,
+                handle,
+                toi,
+            }));
+        }
+        out[start..].sort_by(|a, b| {
+            return a
+                .toi
+                .cmp(&b.toi)
+                .then(a.handle.0.cmp(&b.handle.0))
+                .then(a.area_idx.cmp(&b.area_idx));
+        });
+    }
+
+    fn sweep_area<T: 'static>(
+        world: &mut CollisionWorld<T>,
+        area: &ResHitArea,
+        prev: &Isometry3<Fx>,
+        current: &Isometry3<Fx>,
+        groups: &CollisionGroups,
+        impacts: &mut Vec<(CollisionObjectSlabHandle, Fx)>,
+    ) {
+        let delta = current.translation.vector - prev.translation.vector;
+        let distance = delta.norm();
+
+        // Not moving, falls back to an overlap test at the current position.
+        if distance == fi(0) {
+            if area.ray {
+                return;
+            }
+            let shape = area.shape.handle.as_ref();
+            let aabb = shape.aabb(current);
+            let mut handles = Vec::new();
+            world.interferences_with_aabb(CollisionObjectType::Move, &aabb, groups, &mut handles);
+            for handle in handles {
+                if let Some(co) = world.collision_object(handle) {
+                    let proximity =
+                        query::proximity(current, shape, co.position(), co.shape().as_ref(), fi(0));
+                    if proximity == Proximity::Intersecting {
+                        impacts.push((handle, fi(0)));
+                    }
+                }
+            }
+            return;
+        }
+
+        let direction = Unit::new_normalize(delta);
+        if area.ray {
+            let origin = Point3::from(prev.translation.vector);
+            let ray = Ray::new(origin, direction.into_inner());
+            let mut inters = Vec::new();
+            world.interferences_with_ray(
+                CollisionObjectType::Move,
+                &ray,
+                distance,
+                groups,
+                &mut inters,
+            );
+            impacts.extend(
+                inters
+                    .into_iter()
+                    .map(|(handle, inter)| (handle, inter.toi / distance)),
+            );
+        } else {
+            let mut tois = Vec::new();
+            world.sweep_test(
+                CollisionObjectType::Move,
+                area.shape.handle.as_ref(),
+                prev,
+                &direction,
+                distance,
+                groups,
+                &mut tois,
+            );
+            impacts.extend(
+                tois.into_iter()
+                    .map(|(handle, toi)| (handle, toi.toi / distance)),
+            );
+        }
+    }
+
+    pub fn accept_hit(&mut self, area_idx: usize, target: ObjID, frame: u32) -> bool {
+        return match self.res.areas.get(area_idx) {
+            Some(area) => self.records.accept(area, area_idx, target, frame),
+            None => false,
+        };
+    }
+
+    pub fn clear(&mut self) {
+        for prev in &mut self.prev_positions {
+            *prev = None;
+        }
+        self.records.clear();
+    }
+
+    // Rebinds a reloaded ray, the areas it keeps go on sweeping from their previous positions.
+    pub fn refresh(&mut self, res: Arc<ResHitPathRay>) {
+        self.prev_positions.resize(res.areas.len(), None);
+        self.records.retain_areas(res.areas.len());
+        self.res = res;
+    }
+}
+
+// The ray resource is saved by the owner, impacts only live within a sweep.
+impl LogicSnapshot for HitPaths {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        for prev in &self.prev_positions {
+            writer.write_bool(prev.is_some());
+            if let Some(prev) = prev {
+                writer.write_isometry(prev);
+            }
+        }
+        self.records.save_snapshot(writer);
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        for prev in &mut self.prev_positions {
+            *prev = match reader.read_bool()? {
+                true => Some(reader.read_isometry()?),
+                false => None,
+            };
+        }
+        return self.records.load_snapshot(reader);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collide::pipeline::GeometricQueryType;
+    use collide::shape::{Ball, Cuboid, ShapeHandle};
+    use math::ff;
+    use na::Vector3;
+
+    fn groups() -> CollisionGroups {
+        return CollisionGroups {
+            team_membership: 1,
+            team_whitelist: 0xFFFF,
+            role_membership: 1,
+            role_whitelist: 0xFFFF,
+        };
+    }
+
+    fn new_world() -> CollisionWorld<u32> {
+        let mut world = CollisionWorld::new(fi(0));
+        // Two thin walls at x = 2 and x = 4, a 20fps swing from x = 0 to x = 6 passes both.
+        for (idx, x) in [fi(4), fi(2)].iter().enumerate() {
+            world.add(
+                CollisionObjectType::Move,
+                Isometry3::new(Vector3::new(*x, fi(0), fi(0)), na::zero()),
+                ShapeHandle::new(Cuboid::new(Vector3::new(ff(0.05), fi(1), fi(1)))),
+                groups(),
+                GeometricQueryType::Contacts(fi(0), fi(0)),
+                idx as u32,
+            );
+        }
+        world.update(&[CollisionObjectType::Move]);
+        return world;
+    }
+
+    fn new_hit_paths(ray: bool) -> HitPaths {
+        let mut res: ResHitPathRay = serde_yaml::from_str(&format!(
+            "res_id: Hit.Path\nareas:\n- shape: {{ type: Ball, radius: 0.25 }}\n  coordinate: Source\n  start_frame: 0\n  finish_frame: 10\n  max_hits: 1\n  ray: {}\n",
+            ray
+        ))
+        .unwrap();
+        res.areas[0].shape.handle = ShapeHandle::new(Ball::new(ff(0.25)));
+        return HitPaths::new(Arc::new(res));
+    }
+
+    fn sweep(
+        paths: &mut HitPaths,
+        world: &mut CollisionWorld<u32>,
+        frame: u32,
+        x: Fx,
+    ) -> Vec<HitImpact> {
+        let coords = HitCoords {
+            source: Isometry3::new(Vector3::new(x, fi(0), fi(0)), na::zero()),
+            target: None,
+            skill: Isometry3::identity(),
+            bones: &[],
+        };
+        let mut out = Vec::new();
+        paths.sweep(world, frame, &coords, &groups(), &mut out);
+        return out;
+    }
+
+    #[test]
+    fn test_hit_paths_sweep() {
+        for ray in [false, true].iter() {
+            let mut world = new_world();
+            let mut paths = new_hit_paths(*ray);
+            assert!(sweep(&mut paths, &mut world, 0, fi(0)).is_empty());
+
+            let impacts = sweep(&mut paths, &mut world, 1, fi(6));
+            assert_eq!(impacts.len(), 2);
+            assert!(impacts[0].toi < impacts[1].toi);
+            assert_eq!(
+                *world.collision_object(impacts[0].handle).unwrap().data(),
+                1
+            );
+            assert_eq!(
+                *world.collision_object(impacts[1].handle).unwrap().data(),
+                0
+            );
+            assert!(impacts[1].toi <= fi(1));
+
+            assert!(paths.accept_hit(0, ObjID::from(1), 1));
+            assert!(!paths.accept_hit(0, ObjID::from(1), 2));
+        }
+    }
+
+    #[test]
+    fn test_hit_paths_overlap() {
+        let mut world = new_world();
+        let mut paths = new_hit_paths(false);
+        let impacts = sweep(&mut paths, &mut world, 0, fi(2));
+        assert_eq!(impacts.len(), 1);
+        assert_eq!(impacts[0].toi, fi(0));
+        assert!(paths.is_finished(10));
+    }
+}
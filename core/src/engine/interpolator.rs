@@ -0,0 +1,297 @@
+use super::logic_data::{DataPool, LogicState, LogicStateStatic};
+use crate::ffi::{FFIQuaternion, FFITransform, FFIVec3f};
+use crate::id::{ClassID, ObjID};
+use na::{Quaternion, UnitQuaternion};
+use std::collections::HashMap;
+use std::mem;
+
+// States with a transform in the world, the ones a front end interpolates between ticks.
+pub trait StateTransform {
+    fn transform(&self) -> FFITransform;
+}
+
+type TransformFn = fn(*const LogicState<()>) -> FFITransform;
+
+fn state_transform<S: StateTransform>(state: *const LogicState<()>) -> FFITransform {
+    let state = unsafe { &*(state as *const LogicState<S>) };
+    return state.state().transform();
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterpolatedTransform {
+    pub obj_id: ObjID,
+    pub class_id: ClassID,
+    _padding_: u16,
+    pub transform: FFITransform,
+}
+
+impl InterpolatedTransform {
+    fn new(obj_id: ObjID, class_id: ClassID, transform: FFITransform) -> InterpolatedTransform {
+        return InterpolatedTransform {
+            obj_id,
+            class_id,
+            _padding_: 0,
+            transform,
+        };
+    }
+
+    fn key(&self) -> (u16, u64) {
+        return (self.class_id as u16, u64::from(self.obj_id));
+    }
+}
+
+// Keeps the DataPools of the last two ticks, rendering runs between them at alpha in [0, 1].
+// Objects created in the current tick are not interpolated, objects destroyed in it are dropped.
+#[derive(Debug)]
+pub struct StateInterpolator {
+    transform_fns: HashMap<ClassID, TransformFn>,
+    previous: Option<DataPool>,
+    current: Option<DataPool>,
+    // Transforms of each pool, sorted by ClassID then ObjID.
+    previous_transforms: Vec<InterpolatedTransform>,
+    current_transforms: Vec<InterpolatedTransform>,
+    output: Vec<InterpolatedTransform>,
+}
+
+impl StateInterpolator {
+    pub fn new() -> StateInterpolator {
+        return StateInterpolator {
+            transform_fns: HashMap::new(),
+            previous: None,
+            current: None,
+            previous_transforms: Vec::new(),
+            current_transforms: Vec::new(),
+            output: Vec::new(),
+        };
+    }
+
+    pub fn register<S>(&mut self)
+    where
+        S: LogicStateStatic + StateTransform + 'static,
+    {
+        self.transform_fns.insert(S::id(), state_transform::<S>);
+    }
+
+    #[inline]
+    pub fn previous(&self) -> Option<&DataPool> {
+        return self.previous.as_ref();
+    }
+
+    #[inline]
+    pub fn current(&self) -> Option<&DataPool> {
+        return self.current.as_ref();
+    }
+
+    #[inline]
+    pub fn current_mut(&mut self) -> Option<&mut DataPool> {
+        return self.current.as_mut();
+    }
+
+    // Pushes the pool of a new tick, returns the evicted pool so the caller can reset and reuse it.
+    pub fn push(&mut self, pool: DataPool) -> Option<DataPool> {
+        let evicted = self.previous.take();
+        self.previous = self.current.take();
+        self.current = Some(pool);
+
+        mem::swap(&mut self.previous_transforms, &mut self.current_transforms);
+        self.current_transforms.clear();
+        if let Some(pool) = &self.current {
+            for state in pool.state_ptrs() {
+                let header = unsafe { &**state };
+                if let Some(transform_fn) = self.transform_fns.get(&header.class_id()) {
+                    self.current_transforms.push(InterpolatedTransform::new(
+                        header.obj_id(),
+                        header.class_id(),
+                        transform_fn(*state),
+                    ));
+                }
+            }
+        }
+        self.current_transforms
+            .sort_by_key(|transform| transform.key());
+        return evicted;
+    }
+
+    pub fn clear(&mut self) {
+        self.previous = None;
+        self.current = None;
+        self.previous_transforms.clear();
+        self.current_transforms.clear();
+        self.output.clear();
+    }
+
+    pub fn transform(&self, obj_id: ObjID, class_id: ClassID, alpha: f32) -> Option<FFITransform> {
+        let key = (class_id as u16, u64::from(obj_id));
+        let current = find_transform(&self.current_transforms, key)?;
+        return match find_transform(&self.previous_transforms, key) {
+            Some(previous) => Some(lerp_transform(previous, current, alpha)),
+            None => Some(*current),
+        };
+    }
+
+    // Transforms of all objects in the current pool, sorted by ClassID then ObjID.
+    pub fn transforms(&mut self, alpha: f32) -> &[InterpolatedTransform] {
+        self.output.clear();
+        for current in &self.current_transforms {
+            let transform = match find_transform(&self.previous_transforms, current.key()) {
+                Some(previous) => lerp_transform(previous, &current.transform, alpha),
+                None => current.transform,
+            };
+            self.output.push(InterpolatedTransform::new(
+                current.obj_id,
+                current.class_id,
+                transform,
+            ));
+        }
+        return &self.output;
+    }
+}
+
+fn find_transform(transforms: &[InterpolatedTransform], key: (u16, u64)) -> Option<&FFITransform> {
+    return match transforms.binary_search_by_key(&key, |transform| transform.key()) {
+        Ok(idx) => Some(&transforms[idx].transform),
+        Err(_) => None,
+    };
+}
+
+pub fn lerp_transform(from: &FFITransform, to: &FFITransform, alpha: f32) -> FFITransform {
+    let alpha = alpha.max(0.0).min(1.0);
+    return FFITransform {
+        position: lerp_vec3(&from.position, &to.position, alpha),
+        rotation: slerp_quaternion(&from.rotation, &to.rotation, alpha),
+    };
+}
+
+pub fn lerp_vec3(from: &FFIVec3f, to: &FFIVec3f, alpha: f32) -> FFIVec3f {
+    return FFIVec3f {
+        x: from.x + (to.x - from.x) * alpha,
+        y: from.y + (to.y - from.y) * alpha,
+        z: from.z + (to.z - from.z) * alpha,
+    };
+}
+
+// Takes the shortest path, a zero quaternion (default state) is treated as identity.
+pub fn slerp_quaternion(from: &FFIQuaternion, to: &FFIQuaternion, alpha: f32) -> FFIQuaternion {
+    let from = unit_quaternion(from);
+    let mut to = unit_quaternion(to);
+    if from.coords.dot(&to.coords) < 0.0 {
+        to = UnitQuaternion::new_unchecked(-to.into_inner());
+    }
+    let res = match from.try_slerp(&to, alpha, 1.0e-6) {
+        Some(res) => res,
+        None => from.nlerp(&to, alpha),
+    };
+    return FFIQuaternion {
+        i: res.i,
+        j: res.j,
+        k: res.k,
+        w: res.w,
+    };
+}
+
+fn unit_quaternion(q: &FFIQuaternion) -> UnitQuaternion<f32> {
+    let q = Quaternion::new(q.w, q.i, q.j, q.k);
+    return UnitQuaternion::try_new(q, 1.0e-6).unwrap_or_else(UnitQuaternion::identity);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{LogicLifecycle, StateProjectile};
+    use crate::id::FastResID;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn quaternion(q: UnitQuaternion<f32>) -> FFIQuaternion {
+        return FFIQuaternion {
+            i: q.i,
+            j: q.j,
+            k: q.k,
+            w: q.w,
+        };
+    }
+
+    fn pool(projectiles: &[(u64, f32)]) -> DataPool {
+        let mut pool = DataPool::new(1024 * 4);
+        for (id, x) in projectiles {
+            let rotation = UnitQuaternion::from_euler_angles(0.0, *x * FRAC_PI_2, 0.0);
+            pool.state(
+                ObjID::from(*id),
+                LogicLifecycle::Running,
+                StateProjectile {
+                    source: ObjID::invalid(),
+                    fres_id: FastResID::from(1),
+                    frame: 0,
+                    target: ObjID::invalid(),
+                    position: FFIVec3f::new(*x, 0.0, 0.0),
+                    rotation: quaternion(rotation),
+                },
+            )
+            .unwrap();
+        }
+        return pool;
+    }
+
+    #[test]
+    fn test_slerp_quaternion() {
+        let from = quaternion(UnitQuaternion::identity());
+        let to = quaternion(UnitQuaternion::from_euler_angles(0.0, 0.0, FRAC_PI_2));
+        let half = slerp_quaternion(&from, &to, 0.5);
+        let expected = UnitQuaternion::from_euler_angles(0.0, 0.0, FRAC_PI_2 / 2.0);
+        assert!((unit_quaternion(&half).angle_to(&expected)).abs() < 1.0e-4);
+
+        let neg_to = FFIQuaternion {
+            i: -to.i,
+            j: -to.j,
+            k: -to.k,
+            w: -to.w,
+        };
+        let half = slerp_quaternion(&from, &neg_to, 0.5);
+        assert!((unit_quaternion(&half).angle_to(&expected)).abs() < 1.0e-4);
+
+        let zero = slerp_quaternion(&FFIQuaternion::default(), &from, 0.5);
+        assert_eq!(zero, from);
+    }
+
+    #[test]
+    fn test_state_interpolator() {
+        let mut interp = StateInterpolator::new();
+        interp.register::<StateProjectile>();
+
+        assert!(interp.push(pool(&[(1, 0.0), (2, 1.0)])).is_none());
+        let transform = interp.transform(ObjID::from(1), ClassID::Projectile, 0.5);
+        assert_eq!(transform.unwrap().position, FFIVec3f::new(0.0, 0.0, 0.0));
+
+        assert!(interp.push(pool(&[(1, 1.0), (3, 4.0)])).is_none());
+        let transform = interp
+            .transform(ObjID::from(1), ClassID::Projectile, 0.25)
+            .unwrap();
+        assert_eq!(transform.position, FFIVec3f::new(0.25, 0.0, 0.0));
+        let expected = UnitQuaternion::from_euler_angles(0.0, 0.25 * FRAC_PI_2, 0.0);
+        assert!(unit_quaternion(&transform.rotation).angle_to(&expected) < 1.0e-4);
+        assert!(interp
+            .transform(ObjID::from(2), ClassID::Projectile, 0.5)
+            .is_none());
+        assert!(interp
+            .transform(ObjID::from(1), ClassID::Skill, 0.5)
+            .is_none());
+
+        let transforms = interp.transforms(2.0);
+        assert_eq!(transforms.len(), 2);
+        assert_eq!(transforms[0].obj_id, ObjID::from(1));
+        assert_eq!(
+            transforms[0].transform.position,
+            FFIVec3f::new(1.0, 0.0, 0.0)
+        );
+        assert_eq!(transforms[1].obj_id, ObjID::from(3));
+        assert_eq!(
+            transforms[1].transform.position,
+            FFIVec3f::new(4.0, 0.0, 0.0)
+        );
+
+        let evicted = interp.push(pool(&[])).unwrap();
+        assert_eq!(evicted.state_count(), 2);
+        assert_eq!(interp.previous().unwrap().state_count(), 2);
+        assert!(interp.transforms(0.5).is_empty());
+    }
+}
@@ -1,9 +1,14 @@
+use super::event::{EventPool, LogicEventStatic};
+use super::state_export::StateExport;
 use crate::derive::def_enum;
-use crate::id::{ClassID, ObjID};
+use crate::ffi::{FFIQuaternion, FFIVec2f, FFIVec3f, FFIVec4f};
+use crate::id::{ClassID, FastResID, ObjID};
 use crate::utils;
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::ffi::c_void;
+use std::hash::{Hash, Hasher};
 use std::mem;
 use std::ptr;
 use std::raw::TraitObject;
@@ -14,7 +19,7 @@ use std::raw::TraitObject;
 
 #[def_enum]
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum LogicLifecycle {
     Created,
     Running,
@@ -39,6 +44,89 @@ pub trait LogicStateStatic {
     fn id() -> ClassID;
 }
 
+// Feeds a state into the tick checksum field by field, def_state implements it in declaration order.
+// Floats go in by their bits, padding bytes never reach the hasher.
+pub trait StateHash {
+    fn state_hash(&self, hasher: &mut dyn Hasher);
+}
+
+macro_rules! impl_state_hash {
+    ($($ty:ty),*) => {
+        $(
+            impl StateHash for $ty {
+                #[inline]
+                fn state_hash(&self, mut hasher: &mut dyn Hasher) {
+                    self.hash(&mut hasher);
+                }
+            }
+        )*
+    };
+}
+
+impl_state_hash!(
+    bool,
+    u8,
+    u16,
+    u32,
+    u64,
+    i8,
+    i16,
+    i32,
+    i64,
+    ObjID,
+    FastResID,
+    ClassID,
+    LogicLifecycle
+);
+
+impl StateHash for f32 {
+    #[inline]
+    fn state_hash(&self, hasher: &mut dyn Hasher) {
+        hasher.write_u32(self.to_bits());
+    }
+}
+
+impl StateHash for FFIVec2f {
+    fn state_hash(&self, hasher: &mut dyn Hasher) {
+        self.x.state_hash(hasher);
+        self.y.state_hash(hasher);
+    }
+}
+
+impl StateHash for FFIVec3f {
+    fn state_hash(&self, hasher: &mut dyn Hasher) {
+        self.x.state_hash(hasher);
+        self.y.state_hash(hasher);
+        self.z.state_hash(hasher);
+    }
+}
+
+impl StateHash for FFIVec4f {
+    fn state_hash(&self, hasher: &mut dyn Hasher) {
+        self.x.state_hash(hasher);
+        self.y.state_hash(hasher);
+        self.z.state_hash(hasher);
+        self.w.state_hash(hasher);
+    }
+}
+
+impl StateHash for FFIQuaternion {
+    fn state_hash(&self, hasher: &mut dyn Hasher) {
+        self.i.state_hash(hasher);
+        self.j.state_hash(hasher);
+        self.k.state_hash(hasher);
+        self.w.state_hash(hasher);
+    }
+}
+
+impl<T: StateHash, const N: usize> StateHash for [T; N] {
+    fn state_hash(&self, hasher: &mut dyn Hasher) {
+        for item in self.iter() {
+            item.state_hash(hasher);
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct LogicProp<P> {
@@ -60,17 +148,78 @@ pub struct LogicState<S> {
     state: S,
 }
 
+impl<S> LogicState<S> {
+    #[inline]
+    pub fn obj_id(&self) -> ObjID {
+        return self.obj_id;
+    }
+
+    #[inline]
+    pub fn class_id(&self) -> ClassID {
+        return self.class_id;
+    }
+
+    #[inline]
+    pub fn lifecycle(&self) -> LogicLifecycle {
+        return self.lifecycle;
+    }
+
+    #[inline]
+    pub fn state(&self) -> &S {
+        return &self.state;
+    }
+}
+
+type StateHashFn = fn(*const LogicState<()>, &mut dyn Hasher);
+
+fn state_hash<S: StateHash>(state: *const LogicState<()>, hasher: &mut dyn Hasher) {
+    let state = unsafe { &*(state as *const LogicState<S>) };
+    state.state.state_hash(hasher);
+}
+
 //
 // Data Pool
 //
 
+// Memory usage of a DataPool, for profiling and tuning the chunk size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataPoolStats {
+    pub chunk_size: usize,
+    pub chunks: usize,
+    pub large_chunks: usize,
+    pub spare_large_chunks: usize,
+    // Bytes malloced by all chunks, including the spare ones.
+    pub reserved_bytes: usize,
+    pub used_bytes: usize,
+    // Tail bytes of filled chunks too small for the next allocation.
+    pub wasted_bytes: usize,
+    pub peak_used_bytes: usize,
+    pub props: usize,
+    pub states: usize,
+    pub events: usize,
+    pub resets: u64,
+}
+
 #[derive(Debug)]
 pub struct DataPool {
     props: Vec<*mut LogicProp<()>>,
     states: Vec<*mut LogicState<()>>,
+    state_hashes: Vec<StateHashFn>,
+    state_sizes: Vec<usize>,
+    export: StateExport,
+    events: EventPool,
     chunk_size: usize,
     threshold_size: usize,
     chunks: Vec<MemoryChunk>,
+    // Index of the chunk currently allocated from, chunks after it are empty and kept for reuse.
+    active_chunk: usize,
+    // Allocations above threshold_size, one chunk each.
+    large_chunks: Vec<MemoryChunk>,
+    spare_large_chunks: Vec<MemoryChunk>,
+    used_bytes: usize,
+    wasted_bytes: usize,
+    peak_used_bytes: usize,
+    resets: u64,
 }
 
 unsafe impl Sync for DataPool {}
@@ -81,14 +230,40 @@ impl DataPool {
         let mut pool = DataPool {
             props: Vec::with_capacity(256),
             states: Vec::with_capacity(1024),
+            state_hashes: Vec::with_capacity(1024),
+            state_sizes: Vec::with_capacity(1024),
+            export: StateExport::new(),
+            events: EventPool::new(),
             chunk_size,
             threshold_size: chunk_size / 8,
             chunks: Vec::with_capacity(64),
+            active_chunk: 0,
+            large_chunks: Vec::new(),
+            spare_large_chunks: Vec::new(),
+            used_bytes: 0,
+            wasted_bytes: 0,
+            peak_used_bytes: 0,
+            resets: 0,
         };
         pool.chunks.push(MemoryChunk::new(chunk_size));
         return pool;
     }
 
+    #[inline]
+    pub fn prop_count(&self) -> usize {
+        return self.props.len();
+    }
+
+    #[inline]
+    pub fn state_count(&self) -> usize {
+        return self.states.len();
+    }
+
+    #[inline]
+    pub(crate) fn state_ptrs(&self) -> &[*mut LogicState<()>] {
+        return &self.states;
+    }
+
     pub fn prop<P>(&mut self, obj_id: ObjID, prop: P) -> Result<&mut LogicProp<P>>
     where
         P: LogicPropStatic + 'static,
@@ -118,9 +293,9 @@ impl DataPool {
         state: S,
     ) -> Result<&mut LogicState<S>>
     where
-        S: LogicStateStatic + 'static,
+        S: LogicStateStatic + StateHash + 'static,
     {
-        let size = (mem::size_of::<LogicProp<S>>() + 15) & !15;
+        let size = (mem::size_of::<LogicState<S>>() + 15) & !15;
         let ptr = self.alloc(size)?;
         unsafe {
             ptr::write(
@@ -136,33 +311,160 @@ impl DataPool {
             );
         };
         self.states.push(ptr as *mut LogicState<()>);
+        self.state_hashes.push(state_hash::<S>);
+        self.state_sizes.push(mem::size_of::<S>());
         return Ok(unsafe { &mut *(ptr as *mut LogicState<S>) });
     }
 
-    fn alloc(&mut self, size: usize) -> Result<*mut u8> {
-        if size > self.threshold_size {
-            return Err(anyhow!("DataPool::alloc() => memory too large"));
+    // Finds the state an object wrote this tick, None if it wrote no state of type S.
+    pub fn find_state<S>(&self, obj_id: ObjID) -> Option<&LogicState<S>>
+    where
+        S: LogicStateStatic + 'static,
+    {
+        for ptr in &self.states {
+            let header = unsafe { &**ptr };
+            if header.obj_id == obj_id && header.class_id == S::id() {
+                return Some(unsafe { &*(*ptr as *const LogicState<S>) });
+            }
+        }
+        return None;
+    }
+
+    // Emits an event of the tick, obj_id and class_id are the object producing it.
+    pub fn event<E>(&mut self, obj_id: ObjID, class_id: ClassID, event: E)
+    where
+        E: LogicEventStatic + Copy + Serialize + 'static,
+    {
+        self.events.emit(obj_id, class_id, event);
+    }
+
+    #[inline]
+    pub fn events(&self) -> &EventPool {
+        return &self.events;
+    }
+
+    // Hashes all states ordered by ObjID then ClassID, independent of the order objects were updated in.
+    pub fn hash_states(&self, hasher: &mut dyn Hasher) {
+        let mut order: Vec<usize> = (0..self.states.len()).collect();
+        order.sort_by_key(|idx| {
+            let header = unsafe { &*self.states[*idx] };
+            return (u64::from(header.obj_id), header.class_id as u16);
+        });
+        for idx in order {
+            let header = unsafe { &*self.states[idx] };
+            hasher.write_u64(u64::from(header.obj_id));
+            hasher.write_u16(header.class_id as u16);
+            hasher.write_u8(header.lifecycle as u8);
+            (self.state_hashes[idx])(self.states[idx], hasher);
+        }
+    }
+
+    // Copies all states into a single C ABI buffer, valid until the next export, reset or drop.
+    pub fn export_states(&mut self) -> &StateExport {
+        self.export.build(&self.states, &self.state_sizes);
+        return &self.export;
+    }
+
+    // Drops all props and states but keeps the chunks, so the next tick allocates without malloc.
+    // Large chunks become spare and are reused by large allocations of a fitting size.
+    pub fn reset(&mut self) {
+        self.drop_all();
+        self.events.clear();
+        for chunk in &mut self.chunks[..=self.active_chunk] {
+            chunk.offset = 0;
         }
+        self.active_chunk = 0;
+        for mut chunk in self.large_chunks.drain(..) {
+            chunk.offset = 0;
+            self.spare_large_chunks.push(chunk);
+        }
+        self.used_bytes = 0;
+        self.wasted_bytes = 0;
+        self.resets += 1;
+    }
+
+    // Frees the chunks kept for reuse, only the ones in use remain.
+    pub fn shrink(&mut self) {
+        self.chunks.truncate(self.active_chunk + 1);
+        self.spare_large_chunks.clear();
+    }
+
+    pub fn stats(&self) -> DataPoolStats {
+        let large_reserved: usize = self
+            .large_chunks
+            .iter()
+            .chain(self.spare_large_chunks.iter())
+            .map(|chunk| chunk.size)
+            .sum();
+        return DataPoolStats {
+            chunk_size: self.chunk_size,
+            chunks: self.chunks.len(),
+            large_chunks: self.large_chunks.len(),
+            spare_large_chunks: self.spare_large_chunks.len(),
+            reserved_bytes: self.chunks.len() * self.chunk_size + large_reserved,
+            used_bytes: self.used_bytes,
+            wasted_bytes: self.wasted_bytes,
+            peak_used_bytes: self.peak_used_bytes,
+            props: self.props.len(),
+            states: self.states.len(),
+            events: self.events.len(),
+            resets: self.resets,
+        };
+    }
+
+    fn alloc(&mut self, size: usize) -> Result<*mut u8> {
+        let ptr = if size > self.threshold_size {
+            self.alloc_large(size)?
+        } else {
+            self.alloc_small(size)?
+        };
+        self.used_bytes += size;
+        self.peak_used_bytes = usize::max(self.peak_used_bytes, self.used_bytes);
+        return Ok(ptr);
+    }
 
-        let ptr = self.chunks.last_mut().unwrap().alloc(size);
+    fn alloc_small(&mut self, size: usize) -> Result<*mut u8> {
+        let ptr = self.chunks[self.active_chunk].alloc(size);
         if !ptr.is_null() {
             return Ok(ptr);
         }
 
-        self.chunks.push(MemoryChunk::new(self.chunk_size));
-        let ptr = self.chunks.last_mut().unwrap().alloc(size);
+        let chunk = &self.chunks[self.active_chunk];
+        self.wasted_bytes += chunk.size - chunk.offset;
+        self.active_chunk += 1;
+        if self.active_chunk == self.chunks.len() {
+            self.chunks.push(MemoryChunk::new(self.chunk_size));
+        }
+        let ptr = self.chunks[self.active_chunk].alloc(size);
         if !ptr.is_null() {
             return Ok(ptr);
         }
 
         return Err(anyhow!("DataPool::alloc() => unexcepted error"));
     }
-}
 
-impl Drop for DataPool {
-    fn drop(&mut self) {
-        let props = mem::replace(&mut self.props, Vec::new());
-        for prop in props {
+    fn alloc_large(&mut self, size: usize) -> Result<*mut u8> {
+        let spare = self
+            .spare_large_chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.size >= size)
+            .min_by_key(|(_, chunk)| chunk.size)
+            .map(|(idx, _)| idx);
+        let mut chunk = match spare {
+            Some(idx) => self.spare_large_chunks.swap_remove(idx),
+            None => MemoryChunk::new(size),
+        };
+        let ptr = chunk.alloc(size);
+        if ptr.is_null() {
+            return Err(anyhow!("DataPool::alloc() => unexcepted error"));
+        }
+        self.large_chunks.push(chunk);
+        return Ok(ptr);
+    }
+
+    fn drop_all(&mut self) {
+        for prop in self.props.drain(..) {
             unsafe {
                 let prop = &mut *prop;
                 let to: &mut dyn Any = mem::transmute(TraitObject {
@@ -173,8 +475,7 @@ impl Drop for DataPool {
             };
         }
 
-        let states = mem::replace(&mut self.states, Vec::new());
-        for state in states {
+        for state in self.states.drain(..) {
             unsafe {
                 let state = &mut *state;
                 let to: &mut dyn Any = mem::transmute(TraitObject {
@@ -184,6 +485,14 @@ impl Drop for DataPool {
                 ptr::drop_in_place(to);
             };
         }
+        self.state_hashes.clear();
+        self.state_sizes.clear();
+    }
+}
+
+impl Drop for DataPool {
+    fn drop(&mut self) {
+        self.drop_all();
     }
 }
 
@@ -223,3 +532,144 @@ impl Drop for MemoryChunk {
         self.offset = 0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::FnvHasher;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Each test counts its own drops, tests run in parallel.
+    #[derive(Debug)]
+    struct StateSmall(u64, Arc<AtomicUsize>);
+
+    impl LogicStateStatic for StateSmall {
+        fn id() -> ClassID {
+            return ClassID::Projectile;
+        }
+    }
+
+    impl StateHash for StateSmall {
+        fn state_hash(&self, hasher: &mut dyn Hasher) {
+            self.0.state_hash(hasher);
+        }
+    }
+
+    impl Drop for StateSmall {
+        fn drop(&mut self) {
+            self.1.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[derive(Debug)]
+    struct StateLarge([[u64; 32]; 4]);
+
+    impl LogicStateStatic for StateLarge {
+        fn id() -> ClassID {
+            return ClassID::Projectile;
+        }
+    }
+
+    impl StateHash for StateLarge {
+        fn state_hash(&self, hasher: &mut dyn Hasher) {
+            self.0.state_hash(hasher);
+        }
+    }
+
+    fn pool_hash(pool: &DataPool) -> u64 {
+        let mut hasher = FnvHasher::new();
+        pool.hash_states(&mut hasher);
+        return hasher.finish();
+    }
+
+    fn fill(pool: &mut DataPool, count: u64, drops: &Arc<AtomicUsize>) {
+        for idx in 0..count {
+            let state = pool
+                .state(
+                    ObjID::from(idx),
+                    LogicLifecycle::Running,
+                    StateSmall(idx, drops.clone()),
+                )
+                .unwrap();
+            assert_eq!(state.state.0, idx);
+        }
+    }
+
+    #[test]
+    fn test_data_pool_reset() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut pool = DataPool::new(1024);
+        fill(&mut pool, 100, &drops);
+        let stats = pool.stats();
+        assert_eq!(stats.states, 100);
+        assert_eq!(stats.used_bytes, 100 * 48);
+        assert!(stats.chunks > 1);
+
+        pool.reset();
+        assert_eq!(drops.load(Ordering::SeqCst), 100);
+        assert_eq!(pool.state_count(), 0);
+        fill(&mut pool, 100, &drops);
+        let stats2 = pool.stats();
+        assert_eq!(stats2.chunks, stats.chunks);
+        assert_eq!(stats2.reserved_bytes, stats.reserved_bytes);
+        assert_eq!(stats2.peak_used_bytes, stats.used_bytes);
+        assert_eq!(stats2.resets, 1);
+
+        pool.reset();
+        fill(&mut pool, 1, &drops);
+        pool.shrink();
+        assert_eq!(pool.stats().chunks, 1);
+        mem::drop(pool);
+        assert_eq!(drops.load(Ordering::SeqCst), 201);
+    }
+
+    #[test]
+    fn test_data_pool_large() {
+        let mut pool = DataPool::new(1024);
+        let state = pool
+            .state(
+                ObjID::from(1),
+                LogicLifecycle::Created,
+                StateLarge([[7; 32]; 4]),
+            )
+            .unwrap();
+        assert_eq!(state.state.0[3][31], 7);
+        let stats = pool.stats();
+        assert_eq!((stats.large_chunks, stats.used_bytes), (1, 32 + 1024));
+        let hash = pool_hash(&pool);
+
+        pool.reset();
+        assert_eq!(pool.stats().spare_large_chunks, 1);
+        pool.state(
+            ObjID::from(2),
+            LogicLifecycle::Created,
+            StateLarge([[8; 32]; 4]),
+        )
+        .unwrap();
+        let stats = pool.stats();
+        assert_eq!((stats.large_chunks, stats.spare_large_chunks), (1, 0));
+        assert_eq!(stats.reserved_bytes, 1024 + 32 + 1024);
+        assert_ne!(pool_hash(&pool), hash);
+    }
+
+    #[test]
+    fn test_data_pool_hash_states() {
+        let fill = |pool: &mut DataPool, ids: &[u64], lifecycle: LogicLifecycle| {
+            for id in ids {
+                pool.state(ObjID::from(*id), lifecycle, StateLarge([[*id; 32]; 4]))
+                    .unwrap();
+            }
+        };
+        let mut pool1 = DataPool::new(1024);
+        fill(&mut pool1, &[1, 2, 3], LogicLifecycle::Running);
+        let mut pool2 = DataPool::new(1024);
+        fill(&mut pool2, &[3, 1, 2], LogicLifecycle::Running);
+        assert_eq!(pool_hash(&pool1), pool_hash(&pool2));
+
+        pool2.reset();
+        fill(&mut pool2, &[1, 2], LogicLifecycle::Running);
+        fill(&mut pool2, &[3], LogicLifecycle::Destroyed);
+        assert_ne!(pool_hash(&pool1), pool_hash(&pool2));
+    }
+}
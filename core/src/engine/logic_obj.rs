@@ -1,6 +1,7 @@
 use super::logic_data::DataPool;
-use crate::id::{ClassID, ObjID};
+use crate::id::{ClassID, ObjID, ResID};
 use crate::physics::PhysicsEngine;
+use crate::resource::ResCache;
 use anyhow::Result;
 use std::mem;
 
@@ -16,6 +17,11 @@ pub trait LogicObjSuper {
 pub trait LogicObj: LogicObjSuper {
     fn update_prop(&mut self, pool: &mut DataPool) -> Result<()>;
     fn update_state(&mut self, pool: &mut DataPool) -> Result<()>;
+
+    // Re-fetches the changed resources after a hot reload, runtime values are kept.
+    fn refresh_res(&mut self, _cache: &ResCache, _changed: &[ResID]) -> Result<()> {
+        return Ok(());
+    }
 }
 
 impl dyn LogicObj {
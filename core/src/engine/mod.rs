@@ -1,17 +1,52 @@
 pub mod action;
+pub mod buff;
+pub mod chara;
+pub mod checksum;
+pub mod damage;
 pub mod engine;
+pub mod event;
+pub mod hit_box;
+pub mod hit_path;
+pub mod interpolator;
 pub mod logic_data;
 pub mod logic_obj;
 pub mod logic_obj_ref;
+pub mod motion;
 pub mod operation;
+pub mod projectile;
+pub mod snapshot;
+pub mod state_export;
+pub mod target;
 
-pub(crate) use crate::derive::{def_obj, def_prop, def_state};
+pub(crate) use crate::derive::{def_event, def_obj, def_prop, def_state};
 pub use action::*;
-pub use engine::LogicEngine;
+pub use buff::{BuffContainer, BuffState, BuffTick, CtxBuffTick};
+pub use chara::{LogicCharaHuman, StateCharaHuman};
+pub use checksum::{logic_checksum, DesyncReport, StateDump, StateDumper};
+pub use damage::{
+    CharaStats, DamageEvent, DamagePipeline, DamageReaction, HitEvent, DEFAULT_DAMAGE_SCRIPT,
+};
+pub use engine::{CmdNewCharaHuman, CmdNewPrefab, Command, LogicEngine};
+pub use event::{
+    EventActionStarted, EventBuffApplied, EventDamage, EventDead, EventDump, EventHitLanded,
+    EventID, EventIter, EventPool, EventPoolHeader, LogicEvent, LogicEventStatic,
+    EVENT_POOL_VERSION,
+};
+pub use hit_box::{HitBoxes, HitCoords};
+pub use hit_path::{HitImpact, HitPaths};
+pub use interpolator::{
+    lerp_transform, slerp_quaternion, InterpolatedTransform, StateInterpolator, StateTransform,
+};
 pub use logic_data::{
-    DataPool, LogicLifecycle, LogicProp, LogicPropStatic, LogicState, LogicStateStatic,
+    DataPool, DataPoolStats, LogicLifecycle, LogicProp, LogicPropStatic, LogicState,
+    LogicStateStatic, StateHash,
 };
 pub use logic_obj::{LogicChara, LogicStage};
 pub use logic_obj::{LogicObj, LogicObjStatic, LogicObjSuper};
 pub use logic_obj_ref::{RefObj, RefObjError, RefObjRef, RefObjRefMut};
+pub use motion::MotionCurves;
 pub use operation::{OpAction, OpCommand, OpMode, Operation};
+pub use projectile::{LogicProjectile, StateProjectile};
+pub use snapshot::{LogicSnapshot, SnapshotReader, SnapshotRing, SnapshotWriter};
+pub use state_export::{StateExport, StateExportEntry, StateExportHeader, STATE_EXPORT_VERSION};
+pub use target::{TargetCandidate, TargetLock, TargetSearch};
@@ -0,0 +1,168 @@
+use crate::lerper::Lerper;
+use crate::resource::ResMotion;
+use anyhow::Result;
+use math::Fx;
+use na::{Isometry3, Translation3, UnitQuaternion, Vector3};
+
+#[derive(Debug, Clone)]
+enum MotionCurve {
+    Move(Lerper, Translation3<Fx>, Translation3<Fx>),
+    Rotate(Lerper, UnitQuaternion<Fx>, UnitQuaternion<Fx>),
+    MoveSpeed(Lerper, Translation3<Fx>, Translation3<Fx>),
+    RotateSpeed(Lerper, UnitQuaternion<Fx>, UnitQuaternion<Fx>),
+}
+
+// Lerped ResMotion curves of a skill, Move/Rotate are offsets and MoveSpeed/RotateSpeed are per second.
+#[derive(Debug, Clone)]
+pub struct MotionCurves {
+    curves: Vec<MotionCurve>,
+}
+
+impl MotionCurves {
+    pub fn new(motions: &[ResMotion]) -> Result<MotionCurves> {
+        let mut curves = Vec::with_capacity(motions.len());
+        for motion in motions {
+            let curve = match motion {
+                ResMotion::Move(m) => MotionCurve::Move(
+                    Lerper::new(
+                        &m.lerp_function,
+                        &m.lerp_parameter,
+                        m.start_frame,
+                        m.finish_frame,
+                    )?,
+                    m.start_value,
+                    m.finish_value,
+                ),
+                ResMotion::Rotate(m) => MotionCurve::Rotate(
+                    Lerper::new(
+                        &m.lerp_function,
+                        &m.lerp_parameter,
+                        m.start_frame,
+                        m.finish_frame,
+                    )?,
+                    m.start_value,
+                    m.finish_value,
+                ),
+                ResMotion::MoveSpeed(m) => MotionCurve::MoveSpeed(
+                    Lerper::new(
+                        &m.lerp_function,
+                        &m.lerp_parameter,
+                        m.start_frame,
+                        m.finish_frame,
+                    )?,
+                    m.start_value,
+                    m.finish_value,
+                ),
+                ResMotion::RotateSpeed(m) => MotionCurve::RotateSpeed(
+                    Lerper::new(
+                        &m.lerp_function,
+                        &m.lerp_parameter,
+                        m.start_frame,
+                        m.finish_frame,
+                    )?,
+                    m.start_value,
+                    m.finish_value,
+                ),
+                ResMotion::SearchTarget(_) => continue,
+            };
+            curves.push(curve);
+        }
+        return Ok(MotionCurves { curves });
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        return self.curves.is_empty();
+    }
+
+    // Motion of the tick going from frame - 1 to frame, in the space of the motion coordinate.
+    pub fn delta(&self, frame: u32, frame_time: Fx) -> Isometry3<Fx> {
+        let mut translation = Vector3::zeros();
+        let mut rotation = UnitQuaternion::identity();
+        if frame == 0 {
+            return Isometry3::identity();
+        }
+        let prev = frame - 1;
+
+        for curve in &self.curves {
+            match curve {
+                MotionCurve::Move(lerper, start, finish) if is_ticking(lerper, frame) => {
+                    let current = lerper.translation(frame, start, finish);
+                    let last = lerper.translation(prev, start, finish);
+                    translation += current.vector - last.vector;
+                }
+                MotionCurve::Rotate(lerper, start, finish) if is_ticking(lerper, frame) => {
+                    let current = lerper.rotation(frame, start, finish);
+                    let last = lerper.rotation(prev, start, finish);
+                    rotation = rotation * (last.inverse() * current);
+                }
+                MotionCurve::MoveSpeed(lerper, start, finish) if is_ticking(lerper, frame) => {
+                    let speed = lerper.translation(prev, start, finish);
+                    translation += speed.vector * frame_time;
+                }
+                MotionCurve::RotateSpeed(lerper, start, finish) if is_ticking(lerper, frame) => {
+                    let speed = lerper.rotation(prev, start, finish);
+                    rotation = rotation
+                        * UnitQuaternion::from_scaled_axis(speed.scaled_axis() * frame_time);
+                }
+                _ => {}
+            }
+        }
+        return Isometry3::from_parts(Translation3::from(translation), rotation);
+    }
+
+    // Applies the motion of the tick on a position expressed in the motion coordinate.
+    pub fn apply(&self, position: &mut Isometry3<Fx>, frame: u32, frame_time: Fx) {
+        *position = *position * self.delta(frame, frame_time);
+    }
+}
+
+#[inline]
+fn is_ticking(lerper: &Lerper, frame: u32) -> bool {
+    return lerper.start_frame() < frame && frame <= lerper.finish_frame();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::{ff, fi};
+    use na::{ComplexField, RealField};
+
+    fn new_curves(text: &str) -> MotionCurves {
+        let motions: Vec<ResMotion> = serde_yaml::from_str(text).unwrap();
+        return MotionCurves::new(&motions).unwrap();
+    }
+
+    #[test]
+    fn test_motion_curves_move() {
+        let curves = new_curves(
+            "- { type: Move, start_frame: 0, finish_frame: 10, start_value: [0, 0, 0], finish_value: [0, 0, 10], lerp_function: QuadOut }\n- { type: MoveSpeed, start_frame: 2, finish_frame: 6, start_value: [2, 0, 0], finish_value: [2, 0, 0] }\n",
+        );
+        let mut position = Isometry3::identity();
+        let mut steps = Vec::new();
+        for frame in 1..=12 {
+            curves.apply(&mut position, frame, ff(0.5));
+            steps.push(position.translation.vector.z);
+        }
+        assert_eq!(
+            position.translation.vector,
+            Vector3::new(fi(4), fi(0), fi(10))
+        );
+        // QuadOut moves faster at the beginning.
+        assert!(steps[0] > steps[9] - steps[8]);
+        assert_eq!(steps[10], steps[11]);
+    }
+
+    #[test]
+    fn test_motion_curves_rotate() {
+        let curves = new_curves(
+            "- { type: Rotate, start_frame: 0, finish_frame: 4, start_value: [0, 0, 0, 1], finish_value: [0, 0.70710678, 0, 0.70710678] }\n",
+        );
+        let mut position = Isometry3::identity();
+        for frame in 1..=4 {
+            curves.apply(&mut position, frame, ff(0.5));
+        }
+        assert!((position.rotation.angle() - Fx::frac_pi_2()).abs() < ff(0.001));
+        assert!(curves.delta(5, ff(0.5)).rotation.angle() < ff(0.0001));
+    }
+}
@@ -0,0 +1,41 @@
+use crate::id::ObjID;
+use math::Fx;
+use na::Vector2;
+use serde::{Deserialize, Serialize};
+
+// Frame of reference of the command directions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OpMode {
+    Camera,
+    Character,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OpAction {
+    Press,
+    HoldBegin,
+    HoldEnd,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OpCommand {
+    Move(Vector2<Fx>),
+    Dash(Vector2<Fx>),
+    Jump,
+    Attack1(OpAction, Vector2<Fx>),
+    Attack2(OpAction, Vector2<Fx>),
+    Defend(OpAction, Vector2<Fx>),
+    Skill1(OpAction),
+    Skill2(OpAction),
+    SkillEx(OpAction),
+    Item1,
+    Item2,
+    Item3,
+    Interact,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Operation {
+    pub obj_id: ObjID,
+    pub command: OpCommand,
+}
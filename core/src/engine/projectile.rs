@@ -0,0 +1,595 @@
+use super::damage::HitEvent;
+use super::event::EventHitLanded;
+use super::hit_box::HitCoords;
+use super::interpolator::StateTransform;
+use super::logic_data::{DataPool, LogicLifecycle};
+use super::logic_obj::{LogicHit, LogicObj};
+use super::snapshot::{LogicSnapshot, SnapshotReader, SnapshotWriter};
+use super::target::{TargetLock, TargetSearch};
+use crate::derive::{def_obj, def_state};
+use crate::ffi::{FFIQuaternion, FFITransform, FFIVec3f};
+use crate::id::{ClassID, FastResID, ObjID, ResID};
+use crate::lerper::Lerper;
+use crate::resource::{ResCache, ResProjectile};
+use crate::utils::Prng;
+use anyhow::{anyhow, Result};
+use collide::pipeline::{
+    CollisionGroups, CollisionObjectSlabHandle, CollisionObjectType, CollisionWorld,
+    GeometricQueryType,
+};
+use math::{fi, Fx};
+use na::{Isometry3, Unit, UnitQuaternion, Vector3};
+use serde::Serialize;
+use std::sync::Arc;
+
+#[def_state(ClassID::Projectile)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct StateProjectile {
+    pub source: ObjID,
+    pub fres_id: FastResID,
+    pub frame: u32,
+    pub target: ObjID,
+    pub position: FFIVec3f,
+    pub rotation: FFIQuaternion,
+}
+
+impl StateTransform for StateProjectile {
+    fn transform(&self) -> FFITransform {
+        return FFITransform::new(self.position, self.rotation);
+    }
+}
+
+#[def_obj(ClassID::Projectile)]
+#[derive(Debug)]
+pub struct LogicProjectile {
+    obj_id: ObjID,
+    source: ObjID,
+    res: Arc<ResProjectile>,
+    lifecycle: LogicLifecycle,
+    frame: u32,
+    position: Isometry3<Fx>,
+    fall_speed: Fx,
+    groups: CollisionGroups,
+    handle: Option<CollisionObjectSlabHandle>,
+    speed: Lerper,
+    search: TargetSearch,
+    lock: TargetLock,
+    hits: Vec<ObjID>,
+    // Hits already emitted as EventHitLanded.
+    emitted_hits: usize,
+    impacts: Vec<(CollisionObjectSlabHandle, Fx)>,
+}
+
+impl LogicProjectile {
+    // Launches from res.launch_transform in res.launch_coord, with a Hit collision object in world.
+    pub fn new<T: 'static>(
+        obj_id: ObjID,
+        source: ObjID,
+        res: Arc<ResProjectile>,
+        coords: &HitCoords,
+        groups: CollisionGroups,
+        world: &mut CollisionWorld<T>,
+        data: T,
+    ) -> Result<LogicProjectile> {
+        let speed = Lerper::new(
+            &res.speed.lerp_function,
+            &res.speed.lerp_parameter,
+            0,
+            res.lifetime,
+        )?;
+        let position = coords.coordinate(res.launch_coord) * res.launch_transform;
+        let (handle, _) = world.add(
+            CollisionObjectType::Hit,
+            position * res.shape.transform,
+            res.shape.handle.clone(),
+            groups,
+            GeometricQueryType::Proximity(fi(0)),
+            data,
+        );
+        return Ok(LogicProjectile {
+            obj_id,
+            source,
+            res,
+            lifecycle: LogicLifecycle::Created,
+            frame: 0,
+            position,
+            fall_speed: fi(0),
+            groups,
+            handle: Some(handle),
+            speed,
+            search: TargetSearch::new(),
+            lock: TargetLock::new(),
+            hits: Vec::new(),
+            emitted_hits: 0,
+            impacts: Vec::new(),
+        });
+    }
+
+    // An unlaunched projectile of res, its snapshot is loaded into it on rollback.
+    pub(crate) fn from_res(res: Arc<ResProjectile>) -> Result<LogicProjectile> {
+        let speed = Lerper::new(
+            &res.speed.lerp_function,
+            &res.speed.lerp_parameter,
+            0,
+            res.lifetime,
+        )?;
+        return Ok(LogicProjectile {
+            obj_id: ObjID::invalid(),
+            source: ObjID::invalid(),
+            res,
+            lifecycle: LogicLifecycle::Created,
+            frame: 0,
+            position: Isometry3::identity(),
+            fall_speed: fi(0),
+            groups: CollisionGroups {
+                team_membership: 0,
+                team_whitelist: 0,
+                role_membership: 0,
+                role_whitelist: 0,
+            },
+            handle: None,
+            speed,
+            search: TargetSearch::new(),
+            lock: TargetLock::new(),
+            hits: Vec::new(),
+            emitted_hits: 0,
+            impacts: Vec::new(),
+        });
+    }
+
+    #[inline]
+    pub fn res(&self) -> &Arc<ResProjectile> {
+        return &self.res;
+    }
+
+    #[inline]
+    pub fn position(&self) -> &Isometry3<Fx> {
+        return &self.position;
+    }
+
+    #[inline]
+    pub fn target(&self) -> Option<ObjID> {
+        return self.lock.target();
+    }
+
+    #[inline]
+    pub fn is_destroyed(&self) -> bool {
+        return self.lifecycle == LogicLifecycle::Destroyed;
+    }
+
+    // Moves the projectile by one tick and appends its hits in impact order.
+    // chara maps collision data to a character and whether it guards, None marks an obstacle
+    // that stops the projectile.
+    pub fn update<T: 'static, F: Fn(&T) -> Option<(ObjID, bool)>>(
+        &mut self,
+        world: &mut CollisionWorld<T>,
+        frame_time: Fx,
+        prng: &mut Prng,
+        chara: F,
+        hits: &mut Vec<HitEvent>,
+    ) {
+        if self.is_destroyed() {
+            return;
+        }
+        self.frame += 1;
+        let source = self.source;
+        let target_id = |data: &T| {
+            return chara(data)
+                .map(|(obj_id, _)| obj_id)
+                .filter(|obj_id| *obj_id != source);
+        };
+
+        if let Some(homing) = &self.res.homing {
+            let (start_frame, finish_frame) =
+                (homing.search.start_frame, homing.search.finish_frame);
+            if self.lock.target().is_none()
+                && start_frame <= self.frame
+                && self.frame <= finish_frame
+            {
+                let found = self.search.search(
+                    world,
+                    &homing.search,
+                    &self.position,
+                    &self.groups,
+                    prng,
+                    &target_id,
+                );
+                if let Some(found) = found {
+                    self.lock.lock(&found);
+                }
+            }
+            self.lock.update(world, &target_id);
+            if let Some(target) = self.lock.position() {
+                self.turn_toward(&target.translation.vector, homing.turn_speed * frame_time);
+            }
+        }
+
+        let speed = self.speed.value(
+            self.frame,
+            self.res.speed.start_speed,
+            self.res.speed.finish_speed,
+        );
+        self.fall_speed += self.res.gravity * frame_time;
+        let forward = self.position.rotation * Vector3::z();
+        let delta = forward * speed * frame_time - Vector3::y() * self.fall_speed * frame_time;
+
+        let mut destroy = self.frame >= self.res.lifetime;
+        let distance = delta.norm();
+        let mut moved = delta;
+        if distance > fi(0) {
+            let direction = Unit::new_normalize(delta);
+            self.sweep(world, &direction, distance);
+            for (handle, toi) in &self.impacts {
+                let co = match world.collision_object(*handle) {
+                    Some(co) => co,
+                    None => continue,
+                };
+                let (target, guarding) = match chara(co.data()) {
+                    Some(target) => target,
+                    None => {
+                        moved = direction.into_inner() * *toi;
+                        destroy = true;
+                        break;
+                    }
+                };
+                if target == source || self.hits.contains(&target) {
+                    continue;
+                }
+                self.hits.push(target);
+                hits.push(HitEvent {
+                    source,
+                    target,
+                    damage: self.res.damage.clone(),
+                    scale: self.res.damage_scale,
+                    hit_stun: self.res.hit_stun,
+                    knock_down: self.res.knock_down,
+                    guarding,
+                });
+                if self.hits.len() as u32 > self.res.pierce {
+                    moved = direction.into_inner() * *toi;
+                    destroy = true;
+                    break;
+                }
+            }
+        }
+        self.position.translation.vector += moved;
+
+        if destroy {
+            self.destroy(world);
+        } else if let Some(handle) = self.handle {
+            if let Some(co) = world.get_mut(handle) {
+                co.set_position(self.position * self.res.shape.transform);
+            }
+        }
+    }
+
+    pub fn destroy<T: 'static>(&mut self, world: &mut CollisionWorld<T>) {
+        if let Some(handle) = self.handle.take() {
+            world.remove(&[handle]);
+        }
+        self.lifecycle = LogicLifecycle::Destroyed;
+    }
+
+    fn sweep<T: 'static>(
+        &mut self,
+        world: &mut CollisionWorld<T>,
+        direction: &Unit<Vector3<Fx>>,
+        distance: Fx,
+    ) {
+        let mut tois = Vec::new();
+        world.sweep_test(
+            CollisionObjectType::Move,
+            self.res.shape.handle.as_ref(),
+            &(self.position * self.res.shape.transform),
+            direction,
+            distance,
+            &self.groups,
+            &mut tois,
+        );
+        self.impacts.clear();
+        self.impacts
+            .extend(tois.into_iter().map(|(handle, toi)| (handle, toi.toi)));
+        self.impacts.sort_by(|a, b| {
+            return a.1.cmp(&b.1).then((a.0).0.cmp(&(b.0).0));
+        });
+    }
+
+    fn turn_toward(&mut self, target: &Vector3<Fx>, max_angle: Fx) {
+        let forward = self.position.rotation * Vector3::z();
+        let to_target = target - self.position.translation.vector;
+        if to_target.norm() == fi(0) {
+            return;
+        }
+        let turn = match UnitQuaternion::rotation_between(&forward, &to_target) {
+            Some(turn) => turn,
+            None => return,
+        };
+        let turn = match turn.axis() {
+            Some(axis) if turn.angle() > max_angle => {
+                UnitQuaternion::from_axis_angle(&axis, max_angle)
+            }
+            _ => turn,
+        };
+        self.position.rotation = turn * self.position.rotation;
+    }
+}
+
+// The resource is saved by the owner, the projectile is recreated from it before loading.
+impl LogicSnapshot for LogicProjectile {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_obj_id(self.obj_id);
+        writer.write_obj_id(self.source);
+        writer.write_lifecycle(self.lifecycle);
+        writer.write_u32(self.frame);
+        writer.write_isometry(&self.position);
+        writer.write_fx(self.fall_speed);
+        writer.write_groups(&self.groups);
+        writer.write_bool(self.handle.is_some());
+        if let Some(handle) = self.handle {
+            writer.write_handle(handle);
+        }
+        self.lock.save_snapshot(writer);
+        writer.write_u32(self.hits.len() as u32);
+        for target in &self.hits {
+            writer.write_obj_id(*target);
+        }
+        writer.write_u32(self.emitted_hits as u32);
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        self.obj_id = reader.read_obj_id()?;
+        self.source = reader.read_obj_id()?;
+        self.lifecycle = reader.read_lifecycle()?;
+        self.frame = reader.read_u32()?;
+        self.position = reader.read_isometry()?;
+        self.fall_speed = reader.read_fx()?;
+        self.groups = reader.read_groups()?;
+        self.handle = match reader.read_bool()? {
+            true => Some(reader.read_handle()?),
+            false => None,
+        };
+        self.lock.load_snapshot(reader)?;
+        self.hits.clear();
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            self.hits.push(reader.read_obj_id()?);
+        }
+        self.emitted_hits = reader.read_u32()? as usize;
+        if self.emitted_hits > self.hits.len() {
+            return Err(anyhow!(
+                "Emitted hits {} > {}",
+                self.emitted_hits,
+                self.hits.len()
+            ));
+        }
+        return Ok(());
+    }
+}
+
+impl LogicObj for LogicProjectile {
+    fn update_prop(&mut self, _pool: &mut DataPool) -> Result<()> {
+        return Ok(());
+    }
+
+    fn update_state(&mut self, pool: &mut DataPool) -> Result<()> {
+        pool.state(
+            self.obj_id,
+            self.lifecycle,
+            StateProjectile {
+                source: self.source,
+                fres_id: self.res.fres_id,
+                frame: self.frame,
+                target: self.lock.target().unwrap_or_else(ObjID::invalid),
+                position: FFIVec3f::from(self.position.translation.vector),
+                rotation: FFIQuaternion::from(self.position.rotation),
+            },
+        )?;
+        for target in &self.hits[self.emitted_hits..] {
+            pool.event(
+                self.obj_id,
+                ClassID::Projectile,
+                EventHitLanded {
+                    source: self.source,
+                    target: *target,
+                    position: FFIVec3f::from(self.position.translation.vector),
+                },
+            );
+        }
+        self.emitted_hits = self.hits.len();
+        if self.lifecycle == LogicLifecycle::Created {
+            self.lifecycle = LogicLifecycle::Running;
+        }
+        return Ok(());
+    }
+
+    // The flight keeps its frame, position and target, speed and damage follow the reloaded res.
+    fn refresh_res(&mut self, cache: &ResCache, changed: &[ResID]) -> Result<()> {
+        if !changed.contains(&self.res.res_id) {
+            return Ok(());
+        }
+        let res = cache
+            .find_res_by_id(&self.res.res_id)?
+            .cast_as::<ResProjectile>()?;
+        self.speed = Lerper::new(
+            &res.speed.lerp_function,
+            &res.speed.lerp_parameter,
+            0,
+            res.lifetime,
+        )?;
+        self.res = res;
+        return Ok(());
+    }
+}
+
+impl LogicHit for LogicProjectile {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collide::shape::{Ball, ShapeHandle};
+    use math::ff;
+    use na::ComplexField;
+
+    fn groups() -> CollisionGroups {
+        return CollisionGroups {
+            team_membership: 1,
+            team_whitelist: 0xFFFF,
+            role_membership: 1,
+            role_whitelist: 0xFFFF,
+        };
+    }
+
+    // Data 0 is an obstacle, any other value is the ObjID of a character.
+    fn new_world(objects: &[(u64, Vector3<Fx>)]) -> CollisionWorld<u64> {
+        let mut world = CollisionWorld::new(fi(0));
+        for (data, position) in objects {
+            world.add(
+                CollisionObjectType::Move,
+                Isometry3::new(*position, na::zero()),
+                ShapeHandle::new(Ball::new(ff(0.5))),
+                groups(),
+                GeometricQueryType::Contacts(fi(0), fi(0)),
+                *data,
+            );
+        }
+        world.update(&[CollisionObjectType::Move]);
+        return world;
+    }
+
+    fn new_projectile(world: &mut CollisionWorld<u64>, extra: &str) -> LogicProjectile {
+        let mut res: ResProjectile = serde_yaml::from_str(&format!(
+            "res_id: Projectile.Test\nshape: {{ type: Ball, radius: 0.1 }}\nlaunch_coord: Source\nlaunch_transform:\n  rotation: [0, 0, 0]\n  translation: [0, 0, 0]\nlifetime: 20\nspeed: {{ start_speed: 16, finish_speed: 16 }}\ndamage: {{ health: 10, energy: 0, posture: 0, physical: 0, elemental: 0, arcane: 0 }}\n{}",
+            extra
+        ))
+        .unwrap();
+        res.shape.handle = ShapeHandle::new(Ball::new(ff(0.1)));
+        if let Some(homing) = &mut res.homing {
+            homing.search.shape.handle = ShapeHandle::new(Ball::new(fi(10)));
+        }
+        let coords = HitCoords {
+            source: Isometry3::identity(),
+            target: None,
+            skill: Isometry3::identity(),
+            bones: &[],
+        };
+        return LogicProjectile::new(
+            ObjID::from(100),
+            ObjID::from(9),
+            Arc::new(res),
+            &coords,
+            groups(),
+            world,
+            0,
+        )
+        .unwrap();
+    }
+
+    fn run(
+        projectile: &mut LogicProjectile,
+        world: &mut CollisionWorld<u64>,
+        ticks: u32,
+    ) -> Vec<HitEvent> {
+        let mut prng = Prng::new(1);
+        let mut hits = Vec::new();
+        for _ in 0..ticks {
+            // 1 / 16 second per tick, the projectile moves 1 unit per tick.
+            projectile.update(
+                world,
+                ff(0.0625),
+                &mut prng,
+                // Character 2 guards.
+                |data| match *data {
+                    0 => None,
+                    id => Some((ObjID::from(id), id == 2)),
+                },
+                &mut hits,
+            );
+        }
+        return hits;
+    }
+
+    #[test]
+    fn test_projectile_pierce() {
+        let mut world = new_world(&[
+            (9, Vector3::new(fi(0), fi(0), fi(0))),
+            (1, Vector3::new(fi(0), fi(0), fi(3))),
+            (2, Vector3::new(fi(0), fi(0), fi(5))),
+            (3, Vector3::new(fi(0), fi(0), fi(7))),
+        ]);
+        let mut projectile = new_projectile(&mut world, "pierce: 1\n");
+        assert_eq!(world.objects.len(), 5);
+
+        let hits = run(&mut projectile, &mut world, 20);
+        let targets: Vec<_> = hits.iter().map(|hit| hit.target).collect();
+        assert_eq!(targets, vec![ObjID::from(1), ObjID::from(2)]);
+        assert_eq!(hits[0].source, ObjID::from(9));
+        assert_eq!(hits[0].damage.health, 10);
+        assert_eq!((hits[0].guarding, hits[1].guarding), (false, true));
+        assert!(projectile.is_destroyed());
+        assert!((projectile.position().translation.vector.z - ff(4.4)).abs() < ff(0.01));
+        assert_eq!(world.objects.len(), 4);
+    }
+
+    #[test]
+    fn test_projectile_obstacle_and_lifetime() {
+        let mut world = new_world(&[(0, Vector3::new(fi(0), fi(0), fi(4)))]);
+        let mut projectile = new_projectile(&mut world, "");
+        let hits = run(&mut projectile, &mut world, 3);
+        assert!(hits.is_empty());
+        assert!(!projectile.is_destroyed());
+        run(&mut projectile, &mut world, 1);
+        assert!(projectile.is_destroyed());
+        assert!((projectile.position().translation.vector.z - ff(3.4)).abs() < ff(0.01));
+
+        let mut world = new_world(&[]);
+        let mut projectile = new_projectile(&mut world, "gravity: 8\n");
+        run(&mut projectile, &mut world, 19);
+        assert!(!projectile.is_destroyed());
+        assert!(projectile.position().translation.vector.y < fi(0));
+        run(&mut projectile, &mut world, 1);
+        assert!(projectile.is_destroyed());
+    }
+
+    #[test]
+    fn test_projectile_homing() {
+        let mut world = new_world(&[
+            (9, Vector3::new(fi(0), fi(0), fi(0))),
+            (1, Vector3::new(fi(3), fi(0), fi(4))),
+        ]);
+        let mut projectile = new_projectile(
+            &mut world,
+            "homing:\n  turn_speed: 100\n  search:\n    start_frame: 0\n    finish_frame: 10\n    shape: { type: Ball, radius: 10 }\n    transform:\n      rotation: [0, 0, 0]\n      translation: [0, 0, 0]\n    searcher: Nearest\n",
+        );
+        let hits = run(&mut projectile, &mut world, 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].target, ObjID::from(1));
+        assert!(projectile.is_destroyed());
+
+        let mut pool = DataPool::new(1024 * 16);
+        projectile.update_state(&mut pool).unwrap();
+        let events: Vec<_> = pool.events().iter().collect();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].obj_id(), ObjID::from(100));
+        let hit = events[0].cast::<EventHitLanded>().unwrap();
+        assert_eq!((hit.source, hit.target), (ObjID::from(9), ObjID::from(1)));
+
+        pool.reset();
+        projectile.update_state(&mut pool).unwrap();
+        assert!(pool.events().is_empty());
+    }
+
+    #[test]
+    fn test_projectile_homing_window() {
+        let mut world = new_world(&[
+            (9, Vector3::new(fi(0), fi(0), fi(0))),
+            (1, Vector3::new(fi(3), fi(0), fi(4))),
+        ]);
+        // The search runs on its finish frame too.
+        let mut projectile = new_projectile(
+            &mut world,
+            "homing:\n  turn_speed: 100\n  search:\n    start_frame: 1\n    finish_frame: 1\n    shape: { type: Ball, radius: 10 }\n    transform:\n      rotation: [0, 0, 0]\n      translation: [0, 0, 0]\n    searcher: Nearest\n",
+        );
+        run(&mut projectile, &mut world, 1);
+        assert_eq!(projectile.target(), Some(ObjID::from(1)));
+    }
+}
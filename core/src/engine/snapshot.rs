@@ -0,0 +1,711 @@
+use super::logic_data::LogicLifecycle;
+use super::operation::{OpAction, OpCommand, Operation};
+use crate::id::{ObjID, ObjIDGener};
+use crate::resource::ResActionTrigger;
+use crate::utils::Prng;
+use anyhow::{anyhow, Result};
+use collide::pipeline::{
+    CollisionGroups, CollisionObjectSlabHandle, CollisionObjectType, CollisionWorld,
+    GeometricQueryType,
+};
+use collide::shape::ShapeHandle;
+use math::Fx;
+use na::{Isometry3, Quaternion, Translation3, UnitQuaternion, Vector2, Vector3};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+type SnapshotRef = Arc<dyn Any + Send + Sync>;
+
+// Little endian buffer of a rollback snapshot, read back in the order it was written.
+// Shared resources and shapes are kept aside as references, the buffer only holds their index.
+#[derive(Debug, Default, Clone)]
+pub struct SnapshotWriter {
+    buf: Vec<u8>,
+    refs: Vec<SnapshotRef>,
+}
+
+impl SnapshotWriter {
+    pub fn new() -> SnapshotWriter {
+        return SnapshotWriter::default();
+    }
+
+    pub fn with_capacity(capacity: usize) -> SnapshotWriter {
+        return SnapshotWriter {
+            buf: Vec::with_capacity(capacity),
+            refs: Vec::new(),
+        };
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.refs.clear();
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        return self.buf.len();
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        return &self.buf;
+    }
+
+    #[inline]
+    pub fn reader(&self) -> SnapshotReader {
+        return SnapshotReader::with_refs(&self.buf, &self.refs);
+    }
+
+    #[inline]
+    pub fn write_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    #[inline]
+    pub fn write_bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    #[inline]
+    pub fn write_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    pub fn write_i32(&mut self, value: i32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    pub fn write_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    pub fn write_fx(&mut self, value: Fx) {
+        self.buf.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+
+    #[inline]
+    pub fn write_obj_id(&mut self, value: ObjID) {
+        self.write_u64(u64::from(value));
+    }
+
+    #[inline]
+    pub fn write_handle(&mut self, value: CollisionObjectSlabHandle) {
+        self.write_u32(value.0 as u32);
+    }
+
+    #[inline]
+    pub fn write_lifecycle(&mut self, value: LogicLifecycle) {
+        self.write_u8(value as u8);
+    }
+
+    #[inline]
+    pub fn write_trigger(&mut self, value: ResActionTrigger) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_groups(&mut self, value: &CollisionGroups) {
+        self.buf
+            .extend_from_slice(&value.team_membership.to_le_bytes());
+        self.buf
+            .extend_from_slice(&value.team_whitelist.to_le_bytes());
+        self.buf
+            .extend_from_slice(&value.role_membership.to_le_bytes());
+        self.buf
+            .extend_from_slice(&value.role_whitelist.to_le_bytes());
+    }
+
+    pub fn write_vec2(&mut self, value: &Vector2<Fx>) {
+        self.write_fx(value.x);
+        self.write_fx(value.y);
+    }
+
+    pub fn write_vec3(&mut self, value: &Vector3<Fx>) {
+        self.write_fx(value.x);
+        self.write_fx(value.y);
+        self.write_fx(value.z);
+    }
+
+    pub fn write_isometry(&mut self, value: &Isometry3<Fx>) {
+        self.write_vec3(&value.translation.vector);
+        let coords = &value.rotation.coords;
+        self.write_fx(coords.x);
+        self.write_fx(coords.y);
+        self.write_fx(coords.z);
+        self.write_fx(coords.w);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    // Keeps a shared value without copying it, such as a resource of a logic object.
+    pub fn write_ref<T: Any + Send + Sync>(&mut self, value: &Arc<T>) {
+        self.write_u32(self.refs.len() as u32);
+        self.refs.push(value.clone());
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotReader<'t> {
+    buf: &'t [u8],
+    refs: &'t [SnapshotRef],
+    pos: usize,
+}
+
+impl<'t> SnapshotReader<'t> {
+    // A reader over raw bytes, references can't be read back.
+    pub fn new(buf: &'t [u8]) -> SnapshotReader<'t> {
+        return SnapshotReader::with_refs(buf, &[]);
+    }
+
+    pub fn with_refs(buf: &'t [u8], refs: &'t [SnapshotRef]) -> SnapshotReader<'t> {
+        return SnapshotReader { buf, refs, pos: 0 };
+    }
+
+    #[inline]
+    pub fn is_end(&self) -> bool {
+        return self.pos >= self.buf.len();
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'t [u8]> {
+        if self.pos + size > self.buf.len() {
+            return Err(anyhow!("Snapshot truncated at {}", self.pos));
+        }
+        let bytes = &self.buf[self.pos..self.pos + size];
+        self.pos += size;
+        return Ok(bytes);
+    }
+
+    #[inline]
+    pub fn read_u8(&mut self) -> Result<u8> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    #[inline]
+    pub fn read_bool(&mut self) -> Result<bool> {
+        return Ok(self.read_u8()? != 0);
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        return Ok(u32::from_le_bytes(bytes));
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        return Ok(i32::from_le_bytes(bytes));
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let mut bytes = [0u8; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        return Ok(u16::from_le_bytes(bytes));
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        return Ok(u64::from_le_bytes(bytes));
+    }
+
+    pub fn read_fx(&mut self) -> Result<Fx> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        return Ok(Fx::from_bits(i64::from_le_bytes(bytes)));
+    }
+
+    #[inline]
+    pub fn read_obj_id(&mut self) -> Result<ObjID> {
+        return Ok(ObjID::from(self.read_u64()?));
+    }
+
+    #[inline]
+    pub fn read_handle(&mut self) -> Result<CollisionObjectSlabHandle> {
+        return Ok(CollisionObjectSlabHandle(self.read_u32()? as usize));
+    }
+
+    pub fn read_lifecycle(&mut self) -> Result<LogicLifecycle> {
+        return match self.read_u8()? {
+            0 => Ok(LogicLifecycle::Created),
+            1 => Ok(LogicLifecycle::Running),
+            2 => Ok(LogicLifecycle::Destroyed),
+            tag => Err(anyhow!("Unknown lifecycle tag {}", tag)),
+        };
+    }
+
+    pub fn read_trigger(&mut self) -> Result<ResActionTrigger> {
+        use ResActionTrigger::*;
+        const TRIGGERS: [ResActionTrigger; 14] = [
+            Idle, Move, Dash, Jump, Attack1, Attack2, Defend, DefendEnd, Skill1, Skill2, SkillEx,
+            Finish, Hit, KnockDown,
+        ];
+        let tag = self.read_u8()?;
+        return match TRIGGERS.get(tag as usize) {
+            Some(trigger) => Ok(*trigger),
+            None => Err(anyhow!("Unknown trigger tag {}", tag)),
+        };
+    }
+
+    pub fn read_groups(&mut self) -> Result<CollisionGroups> {
+        return Ok(CollisionGroups {
+            team_membership: self.read_u16()?,
+            team_whitelist: self.read_u16()?,
+            role_membership: self.read_u16()?,
+            role_whitelist: self.read_u16()?,
+        });
+    }
+
+    pub fn read_vec2(&mut self) -> Result<Vector2<Fx>> {
+        return Ok(Vector2::new(self.read_fx()?, self.read_fx()?));
+    }
+
+    pub fn read_vec3(&mut self) -> Result<Vector3<Fx>> {
+        return Ok(Vector3::new(
+            self.read_fx()?,
+            self.read_fx()?,
+            self.read_fx()?,
+        ));
+    }
+
+    pub fn read_isometry(&mut self) -> Result<Isometry3<Fx>> {
+        let translation = self.read_vec3()?;
+        let (x, y, z, w) = (
+            self.read_fx()?,
+            self.read_fx()?,
+            self.read_fx()?,
+            self.read_fx()?,
+        );
+        // The quaternion was unit when saved, renormalizing would change the bits.
+        return Ok(Isometry3::from_parts(
+            Translation3::from(translation),
+            UnitQuaternion::new_unchecked(Quaternion::new(w, x, y, z)),
+        ));
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'t [u8]> {
+        let len = self.read_u32()? as usize;
+        return self.take(len);
+    }
+
+    pub fn read_ref<T: Any + Send + Sync>(&mut self) -> Result<Arc<T>> {
+        let idx = self.read_u32()? as usize;
+        let value = match self.refs.get(idx) {
+            Some(value) => value.clone(),
+            None => return Err(anyhow!("Snapshot reference {} not found", idx)),
+        };
+        return value
+            .downcast::<T>()
+            .map_err(|_| anyhow!("Snapshot reference {} type mismatch", idx));
+    }
+}
+
+// State that is saved every tick and restored on rollback, loading must restore it bit for bit.
+pub trait LogicSnapshot {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter);
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()>;
+}
+
+impl LogicSnapshot for Prng {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_u64(self.state());
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        self.set_state(reader.read_u64()?);
+        return Ok(());
+    }
+}
+
+impl LogicSnapshot for ObjIDGener {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_u64(self.counter());
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        self.set_counter(reader.read_u64()?);
+        return Ok(());
+    }
+}
+
+// The whole object set is saved under its handles, so a rollback restores the objects spawned or
+// despawned since. Handles kept by logic objects stay valid, and the next handles match as well.
+impl LogicSnapshot for CollisionWorld<u64> {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_u32(self.objects.len() as u32);
+        for (handle, co) in self.collision_objects() {
+            writer.write_handle(handle);
+            writer.write_u8(co.obj_type() as u8);
+            writer.write_ref(&Arc::new(co.shape().clone()));
+            writer.write_groups(co.collision_groups());
+            match co.query_type() {
+                GeometricQueryType::Contacts(linear, angular) => {
+                    writer.write_u8(0);
+                    writer.write_fx(linear);
+                    writer.write_fx(angular);
+                }
+                GeometricQueryType::Proximity(linear) => {
+                    writer.write_u8(1);
+                    writer.write_fx(linear);
+                }
+            };
+            writer.write_isometry(co.position());
+            writer.write_u64(*co.data());
+        }
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        let handles: Vec<_> = self.collision_objects().map(|(handle, _)| handle).collect();
+        self.remove(&handles);
+
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            let handle = reader.read_handle()?;
+            let obj_type = match reader.read_u8()? {
+                0 => CollisionObjectType::Static,
+                1 => CollisionObjectType::Move,
+                2 => CollisionObjectType::Hit,
+                tag => return Err(anyhow!("Unknown collision object type {}", tag)),
+            };
+            let shape = reader.read_ref::<ShapeHandle<Fx>>()?;
+            let groups = reader.read_groups()?;
+            let query_type = match reader.read_u8()? {
+                0 => GeometricQueryType::Contacts(reader.read_fx()?, reader.read_fx()?),
+                1 => GeometricQueryType::Proximity(reader.read_fx()?),
+                tag => return Err(anyhow!("Unknown query type {}", tag)),
+            };
+            let position = reader.read_isometry()?;
+            let data = reader.read_u64()?;
+            if self.collision_object(handle).is_some() {
+                return Err(anyhow!("Collision object {:?} duplicated", handle));
+            }
+            self.add_at(
+                handle,
+                obj_type,
+                position,
+                (*shape).clone(),
+                groups,
+                query_type,
+                data,
+            );
+        }
+        return Ok(());
+    }
+}
+
+// Pending operations, not yet consumed by the engine.
+impl LogicSnapshot for Vec<Operation> {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_u32(self.len() as u32);
+        for op in self {
+            writer.write_obj_id(op.obj_id);
+            save_command(&op.command, writer);
+        }
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        self.clear();
+        let count = reader.read_u32()?;
+        for _ in 0..count {
+            let obj_id = reader.read_obj_id()?;
+            let command = load_command(reader)?;
+            self.push(Operation { obj_id, command });
+        }
+        return Ok(());
+    }
+}
+
+fn save_command(command: &OpCommand, writer: &mut SnapshotWriter) {
+    match command {
+        OpCommand::Move(dir) => {
+            writer.write_u8(0);
+            writer.write_vec2(dir);
+        }
+        OpCommand::Dash(dir) => {
+            writer.write_u8(1);
+            writer.write_vec2(dir);
+        }
+        OpCommand::Jump => writer.write_u8(2),
+        OpCommand::Attack1(action, dir) => {
+            writer.write_u8(3);
+            writer.write_u8(*action as u8);
+            writer.write_vec2(dir);
+        }
+        OpCommand::Attack2(action, dir) => {
+            writer.write_u8(4);
+            writer.write_u8(*action as u8);
+            writer.write_vec2(dir);
+        }
+        OpCommand::Defend(action, dir) => {
+            writer.write_u8(5);
+            writer.write_u8(*action as u8);
+            writer.write_vec2(dir);
+        }
+        OpCommand::Skill1(action) => {
+            writer.write_u8(6);
+            writer.write_u8(*action as u8);
+        }
+        OpCommand::Skill2(action) => {
+            writer.write_u8(7);
+            writer.write_u8(*action as u8);
+        }
+        OpCommand::SkillEx(action) => {
+            writer.write_u8(8);
+            writer.write_u8(*action as u8);
+        }
+        OpCommand::Item1 => writer.write_u8(9),
+        OpCommand::Item2 => writer.write_u8(10),
+        OpCommand::Item3 => writer.write_u8(11),
+        OpCommand::Interact => writer.write_u8(12),
+    };
+}
+
+fn load_command(reader: &mut SnapshotReader) -> Result<OpCommand> {
+    let command = match reader.read_u8()? {
+        0 => OpCommand::Move(reader.read_vec2()?),
+        1 => OpCommand::Dash(reader.read_vec2()?),
+        2 => OpCommand::Jump,
+        3 => OpCommand::Attack1(load_action(reader)?, reader.read_vec2()?),
+        4 => OpCommand::Attack2(load_action(reader)?, reader.read_vec2()?),
+        5 => OpCommand::Defend(load_action(reader)?, reader.read_vec2()?),
+        6 => OpCommand::Skill1(load_action(reader)?),
+        7 => OpCommand::Skill2(load_action(reader)?),
+        8 => OpCommand::SkillEx(load_action(reader)?),
+        9 => OpCommand::Item1,
+        10 => OpCommand::Item2,
+        11 => OpCommand::Item3,
+        12 => OpCommand::Interact,
+        tag => return Err(anyhow!("Unknown command tag {}", tag)),
+    };
+    return Ok(command);
+}
+
+fn load_action(reader: &mut SnapshotReader) -> Result<OpAction> {
+    return match reader.read_u8()? {
+        0 => Ok(OpAction::Press),
+        1 => Ok(OpAction::HoldBegin),
+        2 => Ok(OpAction::HoldEnd),
+        tag => Err(anyhow!("Unknown action tag {}", tag)),
+    };
+}
+
+// Snapshots of the last frames, buffers of dropped frames are reused.
+#[derive(Debug)]
+pub struct SnapshotRing {
+    frames: VecDeque<(u32, SnapshotWriter)>,
+    capacity: usize,
+}
+
+impl SnapshotRing {
+    pub fn new(capacity: usize) -> SnapshotRing {
+        assert!(capacity > 0);
+        return SnapshotRing {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+        };
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        return self.frames.len();
+    }
+
+    #[inline]
+    pub fn oldest_frame(&self) -> Option<u32> {
+        return self.frames.front().map(|(frame, _)| *frame);
+    }
+
+    #[inline]
+    pub fn latest_frame(&self) -> Option<u32> {
+        return self.frames.back().map(|(frame, _)| *frame);
+    }
+
+    // Saves the snapshot of frame, frames must be increasing.
+    pub fn save<F: FnOnce(&mut SnapshotWriter)>(&mut self, frame: u32, save: F) -> Result<()> {
+        if let Some(latest) = self.latest_frame() {
+            if frame <= latest {
+                return Err(anyhow!("Snapshot frame {} <= {}", frame, latest));
+            }
+        }
+        let mut writer = if self.frames.len() >= self.capacity {
+            let (_, mut writer) = self.frames.pop_front().unwrap();
+            writer.clear();
+            writer
+        } else {
+            SnapshotWriter::new()
+        };
+        save(&mut writer);
+        self.frames.push_back((frame, writer));
+        return Ok(());
+    }
+
+    pub fn find(&self, frame: u32) -> Option<SnapshotReader> {
+        let idx = self.index(frame)?;
+        return Some(self.frames[idx].1.reader());
+    }
+
+    // Drops the snapshots after frame, before resimulating from it.
+    pub fn rollback(&mut self, frame: u32) -> Result<SnapshotReader> {
+        let idx = match self.index(frame) {
+            Some(idx) => idx,
+            None => return Err(anyhow!("Snapshot frame {} not found", frame)),
+        };
+        self.frames.truncate(idx + 1);
+        return Ok(self.frames[self.frames.len() - 1].1.reader());
+    }
+
+    fn index(&self, frame: u32) -> Option<usize> {
+        return self.frames.iter().position(|(saved, _)| *saved == frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collide::shape::Ball;
+    use math::{ff, fi};
+
+    fn new_world() -> CollisionWorld<u64> {
+        let mut world = CollisionWorld::new(fi(0));
+        for idx in 0..3 {
+            world.add(
+                CollisionObjectType::Move,
+                Isometry3::new(Vector3::new(fi(idx), fi(0), fi(0)), na::zero()),
+                ShapeHandle::new(Ball::new(ff(0.5))),
+                CollisionGroups {
+                    team_membership: 1,
+                    team_whitelist: 0xFFFF,
+                    role_membership: 1,
+                    role_whitelist: 0xFFFF,
+                },
+                GeometricQueryType::Contacts(fi(0), fi(0)),
+                idx as u64,
+            );
+        }
+        return world;
+    }
+
+    fn add_hit(world: &mut CollisionWorld<u64>, data: u64) -> CollisionObjectSlabHandle {
+        let (handle, _) = world.add(
+            CollisionObjectType::Hit,
+            Isometry3::new(Vector3::new(fi(0), fi(1), fi(0)), na::zero()),
+            ShapeHandle::new(Ball::new(ff(0.2))),
+            CollisionGroups {
+                team_membership: 2,
+                team_whitelist: 0xFFFF,
+                role_membership: 1,
+                role_whitelist: 0xFFFF,
+            },
+            GeometricQueryType::Proximity(fi(0)),
+            data,
+        );
+        return handle;
+    }
+
+    #[test]
+    fn test_snapshot_rollback() {
+        let mut prng = Prng::new(5);
+        let mut gener = ObjIDGener::new(100);
+        let mut world = new_world();
+        let mut ops = vec![
+            Operation {
+                obj_id: ObjID::from(100),
+                command: OpCommand::Move(Vector2::new(ff(0.3), ff(-0.7))),
+            },
+            Operation {
+                obj_id: ObjID::from(101),
+                command: OpCommand::Attack1(OpAction::HoldEnd, Vector2::new(fi(1), fi(0))),
+            },
+        ];
+
+        let mut writer = SnapshotWriter::new();
+        prng.save_snapshot(&mut writer);
+        gener.save_snapshot(&mut writer);
+        world.save_snapshot(&mut writer);
+        ops.save_snapshot(&mut writer);
+
+        let values: Vec<_> = (0..4).map(|_| prng.next_u64()).collect();
+        let obj_id = gener.gen();
+        let handle = world.collision_objects().next().unwrap().0;
+        let moved = Isometry3::new(
+            Vector3::new(fi(9), fi(9), fi(9)),
+            Vector3::new(fi(0), ff(0.3), fi(0)),
+        );
+        world.get_mut(handle).unwrap().set_position(moved);
+        let spawned = add_hit(&mut world, 7);
+        let despawned = CollisionObjectSlabHandle(1);
+        world.remove(&[despawned]);
+        ops.clear();
+
+        let mut reader = writer.reader();
+        prng.load_snapshot(&mut reader).unwrap();
+        gener.load_snapshot(&mut reader).unwrap();
+        world.load_snapshot(&mut reader).unwrap();
+        ops.load_snapshot(&mut reader).unwrap();
+        assert!(reader.is_end());
+
+        assert_eq!((0..4).map(|_| prng.next_u64()).collect::<Vec<_>>(), values);
+        assert_eq!(gener.gen(), obj_id);
+        assert_eq!(
+            world.collision_object(handle).unwrap().position(),
+            &Isometry3::identity()
+        );
+        assert_eq!(world.objects.len(), 3);
+        let co = world.collision_object(despawned).unwrap();
+        assert_eq!(*co.data(), 1);
+        assert_eq!(co.obj_type(), CollisionObjectType::Move);
+        assert_eq!(co.query_type(), GeometricQueryType::Contacts(fi(0), fi(0)));
+        assert_eq!(add_hit(&mut world, 8), spawned);
+        assert_eq!(ops.len(), 2);
+        assert_eq!(
+            ops[1].command,
+            OpCommand::Attack1(OpAction::HoldEnd, Vector2::new(fi(1), fi(0)))
+        );
+
+        let mut writer = SnapshotWriter::new();
+        world.get_mut(handle).unwrap().set_position(moved);
+        world.remove(&[despawned]);
+        world.save_snapshot(&mut writer);
+        let mut reader = writer.reader();
+        let mut other = CollisionWorld::new(fi(0));
+        other.load_snapshot(&mut reader).unwrap();
+        assert_eq!(other.objects.len(), 3);
+        assert_eq!(other.collision_object(handle).unwrap().position(), &moved);
+        assert!(other.collision_object(despawned).is_none());
+        let co = other.collision_object(spawned).unwrap();
+        assert_eq!(co.obj_type(), CollisionObjectType::Hit);
+        assert_eq!(co.query_type(), GeometricQueryType::Proximity(fi(0)));
+        assert_eq!(*co.data(), 8);
+        assert_eq!(add_hit(&mut other, 9), despawned);
+
+        let mut reader = SnapshotReader::new(&writer.as_bytes()[..10]);
+        assert!(other.load_snapshot(&mut reader).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_ring() {
+        let mut ring = SnapshotRing::new(3);
+        for frame in 1..=5 {
+            ring.save(frame, |writer| writer.write_u32(frame * 10))
+                .unwrap();
+        }
+        assert!(ring.save(5, |_| {}).is_err());
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.oldest_frame(), Some(3));
+        assert!(ring.find(2).is_none());
+        assert_eq!(ring.find(4).unwrap().read_u32().unwrap(), 40);
+
+        assert_eq!(ring.rollback(3).unwrap().read_u32().unwrap(), 30);
+        assert_eq!(ring.latest_frame(), Some(3));
+        ring.save(4, |writer| writer.write_u32(41)).unwrap();
+        assert_eq!(ring.find(4).unwrap().read_u32().unwrap(), 41);
+        assert!(ring.rollback(9).is_err());
+    }
+}
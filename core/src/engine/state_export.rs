@@ -0,0 +1,221 @@
+use super::logic_data::{LogicLifecycle, LogicState, LogicStateStatic};
+use crate::id::{ClassID, ObjID};
+use std::mem;
+use std::ptr;
+use std::slice;
+
+pub const STATE_EXPORT_VERSION: u32 = 1;
+
+// Layout of an export buffer, all offsets are in bytes from the start of the header:
+// | StateExportHeader | StateExportEntry * count | blob (16 aligned states) |
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateExportHeader {
+    pub version: u32,
+    pub header_size: u32,
+    pub entry_size: u32,
+    pub count: u32,
+    pub entries_offset: u32,
+    pub blob_offset: u32,
+    pub blob_size: u32,
+    pub total_size: u32,
+}
+
+// Entries are sorted by ClassID then ObjID, states of a class are contiguous.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateExportEntry {
+    pub obj_id: ObjID,
+    pub class_id: ClassID,
+    pub lifecycle: LogicLifecycle,
+    _padding_: u8,
+    // Offset of the state in the blob.
+    pub offset: u32,
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+struct Block([u8; 16]);
+
+const BLOCK_SIZE: usize = mem::size_of::<Block>();
+const HEADER_SIZE: usize = mem::size_of::<StateExportHeader>();
+const ENTRY_SIZE: usize = mem::size_of::<StateExportEntry>();
+const STATE_HEADER_SIZE: usize = mem::size_of::<LogicState<()>>();
+
+fn align16(size: usize) -> usize {
+    return (size + 15) & !15;
+}
+
+// A single contiguous buffer holding the states of a DataPool, so front ends walk it without per object calls.
+// The buffer is kept between builds and only grows.
+#[derive(Debug)]
+pub struct StateExport {
+    buffer: Vec<Block>,
+    order: Vec<usize>,
+}
+
+impl StateExport {
+    pub(crate) fn new() -> StateExport {
+        let mut export = StateExport {
+            buffer: Vec::new(),
+            order: Vec::new(),
+        };
+        export.build(&[], &[]);
+        return export;
+    }
+
+    // sizes are the sizes of the state bodies, without the LogicState header.
+    pub(crate) fn build(&mut self, states: &[*mut LogicState<()>], sizes: &[usize]) {
+        self.order.clear();
+        self.order.extend(0..states.len());
+        self.order.sort_by_key(|idx| {
+            let state = unsafe { &*states[*idx] };
+            return (state.class_id() as u16, u64::from(state.obj_id()));
+        });
+
+        let entries_offset = HEADER_SIZE;
+        let blob_offset = align16(entries_offset + ENTRY_SIZE * states.len());
+        let blob_size: usize = sizes.iter().map(|size| align16(*size)).sum();
+        let total_size = blob_offset + blob_size;
+        self.resize(total_size);
+
+        let base = self.buffer.as_mut_ptr() as *mut u8;
+        let mut offset = 0;
+        for (idx, state_idx) in self.order.iter().enumerate() {
+            let header = unsafe { &*states[*state_idx] };
+            let size = sizes[*state_idx];
+            unsafe {
+                let entry = base.add(entries_offset + ENTRY_SIZE * idx) as *mut StateExportEntry;
+                ptr::write(
+                    entry,
+                    StateExportEntry {
+                        obj_id: header.obj_id(),
+                        class_id: header.class_id(),
+                        lifecycle: header.lifecycle(),
+                        _padding_: 0,
+                        offset: offset as u32,
+                    },
+                );
+                let body = (states[*state_idx] as *const u8).add(STATE_HEADER_SIZE);
+                ptr::copy_nonoverlapping(body, base.add(blob_offset + offset), size);
+            };
+            offset += align16(size);
+        }
+
+        let header = StateExportHeader {
+            version: STATE_EXPORT_VERSION,
+            header_size: HEADER_SIZE as u32,
+            entry_size: ENTRY_SIZE as u32,
+            count: states.len() as u32,
+            entries_offset: entries_offset as u32,
+            blob_offset: blob_offset as u32,
+            blob_size: blob_size as u32,
+            total_size: total_size as u32,
+        };
+        unsafe { ptr::write(base as *mut StateExportHeader, header) };
+    }
+
+    fn resize(&mut self, size: usize) {
+        let blocks = (size + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        if blocks > self.buffer.len() {
+            self.buffer.resize(blocks, Block([0; 16]));
+        }
+    }
+
+    #[inline]
+    pub fn as_ptr(&self) -> *const StateExportHeader {
+        return self.buffer.as_ptr() as *const StateExportHeader;
+    }
+
+    #[inline]
+    pub fn header(&self) -> &StateExportHeader {
+        return unsafe { &*self.as_ptr() };
+    }
+
+    pub fn entries(&self) -> &[StateExportEntry] {
+        let header = self.header();
+        return unsafe {
+            let ptr = (self.as_ptr() as *const u8).add(header.entries_offset as usize);
+            slice::from_raw_parts(ptr as *const StateExportEntry, header.count as usize)
+        };
+    }
+
+    // Entries of one class, found by binary search.
+    pub fn class_entries(&self, class_id: ClassID) -> &[StateExportEntry] {
+        let entries = self.entries();
+        let key = class_id as u16;
+        let start = entries.partition_point(|entry| (entry.class_id as u16) < key);
+        let end = entries.partition_point(|entry| (entry.class_id as u16) <= key);
+        return &entries[start..end];
+    }
+
+    pub fn state<S: LogicStateStatic>(&self, entry: &StateExportEntry) -> Option<&S> {
+        if entry.class_id != S::id() {
+            return None;
+        }
+        let header = self.header();
+        let offset = header.blob_offset as usize + entry.offset as usize;
+        if offset + mem::size_of::<S>() > header.total_size as usize {
+            return None;
+        }
+        return Some(unsafe { &*((self.as_ptr() as *const u8).add(offset) as *const S) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{DataPool, StateProjectile};
+    use crate::ffi::FFIVec3f;
+    use crate::id::FastResID;
+
+    fn projectile(frame: u32) -> StateProjectile {
+        return StateProjectile {
+            source: ObjID::from(1),
+            fres_id: FastResID::from(7),
+            frame,
+            target: ObjID::invalid(),
+            position: FFIVec3f::new(1.0, 2.0, 3.0),
+            rotation: Default::default(),
+        };
+    }
+
+    #[test]
+    fn test_state_export() {
+        let mut pool = DataPool::new(1024 * 4);
+        assert_eq!(pool.export_states().header().count, 0);
+        assert_eq!(pool.export_states().entries().len(), 0);
+
+        for id in &[12u64, 10, 11] {
+            pool.state(
+                ObjID::from(*id),
+                LogicLifecycle::Running,
+                projectile(*id as u32),
+            )
+            .unwrap();
+        }
+        let export = pool.export_states();
+        let header = *export.header();
+        assert_eq!(header.version, STATE_EXPORT_VERSION);
+        assert_eq!((header.header_size, header.entry_size), (32, 16));
+        assert_eq!(header.count, 3);
+        assert_eq!(header.entries_offset, 32);
+        assert_eq!(header.blob_offset % 16, 0);
+        assert_eq!(header.total_size, header.blob_offset + header.blob_size);
+
+        let entries = export.class_entries(ClassID::Projectile);
+        let ids: Vec<u64> = entries.iter().map(|e| u64::from(e.obj_id)).collect();
+        assert_eq!(ids, vec![10, 11, 12]);
+        for entry in entries {
+            assert_eq!(entry.offset % 16, 0);
+            assert_eq!(entry.lifecycle, LogicLifecycle::Running);
+            let state = export.state::<StateProjectile>(entry).unwrap();
+            assert_eq!(state.frame as u64, u64::from(entry.obj_id));
+            assert_eq!(state.position, FFIVec3f::new(1.0, 2.0, 3.0));
+        }
+        assert!(export.class_entries(ClassID::Skill).is_empty());
+
+        pool.reset();
+        assert_eq!(pool.export_states().header().count, 0);
+    }
+}
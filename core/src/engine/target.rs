@@ -0,0 +1,289 @@
+use super::snapshot::{LogicSnapshot, SnapshotReader, SnapshotWriter};
+use crate::id::ObjID;
+use crate::resource::{ResMotionSearchTarget, ResMotionSearcher};
+use crate::utils::Prng;
+use anyhow::Result;
+use collide::pipeline::{
+    CollisionGroups, CollisionObjectSlabHandle, CollisionObjectType, CollisionWorld,
+};
+use collide::query::{self, Proximity};
+use collide::shape::Shape;
+use math::{fi, Fx};
+use na::Isometry3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TargetCandidate {
+    pub obj_id: ObjID,
+    pub handle: CollisionObjectSlabHandle,
+    pub position: Isometry3<Fx>,
+    pub distance: Fx,
+}
+
+// Runs a ResMotionSearchTarget against the collision world, keeps its buffers between searches.
+#[derive(Debug, Default)]
+pub struct TargetSearch {
+    handles: Vec<CollisionObjectSlabHandle>,
+    candidates: Vec<TargetCandidate>,
+}
+
+impl TargetSearch {
+    pub fn new() -> TargetSearch {
+        return TargetSearch::default();
+    }
+
+    // obj_id maps the collision data to a searchable character, None skips the object (self, stage...).
+    pub fn search<T: 'static, F: Fn(&T) -> Option<ObjID>>(
+        &mut self,
+        world: &mut CollisionWorld<T>,
+        motion: &ResMotionSearchTarget,
+        origin: &Isometry3<Fx>,
+        groups: &CollisionGroups,
+        prng: &mut Prng,
+        obj_id: F,
+    ) -> Option<TargetCandidate> {
+        let position = *origin * motion.transform * motion.shape.transform;
+        let shape = motion.shape.handle.as_ref();
+        let aabb = shape.aabb(&position);
+
+        self.handles.clear();
+        self.candidates.clear();
+        world.interferences_with_aabb(CollisionObjectType::Move, &aabb, groups, &mut self.handles);
+        for handle in &self.handles {
+            let co = match world.collision_object(*handle) {
+                Some(co) => co,
+                None => continue,
+            };
+            let obj_id = match obj_id(co.data()) {
+                Some(obj_id) => obj_id,
+                None => continue,
+            };
+            let proximity =
+                query::proximity(&position, shape, co.position(), co.shape().as_ref(), fi(0));
+            if proximity != Proximity::Intersecting {
+                continue;
+            }
+            let distance = (co.position().translation.vector - origin.translation.vector).norm();
+            self.candidates.push(TargetCandidate {
+                obj_id,
+                handle: *handle,
+                position: *co.position(),
+                distance,
+            });
+        }
+        if self.candidates.is_empty() {
+            return None;
+        }
+
+        // Broad phase order is not stable, candidates are sorted before picking.
+        self.candidates.sort_by(|a, b| {
+            return a
+                .distance
+                .cmp(&b.distance)
+                .then(u64::from(a.obj_id).cmp(&u64::from(b.obj_id)));
+        });
+        return match motion.searcher {
+            ResMotionSearcher::Nearest => Some(self.candidates[0]),
+            ResMotionSearcher::Random => {
+                let idx = prng.next_range(self.candidates.len() as u32) as usize;
+                Some(self.candidates[idx])
+            }
+        };
+    }
+}
+
+// Keeps following a found target, its position feeds ResCoordinate::Target.
+#[derive(Debug, Clone, Default)]
+pub struct TargetLock {
+    target: Option<(ObjID, CollisionObjectSlabHandle)>,
+    position: Option<Isometry3<Fx>>,
+}
+
+impl TargetLock {
+    pub fn new() -> TargetLock {
+        return TargetLock::default();
+    }
+
+    #[inline]
+    pub fn target(&self) -> Option<ObjID> {
+        return self.target.map(|(obj_id, _)| obj_id);
+    }
+
+    #[inline]
+    pub fn position(&self) -> Option<Isometry3<Fx>> {
+        return self.position;
+    }
+
+    pub fn lock(&mut self, candidate: &TargetCandidate) {
+        self.target = Some((candidate.obj_id, candidate.handle));
+        self.position = Some(candidate.position);
+    }
+
+    pub fn release(&mut self) {
+        self.target = None;
+        self.position = None;
+    }
+
+    // Refreshes the target position, releases the lock once the object left the world.
+    pub fn update<T: 'static, F: Fn(&T) -> Option<ObjID>>(
+        &mut self,
+        world: &CollisionWorld<T>,
+        obj_id: F,
+    ) {
+        let (target, handle) = match self.target {
+            Some(target) => target,
+            None => return,
+        };
+        // Slab handles are reused, so the object behind the handle is checked too.
+        match world.collision_object(handle) {
+            Some(co) if obj_id(co.data()) == Some(target) => {
+                self.position = Some(*co.position());
+            }
+            _ => self.release(),
+        }
+    }
+}
+
+impl LogicSnapshot for TargetLock {
+    fn save_snapshot(&self, writer: &mut SnapshotWriter) {
+        writer.write_bool(self.target.is_some());
+        if let Some((obj_id, handle)) = self.target {
+            writer.write_obj_id(obj_id);
+            writer.write_handle(handle);
+        }
+        writer.write_bool(self.position.is_some());
+        if let Some(position) = &self.position {
+            writer.write_isometry(position);
+        }
+    }
+
+    fn load_snapshot(&mut self, reader: &mut SnapshotReader) -> Result<()> {
+        self.target = match reader.read_bool()? {
+            true => Some((reader.read_obj_id()?, reader.read_handle()?)),
+            false => None,
+        };
+        self.position = match reader.read_bool()? {
+            true => Some(reader.read_isometry()?),
+            false => None,
+        };
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collide::pipeline::GeometricQueryType;
+    use collide::shape::{Ball, ShapeHandle};
+    use math::ff;
+    use na::{ComplexField, Vector3};
+
+    fn groups() -> CollisionGroups {
+        return CollisionGroups {
+            team_membership: 1,
+            team_whitelist: 0xFFFF,
+            role_membership: 1,
+            role_whitelist: 0xFFFF,
+        };
+    }
+
+    fn new_world() -> (CollisionWorld<u64>, Vec<CollisionObjectSlabHandle>) {
+        let mut world = CollisionWorld::new(fi(0));
+        let mut handles = Vec::new();
+        // Object 4 is inside the AABB of the search ball but out of the ball itself.
+        let positions = [
+            (1, fi(3), fi(0)),
+            (2, fi(-2), fi(0)),
+            (3, fi(0), fi(2)),
+            (4, ff(4.5), ff(4.5)),
+            (5, fi(20), fi(0)),
+        ];
+        for (obj_id, x, z) in positions.iter() {
+            let (handle, _) = world.add(
+                CollisionObjectType::Move,
+                Isometry3::new(Vector3::new(*x, fi(0), *z), na::zero()),
+                ShapeHandle::new(Ball::new(ff(0.1))),
+                groups(),
+                GeometricQueryType::Contacts(fi(0), fi(0)),
+                *obj_id,
+            );
+            handles.push(handle);
+        }
+        world.update(&[CollisionObjectType::Move]);
+        return (world, handles);
+    }
+
+    fn new_motion(searcher: &str) -> ResMotionSearchTarget {
+        let mut motion: ResMotionSearchTarget = serde_yaml::from_str(&format!(
+            "start_frame: 0\nfinish_frame: 10\nshape: {{ type: Ball, radius: 5 }}\ntransform:\n  rotation: [0, 0, 0]\n  translation: [0, 0, 0]\nsearcher: {}\n",
+            searcher
+        ))
+        .unwrap();
+        motion.shape.handle = ShapeHandle::new(Ball::new(fi(5)));
+        return motion;
+    }
+
+    fn search(
+        world: &mut CollisionWorld<u64>,
+        motion: &ResMotionSearchTarget,
+        prng: &mut Prng,
+    ) -> Option<TargetCandidate> {
+        let mut search = TargetSearch::new();
+        // Object 2 is the searcher itself.
+        return search.search(
+            world,
+            motion,
+            &Isometry3::identity(),
+            &groups(),
+            prng,
+            |id| {
+                return if *id == 2 {
+                    None
+                } else {
+                    Some(ObjID::from(*id))
+                };
+            },
+        );
+    }
+
+    #[test]
+    fn test_target_search_nearest() {
+        let (mut world, _) = new_world();
+        let mut prng = Prng::new(1);
+        let target = search(&mut world, &new_motion("Nearest"), &mut prng).unwrap();
+        assert_eq!(target.obj_id, ObjID::from(3));
+        assert!((target.distance - fi(2)).abs() < ff(0.001));
+    }
+
+    #[test]
+    fn test_target_search_random() {
+        let (mut world, _) = new_world();
+        let motion = new_motion("Random");
+        let mut prng1 = Prng::new(7);
+        let mut prng2 = Prng::new(7);
+        for _ in 0..10 {
+            let target1 = search(&mut world, &motion, &mut prng1).unwrap();
+            let target2 = search(&mut world, &motion, &mut prng2).unwrap();
+            assert_eq!(target1.obj_id, target2.obj_id);
+            assert!(target1.obj_id == ObjID::from(1) || target1.obj_id == ObjID::from(3));
+        }
+    }
+
+    #[test]
+    fn test_target_lock() {
+        let (mut world, handles) = new_world();
+        let mut prng = Prng::new(1);
+        let target = search(&mut world, &new_motion("Nearest"), &mut prng).unwrap();
+        let mut lock = TargetLock::new();
+        lock.lock(&target);
+
+        let moved = Isometry3::new(Vector3::new(fi(1), fi(0), fi(1)), na::zero());
+        world.get_mut(handles[2]).unwrap().set_position(moved);
+        lock.update(&world, |id| Some(ObjID::from(*id)));
+        assert_eq!(lock.position(), Some(moved));
+
+        world.remove(&[handles[2]]);
+        lock.update(&world, |id| Some(ObjID::from(*id)));
+        assert_eq!(lock.target(), None);
+        assert_eq!(lock.position(), None);
+    }
+}
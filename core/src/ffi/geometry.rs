@@ -1,8 +1,10 @@
+use super::vector::FFIVec3f;
 use math::{fx_f32, Fx};
-use na::{Complex, Quaternion, UnitComplex, UnitQuaternion};
+use na::{Complex, Isometry3, Quaternion, UnitComplex, UnitQuaternion};
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIComplex {
     pub re: f32,
     pub im: f32,
@@ -33,7 +35,7 @@ impl From<FFIComplex> for Complex<Fx> {
 }
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIQuaternion {
     pub i: f32,
     pub j: f32,
@@ -68,3 +70,25 @@ impl From<FFIQuaternion> for Quaternion<Fx> {
         return Quaternion::new(fx_f32(v.w), fx_f32(v.i), fx_f32(v.j), fx_f32(v.k));
     }
 }
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FFITransform {
+    pub position: FFIVec3f,
+    pub rotation: FFIQuaternion,
+}
+
+impl FFITransform {
+    pub fn new(position: FFIVec3f, rotation: FFIQuaternion) -> FFITransform {
+        return FFITransform { position, rotation };
+    }
+}
+
+impl From<Isometry3<Fx>> for FFITransform {
+    fn from(v: Isometry3<Fx>) -> FFITransform {
+        return FFITransform {
+            position: FFIVec3f::from(v.translation),
+            rotation: FFIQuaternion::from(v.rotation),
+        };
+    }
+}
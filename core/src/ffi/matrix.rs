@@ -1,8 +1,9 @@
 use math::{fx_f32, Fx};
 use na::{Matrix2, Matrix3, Matrix4, Rotation2, Rotation3};
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIMat2f {
     pub m11: f32,
     pub m21: f32,
@@ -40,7 +41,7 @@ impl From<FFIMat2f> for Matrix2<Fx> {
 }
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIMat3f {
     pub m11: f32,
     pub m21: f32,
@@ -118,7 +119,7 @@ impl From<FFIMat3f> for Matrix3<Fx> {
 }
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIMat4f {
     pub m11: f32,
     pub m21: f32,
@@ -1,8 +1,9 @@
 use math::{fx_f32, fx_i32, Fx};
 use na::{Translation3, Vector2, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIVec2f {
     pub x: f32,
     pub y: f32,
@@ -30,7 +31,7 @@ impl From<FFIVec2f> for Vector2<Fx> {
 }
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIVec2i {
     pub x: i32,
     pub y: i32,
@@ -70,7 +71,7 @@ impl From<FFIVec2i> for Vector2<i32> {
 }
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIVec3f {
     pub x: f32,
     pub y: f32,
@@ -116,7 +117,7 @@ impl From<FFIVec3f> for Translation3<Fx> {
 }
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIVec3i {
     pub x: i32,
     pub y: i32,
@@ -162,7 +163,7 @@ impl From<FFIVec3i> for Vector3<i32> {
 }
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIVec4f {
     pub x: f32,
     pub y: f32,
@@ -194,7 +195,7 @@ impl From<FFIVec4f> for Vector4<Fx> {
 }
 
 #[repr(C)]
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct FFIVec4i {
     pub x: i32,
     pub y: i32,
@@ -25,6 +25,9 @@ pub enum ClassID {
     Skill = 0x0301,
     HitAttachment = 0x0401,
     HitPathRay = 0x0402,
+    Projectile = 0x0403,
+    Buff = 0x0501,
+    Curve = 0x0601,
     Action = 0xFFFE,
     Prefab = 0xFFFF,
 }
@@ -60,6 +63,18 @@ impl ClassID {
         return ((*self as usize) & 0xFF00) == 0x0300;
     }
 
+    pub fn is_hit(&self) -> bool {
+        return ((*self as usize) & 0xFF00) == 0x0400;
+    }
+
+    pub fn is_buff(&self) -> bool {
+        return ((*self as usize) & 0xFF00) == 0x0500;
+    }
+
+    pub fn is_curve(&self) -> bool {
+        return ((*self as usize) & 0xFF00) == 0x0600;
+    }
+
     pub fn is_action(&self) -> bool {
         return (*self as usize) == 0xFFFE;
     }
@@ -88,6 +103,17 @@ mod tests {
         assert_eq!(ClassID::Skill.is_skill(), true);
         assert_eq!(ClassID::Skill.is_stage(), false);
 
+        assert_eq!(ClassID::HitAttachment.is_hit(), true);
+        assert_eq!(ClassID::HitPathRay.is_hit(), true);
+        assert_eq!(ClassID::Projectile.is_hit(), true);
+        assert_eq!(ClassID::HitAttachment.is_skill(), false);
+
+        assert_eq!(ClassID::Buff.is_buff(), true);
+        assert_eq!(ClassID::Buff.is_hit(), false);
+
+        assert_eq!(ClassID::Curve.is_curve(), true);
+        assert_eq!(ClassID::Curve.is_buff(), false);
+
         assert_eq!(ClassID::Prefab.is_command(), true);
         assert_eq!(ClassID::Prefab.is_skill(), false);
 
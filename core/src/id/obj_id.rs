@@ -62,6 +62,16 @@ impl ObjIDGener {
             panic!("ObjID exhausted!");
         }
     }
+
+    #[inline]
+    pub(crate) fn counter(&self) -> u64 {
+        return self.counter;
+    }
+
+    #[inline]
+    pub(crate) fn set_counter(&mut self, counter: u64) {
+        self.counter = counter;
+    }
 }
 
 #[cfg(test)]
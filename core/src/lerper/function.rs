@@ -1,3 +1,5 @@
+use crate::resource::ResLerpFunction;
+use anyhow::{anyhow, Result};
 use m::{ff, fi, Fx};
 use na::{ComplexField, RealField};
 
@@ -91,7 +93,7 @@ fn lerp_expo_in(progress: Fx) -> Fx {
     if progress == fi(0) {
         return fi(0);
     } else {
-        return Fx::powf(fi(2), fi(10) * progress - fi(10));
+        return Fx::exp2(fi(10) * progress - fi(10));
     }
 }
 
@@ -100,7 +102,7 @@ fn lerp_expo_out(progress: Fx) -> Fx {
         return fi(1);
     } else {
         let progress = fi(1) - progress;
-        return fi(1) - Fx::powf(fi(2), fi(10) * progress - fi(10));
+        return fi(1) - Fx::exp2(fi(10) * progress - fi(10));
     }
 }
 
@@ -110,14 +112,85 @@ fn lerp_expo_inout(progress: Fx) -> Fx {
             return fi(0);
         } else {
             let progress = fi(2) * progress;
-            return ff(0.5) * Fx::powf(fi(2), fi(10) * progress - fi(10));
+            return ff(0.5) * Fx::exp2(fi(10) * progress - fi(10));
         }
     } else {
         if progress == fi(1) {
             return fi(1);
         } else {
             let progress = fi(2) - fi(2) * progress;
-            return fi(1) - ff(0.5) * Fx::powf(fi(2), fi(10) * progress - fi(10));
+            return fi(1) - ff(0.5) * Fx::exp2(fi(10) * progress - fi(10));
         }
     }
 }
+
+impl ResLerpFunction {
+    // A curve has no function, it is sampled by Lerper once resolved.
+    pub fn function(&self) -> Result<LerpFunction> {
+        let function: LerpFunction = match self {
+            ResLerpFunction::Linear => lerp_linear,
+            ResLerpFunction::QuadIn => lerp_quad_in,
+            ResLerpFunction::QuadOut => lerp_quad_out,
+            ResLerpFunction::QuadInOut => lerp_quad_inout,
+            ResLerpFunction::CubicIn => lerp_cubic_in,
+            ResLerpFunction::CubicOut => lerp_cubic_out,
+            ResLerpFunction::CubicInOut => lerp_cubic_inout,
+            ResLerpFunction::QuartIn => lerp_quart_in,
+            ResLerpFunction::QuartOut => lerp_quart_out,
+            ResLerpFunction::QuartInOut => lerp_quart_inout,
+            ResLerpFunction::SinIn => lerp_sin_in,
+            ResLerpFunction::SinOut => lerp_sin_out,
+            ResLerpFunction::SinInOut => lerp_sin_inout,
+            ResLerpFunction::ExpoIn => lerp_expo_in,
+            ResLerpFunction::ExpoOut => lerp_expo_out,
+            ResLerpFunction::ExpoInOut => lerp_expo_inout,
+            ResLerpFunction::Curve(curve) => {
+                return Err(anyhow!("Curve {:?} is not a lerp function", curve.res_id));
+            }
+        };
+        return Ok(function);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lerp_function_bounds() {
+        let functions = [
+            (ResLerpFunction::Linear, ff(0.5)),
+            (ResLerpFunction::QuadIn, ff(0.25)),
+            (ResLerpFunction::QuadOut, ff(0.75)),
+            (ResLerpFunction::QuadInOut, ff(0.5)),
+            (ResLerpFunction::CubicIn, ff(0.125)),
+            (ResLerpFunction::CubicOut, ff(0.875)),
+            (ResLerpFunction::CubicInOut, ff(0.5)),
+            (ResLerpFunction::QuartIn, ff(0.0625)),
+            (ResLerpFunction::QuartOut, ff(0.9375)),
+            (ResLerpFunction::QuartInOut, ff(0.5)),
+            (ResLerpFunction::SinIn, ff(0.29289322)),
+            (ResLerpFunction::SinOut, ff(0.70710678)),
+            (ResLerpFunction::SinInOut, ff(0.5)),
+            (ResLerpFunction::ExpoIn, ff(0.03125)),
+            (ResLerpFunction::ExpoOut, ff(0.96875)),
+            (ResLerpFunction::ExpoInOut, ff(0.5)),
+        ];
+        for (func, middle) in functions.iter() {
+            let func = func.function().unwrap();
+            assert!(func(fi(0)).abs() < ff(0.001));
+            assert!((func(fi(1)) - fi(1)).abs() < ff(0.001));
+            assert!((func(ff(0.5)) - *middle).abs() < ff(0.0001));
+        }
+    }
+
+    #[test]
+    fn test_lerp_function_expo() {
+        assert!((Fx::exp2(fi(3)) - fi(8)).abs() < ff(0.0001));
+        assert!((Fx::exp2(fi(-2)) - ff(0.25)).abs() < ff(0.0001));
+        assert!((Fx::exp2(ff(0.5)) - ff(1.41421356)).abs() < ff(0.0001));
+        assert!((lerp_expo_in(ff(0.5)) - ff(0.03125)).abs() < ff(0.0001));
+        assert!((lerp_expo_out(ff(0.5)) - ff(0.96875)).abs() < ff(0.0001));
+        assert_eq!(lerp_expo_in(ff(0.3)), lerp_expo_in(ff(0.3)));
+    }
+}
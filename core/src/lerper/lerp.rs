@@ -0,0 +1,215 @@
+use super::function::LerpFunction;
+use crate::resource::{ResCurve, ResLerpFunction, ResLerpParameter};
+use anyhow::{anyhow, Result};
+use approx::AbsDiffEq;
+use m::{fi, Fx};
+use na::{Translation3, UnitQuaternion};
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+enum LerpCurve {
+    Function(LerpFunction),
+    Curve(Arc<ResCurve>),
+}
+
+impl LerpCurve {
+    #[inline]
+    fn sample(&self, progress: Fx) -> Fx {
+        return match self {
+            LerpCurve::Function(function) => function(progress),
+            LerpCurve::Curve(curve) => curve.sample(progress),
+        };
+    }
+}
+
+// Evaluates a lerp function over a frame range, the ResLerpParameter picks the used part of the curve.
+#[derive(Debug, Clone)]
+pub struct Lerper {
+    curve: LerpCurve,
+    start_frame: u32,
+    finish_frame: u32,
+    start_progress: Fx,
+    finish_progress: Fx,
+    start_value: Fx,
+    finish_value: Fx,
+}
+
+impl Lerper {
+    pub fn new(
+        function: &ResLerpFunction,
+        parameter: &ResLerpParameter,
+        start_frame: u32,
+        finish_frame: u32,
+    ) -> Result<Lerper> {
+        let curve = match function {
+            ResLerpFunction::Curve(curve) => match &curve.curve {
+                Some(curve) => LerpCurve::Curve(curve.clone()),
+                None => return Err(anyhow!("Curve {:?} not resolved", curve.res_id)),
+            },
+            _ => LerpCurve::Function(function.function()?),
+        };
+        return Ok(Lerper::with_curve(
+            curve,
+            parameter,
+            start_frame,
+            finish_frame,
+        ));
+    }
+
+    pub fn with_function(
+        function: LerpFunction,
+        parameter: &ResLerpParameter,
+        start_frame: u32,
+        finish_frame: u32,
+    ) -> Lerper {
+        return Lerper::with_curve(
+            LerpCurve::Function(function),
+            parameter,
+            start_frame,
+            finish_frame,
+        );
+    }
+
+    fn with_curve(
+        curve: LerpCurve,
+        parameter: &ResLerpParameter,
+        start_frame: u32,
+        finish_frame: u32,
+    ) -> Lerper {
+        return Lerper {
+            start_frame,
+            finish_frame,
+            start_progress: parameter.start_progress,
+            finish_progress: parameter.finish_progress,
+            start_value: curve.sample(parameter.start_progress),
+            finish_value: curve.sample(parameter.finish_progress),
+            curve,
+        };
+    }
+
+    #[inline]
+    pub fn start_frame(&self) -> u32 {
+        return self.start_frame;
+    }
+
+    #[inline]
+    pub fn finish_frame(&self) -> u32 {
+        return self.finish_frame;
+    }
+
+    // Eased ratio in [0, 1] at frame, clamped outside of the frame range.
+    pub fn ratio(&self, frame: u32) -> Fx {
+        if frame <= self.start_frame {
+            return fi(0);
+        }
+        if frame >= self.finish_frame {
+            return fi(1);
+        }
+        let time = fi((frame - self.start_frame) as i64)
+            / fi((self.finish_frame - self.start_frame) as i64);
+        let progress = self.start_progress + (self.finish_progress - self.start_progress) * time;
+        let range = self.finish_value - self.start_value;
+        if range == fi(0) {
+            return time;
+        }
+        return (self.curve.sample(progress) - self.start_value) / range;
+    }
+
+    pub fn value(&self, frame: u32, start: Fx, finish: Fx) -> Fx {
+        return start + (finish - start) * self.ratio(frame);
+    }
+
+    pub fn translation(
+        &self,
+        frame: u32,
+        start: &Translation3<Fx>,
+        finish: &Translation3<Fx>,
+    ) -> Translation3<Fx> {
+        let ratio = self.ratio(frame);
+        return Translation3::from(start.vector + (finish.vector - start.vector) * ratio);
+    }
+
+    pub fn rotation(
+        &self,
+        frame: u32,
+        start: &UnitQuaternion<Fx>,
+        finish: &UnitQuaternion<Fx>,
+    ) -> UnitQuaternion<Fx> {
+        let ratio = self.ratio(frame);
+        return start
+            .try_slerp(finish, ratio, Fx::default_epsilon())
+            .unwrap_or_else(|| start.nlerp(finish, ratio));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::ResID;
+    use crate::resource::ResCurveRef;
+    use m::ff;
+    use na::{ComplexField, RealField, Vector3};
+
+    #[test]
+    fn test_lerper_ratio() {
+        let lerper = Lerper::new(
+            &ResLerpFunction::Linear,
+            &ResLerpParameter::default(),
+            10,
+            20,
+        )
+        .unwrap();
+        assert_eq!(lerper.ratio(0), fi(0));
+        assert_eq!(lerper.ratio(15), ff(0.5));
+        assert_eq!(lerper.ratio(30), fi(1));
+        assert_eq!(lerper.value(15, fi(10), fi(20)), fi(15));
+
+        let parameter = ResLerpParameter {
+            start_progress: ff(0.5),
+            finish_progress: fi(1),
+        };
+        let lerper = Lerper::new(&ResLerpFunction::QuadIn, &parameter, 0, 10).unwrap();
+        assert_eq!(lerper.ratio(0), fi(0));
+        assert!((lerper.ratio(5) - ff(0.4166666)).abs() < ff(0.0001));
+        assert_eq!(lerper.ratio(10), fi(1));
+    }
+
+    #[test]
+    fn test_lerper_curve() {
+        let mut curve: ResCurve = serde_yaml::from_str(
+            "res_id: Curve.Test\ninterpolation: Bezier\nkeys:\n- { time: 0, value: 0, out_value: 1 }\n- { time: 1, value: 1, in_value: 1 }\n",
+        )
+        .unwrap();
+        curve.bake();
+        let mut function = ResLerpFunction::Curve(ResCurveRef {
+            res_id: ResID::from("Curve.Test"),
+            curve: Some(Arc::new(curve)),
+        });
+        let lerper = Lerper::new(&function, &ResLerpParameter::default(), 0, 10).unwrap();
+        assert!(lerper.ratio(2) > ff(0.2));
+        assert_eq!(lerper.ratio(10), fi(1));
+
+        if let ResLerpFunction::Curve(curve) = &mut function {
+            curve.curve = None;
+        }
+        assert!(Lerper::new(&function, &ResLerpParameter::default(), 0, 10).is_err());
+        assert!(function.function().is_err());
+    }
+
+    #[test]
+    fn test_lerper_transform() {
+        let lerper =
+            Lerper::new(&ResLerpFunction::Linear, &ResLerpParameter::default(), 0, 4).unwrap();
+        let translation = lerper.translation(
+            1,
+            &Translation3::new(fi(0), fi(0), fi(0)),
+            &Translation3::new(fi(4), fi(0), fi(-8)),
+        );
+        assert_eq!(translation.vector, Vector3::new(fi(1), fi(0), fi(-2)));
+
+        let start = UnitQuaternion::identity();
+        let finish = UnitQuaternion::from_euler_angles(fi(0), Fx::frac_pi_2(), fi(0));
+        let rotation = lerper.rotation(2, &start, &finish);
+        assert!((rotation.angle() - Fx::frac_pi_4()).abs() < ff(0.001));
+    }
+}
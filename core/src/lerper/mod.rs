@@ -0,0 +1,5 @@
+mod function;
+mod lerp;
+
+pub use function::LerpFunction;
+pub use lerp::Lerper;
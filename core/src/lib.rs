@@ -32,6 +32,7 @@ pub mod agent;
 pub mod character;
 pub mod engine;
 pub mod id;
+pub mod lerper;
 pub mod physic;
 pub mod resource;
 pub mod stage;
@@ -2,11 +2,11 @@ use super::base::ResObj;
 use super::cache::{CompileContext, RestoreContext};
 use crate::derive::def_res;
 use crate::id::{ClassID, FastResID, ResID};
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use lazy_static::lazy_static;
 use math::Fx;
-use nalgebra::Vector2;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
 
@@ -20,6 +20,7 @@ pub struct ResAction {
     pub res_id: ResID,
     #[serde(skip)]
     pub fres_id: FastResID,
+    // The first action is the entry action, a finished action without transition falls back to it.
     pub actions: Vec<ResActionAny>,
 }
 
@@ -27,21 +28,221 @@ pub struct ResAction {
 impl ResObj for ResAction {
     fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
         ctx.insert_res_id(&self.res_id)?;
+        self.resolve()
+            .context(format!("Action {:?}", self.res_id))?;
         return Ok(());
     }
 
     fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
         self.fres_id = ctx.get_fres_id(&self.res_id)?;
+        self.resolve()
+            .context(format!("Action {:?}", self.res_id))?;
+        for action in &self.actions {
+            if let ResActionAny::Attack(ResActAttack {
+                skill: Some(skill), ..
+            }) = action
+            {
+                ctx.find_skill(skill)?;
+            }
+        }
         return Ok(());
     }
 }
 
+impl ResAction {
+    pub(crate) fn resolve(&mut self) -> Result<()> {
+        if self.actions.is_empty() {
+            return Err(anyhow!("Empty action list"));
+        }
+        let mut indexes = HashMap::with_capacity(self.actions.len());
+        for (idx, action) in self.actions.iter().enumerate() {
+            if indexes.insert(action.name().to_string(), idx).is_some() {
+                return Err(anyhow!("Action name conflict {:?}", action.name()));
+            }
+        }
+
+        for action in &mut self.actions {
+            let (name, frames) = (action.name().to_string(), action.frames());
+            for trans in action.transitions_mut() {
+                trans.to_index = match indexes.get(&trans.to) {
+                    Some(idx) => *idx,
+                    None => return Err(anyhow!("Action not found {:?} => {:?}", name, trans.to)),
+                };
+                if let Some((start_frame, finish_frame)) = trans.window {
+                    if start_frame > finish_frame || (frames != 0 && finish_frame > frames) {
+                        return Err(anyhow!(
+                            "Transition window {}..{} out of 0..{} {:?} => {:?}",
+                            start_frame,
+                            finish_frame,
+                            frames,
+                            name,
+                            trans.to
+                        ));
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn find_index(&self, name: &str) -> Option<usize> {
+        return self.actions.iter().position(|action| action.name() == name);
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
-pub enum ResActionAny {}
+pub enum ResActionAny {
+    Idle(ResActIdle),
+    Run(ResActRun),
+    Dash(ResActDash),
+    Jump(ResActJump),
+    Attack(ResActAttack),
+    Guard(ResActGuard),
+    HitStun(ResActHitStun),
+    KnockDown(ResActKnockDown),
+}
+
+macro_rules! match_action {
+    ($action:expr, $act:ident => $expr:expr) => {
+        match $action {
+            ResActionAny::Idle($act) => $expr,
+            ResActionAny::Run($act) => $expr,
+            ResActionAny::Dash($act) => $expr,
+            ResActionAny::Jump($act) => $expr,
+            ResActionAny::Attack($act) => $expr,
+            ResActionAny::Guard($act) => $expr,
+            ResActionAny::HitStun($act) => $expr,
+            ResActionAny::KnockDown($act) => $expr,
+        }
+    };
+}
+
+impl ResActionAny {
+    pub fn name(&self) -> &str {
+        return match_action!(self, act => &act.name);
+    }
+
+    // Zero frames means the action loops until a transition leaves it.
+    pub fn frames(&self) -> u32 {
+        return match self {
+            ResActionAny::Idle(_) | ResActionAny::Run(_) => 0,
+            ResActionAny::Guard(guard) => guard.frames,
+            ResActionAny::Dash(dash) => dash.frames,
+            ResActionAny::Jump(jump) => jump.frames,
+            ResActionAny::Attack(attack) => attack.frames,
+            ResActionAny::HitStun(stun) => stun.frames,
+            ResActionAny::KnockDown(down) => down.frames,
+        };
+    }
+
+    pub fn transitions(&self) -> &[ResActionTransition] {
+        return match_action!(self, act => &act.transitions);
+    }
+
+    fn transitions_mut(&mut self) -> &mut Vec<ResActionTransition> {
+        return match_action!(self, act => &mut act.transitions);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResActIdle {
+    pub name: String,
+    #[serde(default)]
+    pub transitions: Vec<ResActionTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResActRun {
+    pub name: String,
+    pub move_speed: Fx,
+    #[serde(default)]
+    pub transitions: Vec<ResActionTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResActDash {
+    pub name: String,
+    pub frames: u32,
+    pub distance: Fx,
+    #[serde(default)]
+    pub transitions: Vec<ResActionTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResActJump {
+    pub name: String,
+    pub frames: u32,
+    pub height: Fx,
+    #[serde(default)]
+    pub transitions: Vec<ResActionTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResActAttack {
+    pub name: String,
+    pub frames: u32,
+    #[serde(default)]
+    pub skill: Option<ResID>,
+    #[serde(default)]
+    pub transitions: Vec<ResActionTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResActGuard {
+    pub name: String,
+    #[serde(default)]
+    pub frames: u32,
+    #[serde(default)]
+    pub transitions: Vec<ResActionTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResActHitStun {
+    pub name: String,
+    pub frames: u32,
+    #[serde(default)]
+    pub transitions: Vec<ResActionTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResActKnockDown {
+    pub name: String,
+    pub frames: u32,
+    #[serde(default)]
+    pub transitions: Vec<ResActionTransition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResActionTransition {
+    pub to: String,
+    pub trigger: ResActionTrigger,
+    // Frames of the current action in which the transition may fire, None for any frame.
+    #[serde(default)]
+    pub window: Option<(u32, u32)>,
+    // How many ticks an early input is kept for this transition.
+    #[serde(default)]
+    pub buffer: u32,
+    #[serde(skip)]
+    pub to_index: usize,
+}
 
-pub struct ActRun {
-    pub direction: Vector2<Fx>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ResActionTrigger {
+    Idle,
+    Move,
+    Dash,
+    Jump,
+    Attack1,
+    Attack2,
+    Defend,
+    DefendEnd,
+    Skill1,
+    Skill2,
+    SkillEx,
+    Finish,
+    Hit,
+    KnockDown,
 }
 
 // #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -1,6 +1,8 @@
 use super::cache::{CompileContext, RestoreContext};
+use super::curve::{ResCurve, ResCurveRef};
 use crate::id::{ClassID, FastResID, ResID};
 use anyhow::{anyhow, Result};
+use math::{fi, Fx};
 use serde::{Deserialize, Serialize};
 use std::mem;
 use std::raw::TraitObject;
@@ -14,7 +16,7 @@ pub enum ResCoordinate {
     Skill,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum ResLerpFunction {
     Linear,
     QuadIn,
@@ -32,6 +34,7 @@ pub enum ResLerpFunction {
     ExpoIn,
     ExpoOut,
     ExpoInOut,
+    Curve(ResCurveRef),
 }
 
 impl Default for ResLerpFunction {
@@ -40,6 +43,44 @@ impl Default for ResLerpFunction {
     }
 }
 
+impl ResLerpFunction {
+    pub fn curve(&self) -> Option<&Arc<ResCurve>> {
+        return match self {
+            ResLerpFunction::Curve(curve) => curve.curve.as_ref(),
+            _ => None,
+        };
+    }
+
+    pub(crate) fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        return match self {
+            ResLerpFunction::Curve(curve) => curve.restore(ctx),
+            _ => Ok(()),
+        };
+    }
+}
+
+// Maps the motion progress onto [start_progress, finish_progress] of the lerp function.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ResLerpParameter {
+    #[serde(default)]
+    pub start_progress: Fx,
+    #[serde(default = "default_finish_progress")]
+    pub finish_progress: Fx,
+}
+
+fn default_finish_progress() -> Fx {
+    return fi(1);
+}
+
+impl Default for ResLerpParameter {
+    fn default() -> ResLerpParameter {
+        return ResLerpParameter {
+            start_progress: fi(0),
+            finish_progress: fi(1),
+        };
+    }
+}
+
 pub trait ResObjStatic {
     fn id() -> ClassID;
 }
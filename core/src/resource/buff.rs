@@ -0,0 +1,161 @@
+use super::base::ResObj;
+use super::cache::{CompileContext, RestoreContext};
+use crate::derive::def_res;
+use crate::engine::CtxBuffTick;
+use crate::id::{ClassID, FastResID, ResID};
+use crate::script::{ScriptByteCode, ScriptCompiler};
+use anyhow::{anyhow, Result};
+use math::Fx;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ResBuffStacking {
+    // Resets the duration, keeps a single stack.
+    Refresh,
+    // Adds a stack up to max_stacks and resets the duration.
+    Stack,
+    // Adds the duration to the remaining frames.
+    Extend,
+    // Keeps the active buff unchanged.
+    Ignore,
+}
+
+impl Default for ResBuffStacking {
+    fn default() -> ResBuffStacking {
+        return ResBuffStacking::Refresh;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ResBuffStat {
+    MaxHealth,
+    MaxEnergy,
+    MaxPosture,
+    StaggerPosture,
+    PhysicalAttack,
+    PhysicalDefense,
+    ElementalAttack,
+    ElementalDefense,
+    ArcaneAttack,
+    ArcaneDefense,
+}
+
+// Declared in application order, all Add then all Multiply then Override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ResBuffOperator {
+    Add,
+    Multiply,
+    Override,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResBuffModifier {
+    pub stat: ResBuffStat,
+    pub operator: ResBuffOperator,
+    // Multiplied by the stack count, except for Override.
+    pub value: Fx,
+}
+
+#[def_res(ClassID::Buff)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResBuff {
+    pub res_id: ResID,
+    #[serde(skip)]
+    pub fres_id: FastResID,
+    // Frames before the buff expires, 0 never expires.
+    pub duration: u32,
+    #[serde(default)]
+    pub stacking: ResBuffStacking,
+    #[serde(default = "default_max_stacks")]
+    pub max_stacks: u32,
+    #[serde(default)]
+    pub tick_interval: u32,
+    #[serde(default)]
+    pub modifiers: Vec<ResBuffModifier>,
+    #[serde(default)]
+    pub tick_script: Option<String>,
+    #[serde(skip)]
+    pub tick_byte_code: Option<ScriptByteCode>,
+}
+
+fn default_max_stacks() -> u32 {
+    return 1;
+}
+
+#[typetag::serde(name = "Buff")]
+impl ResObj for ResBuff {
+    fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
+        ctx.insert_res_id(&self.res_id)?;
+        if self.max_stacks == 0 {
+            return Err(anyhow!("Buff max_stacks must be positive"));
+        }
+        if self.tick_script.is_some() && self.tick_interval == 0 {
+            return Err(anyhow!("Buff tick_script requires a tick_interval"));
+        }
+        self.compile_script()?;
+        return Ok(());
+    }
+
+    fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        self.fres_id = ctx.get_fres_id(&self.res_id)?;
+        self.tick_byte_code = self.compile_script()?;
+        return Ok(());
+    }
+}
+
+impl ResBuff {
+    fn compile_script(&self) -> Result<Option<ScriptByteCode>> {
+        return match &self.tick_script {
+            Some(script) => {
+                let mut compiler = ScriptCompiler::new();
+                let byte_code = compiler
+                    .run::<CtxBuffTick>(script)
+                    .map_err(|err| anyhow!("Buff tick_script: {}", err))?;
+                Ok(Some(byte_code))
+            }
+            None => Ok(None),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::cache::ResCache;
+    use crate::resource::test_res::{restore_res, write_res};
+
+    const BUFF_BURN: &'static str = "- type: Buff\n  res_id: Buff.Burn\n  duration: 120\n  stacking: Stack\n  max_stacks: 3\n  tick_interval: 30\n  modifiers:\n  - { stat: PhysicalDefense, operator: Multiply, value: -0.1 }\n  tick_script: delta.health = -10 * buff.stacks\n";
+
+    #[test]
+    fn test_res_buff() {
+        let dir = write_res("buff", BUFF_BURN);
+        let cache = restore_res(&dir);
+        let buff = cache
+            .find_res_by_id(&ResID::from("Buff.Burn"))
+            .unwrap()
+            .cast_as::<ResBuff>()
+            .unwrap();
+        assert_eq!(buff.stacking, ResBuffStacking::Stack);
+        assert_eq!(buff.max_stacks, 3);
+        assert_eq!(buff.modifiers[0].stat, ResBuffStat::PhysicalDefense);
+        assert!(buff.tick_byte_code.is_some());
+    }
+
+    #[test]
+    fn test_res_buff_invalid() {
+        let dir = write_res(
+            "buff_script",
+            &BUFF_BURN.replace("delta.health", "buff.stacks"),
+        );
+        let errors = ResCache::check(dir.root_str(), "resource.yml", None);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("tick_script"));
+
+        let dir = write_res(
+            "buff_interval",
+            &BUFF_BURN.replace("tick_interval: 30", "tick_interval: 0"),
+        );
+        let errors = ResCache::check(dir.root_str(), "resource.yml", None);
+        assert_eq!(errors.len(), 1);
+    }
+}
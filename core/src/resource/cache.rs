@@ -1,6 +1,8 @@
 // use super::action::ResAction;
 use super::base::ResObj;
+use super::curve::ResCurve;
 use super::id_table::IDTable;
+use super::overlay::{apply_patch, ResOverlayFile, ResPatchInfo};
 use super::shape::{ShapeCacheKey, ShapeCacheValue};
 use crate::id::{FastResID, FastResIDGener, ResID};
 use crate::utils::deserialize;
@@ -15,6 +17,8 @@ use std::sync::Arc;
 struct ResFile {
     #[serde(default)]
     include: Vec<String>,
+    #[serde(default)]
+    overlay: HashMap<String, Vec<String>>,
     #[derivative(Debug = "ignore")]
     #[serde(default)]
     resource: Vec<Arc<dyn ResObj>>,
@@ -31,12 +35,19 @@ enum CacheStatus {
 
 pub struct ResCache {
     status: CacheStatus,
+    generation: u64,
     root_path: PathBuf,
+    res_file: String,
+    profile: Option<String>,
     file_pathes: HashSet<PathBuf>,
+    overlay_files: Vec<String>,
     id_table: IDTable,
     res_cache: HashMap<ResID, Arc<dyn ResObj>>,
+    patches: HashMap<ResID, Vec<ResPatchInfo>>,
     fres_cache: HashMap<FastResID, Arc<dyn ResObj>>,
     shape_cache: HashMap<ShapeCacheKey, ShapeCacheValue>,
+    // Resources referencing each curve through a ResCurveRef.
+    curve_users: HashMap<ResID, HashSet<ResID>>,
 }
 
 impl ResCache {
@@ -44,35 +55,130 @@ impl ResCache {
         return ResCache {
             root_path: PathBuf::new(),
             status: CacheStatus::Unknown,
+            generation: 0,
+            res_file: String::new(),
+            profile: None,
             file_pathes: HashSet::new(),
+            overlay_files: Vec::new(),
             id_table: IDTable::new(),
             res_cache: HashMap::new(),
+            patches: HashMap::new(),
             fres_cache: HashMap::new(),
             shape_cache: HashMap::new(),
+            curve_users: HashMap::new(),
         };
     }
 
     pub fn compile(root_path: &str, res_file: &str) -> Result<Arc<ResCache>> {
+        return Self::compile_impl(root_path, res_file, None);
+    }
+
+    pub fn compile_with_profile(
+        root_path: &str,
+        res_file: &str,
+        profile: &str,
+    ) -> Result<Arc<ResCache>> {
+        return Self::compile_impl(root_path, res_file, Some(profile));
+    }
+
+    fn compile_impl(
+        root_path: &str,
+        res_file: &str,
+        profile: Option<&str>,
+    ) -> Result<Arc<ResCache>> {
         let mut cache = ResCache::new();
         cache.status = CacheStatus::Compiling;
         cache.root_path = PathBuf::from(root_path);
+        cache.res_file = res_file.to_string();
+        cache.profile = profile.map(|profile| profile.to_string());
 
         cache.load_res_objs(res_file)?;
+        cache.load_overlays()?;
 
-        cache.compile_res_objs()?;
+        cache.compile_res_objs(None)?;
         cache.status = CacheStatus::Compiled;
 
         return Ok(Arc::new(cache));
     }
 
+    // Compiles and restores every resource, collects errors instead of stopping at the first one.
+    pub fn check(root_path: &str, res_file: &str, profile: Option<&str>) -> Vec<ResCheckError> {
+        let mut cache = ResCache::new();
+        cache.status = CacheStatus::Compiling;
+        cache.root_path = PathBuf::from(root_path);
+        cache.res_file = res_file.to_string();
+        cache.profile = profile.map(|profile| profile.to_string());
+
+        let loaded = cache
+            .load_res_objs(res_file)
+            .and_then(|_| cache.load_overlays());
+        if let Err(err) = loaded {
+            return vec![ResCheckError::new(None, err)];
+        }
+
+        let mut errors = Vec::new();
+        let mut res_objs: Vec<_> = cache.res_cache.clone().into_iter().collect();
+        res_objs.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut ctx = CompileContext {
+            cache: &mut cache,
+            prev_table: None,
+            res_gener: FastResIDGener::new(1),
+        };
+        res_objs.retain(|(res_id, res)| {
+            let mut res = res.clone();
+            return match unsafe { Arc::get_mut_unchecked(&mut res).compile(&mut ctx) } {
+                Ok(_) => true,
+                Err(err) => {
+                    errors.push(ResCheckError::new(Some(res_id.clone()), err));
+                    false
+                }
+            };
+        });
+
+        cache.status = CacheStatus::Restoring;
+        let mut ctx = RestoreContext {
+            cache: &mut cache,
+            res_id: None,
+        };
+        for (res_id, res) in &mut res_objs {
+            ctx.res_id = Some(res_id.clone());
+            if let Err(err) = unsafe { Arc::get_mut_unchecked(res).restore(&mut ctx) } {
+                errors.push(ResCheckError::new(Some(res_id.clone()), err));
+            }
+        }
+        return errors;
+    }
+
     pub fn restore(root_path: &str, res_file: &str, id_file: &str) -> Result<Arc<ResCache>> {
+        return Self::restore_impl(root_path, res_file, id_file, None);
+    }
+
+    pub fn restore_with_profile(
+        root_path: &str,
+        res_file: &str,
+        id_file: &str,
+        profile: &str,
+    ) -> Result<Arc<ResCache>> {
+        return Self::restore_impl(root_path, res_file, id_file, Some(profile));
+    }
+
+    fn restore_impl(
+        root_path: &str,
+        res_file: &str,
+        id_file: &str,
+        profile: Option<&str>,
+    ) -> Result<Arc<ResCache>> {
         let mut cache = ResCache::new();
         cache.status = CacheStatus::Restoring;
         cache.root_path = PathBuf::from(root_path);
+        cache.res_file = res_file.to_string();
+        cache.profile = profile.map(|profile| profile.to_string());
 
         let id_path = cache.get_res_path(id_file)?;
         cache.id_table = deserialize(&id_path)?;
         cache.load_res_objs(res_file)?;
+        cache.load_overlays()?;
 
         let res = cache.restore_res_objs();
         res?;
@@ -81,6 +187,30 @@ impl ResCache {
         return Ok(Arc::new(cache));
     }
 
+    // Reloads all resource files of prev, keeps FastResIDs of existing resources,
+    // and returns the new generation with the ResIDs added or changed since prev.
+    pub fn reload(prev: &ResCache) -> Result<(Arc<ResCache>, Vec<ResID>)> {
+        let mut cache = ResCache::new();
+        cache.status = CacheStatus::Compiling;
+        cache.generation = prev.generation + 1;
+        cache.root_path = prev.root_path.clone();
+        cache.res_file = prev.res_file.clone();
+        cache.profile = prev.profile.clone();
+
+        let res_file = prev.res_file.clone();
+        cache.load_res_objs(&res_file)?;
+        cache.load_overlays()?;
+        cache.compile_res_objs(Some(&prev.id_table))?;
+        cache.check_fres_ids(&prev.id_table)?;
+
+        cache.status = CacheStatus::Restoring;
+        cache.restore_res_objs()?;
+        cache.status = CacheStatus::Restored;
+
+        let changed = cache.changed_res_ids(prev)?;
+        return Ok((Arc::new(cache), changed));
+    }
+
     fn load_res_objs(&mut self, res_file: &str) -> Result<()> {
         let get_res_path = self.get_res_path(res_file)?;
         if self.file_pathes.contains(&get_res_path) {
@@ -98,17 +228,76 @@ impl ResCache {
             self.res_cache.insert(res_id, res.clone());
         }
 
+        if let Some(profile) = &self.profile {
+            if let Some(files) = res_file.overlay.get(profile) {
+                self.overlay_files.extend(files.iter().cloned());
+            }
+        }
+
         for inc_file in &res_file.include {
             self.load_res_objs(inc_file)?;
         }
         return Ok(());
     }
 
-    fn compile_res_objs(&mut self) -> Result<()> {
+    fn load_overlays(&mut self) -> Result<()> {
+        let profile = match &self.profile {
+            Some(profile) => profile.clone(),
+            None => return Ok(()),
+        };
+        if self.overlay_files.is_empty() {
+            return Err(anyhow!("Profile not found {:?}", profile));
+        }
+        let overlay_files = self.overlay_files.clone();
+        for overlay_file in &overlay_files {
+            self.load_overlay(&profile, overlay_file)?;
+        }
+        return Ok(());
+    }
+
+    fn load_overlay(&mut self, profile: &str, overlay_file: &str) -> Result<()> {
+        let overlay_path = self.get_res_path(overlay_file)?;
+        if self.file_pathes.contains(&overlay_path) {
+            return Ok(());
+        }
+        let overlay: ResOverlayFile =
+            deserialize(&overlay_path).context(format!("file {:?}", overlay_path))?;
+        self.file_pathes.insert(overlay_path.clone());
+
+        for patch in &overlay.patch {
+            let res = match self.res_cache.get(&patch.res_id) {
+                Some(res) => res,
+                None => return Err(anyhow!("Patch an unknown ResID {:?}", patch.res_id)),
+            };
+            let (res, fields) =
+                apply_patch(res, patch).context(format!("file {:?}", overlay_path))?;
+            self.res_cache.insert(patch.res_id.clone(), res);
+            self.patches
+                .entry(patch.res_id.clone())
+                .or_insert_with(Vec::new)
+                .push(ResPatchInfo {
+                    profile: profile.to_string(),
+                    file: overlay_path.clone(),
+                    fields,
+                });
+        }
+
+        for inc_file in &overlay.include {
+            self.load_overlay(profile, inc_file)?;
+        }
+        return Ok(());
+    }
+
+    fn compile_res_objs(&mut self, prev_table: Option<&IDTable>) -> Result<()> {
+        let start = match prev_table {
+            Some(table) => table.max_fres_id().map_or(1, |id| u64::from(id) + 1),
+            None => 1,
+        };
         let mut res_cache = self.res_cache.clone();
         let mut ctx = CompileContext {
             cache: self,
-            res_gener: FastResIDGener::new(1),
+            prev_table,
+            res_gener: FastResIDGener::new(start),
         };
         for (_, res) in &mut res_cache {
             unsafe { Arc::get_mut_unchecked(res).compile(&mut ctx) }?;
@@ -119,8 +308,12 @@ impl ResCache {
     fn restore_res_objs(&mut self) -> Result<()> {
         let mut res_cache = self.res_cache.clone();
         let mut fres_cache = HashMap::new();
-        let mut ctx = RestoreContext { cache: self };
-        for (_, res) in &mut res_cache {
+        let mut ctx = RestoreContext {
+            cache: self,
+            res_id: None,
+        };
+        for (res_id, res) in &mut res_cache {
+            ctx.res_id = Some(res_id.clone());
             unsafe { Arc::get_mut_unchecked(res).restore(&mut ctx) }?;
             let fres_id = res.fres_id();
             if fres_cache.insert(fres_id, res.clone()).is_some() {
@@ -131,6 +324,44 @@ impl ResCache {
         return Ok(());
     }
 
+    fn check_fres_ids(&self, prev_table: &IDTable) -> Result<()> {
+        for (res_id, prev_fres_id) in prev_table.iter() {
+            let fres_id = self
+                .id_table
+                .get_fres_id(res_id)
+                .context("ResObj removed during reload")?;
+            if fres_id != *prev_fres_id {
+                return Err(anyhow!("FastResID changed {:?}", res_id));
+            }
+        }
+        return Ok(());
+    }
+
+    // A resource referencing a changed curve changes with it, its restored curve is a new one.
+    fn changed_res_ids(&self, prev: &ResCache) -> Result<Vec<ResID>> {
+        let mut changed = HashSet::new();
+        for (res_id, res) in &self.res_cache {
+            let is_changed = match prev.res_cache.get(res_id) {
+                Some(prev_res) => serde_json::to_value(res)? != serde_json::to_value(prev_res)?,
+                None => true,
+            };
+            if is_changed {
+                changed.insert(res_id.clone());
+            }
+        }
+        let mut users = Vec::new();
+        for (curve_id, curve_users) in &self.curve_users {
+            if changed.contains(curve_id) {
+                users.extend(curve_users.iter().cloned());
+            }
+        }
+        changed.extend(users);
+
+        let mut changed: Vec<ResID> = changed.into_iter().collect();
+        changed.sort();
+        return Ok(changed);
+    }
+
     fn get_res_path(&self, file: &str) -> Result<PathBuf> {
         let mut path = self.root_path.clone();
         path.push(file);
@@ -139,6 +370,29 @@ impl ResCache {
 }
 
 impl ResCache {
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        return self.generation;
+    }
+
+    #[inline]
+    pub fn profile(&self) -> Option<&str> {
+        return self.profile.as_deref();
+    }
+
+    #[inline]
+    pub fn file_pathes(&self) -> &HashSet<PathBuf> {
+        return &self.file_pathes;
+    }
+
+    #[inline]
+    pub fn find_patches(&self, res_id: &ResID) -> &[ResPatchInfo] {
+        return match self.patches.get(res_id) {
+            Some(patches) => patches,
+            None => &[],
+        };
+    }
+
     #[inline]
     pub fn id_table(&self) -> &IDTable {
         return &self.id_table;
@@ -149,6 +403,11 @@ impl ResCache {
         return self.id_table.get_fres_id(res_id);
     }
 
+    #[inline]
+    pub fn res_objs(&self) -> impl Iterator<Item = (&ResID, &Arc<dyn ResObj>)> {
+        return self.res_cache.iter();
+    }
+
     #[inline]
     pub fn find_res_by_id(&self, res_id: &ResID) -> Result<Arc<dyn ResObj>> {
         return match self.res_cache.get(res_id) {
@@ -166,25 +425,55 @@ impl ResCache {
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ResCheckError {
+    pub res_id: Option<ResID>,
+    pub message: String,
+}
+
+impl ResCheckError {
+    fn new(res_id: Option<ResID>, err: anyhow::Error) -> ResCheckError {
+        return ResCheckError {
+            res_id,
+            message: format!("{:#}", err),
+        };
+    }
+}
+
 pub struct CompileContext<'t> {
     cache: &'t mut ResCache,
+    prev_table: Option<&'t IDTable>,
     res_gener: FastResIDGener,
 }
 
 impl<'t> CompileContext<'t> {
+    pub(crate) fn root_path(&self) -> &PathBuf {
+        return &self.cache.root_path;
+    }
+
     pub(crate) fn insert_res_id(&mut self, res_id: &ResID) -> Result<()> {
         if self.cache.status != CacheStatus::Compiling {
             return Err(anyhow!("Not in compiling status"));
         }
-        return self
-            .cache
-            .id_table
-            .insert_res_id(res_id, self.res_gener.gen());
+        let fres_id = match self.prev_table.map(|table| table.get_fres_id(res_id)) {
+            Some(Ok(fres_id)) => fres_id,
+            _ => self.res_gener.gen(),
+        };
+        return self.cache.id_table.insert_res_id(res_id, fres_id);
+    }
+
+    pub(crate) fn insert_shape_bake(&mut self, key: &str, hash: u64) -> Result<()> {
+        if self.cache.status != CacheStatus::Compiling {
+            return Err(anyhow!("Not in compiling status"));
+        }
+        return self.cache.id_table.insert_shape_bake(key, hash);
     }
 }
 
 pub struct RestoreContext<'t> {
     cache: &'t mut ResCache,
+    // The resource being restored.
+    res_id: Option<ResID>,
 }
 
 #[allow(dead_code)]
@@ -204,6 +493,13 @@ impl<'t> RestoreContext<'t> {
         return self.cache.id_table.get_fres_id(res_id);
     }
 
+    pub(crate) fn get_shape_bake(&self, key: &str) -> Result<u64> {
+        if self.cache.status != CacheStatus::Restoring {
+            return Err(anyhow!("Not in restoring status"));
+        }
+        return self.cache.id_table.get_shape_bake(key);
+    }
+
     pub(crate) fn find_res<R: ResObj>(&self, res_id: &ResID) -> Result<Arc<R>> {
         if self.cache.status != CacheStatus::Restoring {
             return Err(anyhow!("Not in restoring status"));
@@ -214,6 +510,19 @@ impl<'t> RestoreContext<'t> {
         };
     }
 
+    // Finds a curve and records the restored resource as one of its users.
+    pub(crate) fn find_curve(&mut self, curve_id: &ResID) -> Result<Arc<ResCurve>> {
+        let curve = self.find_res::<ResCurve>(curve_id)?;
+        if let Some(res_id) = &self.res_id {
+            self.cache
+                .curve_users
+                .entry(curve_id.clone())
+                .or_insert_with(HashSet::new)
+                .insert(res_id.clone());
+        }
+        return Ok(curve);
+    }
+
     pub(crate) fn find_stage(&self, stage_id: &ResID) -> Result<Arc<dyn ResObj>> {
         if self.cache.status != CacheStatus::Restoring {
             return Err(anyhow!("Not in restoring status"));
@@ -236,12 +545,40 @@ impl<'t> RestoreContext<'t> {
             Some(chara) => chara,
             None => return Err(anyhow!("Character not found {:?}", chara_id)),
         };
-        if !chara.class_id().is_stage() {
+        if !chara.class_id().is_character() {
             return Err(anyhow!("Character not found {:?}", chara_id));
         }
         return Ok(chara.clone());
     }
 
+    pub(crate) fn find_skill(&self, skill_id: &ResID) -> Result<Arc<dyn ResObj>> {
+        if self.cache.status != CacheStatus::Restoring {
+            return Err(anyhow!("Not in restoring status"));
+        }
+        let skill = match self.cache.res_cache.get(skill_id) {
+            Some(skill) => skill,
+            None => return Err(anyhow!("Skill not found {:?}", skill_id)),
+        };
+        if !skill.class_id().is_skill() {
+            return Err(anyhow!("Skill not found {:?}", skill_id));
+        }
+        return Ok(skill.clone());
+    }
+
+    pub(crate) fn find_hit(&self, hit_id: &ResID) -> Result<Arc<dyn ResObj>> {
+        if self.cache.status != CacheStatus::Restoring {
+            return Err(anyhow!("Not in restoring status"));
+        }
+        let hit = match self.cache.res_cache.get(hit_id) {
+            Some(hit) => hit,
+            None => return Err(anyhow!("Hit not found {:?}", hit_id)),
+        };
+        if !hit.class_id().is_hit() {
+            return Err(anyhow!("Hit not found {:?}", hit_id));
+        }
+        return Ok(hit.clone());
+    }
+
     pub(crate) fn find_shape(&mut self, key: &ShapeCacheKey) -> Option<ShapeCacheValue> {
         if self.cache.status != CacheStatus::Restoring {
             return None;
@@ -1,4 +1,4 @@
-// use super::action::ResAction;
+use super::action::ResAction;
 use super::base::ResObj;
 use super::cache::{CompileContext, RestoreContext};
 use super::shape::ResShape;
@@ -18,6 +18,9 @@ pub struct ResCharaHuman {
     pub max_health: i32,
     pub max_energy: i32,
     pub max_posture: i32,
+    // Posture damage of a single hit that staggers the character, 0 never staggers.
+    #[serde(default)]
+    pub stagger_posture: i32,
     pub move_speed: Fx,
     pub physical_attack: i32,
     pub physical_defense: i32,
@@ -25,18 +28,24 @@ pub struct ResCharaHuman {
     pub elemental_defense: i32,
     pub arcane_attack: i32,
     pub arcane_defense: i32,
+    #[serde(default)]
+    pub action: Option<ResID>,
 }
 
 #[typetag::serde(name = "CharaHuman")]
 impl ResObj for ResCharaHuman {
     fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
         ctx.insert_res_id(&self.res_id)?;
+        self.collision.compile(ctx)?;
         return Ok(());
     }
 
     fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
         self.fres_id = ctx.get_fres_id(&self.res_id)?;
         self.collision.restore(ctx)?;
+        if let Some(action) = &self.action {
+            ctx.find_res::<ResAction>(action)?;
+        }
         return Ok(());
     }
 }
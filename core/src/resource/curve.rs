@@ -0,0 +1,301 @@
+use super::base::ResObj;
+use super::cache::{CompileContext, RestoreContext};
+use crate::derive::def_res;
+use crate::id::{ClassID, FastResID, ResID};
+use anyhow::{anyhow, Result};
+use math::{fi, Fx};
+use na::ComplexField;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ResCurveInterpolation {
+    // in_value/out_value are the slopes at the key.
+    Hermite,
+    // in_value/out_value are the control values at 1/3 and 2/3 of the adjacent segments.
+    Bezier,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ResCurveKey {
+    pub time: Fx,
+    pub value: Fx,
+    #[serde(default)]
+    pub in_value: Fx,
+    #[serde(default)]
+    pub out_value: Fx,
+}
+
+// Reference to a ResCurve from a ResLerpFunction, resolved at restore.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ResCurveRef {
+    pub res_id: ResID,
+    #[serde(skip)]
+    pub curve: Option<Arc<ResCurve>>,
+}
+
+impl PartialEq for ResCurveRef {
+    fn eq(&self, other: &ResCurveRef) -> bool {
+        return self.res_id == other.res_id;
+    }
+}
+
+impl Eq for ResCurveRef {}
+
+impl Hash for ResCurveRef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.res_id.hash(state);
+    }
+}
+
+impl ResCurveRef {
+    pub(crate) fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        self.curve = Some(ctx.find_curve(&self.res_id)?);
+        return Ok(());
+    }
+}
+
+#[def_res(ClassID::Curve)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResCurve {
+    pub res_id: ResID,
+    #[serde(skip)]
+    pub fres_id: FastResID,
+    pub interpolation: ResCurveInterpolation,
+    pub keys: Vec<ResCurveKey>,
+    // Lookup table size baked at restore, 0 evaluates the segments directly.
+    #[serde(default = "default_bake_samples")]
+    pub bake_samples: u32,
+    #[serde(skip)]
+    pub lut: Vec<Fx>,
+}
+
+fn default_bake_samples() -> u32 {
+    return 64;
+}
+
+#[typetag::serde(name = "Curve")]
+impl ResObj for ResCurve {
+    fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
+        ctx.insert_res_id(&self.res_id)?;
+        if self.keys.len() < 2 {
+            return Err(anyhow!("Curve needs at least 2 keys {:?}", self.res_id));
+        }
+        for idx in 1..self.keys.len() {
+            if self.keys[idx].time <= self.keys[idx - 1].time {
+                return Err(anyhow!(
+                    "Curve key[{}] time is not increasing {:?}",
+                    idx,
+                    self.res_id
+                ));
+            }
+        }
+        if self.bake_samples == 1 {
+            return Err(anyhow!("Curve bake_samples must be 0 or >= 2"));
+        }
+        return Ok(());
+    }
+
+    fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        self.fres_id = ctx.get_fres_id(&self.res_id)?;
+        self.bake();
+        return Ok(());
+    }
+}
+
+impl ResCurve {
+    #[inline]
+    pub fn start_time(&self) -> Fx {
+        return self.keys[0].time;
+    }
+
+    #[inline]
+    pub fn finish_time(&self) -> Fx {
+        return self.keys[self.keys.len() - 1].time;
+    }
+
+    // Samples the curve at time, from the lookup table once baked.
+    pub fn sample(&self, time: Fx) -> Fx {
+        if self.lut.len() < 2 {
+            return self.evaluate(time);
+        }
+        let (start, finish) = (self.start_time(), self.finish_time());
+        if time <= start {
+            return self.lut[0];
+        }
+        if time >= finish {
+            return self.lut[self.lut.len() - 1];
+        }
+        let pos = (time - start) / (finish - start) * fi((self.lut.len() - 1) as i64);
+        let idx = pos.floor().to_usize().min(self.lut.len() - 2);
+        let frac = pos - pos.floor();
+        let (a, b) = (self.lut[idx], self.lut[idx + 1]);
+        return a + (b - a) * frac;
+    }
+
+    // Evaluates the cubic segment containing time, clamped to the first and last key.
+    pub fn evaluate(&self, time: Fx) -> Fx {
+        let keys = &self.keys;
+        if time <= keys[0].time {
+            return keys[0].value;
+        }
+        if time >= keys[keys.len() - 1].time {
+            return keys[keys.len() - 1].value;
+        }
+        let idx = match keys.binary_search_by(|key| key.time.cmp(&time)) {
+            Ok(idx) => return keys[idx].value,
+            Err(idx) => idx - 1,
+        };
+        let (k0, k1) = (&keys[idx], &keys[idx + 1]);
+        let duration = k1.time - k0.time;
+        let (p1, p2) = match self.interpolation {
+            ResCurveInterpolation::Hermite => (
+                k0.value + k0.out_value * duration / fi(3),
+                k1.value - k1.in_value * duration / fi(3),
+            ),
+            ResCurveInterpolation::Bezier => (k0.out_value, k1.in_value),
+        };
+
+        let t = (time - k0.time) / duration;
+        let s = fi(1) - t;
+        return s * s * s * k0.value
+            + fi(3) * s * s * t * p1
+            + fi(3) * s * t * t * p2
+            + t * t * t * k1.value;
+    }
+
+    pub(crate) fn bake(&mut self) {
+        self.lut.clear();
+        if self.bake_samples < 2 {
+            return;
+        }
+        let (start, finish) = (self.start_time(), self.finish_time());
+        let last = fi((self.bake_samples - 1) as i64);
+        for idx in 0..self.bake_samples {
+            let time = start + (finish - start) * fi(idx as i64) / last;
+            self.lut.push(self.evaluate(time));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::cache::ResCache;
+    use crate::resource::test_res::{restore_res, write_res};
+    use crate::resource::{ResMotion, ResSkill};
+    use math::ff;
+
+    fn new_curve(interpolation: &str, keys: &str) -> ResCurve {
+        return serde_yaml::from_str(&format!(
+            "res_id: Curve.Test\ninterpolation: {}\nkeys: {}\n",
+            interpolation, keys
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_res_curve_evaluate() {
+        // Hermite with slope 1 everywhere is a straight line.
+        let curve = new_curve(
+            "Hermite",
+            "[{ time: 0, value: 0, out_value: 1 }, { time: 1, value: 1, in_value: 1 }]",
+        );
+        assert_eq!(curve.evaluate(fi(-1)), fi(0));
+        assert!((curve.evaluate(ff(0.5)) - ff(0.5)).abs() < ff(0.0001));
+        assert!((curve.evaluate(ff(0.25)) - ff(0.25)).abs() < ff(0.0001));
+        assert_eq!(curve.evaluate(fi(2)), fi(1));
+
+        let curve = new_curve(
+            "Bezier",
+            "[{ time: 0, value: 0, out_value: 0 }, { time: 0.5, value: 1, in_value: 1, out_value: 1 }, { time: 1, value: 0, in_value: 0 }]",
+        );
+        assert_eq!(curve.evaluate(ff(0.5)), fi(1));
+        assert_eq!(curve.evaluate(ff(0.25)), ff(0.5));
+        assert_eq!(curve.evaluate(ff(0.75)), ff(0.5));
+    }
+
+    #[test]
+    fn test_res_curve_bake() {
+        let mut curve = new_curve(
+            "Bezier",
+            "[{ time: 0, value: 0, out_value: 0.8 }, { time: 1, value: 1, in_value: 1 }]",
+        );
+        curve.bake_samples = 33;
+        curve.bake();
+        assert_eq!(curve.lut.len(), 33);
+        for idx in 0..=20 {
+            let time = ff(0.05) * fi(idx);
+            assert!((curve.sample(time) - curve.evaluate(time)).abs() < ff(0.01));
+        }
+        assert_eq!(curve.sample(fi(1)), fi(1));
+    }
+
+    #[test]
+    fn test_res_curve_invalid() {
+        let dir = write_res(
+            "curve",
+            "- type: Curve\n  res_id: Curve.Bad\n  interpolation: Hermite\n  keys:\n  - { time: 0.5, value: 0 }\n  - { time: 0.5, value: 1 }\n",
+        );
+        let errors = ResCache::check(dir.root_str(), "resource.yml", None);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("not increasing"));
+    }
+
+    const RELOAD: &'static str = r#"- type: Curve
+  res_id: Curve.Ease
+  interpolation: Hermite
+  keys: [{ time: 0, value: 0 }, { time: 1, value: 1 }]
+- type: Skill
+  res_id: Skill.Dash
+  frames: 10
+  shape: { type: Ball, radius: 1 }
+  center: [0, 0, 0]
+  origin:
+    rotation: [0, 0, 0]
+    translation: [0, 0, 0]
+  origin_coord: Source
+  motion_coord: Source
+  motions:
+  - { type: Move, start_frame: 0, finish_frame: 10, start_value: [0, 0, 0], finish_value: [0, 0, 2], lerp_function: { Curve: Curve.Ease } }
+  effect:
+    target_damage: { health: 0, energy: 0, posture: 0, physical: 0, elemental: 0, arcane: 0 }
+- type: Buff
+  res_id: Buff.Test
+  duration: 3
+  modifiers: []
+"#;
+
+    #[test]
+    fn test_res_curve_reload() {
+        let dir = write_res("curve_reload", RELOAD);
+        let cache = restore_res(&dir);
+
+        let resource = RELOAD.replace("{ time: 1, value: 1 }", "{ time: 1, value: 2 }");
+        dir.write("resource.yml", &format!("resource:\n{}", resource));
+        let (cache, changed) = ResCache::reload(&cache).unwrap();
+        assert_eq!(
+            changed,
+            vec![ResID::from("Curve.Ease"), ResID::from("Skill.Dash")]
+        );
+
+        let curve = cache
+            .find_res_by_id(&ResID::from("Curve.Ease"))
+            .unwrap()
+            .cast_as::<ResCurve>()
+            .unwrap();
+        let skill = cache
+            .find_res_by_id(&ResID::from("Skill.Dash"))
+            .unwrap()
+            .cast_as::<ResSkill>()
+            .unwrap();
+        match &skill.motions[0] {
+            ResMotion::Move(motion) => {
+                assert!(Arc::ptr_eq(motion.lerp_function.curve().unwrap(), &curve))
+            }
+            motion => panic!("unexpected motion {:?}", motion),
+        };
+    }
+}
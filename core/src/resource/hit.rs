@@ -0,0 +1,143 @@
+use super::base::{ResCoordinate, ResObj};
+use super::cache::{CompileContext, RestoreContext};
+use super::shape::ResShape;
+use crate::derive::def_res;
+use crate::id::{ClassID, FastResID, ResID};
+use anyhow::{anyhow, Result};
+use math::{fi, Fx};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResHitArea {
+    pub shape: ResShape,
+    pub coordinate: ResCoordinate,
+    // Attaches the area to a bone of the source character instead of its root.
+    #[serde(default)]
+    pub bone: Option<String>,
+    pub start_frame: u32,
+    pub finish_frame: u32,
+    #[serde(default = "default_max_hits")]
+    pub max_hits: u32,
+    #[serde(default)]
+    pub hit_interval: u32,
+    #[serde(default = "default_damage_scale")]
+    pub damage_scale: Fx,
+    #[serde(default)]
+    pub hit_stun: u32,
+    #[serde(default)]
+    pub knock_down: bool,
+    // Only used by ResHitPathRay, sweeps the origin of the shape as a ray instead of the whole shape.
+    #[serde(default)]
+    pub ray: bool,
+}
+
+fn default_max_hits() -> u32 {
+    return 1;
+}
+
+fn default_damage_scale() -> Fx {
+    return fi(1);
+}
+
+impl ResHitArea {
+    #[inline]
+    pub fn is_active(&self, frame: u32) -> bool {
+        return self.start_frame <= frame && frame < self.finish_frame;
+    }
+
+    pub(crate) fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
+        if self.start_frame >= self.finish_frame {
+            return Err(anyhow!(
+                "Hit area frame range {}..{} is empty",
+                self.start_frame,
+                self.finish_frame
+            ));
+        }
+        if self.max_hits == 0 {
+            return Err(anyhow!("Hit area max_hits must be positive"));
+        }
+        return self.shape.compile(ctx);
+    }
+
+    pub(crate) fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        return self.shape.restore(ctx);
+    }
+}
+
+#[def_res(ClassID::HitAttachment)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResHitAttachment {
+    pub res_id: ResID,
+    #[serde(skip)]
+    pub fres_id: FastResID,
+    pub areas: Vec<ResHitArea>,
+}
+
+#[typetag::serde(name = "HitAttachment")]
+impl ResObj for ResHitAttachment {
+    fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
+        ctx.insert_res_id(&self.res_id)?;
+        for area in &mut self.areas {
+            area.compile(ctx)?;
+        }
+        return Ok(());
+    }
+
+    fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        self.fres_id = ctx.get_fres_id(&self.res_id)?;
+        for area in &mut self.areas {
+            area.restore(ctx)?;
+        }
+        return Ok(());
+    }
+}
+
+impl ResHitAttachment {
+    pub fn frames(&self) -> u32 {
+        return self
+            .areas
+            .iter()
+            .map(|area| area.finish_frame)
+            .max()
+            .unwrap_or(0);
+    }
+}
+
+#[def_res(ClassID::HitPathRay)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResHitPathRay {
+    pub res_id: ResID,
+    #[serde(skip)]
+    pub fres_id: FastResID,
+    pub areas: Vec<ResHitArea>,
+}
+
+#[typetag::serde(name = "HitPathRay")]
+impl ResObj for ResHitPathRay {
+    fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
+        ctx.insert_res_id(&self.res_id)?;
+        for area in &mut self.areas {
+            area.compile(ctx)?;
+        }
+        return Ok(());
+    }
+
+    fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        self.fres_id = ctx.get_fres_id(&self.res_id)?;
+        for area in &mut self.areas {
+            area.restore(ctx)?;
+        }
+        return Ok(());
+    }
+}
+
+impl ResHitPathRay {
+    pub fn frames(&self) -> u32 {
+        return self
+            .areas
+            .iter()
+            .map(|area| area.finish_frame)
+            .max()
+            .unwrap_or(0);
+    }
+}
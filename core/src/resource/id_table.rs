@@ -1,17 +1,23 @@
 use crate::id::{FastResID, ResID};
+use crate::utils::FnvHasher;
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IDTable {
     res_table: HashMap<ResID, FastResID>,
+    // Bake hash of every baked shape, recorded by the compiler so restore never hashes sources.
+    #[serde(default)]
+    shape_table: HashMap<String, u64>,
 }
 
 impl IDTable {
     pub fn new() -> IDTable {
         return IDTable {
             res_table: HashMap::with_capacity(128),
+            shape_table: HashMap::new(),
         };
     }
 
@@ -29,7 +35,42 @@ impl IDTable {
         };
     }
 
+    // Resources sharing a shape bake it once, a second insert must agree on the hash.
+    pub(crate) fn insert_shape_bake(&mut self, key: &str, hash: u64) -> Result<()> {
+        return match self.shape_table.insert(key.to_string(), hash) {
+            Some(prev) if prev != hash => Err(anyhow!("Shape bake conflict {:?}", key)),
+            _ => Ok(()),
+        };
+    }
+
+    pub fn get_shape_bake(&self, key: &str) -> Result<u64> {
+        return match self.shape_table.get(key) {
+            Some(hash) => Ok(*hash),
+            None => Err(anyhow!("Shape not baked {:?}", key)),
+        };
+    }
+
     pub fn res_count(&self) -> usize {
         return self.res_table.len();
     }
+
+    pub fn max_fres_id(&self) -> Option<FastResID> {
+        return self.res_table.values().max().cloned();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ResID, &FastResID)> {
+        return self.res_table.iter();
+    }
+
+    // Stable hash of the table, peers and replays must share it to agree on FastResIDs.
+    pub fn checksum(&self) -> u64 {
+        let mut entries: Vec<_> = self.res_table.iter().collect();
+        entries.sort();
+        let mut hasher = FnvHasher::new();
+        for (res_id, fres_id) in entries {
+            res_id.hash(&mut hasher);
+            hasher.write_u64(u64::from(*fres_id));
+        }
+        return hasher.finish();
+    }
 }
@@ -1,24 +1,45 @@
 mod action;
 mod base;
+mod buff;
 mod cache;
 mod character;
+mod curve;
 mod hit;
 mod id_table;
+mod overlay;
 mod prefab;
+mod projectile;
 mod shape;
+mod shape_bake;
+mod skill;
 mod stage;
-// mod skill;
+#[cfg(test)]
+pub(crate) mod test_res;
+mod watcher;
 
-pub use action::ResAction;
-pub use base::{ResObj, ResObjStatic, ResObjSuper};
-pub use cache::{CompileContext, ResCache, RestoreContext};
+pub use action::{
+    ResActAttack, ResActDash, ResActGuard, ResActHitStun, ResActIdle, ResActJump, ResActKnockDown,
+    ResActRun, ResAction, ResActionAny, ResActionTransition, ResActionTrigger,
+};
+pub use base::{
+    ResCoordinate, ResLerpFunction, ResLerpParameter, ResObj, ResObjStatic, ResObjSuper,
+};
+pub use buff::{ResBuff, ResBuffModifier, ResBuffOperator, ResBuffStacking, ResBuffStat};
+pub use cache::{CompileContext, ResCache, ResCheckError, RestoreContext};
 pub use character::ResCharaHuman;
-pub use hit::{ResHitArea, ResHitAttachment};
+pub use curve::{ResCurve, ResCurveInterpolation, ResCurveKey, ResCurveRef};
+pub use hit::{ResHitArea, ResHitAttachment, ResHitPathRay};
 pub use id_table::IDTable;
+pub use overlay::ResPatchInfo;
 pub use prefab::{ResPrefab, ResPrefabArgs, ResPrefabItem};
+pub use projectile::{ResProjectile, ResProjectileHoming, ResProjectileSpeed};
 pub use shape::{
-    ResShape, ResShapeAny, ResShapeBall, ResShapeCapsule, ResShapeCone, ResShapeCuboid,
-    ResShapeCylinder, ResShapeHuman, ResShapeTriMesh,
+    ResShape, ResShapeAny, ResShapeBall, ResShapeCapsule, ResShapeCone, ResShapeConvexHull,
+    ResShapeCuboid, ResShapeCylinder, ResShapeHuman, ResShapeTriMesh,
+};
+pub use skill::{
+    ResDamage, ResEffect, ResMotion, ResMotionMove, ResMotionMoveSpeed, ResMotionRotate,
+    ResMotionRotateSpeed, ResMotionSearchTarget, ResMotionSearcher, ResSkill,
 };
 pub use stage::{ResStageGeneral, ResStageScenery};
-// pub use skill::*;
+pub use watcher::{ResCacheUpdate, ResCacheWatcher};
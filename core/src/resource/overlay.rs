@@ -0,0 +1,145 @@
+use super::base::ResObj;
+use crate::id::ResID;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ResOverlayFile {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub patch: Vec<ResPatch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ResPatch {
+    pub res_id: ResID,
+    pub fields: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResPatchInfo {
+    pub profile: String,
+    pub file: PathBuf,
+    pub fields: Vec<String>,
+}
+
+pub(crate) fn apply_patch(
+    res: &Arc<dyn ResObj>,
+    patch: &ResPatch,
+) -> Result<(Arc<dyn ResObj>, Vec<String>)> {
+    let mut value = serde_json::to_value(res)?;
+    let mut fields = Vec::new();
+    merge_value(&mut value, &patch.fields, "", &mut fields)?;
+    let patched: Arc<dyn ResObj> = serde_json::from_value(value)?;
+    return Ok((patched, fields));
+}
+
+fn merge_value(
+    base: &mut Value,
+    patch: &Value,
+    path: &str,
+    fields: &mut Vec<String>,
+) -> Result<()> {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        _ => return Err(anyhow!("Patch must be a map {:?}", path)),
+    };
+    let base = match base {
+        Value::Object(base) => base,
+        _ => return Err(anyhow!("Patch a non-map field {:?}", path)),
+    };
+
+    for (key, patch_value) in patch {
+        let field = match path {
+            "" => key.clone(),
+            _ => format!("{}.{}", path, key),
+        };
+        if field == "type" || field == "res_id" {
+            return Err(anyhow!("Patch a readonly field {:?}", field));
+        }
+        let base_value = match base.get_mut(key) {
+            Some(base_value) => base_value,
+            None => return Err(anyhow!("Patch an unknown field {:?}", field)),
+        };
+        if base_value.is_object() && patch_value.is_object() {
+            merge_value(base_value, patch_value, &field, fields)?;
+        } else {
+            *base_value = patch_value.clone();
+            fields.push(field);
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::cache::ResCache;
+    use crate::resource::test_res::CHARA_TEST;
+    use crate::resource::ResCharaHuman;
+    use crate::utils::TestDir;
+    use math::fi;
+
+    #[test]
+    fn test_merge_value() {
+        let mut base = serde_json::json!({"type": "A", "x": 1, "y": {"z": 2, "w": 3}});
+        let mut fields = Vec::new();
+        merge_value(
+            &mut base,
+            &serde_json::json!({"x": 5, "y": {"w": 6}}),
+            "",
+            &mut fields,
+        )
+        .unwrap();
+        assert_eq!(
+            base,
+            serde_json::json!({"type": "A", "x": 5, "y": {"z": 2, "w": 6}})
+        );
+        assert_eq!(fields, vec!["x".to_string(), "y.w".to_string()]);
+
+        assert!(merge_value(&mut base, &serde_json::json!({"q": 1}), "", &mut fields).is_err());
+        assert!(merge_value(
+            &mut base,
+            &serde_json::json!({"type": "B"}),
+            "",
+            &mut fields
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_res_cache_overlay() {
+        let dir = TestDir::new("overlay");
+        dir.write(
+            "resource.yml",
+            &format!("overlay:\n  hard:\n  - hard.yml\nresource:\n{}", CHARA_TEST),
+        );
+        dir.write(
+            "hard.yml",
+            "patch:\n- res_id: Chara.Test\n  fields:\n    max_health: 20000\n    move_speed: 12\n",
+        );
+        let root_path = dir.root_str();
+        let chara_id = ResID::from("Chara.Test");
+
+        let cache = ResCache::compile(root_path, "resource.yml").unwrap();
+        let chara = cache.find_res_by_id(&chara_id).unwrap();
+        assert_eq!(chara.cast_as::<ResCharaHuman>().unwrap().max_health, 10000);
+        assert!(cache.find_patches(&chara_id).is_empty());
+
+        let cache = ResCache::compile_with_profile(root_path, "resource.yml", "hard").unwrap();
+        let chara = cache.find_res_by_id(&chara_id).unwrap();
+        let chara = chara.cast_as::<ResCharaHuman>().unwrap();
+        assert_eq!(chara.max_health, 20000);
+        assert_eq!(chara.move_speed, fi(12));
+        let patches = cache.find_patches(&chara_id);
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].profile, "hard");
+        assert_eq!(patches[0].fields, vec!["max_health", "move_speed"]);
+
+        assert!(ResCache::compile_with_profile(root_path, "resource.yml", "easy").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use super::base::{ResCoordinate, ResLerpFunction, ResLerpParameter, ResObj};
+use super::cache::{CompileContext, RestoreContext};
+use super::shape::ResShape;
+use super::skill::{ResDamage, ResMotionSearchTarget};
+use crate::derive::def_res;
+use crate::id::{ClassID, FastResID, ResID};
+use crate::utils::serde_helper;
+use anyhow::{anyhow, Result};
+use math::{fi, Fx};
+use na::Isometry3;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResProjectileSpeed {
+    pub start_speed: Fx,
+    pub finish_speed: Fx,
+    #[serde(default)]
+    pub lerp_function: ResLerpFunction,
+    #[serde(default)]
+    pub lerp_parameter: ResLerpParameter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResProjectileHoming {
+    pub search: ResMotionSearchTarget,
+    // Max turn in radians per second toward the locked target.
+    pub turn_speed: Fx,
+}
+
+#[def_res(ClassID::Projectile)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResProjectile {
+    pub res_id: ResID,
+    #[serde(skip)]
+    pub fres_id: FastResID,
+    pub shape: ResShape,
+    pub launch_coord: ResCoordinate,
+    #[serde(with = "serde_helper::isometry")]
+    pub launch_transform: Isometry3<Fx>,
+    // Frames before the projectile despawns.
+    pub lifetime: u32,
+    // Speed along the forward axis (+z), lerped over the lifetime.
+    pub speed: ResProjectileSpeed,
+    #[serde(default)]
+    pub gravity: Fx,
+    #[serde(default)]
+    pub homing: Option<ResProjectileHoming>,
+    // Targets passed through before despawning, 0 despawns on the first hit.
+    #[serde(default)]
+    pub pierce: u32,
+    #[serde(default)]
+    pub damage: ResDamage,
+    #[serde(default = "default_damage_scale")]
+    pub damage_scale: Fx,
+    #[serde(default)]
+    pub hit_stun: u32,
+    #[serde(default)]
+    pub knock_down: bool,
+}
+
+fn default_damage_scale() -> Fx {
+    return fi(1);
+}
+
+#[typetag::serde(name = "Projectile")]
+impl ResObj for ResProjectile {
+    fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
+        ctx.insert_res_id(&self.res_id)?;
+        if self.lifetime == 0 {
+            return Err(anyhow!(
+                "Projectile lifetime must be positive {:?}",
+                self.res_id
+            ));
+        }
+        self.shape.compile(ctx)?;
+        if let Some(homing) = &mut self.homing {
+            homing.search.shape.compile(ctx)?;
+        }
+        return Ok(());
+    }
+
+    fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        self.fres_id = ctx.get_fres_id(&self.res_id)?;
+        self.shape.restore(ctx)?;
+        self.speed.lerp_function.restore(ctx)?;
+        if let Some(homing) = &mut self.homing {
+            homing.search.shape.restore(ctx)?;
+        }
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::cache::ResCache;
+    use crate::resource::test_res::{restore_res, write_res};
+
+    const PROJECTILE: &'static str = "- type: Projectile\n  res_id: Projectile.Arrow\n  shape: { type: Ball, radius: 0.1 }\n  launch_coord: Source\n  launch_transform:\n    rotation: [0, 0, 0]\n    translation: [0, 1, 0]\n  lifetime: 60\n  speed: { start_speed: 20, finish_speed: 10, lerp_function: QuadOut }\n  gravity: 9.8\n  pierce: 1\n  damage: { health: 0, energy: 0, posture: 10, physical: 50, elemental: 0, arcane: 0 }\n";
+
+    #[test]
+    fn test_res_projectile() {
+        let dir = write_res("projectile", PROJECTILE);
+        let cache = restore_res(&dir);
+        let projectile = cache
+            .find_res_by_id(&ResID::from("Projectile.Arrow"))
+            .unwrap()
+            .cast_as::<ResProjectile>()
+            .unwrap();
+        assert_eq!(projectile.lifetime, 60);
+        assert_eq!(projectile.pierce, 1);
+        assert_eq!(projectile.damage.physical, 50);
+        assert!(projectile.homing.is_none());
+
+        let dir = write_res(
+            "projectile_lifetime",
+            &PROJECTILE.replace("lifetime: 60", "lifetime: 0"),
+        );
+        assert_eq!(
+            ResCache::check(dir.root_str(), "resource.yml", None).len(),
+            1
+        );
+    }
+}
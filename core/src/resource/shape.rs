@@ -1,9 +1,11 @@
-use super::cache::RestoreContext;
+use super::cache::{CompileContext, RestoreContext};
+use super::shape_bake::ShapeBake;
 use crate::utils::serde_helper;
 use anyhow::{anyhow, Result};
 use collide::shape::{
-    Ball, Capsule, Cone, Cuboid, Cylinder, HumanBounding, Plane, ShapeHandle, TriMesh,
+    Ball, Capsule, Cone, ConvexHull, Cuboid, Cylinder, HumanBounding, Plane, ShapeHandle, TriMesh,
 };
+use collide::transformation;
 use lazy_static::lazy_static;
 use math::{fi, fx_f64, Fx};
 use na::{Isometry3, Point3, Unit, Vector3};
@@ -38,6 +40,15 @@ pub struct ResShape {
 }
 
 impl ResShape {
+    pub(crate) fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
+        let (key, hash) = match &self.shape {
+            ResShapeAny::TriMesh(mesh) => (mesh.bake_key(), mesh.bake(ctx.root_path())?),
+            ResShapeAny::ConvexHull(hull) => (hull.bake_key(), hull.bake(ctx.root_path())?),
+            _ => return Ok(()),
+        };
+        return ctx.insert_shape_bake(&key, hash);
+    }
+
     pub(crate) fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
         if let Some(handle) = ctx.find_shape(&self.shape) {
             self.handle = handle;
@@ -50,7 +61,14 @@ impl ResShape {
                 ResShapeAny::Cylinder(cylinder) => cylinder.load(),
                 ResShapeAny::Plane(plane) => plane.load(),
                 ResShapeAny::Human(human) => human.load(),
-                ResShapeAny::TriMesh(mesh) => mesh.load(ctx.root_path())?,
+                ResShapeAny::TriMesh(mesh) => {
+                    let hash = ctx.get_shape_bake(&mesh.bake_key())?;
+                    mesh.load(ctx.root_path(), hash)?
+                }
+                ResShapeAny::ConvexHull(hull) => {
+                    let hash = ctx.get_shape_bake(&hull.bake_key())?;
+                    hull.load(ctx.root_path(), hash)?
+                }
             };
             ctx.insert_shape(self.shape.clone(), self.handle.clone());
         }
@@ -69,6 +87,7 @@ pub enum ResShapeAny {
     Human(ResShapeHuman),
     Plane(ResShapePlane),
     TriMesh(ResShapeTriMesh),
+    ConvexHull(ResShapeConvexHull),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -169,54 +188,167 @@ pub struct ResShapeTriMesh {
 }
 
 impl ResShapeTriMesh {
-    pub(crate) fn load<P: AsRef<Path>>(&mut self, root_path: P) -> Result<ShapeHandle<Fx>> {
-        let mut path = PathBuf::from(root_path.as_ref());
-        path.push(&self.file);
-        if self.file.ends_with(".obj") {
-            return Self::load_obj(&path, &self.name);
-        } else if self.file.ends_with(".glb") {
-            return Err(anyhow!("Not implement .glb"));
-        } else if self.file.ends_with(".gltf") {
-            return Err(anyhow!("Not implement .gltf"));
+    pub fn triangle_count<P: AsRef<Path>>(&self, root_path: P) -> Result<usize> {
+        let bake_path = ShapeBake::bake_path(&root_path, "trimesh", &self.file, &self.name)?;
+        let bake = match ShapeBake::read(&bake_path)? {
+            Some(bake) => bake,
+            None => load_mesh(&root_path, &self.file, &self.name)?,
+        };
+        return Ok(bake.indices.len());
+    }
+
+    pub(crate) fn bake_key(&self) -> String {
+        return bake_key("trimesh", &self.file, &self.name);
+    }
+
+    // Returns the bake hash, the IDTable records it for restore.
+    pub(crate) fn bake<P: AsRef<Path>>(&self, root_path: P) -> Result<u64> {
+        let hash = ShapeBake::bake_hash(&root_path, "trimesh", &self.file, &self.name)?;
+        let bake_path = ShapeBake::hash_path(&root_path, "trimesh", hash);
+        if !bake_path.exists() {
+            load_mesh(&root_path, &self.file, &self.name)?.write(&bake_path)?;
         }
-        return Err(anyhow!("Unknown file format"));
+        return Ok(hash);
     }
 
-    fn load_obj<P: AsRef<Path>>(file: P, name: &str) -> Result<ShapeHandle<Fx>> {
-        let buf = fs::read_to_string(file)?;
-        let model = obj::parse(buf)?;
+    pub(crate) fn load<P: AsRef<Path>>(
+        &mut self,
+        root_path: P,
+        hash: u64,
+    ) -> Result<ShapeHandle<Fx>> {
+        let bake = load_baked_mesh(&root_path, "trimesh", hash, &self.name)?;
+        let mesh = TriMesh::new(bake.vertices, bake.indices, None);
+        return Ok(ShapeHandle::new(mesh));
+    }
+}
 
-        let mut vertices = Vec::<Point3<Fx>>::new();
-        let mut indices = Vec::<Point3<usize>>::new();
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ResShapeConvexHull {
+    pub file: String,
+    pub name: String,
+}
 
-        for obj in model.objects {
-            if obj.name != name {
-                continue;
-            }
+impl ResShapeConvexHull {
+    pub fn triangle_count<P: AsRef<Path>>(&self, root_path: P) -> Result<usize> {
+        let bake_path = ShapeBake::bake_path(&root_path, "hull", &self.file, &self.name)?;
+        let bake = match ShapeBake::read(&bake_path)? {
+            Some(bake) => bake,
+            None => Self::compute_hull(load_mesh(&root_path, &self.file, &self.name)?),
+        };
+        return Ok(bake.indices.len());
+    }
 
-            for vtx in obj.vertices {
-                vertices.push(Point3::new(fx_f64(vtx.x), fx_f64(vtx.y), fx_f64(vtx.z)));
-            }
+    pub(crate) fn bake_key(&self) -> String {
+        return bake_key("hull", &self.file, &self.name);
+    }
+
+    // Returns the bake hash, the IDTable records it for restore.
+    pub(crate) fn bake<P: AsRef<Path>>(&self, root_path: P) -> Result<u64> {
+        let hash = ShapeBake::bake_hash(&root_path, "hull", &self.file, &self.name)?;
+        let bake_path = ShapeBake::hash_path(&root_path, "hull", hash);
+        if !bake_path.exists() {
+            Self::compute_hull(load_mesh(&root_path, &self.file, &self.name)?).write(&bake_path)?;
+        }
+        return Ok(hash);
+    }
 
-            for geo in obj.geometry {
-                for shape in geo.shapes {
-                    match shape.primitive {
-                        Primitive::Triangle(x, y, z) => indices.push(Point3::new(x.0, y.0, z.0)),
-                        _ => return Err(anyhow!("Not a trimesh model")),
-                    }
+    pub(crate) fn load<P: AsRef<Path>>(
+        &mut self,
+        root_path: P,
+        hash: u64,
+    ) -> Result<ShapeHandle<Fx>> {
+        let bake = load_baked_mesh(&root_path, "hull", hash, &self.name)?;
+        let indices: Vec<usize> = bake
+            .indices
+            .iter()
+            .flat_map(|idx| vec![idx.x, idx.y, idx.z])
+            .collect();
+        return match ConvexHull::try_new(bake.vertices, &indices) {
+            Some(hull) => Ok(ShapeHandle::new(hull)),
+            None => Err(anyhow!("Not a convex hull {:?}", self.name)),
+        };
+    }
+
+    fn compute_hull(mesh: ShapeBake) -> ShapeBake {
+        let hull = transformation::convex_hull(&mesh.vertices);
+        let indices = hull
+            .flat_indices()
+            .chunks(3)
+            .map(|idx| Point3::new(idx[0] as usize, idx[1] as usize, idx[2] as usize))
+            .collect();
+        return ShapeBake {
+            vertices: hull.coords,
+            indices,
+        };
+    }
+}
+
+// IDTable key of a baked shape.
+fn bake_key(kind: &str, file: &str, name: &str) -> String {
+    return format!("{}:{}:{}", kind, file, name);
+}
+
+// Restore reads the bake of the compiled hash only, sources are neither hashed nor read.
+fn load_baked_mesh<P: AsRef<Path>>(
+    root_path: P,
+    kind: &str,
+    hash: u64,
+    name: &str,
+) -> Result<ShapeBake> {
+    let bake_path = ShapeBake::hash_path(&root_path, kind, hash);
+    return match ShapeBake::read(&bake_path)? {
+        Some(bake) => Ok(bake),
+        None => Err(anyhow!("Shape bake not found {:?} {:?}", name, bake_path)),
+    };
+}
+
+fn load_mesh<P: AsRef<Path>>(root_path: P, file: &str, name: &str) -> Result<ShapeBake> {
+    let mut path = PathBuf::from(root_path.as_ref());
+    path.push(file);
+    if file.ends_with(".obj") {
+        return load_obj(&path, name);
+    } else if file.ends_with(".glb") {
+        return Err(anyhow!("Not implement .glb"));
+    } else if file.ends_with(".gltf") {
+        return Err(anyhow!("Not implement .gltf"));
+    }
+    return Err(anyhow!("Unknown file format"));
+}
+
+fn load_obj<P: AsRef<Path>>(file: P, name: &str) -> Result<ShapeBake> {
+    let buf = fs::read_to_string(file)?;
+    let model = obj::parse(buf)?;
+
+    let mut vertices = Vec::<Point3<Fx>>::new();
+    let mut indices = Vec::<Point3<usize>>::new();
+
+    for obj in model.objects {
+        if obj.name != name {
+            continue;
+        }
+
+        for vtx in obj.vertices {
+            vertices.push(Point3::new(fx_f64(vtx.x), fx_f64(vtx.y), fx_f64(vtx.z)));
+        }
+
+        for geo in obj.geometry {
+            for shape in geo.shapes {
+                match shape.primitive {
+                    Primitive::Triangle(x, y, z) => indices.push(Point3::new(x.0, y.0, z.0)),
+                    _ => return Err(anyhow!("Not a trimesh model")),
                 }
             }
         }
-
-        let mesh = TriMesh::new(vertices, indices, None);
-        let handle = ShapeHandle::new(mesh);
-        return Ok(handle);
     }
+
+    return Ok(ShapeBake { vertices, indices });
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::resource::shape_bake::SHAPE_BAKE_DIR;
+    use crate::utils::TestDir;
     use math::ff;
 
     #[test]
@@ -326,6 +458,35 @@ mod tests {
             file: "stage-simple.obj".to_string(),
             name: "stage-simple.obj".to_string(),
         };
-        trimesh.load("../test_files/resource/").unwrap();
+        let dir = TestDir::new("shape_trimesh");
+        dir.copy(
+            "../test_files/resource/stage-simple.obj",
+            "stage-simple.obj",
+        );
+        let hash =
+            ShapeBake::bake_hash(dir.root(), "trimesh", &trimesh.file, &trimesh.name).unwrap();
+        assert!(trimesh.load(dir.root(), hash).is_err());
+
+        let bake_path = ShapeBake::hash_path(dir.root(), "trimesh", hash);
+        assert!(!bake_path.exists());
+        assert_eq!(trimesh.bake(dir.root()).unwrap(), hash);
+        assert!(bake_path.exists());
+        trimesh.load(dir.root(), hash).unwrap();
+
+        // Restore finds the bake by hash, the source index is neither read nor rewritten.
+        let cache_dir = dir.path(SHAPE_BAKE_DIR);
+        for entry in fs::read_dir(&cache_dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path != bake_path {
+                fs::remove_file(path).unwrap();
+            }
+        }
+        fs::remove_file(dir.path("stage-simple.obj")).unwrap();
+        trimesh.load(dir.root(), hash).unwrap();
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 1);
+
+        // A broken bake is an error, not silently replaced by the source mesh.
+        fs::write(&bake_path, b"broken").unwrap();
+        assert!(trimesh.load(dir.root(), hash).is_err());
     }
 }
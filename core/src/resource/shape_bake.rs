@@ -0,0 +1,294 @@
+use crate::utils::FnvHasher;
+use anyhow::{anyhow, Result};
+use math::Fx;
+use na::Point3;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::UNIX_EPOCH;
+
+pub(crate) const SHAPE_BAKE_DIR: &'static str = ".shape_cache";
+
+const SHAPE_BAKE_MAGIC: &'static [u8; 4] = b"CPSB";
+const SHAPE_BAKE_VERSION: u32 = 1;
+
+const SOURCE_INDEX_MAGIC: &'static [u8; 4] = b"CPSI";
+// Magic, size, mtime secs, mtime nanos and the content hash.
+const SOURCE_INDEX_SIZE: usize = 4 + 8 + 8 + 4 + 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ShapeBake {
+    pub vertices: Vec<Point3<Fx>>,
+    pub indices: Vec<Point3<usize>>,
+}
+
+static TMP_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+impl ShapeBake {
+    // The name is content-addressed: hash of the source file plus the shape params.
+    // Only the compiler hashes sources, restore finds the bake by the hash recorded in the IDTable.
+    pub(crate) fn bake_hash<P, H>(root_path: P, kind: &str, file: &str, params: &H) -> Result<u64>
+    where
+        P: AsRef<Path>,
+        H: Hash,
+    {
+        let mut hasher = FnvHasher::new();
+        hasher.write_u64(source_hash(&root_path, file)?);
+        kind.hash(&mut hasher);
+        params.hash(&mut hasher);
+        return Ok(hasher.finish());
+    }
+
+    pub(crate) fn hash_path<P: AsRef<Path>>(root_path: P, kind: &str, hash: u64) -> PathBuf {
+        let mut path = PathBuf::from(root_path.as_ref());
+        path.push(SHAPE_BAKE_DIR);
+        path.push(format!("{:016x}.{}", hash, kind));
+        return path;
+    }
+
+    pub(crate) fn bake_path<P, H>(
+        root_path: P,
+        kind: &str,
+        file: &str,
+        params: &H,
+    ) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+        H: Hash,
+    {
+        let hash = Self::bake_hash(&root_path, kind, file, params)?;
+        return Ok(Self::hash_path(root_path, kind, hash));
+    }
+
+    // Ok(None) if the bake file not exists, other errors (broken file, permission...) are returned.
+    pub(crate) fn read<P: AsRef<Path>>(path: P) -> Result<Option<ShapeBake>> {
+        let buf = match read_if_exists(path.as_ref())? {
+            Some(buf) => buf,
+            None => return Ok(None),
+        };
+        let mut reader = BakeReader {
+            buf: &buf,
+            offset: 0,
+        };
+
+        if reader.bytes(4)? != SHAPE_BAKE_MAGIC {
+            return Err(anyhow!("Not a shape bake file {:?}", path.as_ref()));
+        }
+        if reader.u32()? != SHAPE_BAKE_VERSION {
+            return Err(anyhow!("Shape bake version not match {:?}", path.as_ref()));
+        }
+
+        let vertex_count = reader.u32()? as usize;
+        let mut vertices = Vec::with_capacity(vertex_count);
+        for _ in 0..vertex_count {
+            vertices.push(Point3::new(reader.fx()?, reader.fx()?, reader.fx()?));
+        }
+
+        let index_count = reader.u32()? as usize;
+        let mut indices = Vec::with_capacity(index_count);
+        for _ in 0..index_count {
+            let (x, y, z) = (reader.u32()?, reader.u32()?, reader.u32()?);
+            if x as usize >= vertex_count
+                || y as usize >= vertex_count
+                || z as usize >= vertex_count
+            {
+                return Err(anyhow!("Shape bake index out of range {:?}", path.as_ref()));
+            }
+            indices.push(Point3::new(x as usize, y as usize, z as usize));
+        }
+        return Ok(Some(ShapeBake { vertices, indices }));
+    }
+
+    pub(crate) fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut buf = Vec::with_capacity(12 + self.vertices.len() * 24 + self.indices.len() * 12);
+        buf.extend_from_slice(SHAPE_BAKE_MAGIC);
+        buf.extend_from_slice(&SHAPE_BAKE_VERSION.to_le_bytes());
+        buf.extend_from_slice(&(self.vertices.len() as u32).to_le_bytes());
+        for vtx in &self.vertices {
+            buf.extend_from_slice(&vtx.x.to_bits().to_le_bytes());
+            buf.extend_from_slice(&vtx.y.to_bits().to_le_bytes());
+            buf.extend_from_slice(&vtx.z.to_bits().to_le_bytes());
+        }
+        buf.extend_from_slice(&(self.indices.len() as u32).to_le_bytes());
+        for idx in &self.indices {
+            buf.extend_from_slice(&(idx.x as u32).to_le_bytes());
+            buf.extend_from_slice(&(idx.y as u32).to_le_bytes());
+            buf.extend_from_slice(&(idx.z as u32).to_le_bytes());
+        }
+
+        return write_atomic(path, &buf);
+    }
+}
+
+// Content hash of a source file, indexed by its size and mtime in <SHAPE_BAKE_DIR>/<file hash>.src.
+// The source is read and hashed again only if the size or the mtime changed.
+fn source_hash<P: AsRef<Path>>(root_path: P, file: &str) -> Result<u64> {
+    let mut src_path = PathBuf::from(root_path.as_ref());
+    src_path.push(file);
+    let meta = fs::metadata(&src_path)?;
+    let mtime = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| anyhow!("Invalid mtime {:?}", src_path))?;
+
+    let mut hasher = FnvHasher::new();
+    file.hash(&mut hasher);
+    let mut index_path = PathBuf::from(root_path.as_ref());
+    index_path.push(SHAPE_BAKE_DIR);
+    index_path.push(format!("{:016x}.src", hasher.finish()));
+
+    let mut index = Vec::with_capacity(SOURCE_INDEX_SIZE);
+    index.extend_from_slice(SOURCE_INDEX_MAGIC);
+    index.extend_from_slice(&meta.len().to_le_bytes());
+    index.extend_from_slice(&mtime.as_secs().to_le_bytes());
+    index.extend_from_slice(&mtime.subsec_nanos().to_le_bytes());
+    if let Some(buf) = read_if_exists(&index_path)? {
+        if buf.len() == SOURCE_INDEX_SIZE && buf[..index.len()] == index[..] {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&buf[index.len()..]);
+            return Ok(u64::from_le_bytes(bytes));
+        }
+    }
+
+    let mut hasher = FnvHasher::new();
+    hasher.write(&fs::read(&src_path)?);
+    let hash = hasher.finish();
+    index.extend_from_slice(&hash.to_le_bytes());
+    write_atomic(&index_path, &index)?;
+    return Ok(hash);
+}
+
+fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    return Ok(Some(buf));
+}
+
+// Write then rename, a crashed compiler never leaves a truncated file behind.
+// The temp file is unique per process and call, concurrent compilers never share one.
+fn write_atomic(path: &Path, buf: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file_name = path
+        .file_name()
+        .ok_or(anyhow!("Invalid path {:?}", path))?
+        .to_string_lossy();
+    let tmp_path = path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name,
+        process::id(),
+        TMP_SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    ));
+    let res = fs::File::create(&tmp_path)
+        .and_then(|mut file| file.write_all(buf))
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if let Err(err) = res {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err.into());
+    }
+    return Ok(());
+}
+
+struct BakeReader<'t> {
+    buf: &'t [u8],
+    offset: usize,
+}
+
+impl<'t> BakeReader<'t> {
+    fn bytes(&mut self, len: usize) -> Result<&'t [u8]> {
+        if self.offset + len > self.buf.len() {
+            return Err(anyhow!("Shape bake file truncated"));
+        }
+        let bytes = &self.buf[self.offset..self.offset + len];
+        self.offset += len;
+        return Ok(bytes);
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        return Ok(u32::from_le_bytes(bytes));
+    }
+
+    fn fx(&mut self) -> Result<Fx> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        return Ok(Fx::from_bits(i64::from_le_bytes(bytes)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+    use math::{ff, fi};
+
+    #[test]
+    fn test_shape_bake() {
+        let dir = TestDir::new("shape_bake");
+        let bake = ShapeBake {
+            vertices: vec![
+                Point3::new(fi(0), fi(0), fi(0)),
+                Point3::new(ff(1.5), fi(0), fi(-2)),
+                Point3::new(fi(0), ff(0.25), fi(3)),
+            ],
+            indices: vec![Point3::new(0, 1, 2)],
+        };
+        let path = dir.path("test.trimesh");
+        assert_eq!(ShapeBake::read(&path).unwrap(), None);
+        bake.write(&path).unwrap();
+        assert_eq!(ShapeBake::read(&path).unwrap(), Some(bake));
+
+        fs::write(&path, b"broken").unwrap();
+        assert!(ShapeBake::read(&path).is_err());
+
+        // Temp files are renamed away, nothing but the bake is left.
+        bake.write(&path).unwrap();
+        let files: Vec<_> = fs::read_dir(dir.root()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+    }
+
+    #[test]
+    fn test_shape_bake_path() {
+        let dir = TestDir::new("shape_bake_path");
+        dir.copy("../test_files/resource/stage-simple.obj", "stage.obj");
+        let path1 = ShapeBake::bake_path(dir.root(), "trimesh", "stage.obj", &"a").unwrap();
+        let path2 = ShapeBake::bake_path(dir.root(), "trimesh", "stage.obj", &"b").unwrap();
+        assert_ne!(path1, path2);
+        assert!(path1.starts_with(dir.path(SHAPE_BAKE_DIR)));
+        assert!(ShapeBake::bake_path(dir.root(), "trimesh", "missing.obj", &"a").is_err());
+
+        // Same size and mtime, the hash comes from the source index.
+        let hash = source_hash(dir.root(), "stage.obj").unwrap();
+        let src = fs::read(dir.path("stage.obj")).unwrap();
+        let index_path = fs::read_dir(dir.path(SHAPE_BAKE_DIR))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().unwrap() == "src")
+            .unwrap();
+        let mut index = fs::read(&index_path).unwrap();
+        let len = index.len();
+        index[len - 8..].copy_from_slice(&7u64.to_le_bytes());
+        fs::write(&index_path, &index).unwrap();
+        assert_eq!(source_hash(dir.root(), "stage.obj").unwrap(), 7);
+
+        // Size changed, the source is hashed again.
+        let mut changed = src.clone();
+        changed.extend_from_slice(b"\n");
+        fs::write(dir.path("stage.obj"), &changed).unwrap();
+        let hash2 = source_hash(dir.root(), "stage.obj").unwrap();
+        assert_ne!(hash2, 7);
+        assert_ne!(hash2, hash);
+        assert_eq!(source_hash(dir.root(), "stage.obj").unwrap(), hash2);
+    }
+}
@@ -1,32 +1,76 @@
-use super::base::{ResCoordinate, ResLerpFunction, ResLerpParameter};
-use super::serde_helper;
+use super::base::{ResCoordinate, ResLerpFunction, ResLerpParameter, ResObj};
+use super::buff::ResBuff;
+use super::cache::{CompileContext, RestoreContext};
 use super::shape::ResShape;
-use derivative::Derivative;
-use m::Fx;
-use na::Isometry3;
-use nalgebra::{Point3, Translation3, UnitQuaternion};
-use crate::physics::shape::ShapeHandle;
+use crate::derive::def_res;
+use crate::id::{ClassID, FastResID, ResID};
+use crate::utils::serde_helper;
+use anyhow::{anyhow, Result};
+use math::Fx;
+use na::{Isometry3, Point3, Translation3, UnitQuaternion};
 use serde::{Deserialize, Serialize};
 
-#[derive(Derivative, Clone, Deserialize, Serialize)]
-#[derivative(Debug)]
+#[def_res(ClassID::Skill)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResSkill {
+    pub res_id: ResID,
+    #[serde(skip)]
+    pub fres_id: FastResID,
     pub frames: u32,
     pub shape: ResShape,
-    #[derivative(Debug = "ignore")]
-    #[serde(skip)]
-    #[serde(default = "ResShape::default_handle")]
-    pub h_shape: ShapeHandle<Fx>,
     pub center: Point3<Fx>,
     #[serde(with = "serde_helper::isometry")]
     pub origin: Isometry3<Fx>,
     pub origin_coord: ResCoordinate,
-    pub motion: Vec<ResMotion>,
+    #[serde(default)]
+    pub motions: Vec<ResMotion>,
     pub motion_coord: ResCoordinate,
+    #[serde(default)]
+    pub hits: Vec<ResID>,
     pub effect: ResEffect,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[typetag::serde(name = "Skill")]
+impl ResObj for ResSkill {
+    fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
+        ctx.insert_res_id(&self.res_id)?;
+        if self.frames == 0 {
+            return Err(anyhow!("Skill frames must be positive {:?}", self.res_id));
+        }
+        for (idx, motion) in self.motions.iter_mut().enumerate() {
+            let (start_frame, finish_frame) = motion.frame_range();
+            if start_frame > finish_frame || finish_frame > self.frames {
+                return Err(anyhow!(
+                    "Skill motion[{}] frame range {}..{} out of 0..{} {:?}",
+                    idx,
+                    start_frame,
+                    finish_frame,
+                    self.frames,
+                    self.res_id
+                ));
+            }
+            motion.compile(ctx)?;
+        }
+        self.shape.compile(ctx)?;
+        return Ok(());
+    }
+
+    fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        self.fres_id = ctx.get_fres_id(&self.res_id)?;
+        self.shape.restore(ctx)?;
+        for motion in &mut self.motions {
+            motion.restore(ctx)?;
+        }
+        for hit in &self.hits {
+            ctx.find_hit(hit)?;
+        }
+        self.effect.restore(ctx)?;
+        return Ok(());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
 pub enum ResMotion {
     Move(ResMotionMove),
     Rotate(ResMotionRotate),
@@ -35,6 +79,35 @@ pub enum ResMotion {
     SearchTarget(ResMotionSearchTarget),
 }
 
+impl ResMotion {
+    pub fn frame_range(&self) -> (u32, u32) {
+        return match self {
+            ResMotion::Move(motion) => (motion.start_frame, motion.finish_frame),
+            ResMotion::Rotate(motion) => (motion.start_frame, motion.finish_frame),
+            ResMotion::MoveSpeed(motion) => (motion.start_frame, motion.finish_frame),
+            ResMotion::RotateSpeed(motion) => (motion.start_frame, motion.finish_frame),
+            ResMotion::SearchTarget(motion) => (motion.start_frame, motion.finish_frame),
+        };
+    }
+
+    fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
+        return match self {
+            ResMotion::SearchTarget(motion) => motion.shape.compile(ctx),
+            _ => Ok(()),
+        };
+    }
+
+    fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        return match self {
+            ResMotion::Move(motion) => motion.lerp_function.restore(ctx),
+            ResMotion::Rotate(motion) => motion.lerp_function.restore(ctx),
+            ResMotion::MoveSpeed(motion) => motion.lerp_function.restore(ctx),
+            ResMotion::RotateSpeed(motion) => motion.lerp_function.restore(ctx),
+            ResMotion::SearchTarget(motion) => motion.shape.restore(ctx),
+        };
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ResMotionMove {
     pub start_frame: u32,
@@ -83,16 +156,11 @@ pub struct ResMotionRotateSpeed {
     pub lerp_parameter: ResLerpParameter,
 }
 
-#[derive(Derivative, Clone, Deserialize, Serialize)]
-#[derivative(Debug, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ResMotionSearchTarget {
     pub start_frame: u32,
     pub finish_frame: u32,
     pub shape: ResShape,
-    #[derivative(Debug = "ignore", PartialEq = "ignore")]
-    #[serde(skip)]
-    #[serde(default = "ResShape::default_handle")]
-    pub h_shape: ShapeHandle<Fx>,
     #[serde(with = "serde_helper::isometry")]
     pub transform: Isometry3<Fx>,
     pub searcher: ResMotionSearcher,
@@ -104,38 +172,35 @@ pub enum ResMotionSearcher {
     Random,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-pub struct ResMotionMoveDirection {
-    pub start_frame: u32,
-    pub finish_frame: u32,
-    pub start_value: Fx,
-    pub finish_value: Fx,
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ResEffect {
     #[serde(default)]
-    pub lerp_function: ResLerpFunction,
+    pub source_damage: ResDamage,
     #[serde(default)]
-    pub lerp_parameter: ResLerpParameter,
-}
-
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-pub struct ResMotionRotateDirection {
-    pub start_frame: u32,
-    pub finish_frame: u32,
-    pub start_value: Fx,
-    pub finish_value: Fx,
+    pub target_damage: ResDamage,
     #[serde(default)]
-    pub lerp_function: ResLerpFunction,
+    pub new_skill: Option<ResID>,
+    #[serde(skip)]
+    pub new_skill_fres_id: FastResID,
+    // Buffs added on the target by each accepted hit.
     #[serde(default)]
-    pub lerp_parameter: ResLerpParameter,
+    pub target_buffs: Vec<ResID>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct ResEffect {
-    pub source_damage: ResDamage,
-    pub target_damage: ResDamage,
-    pub new_skill: String,
+impl ResEffect {
+    fn restore(&mut self, ctx: &mut RestoreContext) -> Result<()> {
+        if let Some(new_skill) = &self.new_skill {
+            ctx.find_skill(new_skill)?;
+            self.new_skill_fres_id = ctx.get_fres_id(new_skill)?;
+        }
+        for buff in &self.target_buffs {
+            ctx.find_res::<ResBuff>(buff)?;
+        }
+        return Ok(());
+    }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct ResDamage {
     pub health: i32,
     pub energy: i32,
@@ -145,18 +210,48 @@ pub struct ResDamage {
     pub arcane: i32,
 }
 
-// pub struct ResDetectorMelee {
-//     pub duration: u32,
-//     pub shape: ResShape,
-//     #[serde(skip)]
-//     #[serde(default = "ResShape::default_handle")]
-//     pub h_shape: ShapeHandle<Fx>,
-//     pub center: Point3<Fx>,
-//     #[serde(with = "serde_helper::isometry")]
-//     pub origin: Isometry3<Fx>,
-//     pub start_translation: Translation3<Fx>,
-//     pub finish_translation: Translation3<Fx>,
-//     pub start_rotation: UnitQuaternion<Fx>,
-//     pub finish_rotation: UnitQuaternion<Fx>,
-//     pub circle_rotation: i32,
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::cache::ResCache;
+    use crate::resource::test_res::{restore_res, write_res};
+
+    const SKILL_A: &'static str = "- type: Skill\n  res_id: Skill.A\n  frames: 30\n  shape:\n    type: Ball\n    radius: 1\n  center: [0, 0, 0]\n  origin:\n    rotation: [0, 0, 0]\n    translation: [0, 1, 0]\n  origin_coord: Source\n  motion_coord: Source\n  motions:\n  - type: Move\n    start_frame: 0\n    finish_frame: 10\n    start_value: [0, 0, 0]\n    finish_value: [0, 0, 2]\n    lerp_function: QuadOut\n  effect:\n    target_damage:\n      health: 100\n      energy: 0\n      posture: 50\n      physical: 100\n      elemental: 0\n      arcane: 0\n    new_skill: Skill.B\n";
+    const SKILL_B: &'static str = "- type: Skill\n  res_id: Skill.B\n  frames: 20\n  shape:\n    type: Ball\n    radius: 1\n  center: [0, 0, 0]\n  origin:\n    rotation: [0, 0, 0]\n    translation: [0, 0, 0]\n  origin_coord: Source\n  motion_coord: Source\n  effect: {}\n";
+
+    #[test]
+    fn test_res_skill() {
+        let dir = write_res("skill", &format!("{}{}", SKILL_A, SKILL_B));
+        let cache = restore_res(&dir);
+        let skill = cache
+            .find_res_by_id(&ResID::from("Skill.A"))
+            .unwrap()
+            .cast_as::<ResSkill>()
+            .unwrap();
+        assert_eq!(skill.frames, 30);
+        assert_eq!(skill.motions.len(), 1);
+        assert_eq!(skill.motions[0].frame_range(), (0, 10));
+        assert_eq!(skill.effect.target_damage.health, 100);
+        assert_eq!(skill.effect.new_skill, Some(ResID::from("Skill.B")));
+        assert_eq!(
+            skill.effect.new_skill_fres_id,
+            cache.get_fres_id(&ResID::from("Skill.B")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_res_skill_invalid() {
+        let dir = write_res(
+            "skill_frame",
+            &SKILL_A.replace("finish_frame: 10", "finish_frame: 40"),
+        );
+        let errors = ResCache::check(dir.root_str(), "resource.yml", None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].res_id, Some(ResID::from("Skill.A")));
+
+        let dir = write_res("skill_ref", SKILL_A);
+        let errors = ResCache::check(dir.root_str(), "resource.yml", None);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("Skill.B"));
+    }
+}
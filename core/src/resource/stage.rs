@@ -19,6 +19,7 @@ pub struct ResStageGeneral {
 impl ResObj for ResStageGeneral {
     fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
         ctx.insert_res_id(&self.res_id)?;
+        self.world.compile(ctx)?;
         return Ok(());
     }
 
@@ -42,6 +43,7 @@ pub struct ResStageScenery {
 impl ResObj for ResStageScenery {
     fn compile(&mut self, ctx: &mut CompileContext) -> Result<()> {
         ctx.insert_res_id(&self.res_id)?;
+        self.collision.compile(ctx)?;
         return Ok(());
     }
 
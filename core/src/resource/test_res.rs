@@ -0,0 +1,19 @@
+use super::cache::ResCache;
+use crate::utils::{serialize, TestDir};
+use std::sync::Arc;
+
+pub(crate) const CHARA_TEST: &'static str = "- type: CharaHuman\n  res_id: Chara.Test\n  collision:\n    type: Ball\n    radius: 1\n  max_health: 10000\n  max_energy: 1000\n  max_posture: 1000\n  move_speed: 10\n  physical_attack: 1000\n  physical_defense: 500\n  elemental_attack: 300\n  elemental_defense: 300\n  arcane_attack: 750\n  arcane_defense: 500\n";
+
+// Writes the resources into the resource.yml of a new TestDir.
+pub(crate) fn write_res(name: &str, resources: &str) -> TestDir {
+    let dir = TestDir::new(name);
+    dir.write("resource.yml", &format!("resource:\n{}", resources));
+    return dir;
+}
+
+// Compiles resource.yml, serializes its id.yml then restores the cache, as the build does.
+pub(crate) fn restore_res(dir: &TestDir) -> Arc<ResCache> {
+    let cache = ResCache::compile(dir.root_str(), "resource.yml").unwrap();
+    serialize(dir.path("id.yml"), cache.id_table()).unwrap();
+    return ResCache::restore(dir.root_str(), "resource.yml", "id.yml").unwrap();
+}
@@ -0,0 +1,96 @@
+use super::cache::ResCache;
+use crate::id::ResID;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+#[derive(Clone)]
+pub struct ResCacheUpdate {
+    pub cache: Arc<ResCache>,
+    pub changed: Vec<ResID>,
+}
+
+pub struct ResCacheWatcher {
+    cache: Arc<ResCache>,
+    modified: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl ResCacheWatcher {
+    pub fn new(cache: Arc<ResCache>) -> ResCacheWatcher {
+        let modified = Self::modified_times(&cache);
+        return ResCacheWatcher { cache, modified };
+    }
+
+    #[inline]
+    pub fn cache(&self) -> &Arc<ResCache> {
+        return &self.cache;
+    }
+
+    pub fn poll(&mut self) -> Result<Option<ResCacheUpdate>> {
+        let modified = Self::modified_times(&self.cache);
+        if modified == self.modified {
+            return Ok(None);
+        }
+        // Record the times before reloading, a broken file is reported once instead of every poll.
+        self.modified = modified;
+
+        let (cache, changed) = ResCache::reload(&self.cache)?;
+        self.modified = Self::modified_times(&cache);
+        self.cache = cache.clone();
+        return Ok(Some(ResCacheUpdate { cache, changed }));
+    }
+
+    fn modified_times(cache: &ResCache) -> HashMap<PathBuf, Option<SystemTime>> {
+        return cache
+            .file_pathes()
+            .iter()
+            .map(|path| {
+                let time = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+                return (path.clone(), time);
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::test_res::CHARA_TEST;
+    use crate::utils::TestDir;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_res_cache_watcher() {
+        let dir = TestDir::new("watcher");
+        dir.write("resource.yml", "include:\n- chara.yml\n");
+        let path = dir.write("chara.yml", &format!("resource:\n{}", CHARA_TEST));
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+
+        let cache = ResCache::compile(dir.root_str(), "resource.yml").unwrap();
+        let fres_id = cache.get_fres_id(&ResID::from("Chara.Test")).unwrap();
+        let mut watcher = ResCacheWatcher::new(cache);
+        assert!(watcher.poll().unwrap().is_none());
+
+        // Rewrites until the mtime moves, the file system may not tell two writes in a row apart.
+        let chara = CHARA_TEST.replace("max_health: 10000", "max_health: 20000");
+        while fs::metadata(&path).unwrap().modified().unwrap() == mtime {
+            thread::sleep(Duration::from_millis(10));
+            dir.write("chara.yml", &format!("resource:\n{}", chara));
+        }
+        let update = watcher.poll().unwrap().unwrap();
+        assert_eq!(update.changed, vec![ResID::from("Chara.Test")]);
+        assert_eq!(update.cache.generation(), 1);
+        assert_eq!(
+            update
+                .cache
+                .get_fres_id(&ResID::from("Chara.Test"))
+                .unwrap(),
+            fres_id
+        );
+        assert!(watcher.poll().unwrap().is_none());
+    }
+}
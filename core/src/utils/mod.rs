@@ -1,10 +1,18 @@
 #![allow(dead_code)]
 
+mod hasher;
 mod ptr;
+mod random;
 mod rc_cell;
 pub mod serde_helper;
 mod serialize;
+#[cfg(test)]
+mod test_dir;
 
+pub use hasher::FnvHasher;
 pub use ptr::{const_ptr, mut_ptr, size_of_array, size_of_type, CastArc, CastRc};
+pub use random::Prng;
 pub use rc_cell::{RcCell, RcCellError, RcCellRef, RcCellRefMut};
 pub use serialize::{deserialize, serialize};
+#[cfg(test)]
+pub use test_dir::TestDir;
@@ -0,0 +1,67 @@
+use math::Fx;
+use serde::{Deserialize, Serialize};
+
+// SplitMix64, a small PRNG whose whole state is one u64 so it can be saved with the logic state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Prng {
+    state: u64,
+}
+
+impl Prng {
+    pub fn new(seed: u64) -> Prng {
+        return Prng { state: seed };
+    }
+
+    #[inline]
+    pub fn state(&self) -> u64 {
+        return self.state;
+    }
+
+    #[inline]
+    pub fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        return z ^ (z >> 31);
+    }
+
+    // Uniform in 0..range, range must be positive.
+    pub fn next_range(&mut self, range: u32) -> u32 {
+        return ((self.next_u64() >> 32) * (range as u64) >> 32) as u32;
+    }
+
+    // Uniform in [0, 1).
+    pub fn next_fx(&mut self) -> Fx {
+        return Fx::from_bits((self.next_u64() >> 32) as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::fi;
+
+    #[test]
+    fn test_prng() {
+        let mut prng1 = Prng::new(42);
+        let mut prng2 = Prng::new(42);
+        for _ in 0..100 {
+            assert_eq!(prng1.next_u64(), prng2.next_u64());
+        }
+        let state = prng1.state();
+        let value = prng1.next_range(10);
+        prng2.set_state(state);
+        assert_eq!(prng2.next_range(10), value);
+
+        for _ in 0..100 {
+            assert!(prng1.next_range(7) < 7);
+            let fx = prng1.next_fx();
+            assert!(fi(0) <= fx && fx < fi(1));
+        }
+    }
+}
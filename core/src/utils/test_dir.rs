@@ -0,0 +1,64 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static TEST_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// A temp directory unique to one test, tests running in parallel never share files.
+// Removed on drop.
+#[derive(Debug)]
+pub struct TestDir {
+    root: PathBuf,
+}
+
+impl TestDir {
+    pub fn new(name: &str) -> TestDir {
+        let count = TEST_DIR_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let root = env::temp_dir().join(format!(
+            "critical_point_{}_{}_{}",
+            name,
+            process::id(),
+            count
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        return TestDir { root };
+    }
+
+    #[inline]
+    pub fn root(&self) -> &Path {
+        return &self.root;
+    }
+
+    #[inline]
+    pub fn root_str(&self) -> &str {
+        return self.root.to_str().unwrap();
+    }
+
+    pub fn path(&self, file: &str) -> PathBuf {
+        return self.root.join(file);
+    }
+
+    pub fn write(&self, file: &str, text: &str) -> PathBuf {
+        let path = self.path(file);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(&path, text).unwrap();
+        return path;
+    }
+
+    pub fn copy<P: AsRef<Path>>(&self, src: P, file: &str) -> PathBuf {
+        let path = self.path(file);
+        fs::copy(src, &path).unwrap();
+        return path;
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
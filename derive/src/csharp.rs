@@ -83,16 +83,21 @@ pub fn csharp_enum(item_enum: ItemEnum) {
 }
 
 pub fn csharp_prop(item_struct: ItemStruct, class_id: &str) {
-    let lines = csharp_prop_or_state(item_struct, "DefProp", class_id);
+    let lines = csharp_def_struct(item_struct, "DefProp", class_id);
     FILE.write_all(&lines).unwrap();
 }
 
 pub fn csharp_state(item_struct: ItemStruct, class_id: &str) {
-    let lines = csharp_prop_or_state(item_struct, "DefState", class_id);
+    let lines = csharp_def_struct(item_struct, "DefState", class_id);
     FILE.write_all(&lines).unwrap();
 }
 
-fn csharp_prop_or_state(item_struct: ItemStruct, def: &str, class_id: &str) -> Vec<String> {
+pub fn csharp_event(item_struct: ItemStruct, event_id: &str) {
+    let lines = csharp_def_struct(item_struct, "DefEvent", event_id);
+    FILE.write_all(&lines).unwrap();
+}
+
+fn csharp_def_struct(item_struct: ItemStruct, def: &str, class_id: &str) -> Vec<String> {
     let mut lines = Vec::with_capacity(item_struct.fields.len() + 2);
     lines.push(format!(""));
     lines.push(format!("    [StructLayout(LayoutKind.Sequential)]"));
@@ -104,7 +109,16 @@ fn csharp_prop_or_state(item_struct: ItemStruct, def: &str, class_id: &str) -> V
     lines.push(format!("    public struct {} {{", item_struct.ident));
     let mut counter = 1;
     for field in &item_struct.fields {
-        let cs_type = translate_type(&field.ty);
+        let mut cs_type = translate_type(&field.ty);
+        if let Type::Array(array) = &field.ty {
+            let len = &array.len;
+            let len = TokenStream::from(quote! { #len }).to_string();
+            lines.push(format!(
+                "        [MarshalAs(UnmanagedType.ByValArray, SizeConst = {})]",
+                len
+            ));
+            cs_type.push_str("[]");
+        }
         if let Some(ident) = &field.ident {
             lines.push(format!("        public {} {};", cs_type, ident));
         } else {
@@ -116,7 +130,11 @@ fn csharp_prop_or_state(item_struct: ItemStruct, def: &str, class_id: &str) -> V
     return lines;
 }
 
+// Arrays translate to their element type, the field declares the fixed size.
 fn translate_type(ty: &Type) -> String {
+    if let Type::Array(array) = ty {
+        return translate_type(&array.elem);
+    }
     if let Type::Path(path) = ty {
         if let Some(ident) = path.path.get_ident() {
             let ident = ident.to_string();
@@ -9,7 +9,7 @@ mod csharp;
 mod script;
 mod utils;
 
-use csharp::{csharp_enum, csharp_prop, csharp_state};
+use csharp::{csharp_enum, csharp_event, csharp_prop, csharp_state};
 use proc_macro::TokenStream;
 use quote::quote;
 use script::{script_ctx_impl, script_var_impl};
@@ -91,6 +91,19 @@ pub fn def_state(attr: TokenStream, class: TokenStream) -> TokenStream {
 
     let class_name = &class.ident;
     let class_id = parse_macro_input!(attr as Expr);
+    // Fields in declaration order, the checksum must not depend on the memory layout.
+    let fields: Vec<proc_macro2::TokenStream> = class
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| match &field.ident {
+            Some(ident) => quote! { #ident },
+            None => {
+                let idx = Index::from(idx);
+                quote! { #idx }
+            }
+        })
+        .collect();
 
     return TokenStream::from(quote! {
         #[repr(C)]
@@ -102,6 +115,33 @@ pub fn def_state(attr: TokenStream, class: TokenStream) -> TokenStream {
                 return #class_id;
             }
         }
+
+        impl crate::engine::StateHash for #class_name {
+            fn state_hash(&self, hasher: &mut dyn std::hash::Hasher) {
+                #(crate::engine::StateHash::state_hash(&self.#fields, hasher);)*
+            }
+        }
+    });
+}
+
+#[proc_macro_attribute]
+pub fn def_event(attr: TokenStream, class: TokenStream) -> TokenStream {
+    let class = parse_macro_input!(class as ItemStruct);
+    csharp_event(class.clone(), &attr.to_string());
+
+    let class_name = &class.ident;
+    let event_id = parse_macro_input!(attr as Expr);
+
+    return TokenStream::from(quote! {
+        #[repr(C)]
+        #class
+
+        impl crate::engine::LogicEventStatic for #class_name {
+            #[inline]
+            fn id() -> crate::engine::EventID {
+                return #event_id;
+            }
+        }
     });
 }
 
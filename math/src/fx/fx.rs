@@ -545,8 +545,22 @@ impl ComplexField for Fx {
     }
 
     #[inline]
-    fn powi(self, _n: i32) -> Self {
-        unimplemented!()
+    fn powi(self, n: i32) -> Self {
+        // 0 ^ -n saturates as 1 / 0 does.
+        if n < 0 && self.is_zero() {
+            return Self::max_value();
+        }
+        let mut base = if n < 0 { Self::one() / self } else { self };
+        let mut exp = (n as i64).abs() as u64;
+        let mut result = Self::one();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exp >>= 1;
+        }
+        return result;
     }
 
     #[inline]
@@ -580,7 +594,21 @@ impl ComplexField for Fx {
 
     #[inline]
     fn exp2(self) -> Self {
-        unimplemented!()
+        // 2 ^ (n + f) = exp(f * ln2) << n, exp() only sees the fraction in [0, 1).
+        let int = self.0.floor();
+        let shift = int.to_num::<i32>();
+        if shift >= 31 {
+            return Self(I32F32::MAX);
+        }
+        if shift <= -64 {
+            return Self::zero();
+        }
+        let bits = cordic::exp((self.0 - int) * I32F32::LN_2).to_bits();
+        if shift >= 0 {
+            return Self(I32F32::from_bits(bits << shift));
+        } else {
+            return Self(I32F32::from_bits(bits >> -shift));
+        }
     }
 
     #[inline]
@@ -845,6 +873,16 @@ impl RealField for Fx {
 //
 
 impl Fx {
+    #[inline]
+    pub const fn from_bits(bits: i64) -> Fx {
+        return Fx(I32F32::from_bits(bits));
+    }
+
+    #[inline]
+    pub const fn to_bits(&self) -> i64 {
+        return self.0.to_bits();
+    }
+
     #[inline]
     pub fn to_i8(&self) -> i8 {
         return self.0.to_num::<i8>();
@@ -905,3 +943,23 @@ impl Fx {
         return self.0.to_num::<f64>();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fx_powi() {
+        let two = Fx(I32F32::from_num(2));
+        assert_eq!(two.powi(0), Fx::one());
+        assert_eq!(two.powi(10), Fx(I32F32::from_num(1024)));
+        assert_eq!(two.powi(-2), Fx(I32F32::from_num(0.25)));
+        assert_eq!((-two).powi(3), Fx(I32F32::from_num(-8)));
+        assert_eq!(two.powi(40), Fx::max_value());
+
+        assert_eq!(Fx::zero().powi(3), Fx::zero());
+        assert_eq!(Fx::zero().powi(0), Fx::one());
+        assert_eq!(Fx::zero().powi(-1), Fx::max_value());
+        assert_eq!(Fx::zero().powi(i32::MIN), Fx::max_value());
+    }
+}
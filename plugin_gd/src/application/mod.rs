@@ -2,7 +2,7 @@ mod camera;
 mod input;
 mod operation;
 
-use crate::core_ex::SYNC_AGENT;
+use crate::core_ex::{INTERPOLATOR, RES_WATCHER, SYNC_AGENT};
 use crate::utils::NodeExt;
 use anyhow::Result;
 use camera::AppCamera;
@@ -71,8 +71,23 @@ impl Application {
             godot_print!("{:?}", ops);
         }
 
-        if let Err(err) = SYNC_AGENT().run_tick() {
-            godot_error!("Application::_physics_process() => {:?}", err);
+        match RES_WATCHER().poll() {
+            Ok(Some(update)) => {
+                godot_print!("ResCache reloaded => {:?}", update.changed);
+                SYNC_AGENT().update_res_cache(update);
+            }
+            Ok(None) => {}
+            Err(err) => godot_error!("Application::_physics_process() => {:?}", err),
+        }
+
+        match SYNC_AGENT().run_tick_pool() {
+            Ok(pool) => {
+                // The pool two ticks old is no longer rendered, the engine reuses it.
+                if let Some(evicted) = INTERPOLATOR().push(*pool) {
+                    SYNC_AGENT().recycle_pool(Box::new(evicted));
+                }
+            }
+            Err(err) => godot_error!("Application::_physics_process() => {:?}", err),
         }
     }
 
@@ -1,10 +1,9 @@
-use crate::core_ex::{RES_CACHE, SYNC_AGENT};
-use anyhow::Result;
-use core::character::StateCharaHuman;
-use core::id::ObjID;
-use core::state::StateRef;
+use crate::core_ex::INTERPOLATOR;
+use core::id::{ClassID, ObjID};
 use euclid::Vector3D;
+use gdnative::api::Engine;
 use gdnative::prelude::*;
+use na::{Quaternion, UnitQuaternion};
 
 #[derive(NativeClass)]
 #[inherit(Spatial)]
@@ -12,8 +11,6 @@ use gdnative::prelude::*;
 #[user_data(LocalCellData<CharaHuman>)]
 pub struct CharaHuman {
     obj_id: ObjID,
-    state: StateRef<StateCharaHuman>,
-    counter: u32,
 }
 
 fn register_properties(builder: &ClassBuilder<CharaHuman>) {
@@ -32,78 +29,37 @@ impl CharaHuman {
 
         return CharaHuman {
             obj_id: ObjID::invalid(),
-            state: StateRef::invaild(),
-            counter: 0,
         };
     }
 
     #[export]
-    fn _ready(&mut self, owner: &Spatial) {
-        godot_print!("CharaHuman::_ready()");
-        owner.set_physics_process(true);
-
-        let result: Result<()> = try {
-            let fobj_id = RES_CACHE().get_fobj_id(&self.obj_id)?;
-            let binder = SYNC_AGENT().new_binder();
-            self.state.start(fobj_id, binder)?;
+    fn _process(&mut self, owner: &Spatial, _delta: f64) {
+        // Logic ticks run in Application::_physics_process(), the fraction is the alpha between the last two.
+        let alpha = Engine::godot_singleton().get_physics_interpolation_fraction() as f32;
+        let transform = match INTERPOLATOR().transform(self.obj_id, ClassID::CharaHuman, alpha) {
+            Some(transform) => transform,
+            None => return,
         };
-        if let Err(err) = result {
-            godot_error!("CharaHuman::_ready() => {:?}", err);
-        }
-    }
-
-    #[export]
-    fn _physics_process(&mut self, owner: &Spatial, _delta: f64) {
-        let result: Result<()> = try {
-            let state = self.state.state()?;
-
-            owner.set_translation(Vector3D::new(
-                state.position.translation.vector.x.to_f32(),
-                state.position.translation.vector.y.to_f32(),
-                state.position.translation.vector.z.to_f32(),
-            ));
-
-            self.counter += 1;
-            if self.counter % 60 == 0 {
-                godot_print!("{:?}", state.position);
-            }
-        };
-        if let Err(err) = result {
-            godot_error!("CharaHuman::_physics_process() => {:?}", err);
-        }
-
-        // let state = self.state.state().unwrap();
-
-        // let model = unsafe { owner.typed_node_tref::<Spatial, _>("./Model").unwrap() };
-        // let mat = state
-        //     .isometry
-        //     .rotation
-        //     .to_rotation_matrix()
-        //     .matrix()
-        //     .clone();
-        // // godot_print!("===== {} ===== {} {} {}", state.isometry.rotation, x, y, z);
-        // model.set_transform(Transform {
-        //     basis: Basis::from_elements([
-        //         Vector3D::new(
-        //             mat[(0, 0)].to_f32(),
-        //             mat[(1, 0)].to_f32(),
-        //             mat[(2, 0)].to_f32(),
-        //         ),
-        //         Vector3D::new(
-        //             mat[(0, 1)].to_f32(),
-        //             mat[(1, 1)].to_f32(),
-        //             mat[(2, 1)].to_f32(),
-        //         ),
-        //         Vector3D::new(
-        //             mat[(0, 2)].to_f32(),
-        //             mat[(1, 2)].to_f32(),
-        //             mat[(2, 2)].to_f32(),
-        //         ),
-        //     ]),
-        //     origin: Vector3D::zero(),
-        // });
 
-        // self.update_camera(owner).unwrap();
+        let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+            transform.rotation.w,
+            transform.rotation.i,
+            transform.rotation.j,
+            transform.rotation.k,
+        ));
+        let mat = rotation.to_rotation_matrix().matrix().clone();
+        owner.set_transform(Transform {
+            basis: Basis::from_elements([
+                Vector3D::new(mat[(0, 0)], mat[(0, 1)], mat[(0, 2)]),
+                Vector3D::new(mat[(1, 0)], mat[(1, 1)], mat[(1, 2)]),
+                Vector3D::new(mat[(2, 0)], mat[(2, 1)], mat[(2, 2)]),
+            ]),
+            origin: Vector3D::new(
+                transform.position.x,
+                transform.position.y,
+                transform.position.z,
+            ),
+        });
     }
 
     // #[export]
@@ -2,14 +2,17 @@
 
 use anyhow::{anyhow, Result};
 use core::agent::{AsyncLogicAgent, SyncLogicAgent};
-use core::resource::ResCache;
+use core::engine::{StateCharaHuman, StateInterpolator};
+use core::resource::{ResCache, ResCacheWatcher};
 use m::fi;
 use std::ptr;
 use std::sync::Arc;
 
 static mut PTR_RES_CACHE: *const Arc<ResCache> = ptr::null_mut();
+static mut PTR_RES_WATCHER: *mut ResCacheWatcher = ptr::null_mut();
 static mut PTR_SYNC_AGENT: *mut SyncLogicAgent = ptr::null_mut();
 static mut PTR_ASYNC_AGENT: *mut AsyncLogicAgent = ptr::null_mut();
+static mut PTR_INTERPOLATOR: *mut StateInterpolator = ptr::null_mut();
 
 pub fn load_res_cache() -> Result<()> {
     if !unsafe { PTR_RES_CACHE.is_null() } {
@@ -28,6 +31,20 @@ pub fn RES_CACHE() -> &'static Arc<ResCache> {
     return unsafe { &*PTR_RES_CACHE };
 }
 
+pub fn init_res_watcher() -> Result<()> {
+    if !unsafe { PTR_RES_WATCHER.is_null() } {
+        return Err(anyhow!("ResCacheWatcher inited"));
+    }
+    let watcher = Box::new(ResCacheWatcher::new(RES_CACHE().clone()));
+    unsafe { PTR_RES_WATCHER = Box::into_raw(watcher) };
+    return Ok(());
+}
+
+#[allow(non_snake_case)]
+pub fn RES_WATCHER() -> &'static mut ResCacheWatcher {
+    return unsafe { &mut *PTR_RES_WATCHER };
+}
+
 pub fn init_sync_agent() -> Result<()> {
     if !unsafe { PTR_SYNC_AGENT.is_null() } {
         return Err(anyhow!("SyncLogicAgent inited"));
@@ -52,6 +69,21 @@ pub fn init_async_agent() -> Result<()> {
 }
 
 #[allow(non_snake_case)]
-pub fn ASYNC_AGENT() -> &'static AsyncLogicAgent {
-    return unsafe { &*PTR_ASYNC_AGENT };
+pub fn ASYNC_AGENT() -> &'static mut AsyncLogicAgent {
+    return unsafe { &mut *PTR_ASYNC_AGENT };
+}
+
+pub fn init_interpolator() -> Result<()> {
+    if !unsafe { PTR_INTERPOLATOR.is_null() } {
+        return Err(anyhow!("StateInterpolator inited"));
+    }
+    let mut interpolator = Box::new(StateInterpolator::new());
+    interpolator.register::<StateCharaHuman>();
+    unsafe { PTR_INTERPOLATOR = Box::into_raw(interpolator) };
+    return Ok(());
+}
+
+#[allow(non_snake_case)]
+pub fn INTERPOLATOR() -> &'static mut StateInterpolator {
+    return unsafe { &mut *PTR_INTERPOLATOR };
 }
@@ -22,7 +22,7 @@ mod utils;
 
 use crate::application::Application;
 use crate::character::CharaHuman;
-use crate::core_ex::{init_sync_agent, load_res_cache};
+use crate::core_ex::{init_interpolator, init_res_watcher, init_sync_agent, load_res_cache};
 use crate::stage::StageGeneral;
 use gdnative::prelude::*;
 
@@ -33,12 +33,24 @@ fn init(handle: InitHandle) {
         godot_print!("init ResCache success");
     }
 
+    if let Err(err) = init_res_watcher() {
+        godot_error!("init_res_watcher() => {:?}", err);
+    } else {
+        godot_print!("init ResCacheWatcher success");
+    }
+
     if let Err(err) = init_sync_agent() {
         godot_error!("init_sync_agent() => {:?}", err);
     } else {
         godot_print!("init SyncLogicAgent success");
     }
 
+    if let Err(err) = init_interpolator() {
+        godot_error!("init_interpolator() => {:?}", err);
+    } else {
+        godot_print!("init StateInterpolator success");
+    }
+
     handle.add_class::<Application>();
     handle.add_class::<StageGeneral>();
     handle.add_class::<CharaHuman>();
@@ -46,9 +46,40 @@ namespace CriticalPoint {
     [UnmanagedFunctionPointer(CallingConvention.StdCall)]
     internal delegate IntPtr SyncAgentUpdateFunc(IntPtr syncAgent);
 
+    [UnmanagedFunctionPointer(CallingConvention.StdCall)]
+    internal delegate IntPtr DataPoolExportStatesFunc(IntPtr dataPool);
+
+    [UnmanagedFunctionPointer(CallingConvention.StdCall)]
+    internal delegate IntPtr DataPoolEventsFunc(IntPtr dataPool);
+
     [UnmanagedFunctionPointer(CallingConvention.StdCall)]
     internal delegate void FreeDataPoolFunc(IntPtr dataPool);
 
+    [UnmanagedFunctionPointer(CallingConvention.StdCall)]
+    internal delegate IntPtr CreateStateInterpolatorFunc();
+
+    [UnmanagedFunctionPointer(CallingConvention.StdCall)]
+    internal delegate void DestroyStateInterpolatorFunc(IntPtr interpolator);
+
+    [UnmanagedFunctionPointer(CallingConvention.StdCall)]
+    internal delegate IntPtr StateInterpolatorPushFunc(IntPtr interpolator, IntPtr dataPool);
+
+    [UnmanagedFunctionPointer(CallingConvention.StdCall)]
+    internal delegate IntPtr StateInterpolatorCurrentFunc(IntPtr interpolator);
+
+    [UnmanagedFunctionPointer(CallingConvention.StdCall)]
+    [return: MarshalAs(UnmanagedType.Bool)]
+    internal delegate bool StateInterpolatorTransformFunc(
+        IntPtr interpolator,
+        ObjID objId,
+        ClassID classId,
+        float alpha,
+        out FFITransform transform
+    );
+
+    [UnmanagedFunctionPointer(CallingConvention.StdCall)]
+    internal delegate IntPtr StateInterpolatorTransformsFunc(IntPtr interpolator, float alpha, out uint len);
+
     internal static class FFI {
         private static IntPtr hModule;
 
@@ -58,7 +89,15 @@ namespace CriticalPoint {
         public static CreateSyncAgentFunc CreateSyncAgent;
         public static DestorySyncAgentFunc DestorySyncAgent;
         public static SyncAgentUpdateFunc SyncAgentUpdate;
+        public static DataPoolExportStatesFunc DataPoolExportStates;
+        public static DataPoolEventsFunc DataPoolEvents;
         public static FreeDataPoolFunc FreeDataPool;
+        public static CreateStateInterpolatorFunc CreateStateInterpolator;
+        public static DestroyStateInterpolatorFunc DestroyStateInterpolator;
+        public static StateInterpolatorPushFunc StateInterpolatorPush;
+        public static StateInterpolatorCurrentFunc StateInterpolatorCurrent;
+        public static StateInterpolatorTransformFunc StateInterpolatorTransform;
+        public static StateInterpolatorTransformsFunc StateInterpolatorTransforms;
 
         public static void Load() {
             if (hModule != IntPtr.Zero) {
@@ -76,7 +115,15 @@ namespace CriticalPoint {
             CreateSyncAgent = LoadFunc<CreateSyncAgentFunc>("create_sync_agent");
             DestorySyncAgent = LoadFunc<DestorySyncAgentFunc>("destroy_sync_agent");
             SyncAgentUpdate = LoadFunc<SyncAgentUpdateFunc>("sync_agent_update");
+            DataPoolExportStates = LoadFunc<DataPoolExportStatesFunc>("data_pool_export_states");
+            DataPoolEvents = LoadFunc<DataPoolEventsFunc>("data_pool_events");
             FreeDataPool = LoadFunc<FreeDataPoolFunc>("free_data_pool");
+            CreateStateInterpolator = LoadFunc<CreateStateInterpolatorFunc>("create_state_interpolator");
+            DestroyStateInterpolator = LoadFunc<DestroyStateInterpolatorFunc>("destroy_state_interpolator");
+            StateInterpolatorPush = LoadFunc<StateInterpolatorPushFunc>("state_interpolator_push");
+            StateInterpolatorCurrent = LoadFunc<StateInterpolatorCurrentFunc>("state_interpolator_current");
+            StateInterpolatorTransform = LoadFunc<StateInterpolatorTransformFunc>("state_interpolator_transform");
+            StateInterpolatorTransforms = LoadFunc<StateInterpolatorTransformsFunc>("state_interpolator_transforms");
         }
 
         private static F LoadFunc<F>(string funcName) where F : Delegate {
@@ -113,6 +160,15 @@ namespace CriticalPoint {
         }
     }
 
+    [AttributeUsage(AttributeTargets.Struct, AllowMultiple = false, Inherited = false)]
+    internal class DefEvent : Attribute {
+        public EventID eventId { get; }
+
+        public DefEvent(EventID eventId) {
+            this.eventId = eventId;
+        }
+    }
+
     public abstract class BaseRefState {
         internal BaseRefState next;
         protected ObjID _objId;
@@ -202,6 +258,7 @@ namespace CriticalPoint {
     public static class SyncAgent {
         private static IntPtr resCache = IntPtr.Zero;
         private static IntPtr syncAgent = IntPtr.Zero;
+        private static IntPtr interpolator = IntPtr.Zero;
 
         private static Dictionary<ClassID, FactoryFunc> factories = new Dictionary<ClassID, FactoryFunc>();
         internal static Dictionary<ObjID, BaseRefState> states = new Dictionary<ObjID, BaseRefState>();
@@ -238,9 +295,19 @@ namespace CriticalPoint {
             if (syncAgent == IntPtr.Zero) {
                 throw new Exception("CreateSyncAgent()");
             }
+
+            interpolator = FFI.CreateStateInterpolator();
+            if (interpolator == IntPtr.Zero) {
+                throw new Exception("CreateStateInterpolator()");
+            }
         }
 
-        public static void Finialize() {
+        public static unsafe void Finialize() {
+            if (interpolator != IntPtr.Zero) {
+                FFI.DestroyStateInterpolator(interpolator);
+                interpolator = IntPtr.Zero;
+                dataPool = null;
+            }
             if (syncAgent != IntPtr.Zero) {
                 FFI.DestorySyncAgent(syncAgent);
                 syncAgent = IntPtr.Zero;
@@ -274,10 +341,55 @@ namespace CriticalPoint {
             DispatchProp((*newPool).props);
             DispatchState((*newPool).states);
 
-            if (dataPool != null) {
-                FFI.FreeDataPool((IntPtr)dataPool);
+            // The interpolator owns the pools of the last two updates, state pointers stay valid.
+            var evicted = FFI.StateInterpolatorPush(interpolator, (IntPtr)newPool);
+            if (evicted != IntPtr.Zero) {
+                FFI.FreeDataPool(evicted);
+            }
+            dataPool = (DataPool*)FFI.StateInterpolatorCurrent(interpolator);
+        }
+
+        // Transform of an object between the last two updates, alpha is the time since the last
+        // Update() divided by the logic frame time.
+        public static bool InterpolateTransform(ObjID objId, ClassID classId, float alpha, out FFITransform transform) {
+            if (interpolator == IntPtr.Zero) {
+                throw new Exception("InterpolateTransform()");
+            }
+            return FFI.StateInterpolatorTransform(interpolator, objId, classId, alpha, out transform);
+        }
+
+        // Transforms of all interpolated objects, valid until the next call.
+        public static FFISlice<InterpolatedTransform> InterpolateTransforms(float alpha) {
+            if (interpolator == IntPtr.Zero) {
+                throw new Exception("InterpolateTransforms()");
+            }
+            uint len;
+            var ptr = FFI.StateInterpolatorTransforms(interpolator, alpha, out len);
+            return new FFISlice<InterpolatedTransform>(ptr, len);
+        }
+
+        // All states of the last update in a single buffer, valid until the next Update().
+        public static unsafe StateExport ExportStates() {
+            if (dataPool == null) {
+                throw new Exception("ExportStates()");
+            }
+            var export = FFI.DataPoolExportStates((IntPtr)dataPool);
+            if (export == IntPtr.Zero) {
+                throw new Exception("DataPoolExportStates()");
+            }
+            return new StateExport(export);
+        }
+
+        // Events of the last update in emission order, valid until the next Update().
+        public static unsafe EventStream Events() {
+            if (dataPool == null) {
+                throw new Exception("Events()");
+            }
+            var events = FFI.DataPoolEvents((IntPtr)dataPool);
+            if (events == IntPtr.Zero) {
+                throw new Exception("DataPoolEvents()");
             }
-            dataPool = newPool;
+            return new EventStream(events);
         }
 
         private static List<GameObject> DispatchProp(FFIArray<IntPtr> ptrs) {
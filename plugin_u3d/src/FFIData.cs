@@ -189,6 +189,21 @@ namespace CriticalPoint {
         }
     }
 
+    [StructLayout(LayoutKind.Sequential)]
+    public struct FFITransform {
+        public FFIVec3f position;
+        public FFIQuaternion rotation;
+
+        public FFITransform(FFIVec3f position, FFIQuaternion rotation) {
+            this.position = position;
+            this.rotation = rotation;
+        }
+
+        public override string ToString() {
+            return string.Format("FFITransform({0}, {1})", this.position, this.rotation);
+        }
+    }
+
     [StructLayout(LayoutKind.Sequential)]
     public struct FFIMat2f {
         public float m11;
@@ -439,11 +454,29 @@ namespace CriticalPoint {
 #if UNITY_64
         public IntPtr ptr;
         public ulong len;
+
+        public FFISlice(IntPtr ptr, uint len) {
+            this.ptr = ptr;
+            this.len = (ulong)len;
+        }
 #else
         public IntPtr ptr;
         public uint len;
+
+        public FFISlice(IntPtr ptr, uint len) {
+            this.ptr = ptr;
+            this.len = len;
+        }
 #endif
 
+        public ref T this[int idx] {
+            get {
+                unsafe {
+                    return ref *((T*)this.ptr + idx);
+                }
+            }
+        }
+
         public override string ToString() {
             return string.Format("FFIArray(ptr: 0x{0:X}, len: {1})", this.ptr, this.len);
         }
@@ -539,6 +572,15 @@ namespace CriticalPoint {
         }
     }
 
+    [StructLayout(LayoutKind.Sequential)]
+    public struct BuffState {
+        public FastResID fres_id;
+        public ObjID source;
+        public uint stacks;
+        public uint frame;
+        public uint remain;
+    }
+
     public enum OptAction : byte {
         None,
         Move,
@@ -639,4 +681,169 @@ namespace CriticalPoint {
         public FFIArray<IntPtr> props;
         public FFIArray<IntPtr> states;
     }
+
+    [StructLayout(LayoutKind.Sequential)]
+    public struct StateExportHeader {
+        public uint version;
+        public uint headerSize;
+        public uint entrySize;
+        public uint count;
+        public uint entriesOffset;
+        public uint blobOffset;
+        public uint blobSize;
+        public uint totalSize;
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    public struct StateExportEntry {
+        public ObjID objId;
+        public ClassID classId;
+        public LogicLifecycle lifecycle;
+        private byte _padding_;
+        public uint offset;
+    }
+
+    // Read only view of DataPool states, entries are sorted by classId then objId.
+    public struct StateExport {
+        public const uint Version = 1;
+
+        private readonly IntPtr ptr;
+
+        public StateExport(IntPtr ptr) {
+            this.ptr = ptr;
+            if (this.header.version != Version || this.header.entrySize != Marshal.SizeOf<StateExportEntry>()) {
+                throw new Exception(string.Format("Invalid StateExport version {0}", this.header.version));
+            }
+        }
+
+        public ref StateExportHeader header {
+            get {
+                unsafe { return ref *(StateExportHeader*)this.ptr; }
+            }
+        }
+
+        public int count { get { return (int)this.header.count; } }
+
+        public ref StateExportEntry Entry(int idx) {
+            unsafe {
+                var entries = (StateExportEntry*)((byte*)this.ptr + this.header.entriesOffset);
+                return ref entries[idx];
+            }
+        }
+
+        public ref S State<S>(ref StateExportEntry entry) where S : unmanaged {
+            unsafe {
+                return ref *(S*)((byte*)this.ptr + this.header.blobOffset + entry.offset);
+            }
+        }
+
+        // Range [start, end) of the entries of a class.
+        public (int, int) ClassRange(ClassID classId) {
+            int start = this.LowerBound((ushort)classId);
+            int end = this.LowerBound((ushort)((ushort)classId + 1));
+            return (start, end);
+        }
+
+        private int LowerBound(ushort classId) {
+            int low = 0;
+            int high = this.count;
+            while (low < high) {
+                int mid = (low + high) / 2;
+                if ((ushort)this.Entry(mid).classId < classId) {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            return low;
+        }
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    public struct InterpolatedTransform {
+        public ObjID objId;
+        public ClassID classId;
+        private ushort _padding_;
+        public FFITransform transform;
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    public struct EventPoolHeader {
+        public uint version;
+        public uint headerSize;
+        public uint count;
+        public uint totalSize;
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    public struct LogicEventHeader {
+        public ObjID objId;
+        public ClassID classId;
+        public EventID eventId;
+        public uint size;
+
+        public unsafe static ref LogicEventHeader FromPtr(IntPtr ptr) {
+            return ref *(LogicEventHeader*)ptr;
+        }
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    public struct LogicEvent<E> where E : unmanaged {
+        public ObjID objId;
+        public ClassID classId;
+        public EventID eventId;
+        private uint size;
+        public E evt;
+    }
+
+    // Read only view of the events of a tick, walked in emission order.
+    public struct EventStream : IEnumerable<IntPtr> {
+        public const uint Version = 1;
+
+        private readonly IntPtr ptr;
+
+        public EventStream(IntPtr ptr) {
+            this.ptr = ptr;
+            if (this.header.version != Version) {
+                throw new Exception(string.Format("Invalid EventStream version {0}", this.header.version));
+            }
+        }
+
+        public ref EventPoolHeader header {
+            get {
+                unsafe { return ref *(EventPoolHeader*)this.ptr; }
+            }
+        }
+
+        public int count { get { return (int)this.header.count; } }
+
+        // The event at ptr if its type is E, the ptr comes from the enumerator.
+        public static bool Event<E>(IntPtr ptr, out LogicEvent<E> evt) where E : unmanaged {
+            var attr = (DefEvent)Attribute.GetCustomAttribute(typeof(E), typeof(DefEvent));
+            if (attr == null) {
+                throw new Exception("Invalid Event type");
+            }
+            unsafe {
+                if (LogicEventHeader.FromPtr(ptr).eventId != attr.eventId) {
+                    evt = default;
+                    return false;
+                }
+                evt = *(LogicEvent<E>*)ptr;
+                return true;
+            }
+        }
+
+        public IEnumerator<IntPtr> GetEnumerator() {
+            var offset = this.header.headerSize;
+            while (offset < this.header.totalSize) {
+                var evt = this.ptr + (int)offset;
+                yield return evt;
+                offset += LogicEventHeader.FromPtr(evt).size;
+            }
+        }
+
+        IEnumerator IEnumerable.GetEnumerator() {
+            return this.GetEnumerator();
+        }
+    }
 }
@@ -9,9 +9,12 @@ extern crate math;
 
 use anyhow::Result;
 use core::agent::SyncAgent;
-use core::engine::DataPool;
-use core::ffi::{FFIArray, FFISlice};
-use core::id::ResID;
+use core::engine::{
+    DataPool, EventPoolHeader, InterpolatedTransform, StateCharaHuman, StateExportHeader,
+    StateInterpolator, StateProjectile,
+};
+use core::ffi::{FFIArray, FFISlice, FFITransform};
+use core::id::{ClassID, ObjID, ResID};
 use core::resource::ResCache;
 use libc::{c_char, c_uint};
 use simplelog::{Config, LevelFilter, WriteLogger};
@@ -124,6 +127,28 @@ unsafe extern "stdcall" fn sync_agent_update(agent: *mut SyncAgent) -> *mut Data
     }
 }
 
+// The export belongs to the pool, it is valid until the pool is freed or exported again.
+#[no_mangle]
+unsafe extern "stdcall" fn data_pool_export_states(
+    pool: *mut DataPool,
+) -> *const StateExportHeader {
+    if pool.is_null() {
+        error!("data_pool_export_states() => DataPool is null");
+        return ptr::null();
+    }
+    return (*pool).export_states().as_ptr();
+}
+
+// Events of the tick of the pool, valid until the pool is freed.
+#[no_mangle]
+unsafe extern "stdcall" fn data_pool_events(pool: *mut DataPool) -> *const EventPoolHeader {
+    if pool.is_null() {
+        error!("data_pool_events() => DataPool is null");
+        return ptr::null();
+    }
+    return (*pool).events().as_ptr();
+}
+
 #[no_mangle]
 unsafe extern "stdcall" fn free_data_pool(pool: *mut DataPool) {
     if !pool.is_null() {
@@ -131,6 +156,99 @@ unsafe extern "stdcall" fn free_data_pool(pool: *mut DataPool) {
     }
 }
 
+#[no_mangle]
+unsafe extern "stdcall" fn create_state_interpolator() -> *mut StateInterpolator {
+    let mut interpolator = StateInterpolator::new();
+    interpolator.register::<StateCharaHuman>();
+    interpolator.register::<StateProjectile>();
+    let interpolator = Box::into_raw(Box::new(interpolator));
+    info!("create_state_interpolator() => {:?}", interpolator);
+    return interpolator;
+}
+
+#[no_mangle]
+unsafe extern "stdcall" fn destroy_state_interpolator(interpolator: *mut StateInterpolator) {
+    if interpolator.is_null() {
+        error!("destroy_state_interpolator() => StateInterpolator is null");
+        return;
+    }
+    Box::from_raw(interpolator);
+    info!("destroy_state_interpolator() => {:?}", interpolator);
+}
+
+// Takes the ownership of pool, returns the evicted pool (or null) to be freed by free_data_pool().
+#[no_mangle]
+unsafe extern "stdcall" fn state_interpolator_push(
+    interpolator: *mut StateInterpolator,
+    pool: *mut DataPool,
+) -> *mut DataPool {
+    if interpolator.is_null() {
+        error!("state_interpolator_push() => StateInterpolator is null");
+        return ptr::null_mut();
+    }
+    if pool.is_null() {
+        error!("state_interpolator_push() => DataPool is null");
+        return ptr::null_mut();
+    }
+    let pool = Box::from_raw(pool);
+    return match (*interpolator).push(*pool) {
+        Some(evicted) => Box::into_raw(Box::new(evicted)),
+        None => ptr::null_mut(),
+    };
+}
+
+// The pool of the last push, owned by the interpolator.
+#[no_mangle]
+unsafe extern "stdcall" fn state_interpolator_current(
+    interpolator: *mut StateInterpolator,
+) -> *mut DataPool {
+    if interpolator.is_null() {
+        error!("state_interpolator_current() => StateInterpolator is null");
+        return ptr::null_mut();
+    }
+    return match (*interpolator).current_mut() {
+        Some(pool) => pool as *mut DataPool,
+        None => ptr::null_mut(),
+    };
+}
+
+#[no_mangle]
+unsafe extern "stdcall" fn state_interpolator_transform(
+    interpolator: *mut StateInterpolator,
+    obj_id: ObjID,
+    class_id: ClassID,
+    alpha: f32,
+    transform: *mut FFITransform,
+) -> bool {
+    if interpolator.is_null() || transform.is_null() {
+        error!("state_interpolator_transform() => StateInterpolator or FFITransform is null");
+        return false;
+    }
+    return match (*interpolator).transform(obj_id, class_id, alpha) {
+        Some(res) => {
+            *transform = res;
+            true
+        }
+        None => false,
+    };
+}
+
+// The transforms are valid until the next call.
+#[no_mangle]
+unsafe extern "stdcall" fn state_interpolator_transforms(
+    interpolator: *mut StateInterpolator,
+    alpha: f32,
+    len: *mut c_uint,
+) -> *const InterpolatedTransform {
+    if interpolator.is_null() || len.is_null() {
+        error!("state_interpolator_transforms() => StateInterpolator or len is null");
+        return ptr::null();
+    }
+    let transforms = (*interpolator).transforms(alpha);
+    *len = transforms.len() as c_uint;
+    return transforms.as_ptr();
+}
+
 // #[no_mangle]
 // unsafe extern "stdcall" fn sync_enter_scene(cmd_id: *const c_char) -> bool {
 //     if SYNC_AGENT.is_null() {